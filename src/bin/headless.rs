@@ -0,0 +1,811 @@
+use anyhow::*;
+use checkpoint::SolverState;
+use hydrocode::*;
+use simulation::{CpuSimulation, Scene, Simulation};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const USAGE: &str = "\
+Usage: headless [options]
+  --scene <scene>          .toml or .ron scene file, or built-in sph-block or mpm-block
+                           (default sph-block)
+  --steps <n>              number of time steps to advance (default 1000)
+  --time <t>               physical end time, overrides --steps
+  --substeps <n>           steps per GPU submission (default 10)
+  --output-interval <n>    steps between particle snapshots, 0 disables output (default 0)
+  --output-dir <dir>       snapshot directory (default output)
+  --output-steps <list>    comma separated steps with a snapshot, besides the interval ones
+  --output-format <format> csv, ply (binary), ply-ascii, vtu or vtk snapshots, the VTK formats
+                           are listed in particles.pvd for ParaView (default csv)
+  --checkpoint-interval <n>
+                           steps between checkpoints, written to checkpoint.bin in the output
+                           directory, 0 disables them (default 0)
+  --restart <checkpoint>   continue a run from a checkpoint of the same scene, the step count
+                           and end time still count from the start of the run
+  --conservation-interval <n>
+                           steps between mass, momentum and energy totals, written to
+                           conservation.csv in the output directory, 0 disables them (default 0)
+  --load-interval <n>      steps averaged into one row of loads.csv (default 1)
+  --fallback               use the software adapter
+  --cpu                    step the SPH scene with the rayon CPU solver, the leap frog integrator
+                           and box walls only, without creating a wgpu device
+The end time and output settings of a scene file apply unless given on the command line. Probes
+of a scene file are recorded every step to probes.csv in the output directory, the forces and
+moments on its load targets to loads.csv and the motion of its rigid bodies to bodies.csv.";
+
+struct Options {
+    scene: String,
+    steps: Option<u32>,
+    time: Option<f32>,
+    substeps: u32,
+    output_interval: Option<u32>,
+    output_steps: Vec<u32>,
+    output_dir: Option<PathBuf>,
+    output_format: OutputFormat,
+    checkpoint_interval: u32,
+    conservation_interval: Option<u32>,
+    load_interval: Option<u32>,
+    restart: Option<PathBuf>,
+    fallback: bool,
+    cpu: bool,
+}
+
+// Runs a scene without a window, e.g. on cluster nodes and CI machines without a display
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut options = parse_options(std::env::args().skip(1))?;
+    let restart = options
+        .restart
+        .as_ref()
+        .map(checkpoint::Checkpoint::load)
+        .transpose()?;
+    let (start_step, start_time) = match &restart {
+        Some(checkpoint) => (
+            u32::try_from(checkpoint.step).context("checkpoint step count is too large")?,
+            checkpoint.time,
+        ),
+        None => (0, 0.0),
+    };
+    let scene = load_scene(&mut options, restart.map(|checkpoint| checkpoint.state))?;
+    let output_interval = options.output_interval.unwrap_or(0);
+    let conservation_interval = options.conservation_interval.unwrap_or(0);
+    let load_interval = options.load_interval.unwrap_or(1);
+    if load_interval == 0 {
+        bail!("--load-interval must be at least 1");
+    }
+    let output_dir = options
+        .output_dir
+        .unwrap_or_else(|| PathBuf::from("output"));
+
+    let dt = scene.dt();
+    let steps = match options.time {
+        Some(time) => (time / dt).ceil() as u32,
+        None => options.steps.unwrap_or(1000),
+    };
+    let time_at = |step: u32| start_time + (step - start_step) as f64 * dt as f64;
+    let mut solver = match options.cpu {
+        true => Solver::Cpu(scene.init_cpu()?),
+        false => {
+            let (adapter, device, queue) =
+                pollster::block_on(headless::request_device(options.fallback))?;
+            let info = adapter.get_info();
+            println!(
+                "adapter {:?} ({:?}, {:?})",
+                info.name, info.device_type, info.backend
+            );
+            let mut simulation = scene.init(&device, &queue);
+            if conservation_interval > 0 {
+                simulation
+                    .conservation_mut()
+                    .context("the solver has no conservation totals")?
+                    .attach_conservation(&device);
+            }
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            }
+        }
+    };
+    println!(
+        "scene {}: {} particles, capacity {}, dt {:e}, {} steps to t = {}",
+        options.scene,
+        solver.num_particles(),
+        solver.capacity(),
+        dt,
+        steps,
+        steps as f32 * dt
+    );
+    if start_step > 0 {
+        println!("restarting at step {} t = {:.5}", start_step, start_time);
+    }
+    let output_steps = &options.output_steps;
+    let probe_names = solver.probe_names();
+    let load_targets = solver.load_targets();
+    let num_bodies = solver.rigid_body_motion().len();
+    if output_interval > 0
+        || !output_steps.is_empty()
+        || options.checkpoint_interval > 0
+        || conservation_interval > 0
+        || !probe_names.is_empty()
+        || !load_targets.is_empty()
+        || num_bodies > 0
+    {
+        std::fs::create_dir_all(&output_dir)
+            .with_context(|| format!("failed to create {}", output_dir.display()))?;
+    }
+    let mut snapshots = Snapshots::new(&output_dir, options.output_format, start_time)?;
+    if (output_interval > 0 || output_steps.contains(&0)) && start_step == 0 {
+        snapshots.write(&solver.state()?, 0, 0.0)?;
+    }
+    let mut probes = match (probe_names.is_empty(), start_step) {
+        (true, _) => None,
+        (false, 0) => Some(time_series::TimeSeriesWriter::create(
+            output_dir.join("probes.csv"),
+            &probe_names,
+        )?),
+        (false, _) => Some(time_series::TimeSeriesWriter::resume(
+            output_dir.join("probes.csv"),
+            &probe_names,
+            start_time,
+        )?),
+    };
+
+    // Rows of the mean loads since the previous row, the filters restart with the run
+    let mut load_history = loads::LoadHistory::new(&load_targets);
+    let mut loads = match (load_targets.is_empty(), start_step) {
+        (true, _) => None,
+        (false, 0) => Some(time_series::TimeSeriesWriter::create(
+            output_dir.join("loads.csv"),
+            load_history.names(),
+        )?),
+        (false, _) => Some(time_series::TimeSeriesWriter::resume(
+            output_dir.join("loads.csv"),
+            load_history.names(),
+            start_time,
+        )?),
+    };
+    let mut last_load_step = start_step;
+
+    // Pose and velocities of the rigid bodies at the end of every submission
+    let mut bodies = match num_bodies {
+        0 => None,
+        _ => {
+            let names = rigid_body_names(num_bodies);
+            let mut writer =
+                time_series::TimeSeriesWriter::create(output_dir.join("bodies.csv"), &names)?;
+            write_bodies(&mut writer, &solver, start_time)?;
+            Some(writer)
+        }
+    };
+
+    let conservation_path = output_dir.join("conservation.csv");
+    let mut conservation = match (conservation_interval, start_step) {
+        (0, _) => None,
+        (_, 0) => {
+            let names = conservation::Totals::names();
+            let mut writer = time_series::TimeSeriesWriter::create(&conservation_path, &names)?;
+            write_totals(&mut writer, &solver, 0.0)?;
+            Some(writer)
+        }
+        (_, _) => Some(time_series::TimeSeriesWriter::resume(
+            &conservation_path,
+            &conservation::Totals::names(),
+            start_time,
+        )?),
+    };
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut step = start_step;
+    while step < steps {
+        // Submissions end on output, checkpoint, conservation and load steps so that they are
+        // taken at the requested times
+        let mut n_substeps = options.substeps.min(steps - step);
+        for interval in [
+            output_interval,
+            options.checkpoint_interval,
+            conservation_interval,
+            if loads.is_some() { load_interval } else { 0 },
+        ] {
+            if interval > 0 {
+                n_substeps = n_substeps.min(interval - step % interval);
+            }
+        }
+        if let Some(next) = output_steps.iter().filter(|&&s| s > step).min() {
+            n_substeps = n_substeps.min(next - step);
+        }
+        // The GPU keeps a limited number of probe records between readbacks
+        if probes.is_some() {
+            n_substeps = n_substeps.min(probe::MAX_RECORDS);
+        }
+        solver.step(n_substeps);
+        step += n_substeps;
+        let time = time_at(step);
+
+        if let Some(probes) = &mut probes {
+            let rows = solver.probes();
+            if rows.len() != n_substeps as usize {
+                bail!("{} probe records for {} steps", rows.len(), n_substeps);
+            }
+            for (i, row) in rows.iter().enumerate() {
+                probes.write_row(time_at(step - n_substeps + i as u32 + 1), row)?;
+            }
+            probes.flush()?;
+        }
+
+        if let Some(writer) = &mut bodies {
+            write_bodies(writer, &solver, time)?;
+        }
+
+        if let Some(writer) = &mut loads
+            && (step % load_interval == 0 || step == steps)
+        {
+            let elapsed = (step - last_load_step) as f32 * dt;
+            let values = solver.loads(elapsed);
+            writer.write_row(time, &load_history.row(elapsed, &values))?;
+            writer.flush()?;
+            last_load_step = step;
+        }
+
+        let output = (output_interval > 0 && (step % output_interval == 0 || step == steps))
+            || output_steps.contains(&step);
+        if output {
+            let diagnostics = snapshots.write(&solver.state()?, step, time)?;
+            println!("step {} t = {:.5}: {}", step, time, diagnostics);
+            if diagnostics.num_non_finite > 0 {
+                bail!("non-finite particle state at step {}", step);
+            }
+        }
+        if let Some(writer) = &mut conservation
+            && (step % conservation_interval == 0 || step == steps)
+        {
+            write_totals(writer, &solver, time)?;
+        }
+        let interval = options.checkpoint_interval;
+        if interval > 0 && (step % interval == 0 || step == steps) {
+            let path = output_dir.join("checkpoint.bin");
+            let checkpoint = checkpoint::Checkpoint {
+                step: step as u64,
+                time,
+                state: solver.state()?,
+            };
+            checkpoint.save(&path)?;
+        }
+        if last_report.elapsed().as_secs_f32() >= 1.0 || step == steps {
+            last_report = Instant::now();
+            let elapsed = start.elapsed().as_secs_f32();
+            let rate = (step - start_step) as f32 / elapsed;
+            println!(
+                "step {}/{} t = {:.5} ({:.1} steps/s, eta {:.0} s)",
+                step,
+                steps,
+                time,
+                rate,
+                (steps - step) as f32 / rate
+            );
+        }
+    }
+
+    let data = solver.particle_data();
+    println!(
+        "finished {} steps in {:.1} s: {}",
+        steps,
+        start.elapsed().as_secs_f32(),
+        Diagnostics::new(&data.positions, &data.velocities)
+    );
+    Ok(())
+}
+
+fn write_totals(
+    writer: &mut time_series::TimeSeriesWriter<std::io::BufWriter<std::fs::File>>,
+    solver: &Solver,
+    time: f64,
+) -> Result<()> {
+    let totals = solver
+        .totals()
+        .context("conservation totals are not attached")?;
+    writer.write_row(time, &totals.values())?;
+    writer.flush()
+}
+
+// Position, orientation quaternion, linear and angular velocity of every body
+fn rigid_body_names(num_bodies: usize) -> Vec<String> {
+    let columns = [
+        "x", "y", "z", "qx", "qy", "qz", "qw", "vx", "vy", "vz", "wx", "wy", "wz",
+    ];
+    (0..num_bodies)
+        .flat_map(|idx| columns.map(|column| format!("body{}_{}", idx, column)))
+        .collect()
+}
+
+fn write_bodies(
+    writer: &mut time_series::TimeSeriesWriter<std::io::BufWriter<std::fs::File>>,
+    solver: &Solver,
+    time: f64,
+) -> Result<()> {
+    let row: Vec<f32> = solver
+        .rigid_body_motion()
+        .iter()
+        .flat_map(|motion| {
+            motion
+                .position
+                .into_iter()
+                .chain(motion.orientation)
+                .chain(motion.linear_velocity)
+                .chain(motion.angular_velocity)
+        })
+        .collect();
+    writer.write_row(time, &row)?;
+    writer.flush()
+}
+
+// The scene stepped on the GPU, or on the CPU without any device. The CPU solver has no probes,
+// loads or rigid bodies, see Scene::init_cpu. Records a solver does not have are empty.
+enum Solver {
+    Gpu {
+        simulation: Box<dyn Simulation>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+    },
+    Cpu(Box<dyn CpuSimulation>),
+}
+
+impl Solver {
+    fn capacity(&self) -> u32 {
+        match self {
+            Solver::Gpu { simulation, .. } => simulation.capacity(),
+            Solver::Cpu(cpu) => cpu.capacity(),
+        }
+    }
+    fn num_particles(&self) -> u32 {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation.num_particles(device, queue),
+            Solver::Cpu(cpu) => cpu.capacity(),
+        }
+    }
+    fn step(&mut self, n_substeps: u32) {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation.step(device, queue, n_substeps),
+            Solver::Cpu(cpu) => cpu.step(n_substeps),
+        }
+    }
+    fn particle_data(&self) -> simulation::ParticleData {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation.gpu2cpu_particle_data(device, queue),
+            Solver::Cpu(cpu) => cpu.particle_data(),
+        }
+    }
+    fn state(&self) -> Result<SolverState> {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => Ok(simulation
+                .checkpointing()
+                .context("the solver can not read its state back")?
+                .gpu2cpu_state(device, queue)),
+            Solver::Cpu(cpu) => Ok(cpu.state()),
+        }
+    }
+    // The CPU solver computes them on request
+    fn totals(&self) -> Option<conservation::Totals> {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation
+                .conservation()
+                .and_then(|conservation| conservation.gpu2cpu_totals(device, queue)),
+            Solver::Cpu(cpu) => Some(cpu.totals()),
+        }
+    }
+    fn probe_names(&self) -> Vec<String> {
+        match self {
+            Solver::Gpu { simulation, .. } => simulation
+                .probes()
+                .map_or(vec![], |probes| probes.probe_names()),
+            Solver::Cpu(_) => vec![],
+        }
+    }
+    fn probes(&self) -> Vec<Vec<f32>> {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation
+                .probes()
+                .map_or(vec![], |probes| probes.gpu2cpu_probes(device, queue)),
+            Solver::Cpu(_) => vec![],
+        }
+    }
+    fn load_targets(&self) -> Vec<loads::LoadTarget> {
+        match self {
+            Solver::Gpu { simulation, .. } => simulation
+                .loads()
+                .map_or(vec![], |loads| loads.load_targets()),
+            Solver::Cpu(_) => vec![],
+        }
+    }
+    fn loads(&self, elapsed: f32) -> Vec<loads::Load> {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation
+                .loads()
+                .map_or(vec![], |loads| loads.gpu2cpu_loads(device, queue, elapsed)),
+            Solver::Cpu(_) => vec![],
+        }
+    }
+    fn rigid_body_motion(&self) -> Vec<simulation::RigidBodyMotion> {
+        match self {
+            Solver::Gpu {
+                simulation,
+                device,
+                queue,
+            } => simulation.rigid_bodies().map_or(vec![], |rigid_bodies| {
+                rigid_bodies.gpu2cpu_rigid_body_motion(device, queue)
+            }),
+            Solver::Cpu(_) => vec![],
+        }
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut options = Options {
+        scene: "sph-block".to_string(),
+        steps: None,
+        time: None,
+        substeps: 10,
+        output_interval: None,
+        output_steps: vec![],
+        output_dir: None,
+        output_format: OutputFormat::Csv,
+        checkpoint_interval: 0,
+        conservation_interval: None,
+        load_interval: None,
+        restart: None,
+        fallback: false,
+        cpu: false,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--fallback" {
+            options.fallback = true;
+            continue;
+        }
+        if arg == "--cpu" {
+            options.cpu = true;
+            continue;
+        }
+        if arg == "--help" || arg == "-h" {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        let value = args
+            .next()
+            .with_context(|| format!("missing value for {}\n{}", arg, USAGE))?;
+        let invalid = || format!("invalid value {:?} for {}", value, arg);
+        match arg.as_str() {
+            "--scene" => options.scene = value.clone(),
+            "--steps" => options.steps = Some(value.parse().with_context(invalid)?),
+            "--time" => options.time = Some(value.parse().with_context(invalid)?),
+            "--substeps" => options.substeps = value.parse().with_context(invalid)?,
+            "--output-interval" => {
+                options.output_interval = Some(value.parse().with_context(invalid)?)
+            }
+            "--output-dir" => options.output_dir = Some(PathBuf::from(&value)),
+            "--output-steps" => {
+                options.output_steps = value
+                    .split(',')
+                    .map(|step| step.trim().parse())
+                    .collect::<std::result::Result<_, _>>()
+                    .with_context(invalid)?
+            }
+            "--output-format" => {
+                options.output_format = match value.as_str() {
+                    "csv" => OutputFormat::Csv,
+                    "ply" => OutputFormat::Ply,
+                    "ply-ascii" => OutputFormat::PlyAscii,
+                    "vtu" => OutputFormat::Vtu,
+                    "vtk" => OutputFormat::Vtk,
+                    _ => bail!(invalid()),
+                }
+            }
+            "--checkpoint-interval" => {
+                options.checkpoint_interval = value.parse().with_context(invalid)?
+            }
+            "--conservation-interval" => {
+                options.conservation_interval = Some(value.parse().with_context(invalid)?)
+            }
+            "--load-interval" => options.load_interval = Some(value.parse().with_context(invalid)?),
+            "--restart" => options.restart = Some(PathBuf::from(&value)),
+            _ => bail!("unknown option {}\n{}", arg, USAGE),
+        }
+    }
+    if options.substeps == 0 {
+        bail!("--substeps must be at least 1");
+    }
+    Ok(options)
+}
+
+// Built-in scene or scene file, whose run settings fill in the options not given. A restarted
+// run takes the particles and parameters from the checkpoint state.
+fn load_scene(options: &mut Options, restart: Option<SolverState>) -> Result<Box<dyn Scene>> {
+    let builtin = match options.scene.as_str() {
+        "sph-block" => Some(SolverState::Sph(sph_block(12))),
+        "mpm-block" => Some(SolverState::MlsMpm(mpm_block(32))),
+        _ => None,
+    };
+    if let Some(builtin) = builtin {
+        return match restart {
+            Some(state) if state.solver_name() != builtin.solver_name() => bail!(
+                "checkpoint of an {} run does not match the scene solver",
+                state.solver_name()
+            ),
+            Some(state) => Ok(Box::new(state)),
+            None => Ok(Box::new(builtin)),
+        };
+    }
+    let file = scene::SceneFile::load(&options.scene)?;
+    if options.steps.is_none() && options.time.is_none() {
+        options.time = file.end_time.map(|time| time.0);
+    }
+    options.output_interval = options.output_interval.or(file.output.interval);
+    options.output_dir = options.output_dir.take().or(file.output.dir.clone());
+    options.conservation_interval = options
+        .conservation_interval
+        .or(file.output.conservation_interval);
+    options.load_interval = options.load_interval.or(file.output.load_interval);
+    let mut scene = file
+        .build()
+        .with_context(|| format!("invalid scene {}", options.scene))?;
+    if let Some(state) = restart {
+        scene.restore(state)?;
+    }
+    Ok(Box::new(scene))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Csv,
+    Ply,
+    PlyAscii,
+    Vtu,
+    Vtk,
+}
+
+// Particle snapshots `particles_<step>.<format>` with positions, velocities, density, pressure,
+// mass, material and id. VTK snapshots are also added to `particles.pvd`.
+struct Snapshots {
+    dir: PathBuf,
+    format: OutputFormat,
+    pvd: Option<vtk::PvdWriter>,
+}
+
+impl Snapshots {
+    // A restarted run keeps the collection entries up to the restart time
+    fn new(dir: &Path, format: OutputFormat, start_time: f64) -> Result<Self> {
+        let pvd = match format {
+            OutputFormat::Csv | OutputFormat::Ply | OutputFormat::PlyAscii => None,
+            OutputFormat::Vtu | OutputFormat::Vtk => Some(vtk::PvdWriter::resume(
+                dir.join("particles.pvd"),
+                start_time,
+            )?),
+        };
+        Ok(Snapshots {
+            dir: dir.to_path_buf(),
+            format,
+            pvd,
+        })
+    }
+
+    fn write(&mut self, state: &SolverState, step: u32, time: f64) -> Result<Diagnostics> {
+        let extension = match self.format {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Ply | OutputFormat::PlyAscii => "ply",
+            OutputFormat::Vtu => "vtu",
+            OutputFormat::Vtk => "vtk",
+        };
+        let path = self
+            .dir
+            .join(format!("particles_{:06}.{}", step, extension));
+        let data = vtk::PointData::from_state(state);
+        match self.format {
+            OutputFormat::Vtu | OutputFormat::Vtk => {
+                data.save(&path, time)?;
+                if let Some(pvd) = &mut self.pvd {
+                    pvd.add(time, &path)?;
+                }
+            }
+            format => {
+                let ply = match format {
+                    OutputFormat::Ply => Some(point_cloud::PlyFormat::BinaryLittleEndian),
+                    OutputFormat::PlyAscii => Some(point_cloud::PlyFormat::Ascii),
+                    _ => None,
+                };
+                let mut cloud = point_cloud::PointCloud::from_point_data(&data);
+                cloud.comments.push(format!("time {}", time));
+                cloud.save_as(&path, ply)?;
+            }
+        }
+        Ok(Diagnostics::new(&data.positions, &data.velocities))
+    }
+}
+
+// Summary of a snapshot to spot blow ups and escaping particles
+struct Diagnostics {
+    num_particles: usize,
+    num_non_finite: usize,
+    max_speed: f32,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Diagnostics {
+    fn new(positions: &[[f32; 3]], velocities: &[[f32; 3]]) -> Self {
+        let mut diagnostics = Diagnostics {
+            num_particles: positions.len(),
+            num_non_finite: 0,
+            max_speed: 0.0,
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        };
+        for (position, velocity) in positions.iter().zip(velocities) {
+            if position.iter().chain(velocity).any(|x| !x.is_finite()) {
+                diagnostics.num_non_finite += 1;
+                continue;
+            }
+            let speed = velocity.iter().map(|v| v * v).sum::<f32>().sqrt();
+            diagnostics.max_speed = diagnostics.max_speed.max(speed);
+            diagnostics.min = std::array::from_fn(|a| diagnostics.min[a].min(position[a]));
+            diagnostics.max = std::array::from_fn(|a| diagnostics.max[a].max(position[a]));
+        }
+        diagnostics
+    }
+}
+
+impl std::fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} particles, max speed {:.4}, bounds {:.3?} to {:.3?}",
+            self.num_particles, self.max_speed, self.min, self.max
+        )?;
+        if self.num_non_finite > 0 {
+            write!(f, ", {} non-finite", self.num_non_finite)?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+// Cube of SPH particles falling under gravity inside the unit box
+fn sph_block(side: u32) -> sph::Sph {
+    use sph::*;
+    let spacing = 0.6 / side as f32;
+    let num_particles = side * side * side;
+    let params = SimParams {
+        grid_prime: [59, 519, 1087],
+        dt: 0.001,
+        grid_size: 2.0 * spacing,
+        num_particles,
+        _padding: [0.0; 2],
+    };
+    let water = Material {
+        density_reference: 200.0,
+        density_ref_threshold: 0.7,
+        compressibility: 0.1,
+        boundary_damping: 0.8,
+        cs: 5.0,
+        alpha: 1.0,
+        beta: 2.0,
+        eps: 0.01,
+        color: [0.0, 0.0, 1.0, 1.0],
+    };
+    let mut particles = vec![];
+    let mut motion = vec![];
+    for i in 0..num_particles {
+        let index = [i % side, (i / side) % side, i / side / side];
+        let mut coord = [0; 3];
+        let mut position = [0.0; 3];
+        for a in 0..3 {
+            let x = (index[a] as f32 + 0.5) * spacing - 0.3;
+            let cell = (x / params.grid_size).floor();
+            coord[a] = cell as i32;
+            position[a] = x / params.grid_size - cell;
+        }
+        particles.push(Particle {
+            coord,
+            mass: 0.1,
+            position,
+            density: 0.0,
+            pressure: 0.0,
+            smoothing_length: 2.0 * spacing,
+            material_idx: 0,
+            id: i,
+        });
+        motion.push(ParticleMotion {
+            velocity: [0.0; 3],
+            drho_dt: 0.0,
+            acceleration: [0.0; 3],
+            _padding: 0.0,
+            velocity_p: [0.0; 3],
+            tag: 0,
+        });
+    }
+    Sph {
+        params,
+        disturbance: Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        },
+        particles,
+        motion,
+        materials: vec![water],
+    }
+}
+
+// Dam break of MLS-MPM fluid, two particles per cell and axis in a corner of the grid
+fn mpm_block(grid_resolution: u32) -> mls_mpm::MlsMpm {
+    use mls_mpm::*;
+    let spacing = 0.5 / grid_resolution as f32;
+    let side = grid_resolution / 2;
+    let mut particles = vec![];
+    for k in 0..side {
+        for j in 0..side {
+            for i in 0..side {
+                let index = [i, j, k];
+                particles.push(Particle {
+                    position: index.map(|n| 0.1 + (n as f32 + 0.5) * spacing),
+                    // Reference density with eight particles per cell
+                    mass: 0.125,
+                    velocity: [0.0; 3],
+                    material_idx: 0,
+                    C: [0.0; 12],
+                    id: particles.len() as u32,
+                    tag: 0,
+                    _padding: [0; 2],
+                });
+            }
+        }
+    }
+    let params = SimParams {
+        grid_resolution,
+        dt: 0.02,
+        scale_distance: 1.0,
+        num_particles: particles.len() as u32,
+        num_nodes: grid_resolution * grid_resolution * grid_resolution,
+        periodic_axes: 0,
+    };
+    let water = Material {
+        color: [0.0, 0.0, 1.0, 1.0],
+        eos_density: 1.0,
+        eos_threshold: 0.7,
+        eos_stiffness: 10.0,
+        eos_n: 4.0,
+        dynamic_viscosity: 0.1,
+        rigid_flag: 0,
+        _padding: [0; 2],
+    };
+    let disturbance = Disturbance {
+        field: [0.0, -0.3, 0.0],
+        _padding: 0,
+    };
+    MlsMpm::new(params, disturbance, particles, vec![water])
+}
@@ -0,0 +1,172 @@
+use hydrocode::*;
+use sph::*;
+
+// Runs the same settling block with every integrator at halving time steps and reports the
+// position error against the finest run of each integrator.
+// Usage: integrator_convergence [particles per side] [end time] [coarsest dt]
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    let side: u32 = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(12);
+    let end_time: f32 = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(0.1);
+    let coarsest_dt: f32 = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(0.002);
+
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))
+    .expect("no adapter");
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        required_features: wgpu::Features::empty(),
+        required_limits: wgpu::Limits::default(),
+        label: Some("Device"),
+        memory_hints: Default::default(),
+        trace: wgpu::Trace::Off,
+    }))
+    .expect("no device");
+    println!("adapter {:?}", adapter.get_info().name);
+
+    let sph = block(side);
+    let integrators = [
+        Integrator::LeapFrog,
+        Integrator::VelocityVerlet,
+        Integrator::SymplecticEuler,
+        Integrator::PredictorCorrector,
+        Integrator::MidpointRk2,
+    ];
+    let dts: Vec<f32> = (0..4).map(|level| coarsest_dt / 2f32.powi(level)).collect();
+    for integrator in integrators {
+        let runs: Vec<Vec<[f32; 3]>> = dts
+            .iter()
+            .map(|&dt| run(&device, &queue, &sph, integrator, dt, end_time))
+            .collect();
+        let reference = runs.last().unwrap();
+        println!("{:?}", integrator);
+        let mut last_error: Option<f32> = None;
+        for (dt, positions) in dts.iter().zip(&runs).take(dts.len() - 1) {
+            let error = rms_distance(positions, reference);
+            match last_error {
+                Some(last_error) => println!(
+                    "  dt {:.2e}: rms error {:.3e}, observed order {:.2}",
+                    dt,
+                    error,
+                    (last_error / error).log2()
+                ),
+                None => println!("  dt {:.2e}: rms error {:.3e}", dt, error),
+            }
+            last_error = Some(error);
+        }
+    }
+}
+
+// World positions in id order at `end_time`
+fn run(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sph: &Sph,
+    integrator: Integrator,
+    dt: f32,
+    end_time: f32,
+) -> Vec<[f32; 3]> {
+    let params = SimParams { dt, ..sph.params };
+    let mut compute = pollster::block_on(SphCompute::new(device, &params));
+    compute.cpu2gpu_params(queue, &params);
+    compute.cpu2gpu_disturbance(queue, &sph.disturbance);
+    compute.cpu2gpu_materials(queue, &sph.materials);
+    compute.set_integrator(device, integrator);
+    let ids: Vec<u32> = sph.particles.iter().map(|particle| particle.id).collect();
+    compute.attach_tracers(device, &ids);
+    compute.cpu2gpu_particles(queue, &sph.particles, &sph.motion);
+    let steps = (end_time / dt).round() as u32;
+    for _ in 0..steps {
+        for stage in 0..integrator.num_stages() {
+            compute.compute_spatial_lookup(device, queue);
+            compute.compute_density_interpolant(device, queue);
+            compute.compute_pressure_equation_of_state(device, queue);
+            compute.compute_equation_of_motion(device, queue);
+            compute.compute_integration(device, queue, stage);
+        }
+        compute.compute_boundaries(device, queue);
+    }
+    compute
+        .gpu2cpu_tracers(device, queue)
+        .iter()
+        .map(|sample| sample.position)
+        .collect()
+}
+
+fn rms_distance(a: &[[f32; 3]], b: &[[f32; 3]]) -> f32 {
+    let sum: f32 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>())
+        .sum();
+    (sum / a.len() as f32).sqrt()
+}
+
+// Cube of particles falling under gravity inside the unit box
+fn block(side: u32) -> Sph {
+    let spacing = 0.6 / side as f32;
+    let num_particles = side * side * side;
+    let params = SimParams {
+        grid_prime: [59, 519, 1087],
+        dt: 0.001,
+        grid_size: 2.0 * spacing,
+        num_particles,
+        _padding: [0.0; 2],
+    };
+    let water = Material {
+        density_reference: 200.0,
+        density_ref_threshold: 0.7,
+        compressibility: 0.1,
+        boundary_damping: 0.8,
+        cs: 5.0,
+        alpha: 1.0,
+        beta: 2.0,
+        eps: 0.01,
+        color: [0.0, 0.0, 1.0, 1.0],
+    };
+    let mut particles = vec![];
+    let mut motion = vec![];
+    for i in 0..num_particles {
+        let index = [i % side, (i / side) % side, i / side / side];
+        let mut coord = [0; 3];
+        let mut position = [0.0; 3];
+        for a in 0..3 {
+            let x = (index[a] as f32 + 0.5) * spacing - 0.3;
+            let cell = (x / params.grid_size).floor();
+            coord[a] = cell as i32;
+            position[a] = x / params.grid_size - cell;
+        }
+        particles.push(Particle {
+            coord,
+            mass: 0.1,
+            position,
+            density: 0.0,
+            pressure: 0.0,
+            smoothing_length: 2.0 * spacing,
+            material_idx: 0,
+            id: i,
+        });
+        motion.push(ParticleMotion {
+            velocity: [0.0; 3],
+            drho_dt: 0.0,
+            acceleration: [0.0; 3],
+            _padding: 0.0,
+            velocity_p: [0.0; 3],
+            tag: 0,
+        });
+    }
+    Sph {
+        params,
+        disturbance: Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        },
+        particles,
+        motion,
+        materials: vec![water],
+    }
+}
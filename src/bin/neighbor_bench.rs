@@ -0,0 +1,226 @@
+use hydrocode::*;
+use rand::seq::SliceRandom;
+use sph::*;
+use std::time::Instant;
+
+// Compares the per-pass hash grid search with cached neighbor lists, the dense cell list, Morton
+// reordering and single-submission stepping on a settling block of particles.
+// Usage: neighbor_bench [particles per side] [steps] [skin]
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    let side: u32 = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(32);
+    let steps: u32 = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(500);
+    let skin: Option<f32> = args.get(3).and_then(|a| a.parse().ok());
+
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))
+    .expect("no adapter");
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        required_features: wgpu::Features::empty(),
+        required_limits: wgpu::Limits::default(),
+        label: Some("Device"),
+        memory_hints: Default::default(),
+        trace: wgpu::Trace::Off,
+    }))
+    .expect("no device");
+    println!("adapter {:?}", adapter.get_info().name);
+
+    let sph = block(side);
+    println!(
+        "num particles {}, steps {}",
+        sph.params.num_particles, steps
+    );
+    // Largest skin that keeps the kernel support inside the list radius
+    let skin = skin.unwrap_or(sph.params.grid_size - sph.particles[0].smoothing_length);
+
+    let collisions = HashCollisions::from_particles(&sph.particles, &sph.params);
+    println!(
+        "hash keys: {} cells in {} keys, {} colliding, {:.1}% of walked particles in other cells",
+        collisions.occupied_cells,
+        collisions.occupied_keys,
+        collisions.colliding_keys,
+        100.0 * collisions.foreign_fraction
+    );
+
+    let hash = NeighborSearch::SpatialHash;
+    let cells = NeighborSearch::CellList {
+        min: [-0.5; 3],
+        max: [0.5; 3],
+    };
+    let per_pass = run(&device, &queue, &sph, steps, hash, None, false);
+    println!("hash grid search: {:.3} ms/step", per_pass);
+    let cached = run(&device, &queue, &sph, steps, hash, Some(skin), false);
+    println!("neighbor list (skin {}): {:.3} ms/step", skin, cached);
+    println!("speedup {:.2}x", per_pass / cached);
+    let dense = run(&device, &queue, &sph, steps, cells, None, false);
+    println!("cell list search: {:.3} ms/step", dense);
+    println!("speedup {:.2}x", per_pass / dense);
+    let reordered = run(&device, &queue, &sph, steps, hash, None, true);
+    println!(
+        "hash grid search with Morton reordering: {:.3} ms/step",
+        reordered
+    );
+    println!("speedup {:.2}x", per_pass / reordered);
+    let single = run_single_submission(&device, &queue, &sph, steps);
+    println!(
+        "hash grid search in one submission per {} steps: {:.3} ms/step",
+        SUBSTEPS, single
+    );
+    println!("speedup {:.2}x", per_pass / single);
+}
+
+const REORDER_INTERVAL: u32 = 100;
+const SUBSTEPS: u32 = 10;
+
+// Mean wall time per step in milliseconds
+fn run(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sph: &Sph,
+    steps: u32,
+    neighbor_search: NeighborSearch,
+    skin: Option<f32>,
+    reorder: bool,
+) -> f64 {
+    let num_particles = sph.params.num_particles;
+    let mut compute = pollster::block_on(SphCompute::with_neighbor_search(
+        device,
+        &sph.params,
+        num_particles,
+        neighbor_search,
+    ));
+    compute.cpu2gpu_params(queue, &sph.params);
+    compute.cpu2gpu_disturbance(queue, &sph.disturbance);
+    compute.cpu2gpu_materials(queue, &sph.materials);
+    if let Some(skin) = skin {
+        compute.attach_neighbor_list(device, 192, skin);
+    }
+    if reorder {
+        compute.attach_morton_reorder(device, 0);
+    }
+    compute.cpu2gpu_particles(queue, &sph.particles, &sph.motion);
+    let mut rebuilds = 0;
+    let mut max_count = 0;
+    let start = Instant::now();
+    for step in 0..steps {
+        if step % REORDER_INTERVAL == 0 {
+            compute.compute_morton_reorder(device, queue);
+        }
+        let status = compute.gpu2cpu_neighbor_list_status(device, queue);
+        if let Some(status) = status {
+            max_count = max_count.max(status.max_count);
+        }
+        match status {
+            None => compute.compute_spatial_lookup(device, queue),
+            // The rebuild runs its own spatial lookup
+            Some(status) if status.expired != 0 => {
+                compute.compute_neighbor_list(device, queue);
+                rebuilds += 1;
+            }
+            Some(_) => {}
+        }
+        compute.compute_density_interpolant(device, queue);
+        compute.compute_pressure_equation_of_state(device, queue);
+        compute.compute_equation_of_motion(device, queue);
+        compute.compute_integration(device, queue, 0);
+        compute.compute_boundaries(device, queue);
+        _ = device.poll(wgpu::PollType::Wait);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    if skin.is_some() {
+        println!("  {} rebuilds, longest list {}", rebuilds, max_count);
+    }
+    1e3 * elapsed / steps as f64
+}
+
+// Mean wall time per step in milliseconds when recording SUBSTEPS steps per submission
+fn run_single_submission(device: &wgpu::Device, queue: &wgpu::Queue, sph: &Sph, steps: u32) -> f64 {
+    let compute = pollster::block_on(SphCompute::new(device, &sph.params));
+    compute.cpu2gpu_params(queue, &sph.params);
+    compute.cpu2gpu_disturbance(queue, &sph.disturbance);
+    compute.cpu2gpu_materials(queue, &sph.materials);
+    compute.cpu2gpu_particles(queue, &sph.particles, &sph.motion);
+    let start = Instant::now();
+    for _ in 0..steps.div_ceil(SUBSTEPS) {
+        compute.step(device, queue, SUBSTEPS);
+        _ = device.poll(wgpu::PollType::Wait);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    1e3 * elapsed / (steps.div_ceil(SUBSTEPS) * SUBSTEPS) as f64
+}
+
+// Cube of particles falling under gravity inside the unit box
+fn block(side: u32) -> Sph {
+    let spacing = 0.9 / side as f32;
+    let num_particles = side * side * side;
+    let params = SimParams {
+        grid_prime: [59, 519, 1087],
+        dt: 0.001,
+        grid_size: 3.0 * spacing,
+        num_particles,
+        _padding: [0.0; 2],
+    };
+    let water = Material {
+        density_reference: 200.0,
+        density_ref_threshold: 0.7,
+        compressibility: 0.1,
+        boundary_damping: 0.8,
+        cs: 5.0,
+        alpha: 1.0,
+        beta: 2.0,
+        eps: 0.01,
+        color: [0.0, 0.0, 1.0, 1.0],
+    };
+    let mut particles = vec![];
+    let mut motion = vec![];
+    for i in 0..num_particles {
+        let index = [i % side, (i / side) % side, i / side / side];
+        let mut coord = [0; 3];
+        let mut position = [0.0; 3];
+        for a in 0..3 {
+            let x = (index[a] as f32 + 0.5) * spacing - 0.45;
+            let cell = (x / params.grid_size).floor();
+            coord[a] = cell as i32;
+            position[a] = x / params.grid_size - cell;
+        }
+        particles.push(Particle {
+            coord,
+            mass: 0.1,
+            position,
+            density: 0.0,
+            pressure: 0.0,
+            // Leaves room for a skin of one grid spacing
+            smoothing_length: 2.0 * spacing,
+            material_idx: 0,
+            id: i,
+        });
+        motion.push(ParticleMotion {
+            velocity: [0.0; 3],
+            drho_dt: 0.0,
+            acceleration: [0.0; 3],
+            _padding: 0.0,
+            velocity_p: [0.0; 3],
+            tag: 0,
+        });
+    }
+    // Shuffled as after a long run of mixing
+    let mut order: Vec<usize> = (0..particles.len()).collect();
+    order.shuffle(&mut rand::rng());
+    let particles = order.iter().map(|&i| particles[i]).collect();
+    let motion = order.iter().map(|&i| motion[i]).collect();
+    Sph {
+        params,
+        disturbance: Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        },
+        particles,
+        motion,
+        materials: vec![water],
+    }
+}
@@ -81,7 +81,7 @@ fn main() {
             pressure,
             material_idx,
             smoothing_length,
-            _padding: 0.0,
+            id: i,
         });
         motion.push(ParticleMotion {
             velocity,
@@ -89,7 +89,7 @@ fn main() {
             acceleration,
             _padding: 0.0,
             velocity_p: velocity,
-            _padding2: 0.0,
+            tag: 0,
         });
         x += spacing;
         if x >= init_box_size / 2.0 {
@@ -0,0 +1,337 @@
+use crate::mls_mpm::{self, MlsMpm};
+use crate::simulation::{Checkpointing, CpuSimulation, Scene, Simulation};
+use crate::sph::{self, Sph};
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Binary snapshot of a run to continue it after the process ends, e.g. when a job is pre-empted.
+// The file starts with `MAGIC` and `VERSION` followed by little-endian fields:
+//
+//     solver      u32, 0 for SPH and 1 for MLS-MPM
+//     step        u64
+//     time        f64
+//     sections    u64 byte length then the raw structs, in the order params, disturbance,
+//                 materials, particles and, for SPH, motion
+//
+// The structs are stored with their GPU layout, so the version must change with any of them.
+// Boundaries, obstacles and inlets are not saved, they come from the scene the run is restarted
+// with.
+pub const MAGIC: [u8; 8] = *b"HYDROCKP";
+pub const VERSION: u32 = 1;
+
+const SOLVER_SPH: u32 = 0;
+const SOLVER_MLS_MPM: u32 = 1;
+
+// Particles, materials, parameters and disturbance of either solver, read back from the GPU
+pub enum SolverState {
+    Sph(Sph),
+    MlsMpm(MlsMpm),
+}
+
+pub struct Checkpoint {
+    // Completed time steps and the simulation time they reached
+    pub step: u64,
+    pub time: f64,
+    pub state: SolverState,
+}
+
+impl SolverState {
+    pub fn solver_name(&self) -> &'static str {
+        match self {
+            SolverState::Sph(_) => "SPH",
+            SolverState::MlsMpm(_) => "MLS-MPM",
+        }
+    }
+}
+
+impl Scene for SolverState {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        match self {
+            SolverState::Sph(sph) => sph.init(device, queue),
+            SolverState::MlsMpm(mls_mpm) => mls_mpm.init(device, queue),
+        }
+    }
+    fn dt(&self) -> f32 {
+        match self {
+            SolverState::Sph(sph) => sph.dt(),
+            SolverState::MlsMpm(mls_mpm) => mls_mpm.dt(),
+        }
+    }
+    fn init_cpu(&self) -> anyhow::Result<Box<dyn CpuSimulation>> {
+        match self {
+            SolverState::Sph(sph) => sph.init_cpu(),
+            SolverState::MlsMpm(mls_mpm) => mls_mpm.init_cpu(),
+        }
+    }
+}
+
+impl Checkpoint {
+    // Reads the complete state back from the GPU
+    pub fn capture(
+        simulation: &dyn Checkpointing,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        step: u64,
+        time: f64,
+    ) -> Self {
+        Checkpoint {
+            step,
+            time,
+            state: simulation.gpu2cpu_state(device, queue),
+        }
+    }
+
+    // Written to a temporary file first so that an interrupted save keeps the previous checkpoint
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let file = File::create(&partial)
+            .with_context(|| format!("failed to create {}", partial.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)
+            .and_then(|_| Ok(writer.flush()?))
+            .with_context(|| format!("failed to write {}", partial.display()))?;
+        drop(writer);
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("failed to move {} to {}", partial.display(), path.display()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::read(&mut BufReader::new(file))
+            .with_context(|| format!("invalid checkpoint {}", path.display()))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        let solver = match self.state {
+            SolverState::Sph(_) => SOLVER_SPH,
+            SolverState::MlsMpm(_) => SOLVER_MLS_MPM,
+        };
+        writer.write_all(&solver.to_le_bytes())?;
+        writer.write_all(&self.step.to_le_bytes())?;
+        writer.write_all(&self.time.to_le_bytes())?;
+        match &self.state {
+            SolverState::Sph(sph) => {
+                write_section(writer, std::slice::from_ref(&sph.params))?;
+                write_section(writer, std::slice::from_ref(&sph.disturbance))?;
+                write_section(writer, &sph.materials)?;
+                write_section(writer, &sph.particles)?;
+                write_section(writer, &sph.motion)?;
+            }
+            SolverState::MlsMpm(mls_mpm) => {
+                write_section(writer, std::slice::from_ref(&mls_mpm.params))?;
+                write_section(writer, std::slice::from_ref(&mls_mpm.disturbance))?;
+                write_section(writer, &mls_mpm.materials)?;
+                write_section(writer, &mls_mpm.particles)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader
+            .read_exact(&mut magic)
+            .context("file is too short for a checkpoint")?;
+        if magic != MAGIC {
+            bail!("not a checkpoint file");
+        }
+        let version = u32::from_le_bytes(read_array(reader)?);
+        if version != VERSION {
+            bail!(
+                "checkpoint version {} is not supported, expected version {}",
+                version,
+                VERSION
+            );
+        }
+        let solver = u32::from_le_bytes(read_array(reader)?);
+        let step = u64::from_le_bytes(read_array(reader)?);
+        let time = f64::from_le_bytes(read_array(reader)?);
+        let state = match solver {
+            SOLVER_SPH => {
+                let params: sph::SimParams = read_single(reader, "parameters")?;
+                let disturbance = read_single(reader, "disturbance")?;
+                let materials = read_section(reader, "materials")?;
+                let particles: Vec<sph::Particle> = read_section(reader, "particles")?;
+                let motion: Vec<sph::ParticleMotion> = read_section(reader, "motion")?;
+                check_particle_count(params.num_particles, particles.len())?;
+                if motion.len() != particles.len() {
+                    bail!(
+                        "{} particles but {} motion entries",
+                        particles.len(),
+                        motion.len()
+                    );
+                }
+                SolverState::Sph(Sph {
+                    params,
+                    disturbance,
+                    particles,
+                    motion,
+                    materials,
+                })
+            }
+            SOLVER_MLS_MPM => {
+                let params: mls_mpm::SimParams = read_single(reader, "parameters")?;
+                let disturbance = read_single(reader, "disturbance")?;
+                let materials = read_section(reader, "materials")?;
+                let particles: Vec<mls_mpm::Particle> = read_section(reader, "particles")?;
+                check_particle_count(params.num_particles, particles.len())?;
+                SolverState::MlsMpm(MlsMpm::new(params, disturbance, particles, materials))
+            }
+            _ => bail!("unknown solver {} in checkpoint", solver),
+        };
+        let mut trailing = [0u8; 1];
+        if reader.read(&mut trailing)? != 0 {
+            bail!("unexpected data after the last section");
+        }
+        Ok(Checkpoint { step, time, state })
+    }
+}
+
+fn write_section<W: Write, T: bytemuck::Pod>(writer: &mut W, data: &[T]) -> Result<()> {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader
+        .read_exact(&mut bytes)
+        .context("checkpoint ends early")?;
+    Ok(bytes)
+}
+
+fn read_section<R: Read, T: bytemuck::Pod>(reader: &mut R, name: &str) -> Result<Vec<T>> {
+    let len = u64::from_le_bytes(read_array(reader)?) as usize;
+    let size = std::mem::size_of::<T>();
+    if !len.is_multiple_of(size) {
+        bail!(
+            "{} section of {} bytes is not a multiple of {}",
+            name,
+            len,
+            size
+        );
+    }
+    let mut bytes = vec![];
+    reader
+        .take(len as u64)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read the {} section", name))?;
+    if bytes.len() != len {
+        bail!("checkpoint ends inside the {} section", name);
+    }
+    Ok(bytemuck::pod_collect_to_vec(&bytes))
+}
+
+fn read_single<R: Read, T: bytemuck::Pod>(reader: &mut R, name: &str) -> Result<T> {
+    let data: Vec<T> = read_section(reader, name)?;
+    if data.len() != 1 {
+        bail!(
+            "{} section holds {} entries instead of one",
+            name,
+            data.len()
+        );
+    }
+    Ok(data[0])
+}
+
+fn check_particle_count(num_particles: u32, len: usize) -> Result<()> {
+    if num_particles as usize != len {
+        bail!(
+            "parameters count {} particles but {} are stored",
+            num_particles,
+            len
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sph_state() -> Sph {
+        let particles: Vec<sph::Particle> = (0..3)
+            .map(|i| sph::Particle {
+                coord: [i, -i, 2],
+                mass: 0.5,
+                position: [0.25, 0.5, 0.75],
+                density: 1000.0 + i as f32,
+                pressure: 10.0,
+                smoothing_length: 0.1,
+                material_idx: 0,
+                id: 10 + i as u32,
+            })
+            .collect();
+        let motion = particles
+            .iter()
+            .map(|particle| sph::ParticleMotion {
+                velocity: [particle.density, 1.0, -1.0],
+                drho_dt: 2.0,
+                acceleration: [0.0, -9.81, 0.0],
+                _padding: 0.0,
+                velocity_p: [0.5; 3],
+                tag: particle.id % 2,
+            })
+            .collect();
+        Sph {
+            params: sph::SimParams {
+                grid_prime: [59, 519, 1087],
+                dt: 0.001,
+                grid_size: 0.2,
+                num_particles: 3,
+                _padding: [0.0; 2],
+            },
+            disturbance: sph::Disturbance {
+                field: [0.0, -9.81, 0.0],
+                _padding: 0.0,
+            },
+            particles,
+            motion,
+            materials: vec![bytemuck::Zeroable::zeroed(); 4],
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let checkpoint = Checkpoint {
+            step: 1234,
+            time: 1.234,
+            state: SolverState::Sph(sph_state()),
+        };
+        let mut bytes = vec![];
+        checkpoint.write(&mut bytes).unwrap();
+        let restored = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(restored.step, 1234);
+        assert_eq!(restored.time, 1.234);
+        let (SolverState::Sph(a), SolverState::Sph(b)) = (&checkpoint.state, &restored.state)
+        else {
+            panic!("solver changed on restore");
+        };
+        assert_eq!(bytemuck::bytes_of(&a.params), bytemuck::bytes_of(&b.params));
+        let as_bytes = |sph: &Sph| {
+            [
+                bytemuck::cast_slice::<_, u8>(&sph.particles).to_vec(),
+                bytemuck::cast_slice(&sph.motion).to_vec(),
+                bytemuck::cast_slice(&sph.materials).to_vec(),
+            ]
+        };
+        assert_eq!(as_bytes(a), as_bytes(b));
+
+        // Other versions, truncated files and foreign files are rejected
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = Checkpoint::read(&mut newer.as_slice()).err().unwrap();
+        assert!(error.to_string().contains("version"), "{}", error);
+        assert!(Checkpoint::read(&mut &bytes[..bytes.len() - 1]).is_err());
+        assert!(Checkpoint::read(&mut &b"solver = \"sph\""[..]).is_err());
+    }
+}
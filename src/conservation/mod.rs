@@ -0,0 +1,227 @@
+use crate::shader_module::ShaderModuleBuilder;
+
+// Invocations per workgroup of the solver reduction passes, one partial per workgroup
+pub const REDUCE_SIZE: u32 = 128;
+
+// Global sums and extrema over the alive particles in world units, reduced on the GPU to check
+// what a run conserves. Potential energy is zero at the origin, momentum is taken about it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Totals {
+    pub momentum: [f32; 3],
+    pub mass: f32,
+    pub angular_momentum: [f32; 3],
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub internal_energy: f32,
+    pub min_density: f32,
+    pub max_density: f32,
+    pub min_pressure: f32,
+    pub max_pressure: f32,
+    pub max_speed: f32,
+    pub count: u32,
+    // 64 bytes
+}
+
+// Final pass combining the per-workgroup partials of a solver pass into one `Totals`
+pub struct TotalsReduction {
+    pub buffer_partials: wgpu::Buffer,
+    buffer_totals: wgpu::Buffer,
+    staging_buffer_totals: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+impl Totals {
+    // Columns of `values`
+    pub fn names() -> Vec<String> {
+        [
+            "mass",
+            "momentum_x",
+            "momentum_y",
+            "momentum_z",
+            "angular_momentum_x",
+            "angular_momentum_y",
+            "angular_momentum_z",
+            "kinetic_energy",
+            "potential_energy",
+            "internal_energy",
+            "total_energy",
+            "min_density",
+            "max_density",
+            "min_pressure",
+            "max_pressure",
+            "max_speed",
+            "particles",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    pub fn values(&self) -> Vec<f32> {
+        let mut values = vec![self.mass];
+        values.extend(self.momentum);
+        values.extend(self.angular_momentum);
+        values.extend([
+            self.kinetic_energy,
+            self.potential_energy,
+            self.internal_energy,
+            self.total_energy(),
+            self.min_density,
+            self.max_density,
+            self.min_pressure,
+            self.max_pressure,
+            self.max_speed,
+            self.count as f32,
+        ]);
+        values
+    }
+
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy + self.potential_energy + self.internal_energy
+    }
+}
+
+impl TotalsReduction {
+    // `num_partials` is the workgroup count of the solver pass
+    pub fn new(device: &wgpu::Device, num_partials: u32) -> Self {
+        let module_reduce = ShaderModuleBuilder::new()
+            .add_module(include_str!("./totals.wgsl"))
+            .add_module(include_str!("./reduce.wgsl"))
+            .build(device, Some("Shader Module Reduce Totals"));
+        let totals_size = std::mem::size_of::<Totals>() as u64;
+        let buffer_partials = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Partial Totals"),
+            size: num_partials.max(1) as u64 * totals_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let buffer_totals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Totals"),
+            size: totals_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_totals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Totals"),
+            size: totals_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Reduce Totals"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Reduce Totals"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_partials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_totals.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Reduce Totals"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Reduce Totals"),
+            layout: Some(&pipeline_layout),
+            module: &module_reduce,
+            entry_point: Some("reduce_totals"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        TotalsReduction {
+            buffer_partials,
+            buffer_totals,
+            staging_buffer_totals,
+            bind_group,
+            compute_pipeline,
+        }
+    }
+
+    // Record after the solver pass has written the partials
+    pub fn encode(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    pub fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Totals {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Totals"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_totals,
+            0,
+            &self.staging_buffer_totals,
+            0,
+            self.buffer_totals.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_totals.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let totals: Totals = bytemuck::pod_read_unaligned(&output_data);
+        drop(output_data);
+        self.staging_buffer_totals.unmap();
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_columns() {
+        assert_eq!(std::mem::size_of::<Totals>(), 64);
+        let totals = Totals {
+            momentum: [1.0, 2.0, 3.0],
+            mass: 4.0,
+            kinetic_energy: 1.0,
+            potential_energy: -3.0,
+            internal_energy: 0.5,
+            count: 7,
+            ..Default::default()
+        };
+        let values = totals.values();
+        assert_eq!(values.len(), Totals::names().len());
+        assert_eq!(values[..4], [4.0, 1.0, 2.0, 3.0]);
+        let energy = Totals::names().iter().position(|n| n == "total_energy");
+        assert_eq!(values[energy.unwrap()], -1.5);
+        assert_eq!(values.last(), Some(&7.0));
+    }
+}
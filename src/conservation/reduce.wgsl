@@ -0,0 +1,15 @@
+@group(0) @binding(0) var<storage, read> partials: array<Totals>;
+@group(0) @binding(1) var<storage, read_write> totals: Totals;
+
+// Single workgroup combining the partials of the solver pass
+@compute @workgroup_size(128)
+fn reduce_totals(@builtin(local_invocation_index) local_index: u32) {
+    var sum = empty_totals();
+    for (var i = local_index; i < arrayLength(&partials); i += REDUCE_SIZE) {
+        sum = combine_totals(sum, partials[i]);
+    }
+    let result = reduce_workgroup(local_index, sum);
+    if (local_index == 0u) {
+        totals = result;
+    }
+}
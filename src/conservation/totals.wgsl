@@ -0,0 +1,94 @@
+// WGSL file for the conservation totals reduced over the particles
+// The solver shaders reduce one partial per workgroup with reduce_workgroup, reduce.wgsl then
+// combines the partials
+
+struct Totals {
+    momentum: vec3f,
+    mass: f32,
+    // About the origin
+    angular_momentum: vec3f,
+    kinetic_energy: f32,
+    potential_energy: f32,
+    internal_energy: f32,
+    min_density: f32,
+    max_density: f32,
+    min_pressure: f32,
+    max_pressure: f32,
+    max_speed: f32,
+    count: u32,
+    // 64 bytes
+}
+
+const REDUCE_SIZE: u32 = 128u;
+const F32_MAX: f32 = 3.4e38;
+
+var<workgroup> workgroup_totals: array<Totals, REDUCE_SIZE>;
+
+// Totals of no particle, neutral for combine_totals
+fn empty_totals() -> Totals {
+    var totals = Totals();
+    totals.min_density = F32_MAX;
+    totals.max_density = -F32_MAX;
+    totals.min_pressure = F32_MAX;
+    totals.max_pressure = -F32_MAX;
+    return totals;
+}
+
+fn combine_totals(a: Totals, b: Totals) -> Totals {
+    var totals: Totals;
+    totals.momentum = a.momentum + b.momentum;
+    totals.mass = a.mass + b.mass;
+    totals.angular_momentum = a.angular_momentum + b.angular_momentum;
+    totals.kinetic_energy = a.kinetic_energy + b.kinetic_energy;
+    totals.potential_energy = a.potential_energy + b.potential_energy;
+    totals.internal_energy = a.internal_energy + b.internal_energy;
+    totals.min_density = min(a.min_density, b.min_density);
+    totals.max_density = max(a.max_density, b.max_density);
+    totals.min_pressure = min(a.min_pressure, b.min_pressure);
+    totals.max_pressure = max(a.max_pressure, b.max_pressure);
+    totals.max_speed = max(a.max_speed, b.max_speed);
+    totals.count = a.count + b.count;
+    return totals;
+}
+
+// Totals of one particle in world units, the gravity potential is zero at the origin
+fn particle_totals(
+    mass: f32,
+    position: vec3f,
+    velocity: vec3f,
+    gravity: vec3f,
+    density: f32,
+    pressure: f32,
+    internal_energy: f32,
+) -> Totals {
+    var totals: Totals;
+    totals.momentum = mass * velocity;
+    totals.mass = mass;
+    totals.angular_momentum = cross(position, mass * velocity);
+    totals.kinetic_energy = 0.5 * mass * dot(velocity, velocity);
+    totals.potential_energy = -mass * dot(gravity, position);
+    totals.internal_energy = internal_energy;
+    totals.min_density = density;
+    totals.max_density = density;
+    totals.min_pressure = pressure;
+    totals.max_pressure = pressure;
+    totals.max_speed = length(velocity);
+    totals.count = 1u;
+    return totals;
+}
+
+// Tree reduction over the workgroup, every invocation has to call it
+fn reduce_workgroup(local_index: u32, totals: Totals) -> Totals {
+    workgroup_totals[local_index] = totals;
+    workgroupBarrier();
+    for (var stride = REDUCE_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if (local_index < stride) {
+            workgroup_totals[local_index] = combine_totals(
+                workgroup_totals[local_index],
+                workgroup_totals[local_index + stride],
+            );
+        }
+        workgroupBarrier();
+    }
+    return workgroup_totals[0];
+}
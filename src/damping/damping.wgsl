@@ -0,0 +1,66 @@
+// WGSL file for velocity relaxation zones
+// Requires `zones: array<DampingZone>` and `profiles: array<f32>` storage bindings
+
+struct DampingZone {
+    min: vec3f,
+    strength: f32,
+    max: vec3f,
+    profile: u32,
+    direction: vec3f,
+    exponent: f32,
+    target_velocity: vec3f,
+    table_offset: u32,
+    table_len: u32,
+    start: f32,
+    length: f32,
+    _padding: f32,
+    // 80 bytes
+}
+
+const PROFILE_POWER: u32 = 0u;
+const PROFILE_EXPONENTIAL: u32 = 1u;
+const PROFILE_TABLE: u32 = 2u;
+// Exponential ramps flatter than this are linear
+const EXPONENTIAL_LINEAR_LIMIT: f32 = 1.0e-4;
+
+// Profile of the zone at a position, zero outside of it
+fn damping_profile(zone: DampingZone, position: vec3f) -> f32 {
+    if (any(position < zone.min) || any(position > zone.max)) {
+        return 0.0;
+    }
+    let s = clamp((dot(position, zone.direction) - zone.start) / max(zone.length, 1e-12), 0.0, 1.0);
+    switch zone.profile {
+        case PROFILE_EXPONENTIAL: {
+            if (abs(zone.exponent) < EXPONENTIAL_LINEAR_LIMIT) {
+                return s;
+            }
+            return (exp(zone.exponent * s) - 1.0) / (exp(zone.exponent) - 1.0);
+        }
+        case PROFILE_TABLE: {
+            if (zone.table_len < 2u) {
+                return select(0.0, profiles[zone.table_offset], zone.table_len == 1u);
+            }
+            let x = s * f32(zone.table_len - 1u);
+            let i = min(u32(floor(x)), zone.table_len - 2u);
+            let a = profiles[zone.table_offset + i];
+            let b = profiles[zone.table_offset + i + 1u];
+            return mix(a, b, x - f32(i));
+        }
+        default: {
+            return pow(s, zone.exponent);
+        }
+    }
+}
+
+// Exponential relaxation of the velocity towards the zone targets over a time step
+fn relax_velocity(position: vec3f, velocity: vec3f, dt: f32) -> vec3f {
+    var relaxed = velocity;
+    for (var zone_idx = 0u; zone_idx < arrayLength(&zones); zone_idx++) {
+        let zone = zones[zone_idx];
+        let rate = zone.strength * damping_profile(zone, position);
+        if (rate > 0.0) {
+            relaxed = zone.target_velocity + (relaxed - zone.target_velocity) * exp(-rate * dt);
+        }
+    }
+    return relaxed;
+}
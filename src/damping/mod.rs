@@ -0,0 +1,204 @@
+use wgpu::util::DeviceExt;
+
+pub const PROFILE_POWER: u32 = 0;
+pub const PROFILE_EXPONENTIAL: u32 = 1;
+pub const PROFILE_TABLE: u32 = 2;
+// Exponential ramps flatter than this are linear, matches damping.wgsl
+const EXPONENTIAL_LINEAR_LIMIT: f32 = 1.0e-4;
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct DampingZoneParams {
+    pub min: [f32; 3],
+    pub strength: f32,
+    pub max: [f32; 3],
+    pub profile: u32,
+    pub direction: [f32; 3],
+    pub exponent: f32,
+    pub target_velocity: [f32; 3],
+    pub table_offset: u32,
+    pub table_len: u32,
+    pub start: f32,
+    pub length: f32,
+    pub _padding: f32,
+    // 80 bytes
+}
+
+// Shape of the relaxation rate across the zone, from 0 at the entry to 1 at the far end
+#[derive(Clone, Debug)]
+pub enum DampingProfile {
+    // s^n
+    Power(f32),
+    // (e^(a s) - 1) / (e^a - 1), the classic sponge layer ramp, linear for a = 0
+    Exponential(f32),
+    // Samples uniformly spaced over the zone, linearly interpolated
+    Table(Vec<f32>),
+}
+
+// Axis aligned region where velocity is relaxed towards a target at a rate of
+// `strength * profile(s)` per second, with `s` increasing along `direction`
+#[derive(Clone, Debug)]
+pub struct DampingZone {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub direction: [f32; 3],
+    pub strength: f32,
+    pub target_velocity: [f32; 3],
+    pub profile: DampingProfile,
+}
+
+pub struct DampingBuffers {
+    pub num_zones: u32,
+    pub buffer_zones: wgpu::Buffer,
+    pub buffer_profiles: wgpu::Buffer,
+}
+
+impl DampingZone {
+    pub fn new(min: [f32; 3], max: [f32; 3], direction: [f32; 3], strength: f32) -> Self {
+        DampingZone {
+            min,
+            max,
+            direction,
+            strength,
+            target_velocity: [0.0; 3],
+            profile: DampingProfile::Power(2.0),
+        }
+    }
+
+    pub fn with_profile(mut self, profile: DampingProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_target_velocity(mut self, target_velocity: [f32; 3]) -> Self {
+        self.target_velocity = target_velocity;
+        self
+    }
+
+    // Normalized ramp direction with the extent of the box along it
+    fn ramp(&self) -> ([f32; 3], f32, f32) {
+        let [dx, dy, dz] = self.direction;
+        let norm = (dx * dx + dy * dy + dz * dz).sqrt().max(f32::EPSILON);
+        let direction = [dx / norm, dy / norm, dz / norm];
+        let mut start = f32::MAX;
+        let mut end = f32::MIN;
+        for corner in 0..8 {
+            let mut projection = 0.0;
+            for (a, d) in direction.iter().enumerate() {
+                let bound = if corner & (1 << a) == 0 {
+                    self.min[a]
+                } else {
+                    self.max[a]
+                };
+                projection += bound * d;
+            }
+            start = start.min(projection);
+            end = end.max(projection);
+        }
+        (direction, start, end - start)
+    }
+
+    // Profile value at a point, mirrors damping_profile in damping.wgsl
+    pub fn profile_value(&self, position: [f32; 3]) -> f32 {
+        let inside = (0..3).all(|a| position[a] >= self.min[a] && position[a] <= self.max[a]);
+        if !inside {
+            return 0.0;
+        }
+        let (direction, start, length) = self.ramp();
+        let projection: f32 = (0..3).map(|a| position[a] * direction[a]).sum();
+        let s = ((projection - start) / length.max(f32::EPSILON)).clamp(0.0, 1.0);
+        match &self.profile {
+            DampingProfile::Power(n) => s.powf(*n),
+            // The ramp tends to s as a goes to zero
+            DampingProfile::Exponential(a) if a.abs() < EXPONENTIAL_LINEAR_LIMIT => s,
+            DampingProfile::Exponential(a) => (a * s).exp_m1() / a.exp_m1(),
+            DampingProfile::Table(samples) => match samples.len() {
+                0 => 0.0,
+                1 => samples[0],
+                len => {
+                    let x = s * (len - 1) as f32;
+                    let i = (x.floor() as usize).min(len - 2);
+                    samples[i] + (x - i as f32) * (samples[i + 1] - samples[i])
+                }
+            },
+        }
+    }
+}
+
+impl DampingBuffers {
+    pub fn new(device: &wgpu::Device, zones: &[DampingZone]) -> Self {
+        let mut params: Vec<DampingZoneParams> = vec![];
+        let mut tables: Vec<f32> = vec![];
+        for zone in zones {
+            let (direction, start, length) = zone.ramp();
+            let (profile, exponent) = match &zone.profile {
+                DampingProfile::Power(n) => (PROFILE_POWER, *n),
+                DampingProfile::Exponential(a) => (PROFILE_EXPONENTIAL, *a),
+                DampingProfile::Table(_) => (PROFILE_TABLE, 0.0),
+            };
+            let table_offset = tables.len() as u32;
+            if let DampingProfile::Table(samples) = &zone.profile {
+                tables.extend_from_slice(samples);
+            }
+            params.push(DampingZoneParams {
+                min: zone.min,
+                strength: zone.strength,
+                max: zone.max,
+                profile,
+                direction,
+                exponent,
+                target_velocity: zone.target_velocity,
+                table_offset,
+                table_len: tables.len() as u32 - table_offset,
+                start,
+                length,
+                _padding: 0.0,
+            });
+        }
+        // Bindings cannot be zero sized
+        if params.is_empty() {
+            params.push(bytemuck::Zeroable::zeroed());
+        }
+        if tables.is_empty() {
+            tables.push(0.0);
+        }
+        let buffer_zones = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Damping Zones"),
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_profiles = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Damping Profiles"),
+            contents: bytemuck::cast_slice(&tables),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        DampingBuffers {
+            num_zones: zones.len() as u32,
+            buffer_zones,
+            buffer_profiles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damping_profile() {
+        let zone = DampingZone::new([1.0, 0.0, 0.0], [2.0, 1.0, 1.0], [1.0, 0.0, 0.0], 10.0);
+        assert_eq!(zone.profile_value([0.5, 0.5, 0.5]), 0.0);
+        assert!((zone.profile_value([1.5, 0.5, 0.5]) - 0.25).abs() < 1e-6);
+        assert!((zone.profile_value([2.0, 0.5, 0.5]) - 1.0).abs() < 1e-6);
+        // Ramp against the axis
+        let zone = DampingZone::new([1.0, 0.0, 0.0], [2.0, 1.0, 1.0], [-1.0, 0.0, 0.0], 10.0)
+            .with_profile(DampingProfile::Table(vec![0.0, 1.0, 1.0]));
+        assert!((zone.profile_value([1.75, 0.5, 0.5]) - 0.5).abs() < 1e-6);
+        assert!((zone.profile_value([1.25, 0.5, 0.5]) - 1.0).abs() < 1e-6);
+        // A flat exponential ramp is linear rather than 0 / 0
+        let zone = zone.with_profile(DampingProfile::Exponential(0.0));
+        assert!((zone.profile_value([1.25, 0.5, 0.5]) - 0.75).abs() < 1e-6);
+        let zone = zone.with_profile(DampingProfile::Exponential(1e-3));
+        assert!((zone.profile_value([1.25, 0.5, 0.5]) - 0.75).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,20 @@
+// WGSL file for particles appended by inlets
+
+struct EmittedParticle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    density: f32,
+    smoothing_length: f32,
+    material_idx: u32,
+    tag: u32,
+    _padding: f32,
+    // 48 bytes
+}
+
+struct Emission {
+    num_emitted: u32,
+    // Particle id of the first emitted particle
+    first_id: u32,
+    _padding: vec2u,
+}
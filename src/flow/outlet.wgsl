@@ -0,0 +1,20 @@
+// WGSL file for outlets deleting particles
+// Requires an `outlets: array<Outlet>` storage binding
+
+struct Outlet {
+    min: vec3f,
+    _padding: f32,
+    max: vec3f,
+    _padding2: f32,
+    // 32 bytes
+}
+
+fn in_outlet(position: vec3f) -> bool {
+    for (var outlet_idx = 0u; outlet_idx < arrayLength(&outlets); outlet_idx++) {
+        let outlet = outlets[outlet_idx];
+        if (all(position >= outlet.min) && all(position <= outlet.max)) {
+            return true;
+        }
+    }
+    return false;
+}
@@ -0,0 +1,38 @@
+use anyhow::*;
+
+// Adapter, device and queue without a surface, for batch runs on machines without a display.
+// Falls back to the software adapter when no hardware adapter is available.
+pub async fn request_device(
+    force_fallback_adapter: bool,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let mut adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter,
+        })
+        .await;
+    if adapter.is_err() && !force_fallback_adapter {
+        adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await;
+    }
+    let adapter = adapter.context("no wgpu adapter, including the fallback adapter")?;
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::empty(),
+            // Software adapters may not reach the default limits, take what the adapter offers
+            required_limits: adapter.limits(),
+            label: Some("Device"),
+            memory_hints: Default::default(),
+            trace: wgpu::Trace::Off,
+        })
+        .await
+        .context("failed to request a device")?;
+    Ok((adapter, device, queue))
+}
@@ -1,7 +1,27 @@
 pub mod camera;
+pub mod checkpoint;
+pub mod conservation;
+pub mod damping;
+pub mod flow;
 pub mod geometry;
+pub mod headless;
+pub mod loads;
 pub mod mls_mpm;
+pub mod obstacle;
+pub mod point_cloud;
+pub mod prefix_sum;
+pub mod probe;
 pub mod renderer;
+pub mod resample;
+pub mod rigid_body;
+pub mod scene;
+pub mod seeding;
 pub mod shader_module;
+pub mod simulation;
+pub mod sort;
+pub mod sph;
 pub mod texture;
-pub mod sph;
\ No newline at end of file
+pub mod time_series;
+pub mod tracer;
+pub mod vtk;
+pub mod wavemaker;
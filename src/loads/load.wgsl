@@ -0,0 +1,59 @@
+// WGSL file for the loads of the particles on boundaries, as impulses reduced over the particles
+// The solver shaders reduce one partial per workgroup and target with reduce_workgroup,
+// reduce.wgsl then adds the partials to the impulses accumulated since the last readback
+
+struct LoadTarget {
+    min: vec3f,
+    kind: u32,
+    max: vec3f,
+    // Tag or obstacle index
+    index: u32,
+    reference: vec3f,
+    _padding: u32,
+    // 48 bytes
+}
+
+// Impulse on a target with its moment about the reference point
+struct Load {
+    force: vec3f,
+    moment: vec3f,
+    // 32 bytes
+}
+
+const LOAD_REGION: u32 = 0u;
+const LOAD_TAG: u32 = 1u;
+const LOAD_OBSTACLE: u32 = 2u;
+const REDUCE_SIZE: u32 = 128u;
+
+var<workgroup> workgroup_loads: array<Load, REDUCE_SIZE>;
+
+fn target_load(load_target: LoadTarget, position: vec3f, impulse: vec3f) -> Load {
+    return Load(impulse, cross(position - load_target.reference, impulse));
+}
+
+fn combine_loads(a: Load, b: Load) -> Load {
+    return Load(a.force + b.force, a.moment + b.moment);
+}
+
+fn in_region(load_target: LoadTarget, position: vec3f) -> bool {
+    return all(position >= load_target.min) && all(position <= load_target.max);
+}
+
+// Tree reduction over the workgroup, every invocation has to call it
+fn reduce_workgroup(local_index: u32, load: Load) -> Load {
+    workgroup_loads[local_index] = load;
+    workgroupBarrier();
+    for (var stride = REDUCE_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if (local_index < stride) {
+            workgroup_loads[local_index] = combine_loads(
+                workgroup_loads[local_index],
+                workgroup_loads[local_index + stride],
+            );
+        }
+        workgroupBarrier();
+    }
+    let result = workgroup_loads[0];
+    // The next reduction overwrites the result
+    workgroupBarrier();
+    return result;
+}
@@ -0,0 +1,425 @@
+use crate::shader_module::ShaderModuleBuilder;
+use anyhow::*;
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+const LOAD_REGION: u32 = 0;
+const LOAD_TAG: u32 = 1;
+const LOAD_OBSTACLE: u32 = 2;
+
+// Boundary on which the particles exert pressure and viscous forces, in world units. Moments are
+// taken about `reference` and a `cutoff` frequency low-pass filters the time history.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LoadTarget {
+    // Walls inside the box between `min` and `max`. With mirror walls, the pressure and viscous
+    // forces of the fluid on its images across the walls act where the particles project on them.
+    // The momentum the walls and obstacles give to the particles they reflect is added on top.
+    Region {
+        name: String,
+        min: [f32; 3],
+        max: [f32; 3],
+        #[serde(default)]
+        reference: [f32; 3],
+        #[serde(default)]
+        cutoff: Option<f32>,
+    },
+    // Particles with the given tag, from the pair forces of the other particles on them. Forces
+    // between two tagged particles cancel.
+    Tag {
+        name: String,
+        tag: u32,
+        #[serde(default)]
+        reference: [f32; 3],
+        #[serde(default)]
+        cutoff: Option<f32>,
+    },
+    // Obstacle at the given index of the attached obstacles. The obstacle has no pressure of its
+    // own, its load is the momentum it removes from the particles it projects out of its surface.
+    Obstacle {
+        name: String,
+        obstacle: u32,
+        #[serde(default)]
+        reference: [f32; 3],
+        #[serde(default)]
+        cutoff: Option<f32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct LoadInfo {
+    pub min: [f32; 3],
+    pub kind: u32,
+    pub max: [f32; 3],
+    // Tag or obstacle index
+    pub index: u32,
+    pub reference: [f32; 3],
+    pub _padding: u32,
+    // 48 bytes
+}
+
+// Impulse on a target and its moment, as accumulated on the GPU
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Impulse {
+    force: [f32; 3],
+    _padding: f32,
+    moment: [f32; 3],
+    _padding2: f32,
+    // 32 bytes
+}
+
+// Mean force and moment on a target over the steps since the last readback
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Load {
+    pub force: [f32; 3],
+    pub moment: [f32; 3],
+}
+
+// Targets and impulses of the loads of a solver. The solver passes reduce one partial per
+// workgroup and target into `buffer_partials` and then record `encode`, see attach_loads of the
+// SPH solver.
+pub struct LoadReduction {
+    targets: Vec<LoadTarget>,
+    pub buffer_targets: wgpu::Buffer,
+    pub buffer_partials: wgpu::Buffer,
+    buffer_impulses: wgpu::Buffer,
+    staging_buffer_impulses: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+// One-pole low-pass filter of a time history sampled at varying intervals
+#[derive(Clone, Copy, Debug)]
+pub struct LowPass {
+    cutoff: f32,
+    state: Option<[f32; 6]>,
+}
+
+// Rows of force and moment components of the targets, filtered where a target has a cutoff
+pub struct LoadHistory {
+    names: Vec<String>,
+    filters: Vec<Option<LowPass>>,
+}
+
+impl LoadTarget {
+    pub fn name(&self) -> &str {
+        match self {
+            LoadTarget::Region { name, .. }
+            | LoadTarget::Tag { name, .. }
+            | LoadTarget::Obstacle { name, .. } => name,
+        }
+    }
+
+    pub fn cutoff(&self) -> Option<f32> {
+        match *self {
+            LoadTarget::Region { cutoff, .. }
+            | LoadTarget::Tag { cutoff, .. }
+            | LoadTarget::Obstacle { cutoff, .. } => cutoff,
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        let name = self.name();
+        if name.is_empty() || name.contains([',', '"', '\n']) {
+            bail!("load name {:?} can't be a CSV column", name);
+        }
+        if let Some(cutoff) = self.cutoff()
+            && !(cutoff > 0.0 && cutoff.is_finite())
+        {
+            bail!("load {:?} needs a positive cutoff frequency", name);
+        }
+        if let LoadTarget::Region { min, max, .. } = self
+            && (0..3).any(|a| min[a] > max[a])
+        {
+            bail!("load region {:?} has min above max", name);
+        }
+        Ok(())
+    }
+
+    pub fn info(&self) -> LoadInfo {
+        match *self {
+            LoadTarget::Region {
+                min,
+                max,
+                reference,
+                ..
+            } => LoadInfo {
+                min,
+                kind: LOAD_REGION,
+                max,
+                reference,
+                ..Default::default()
+            },
+            LoadTarget::Tag { tag, reference, .. } => LoadInfo {
+                kind: LOAD_TAG,
+                index: tag,
+                reference,
+                ..Default::default()
+            },
+            LoadTarget::Obstacle {
+                obstacle,
+                reference,
+                ..
+            } => LoadInfo {
+                kind: LOAD_OBSTACLE,
+                index: obstacle,
+                reference,
+                ..Default::default()
+            },
+        }
+    }
+
+    // Whether the boundary passes load the target, the tags are loaded by the pair forces
+    pub fn is_boundary(&self) -> bool {
+        !matches!(self, LoadTarget::Tag { .. })
+    }
+
+    // Whether the forces of the particles load the target, the regions through the wall images
+    pub fn has_forces(&self) -> bool {
+        !matches!(self, LoadTarget::Obstacle { .. })
+    }
+}
+
+impl LoadReduction {
+    // `num_partials` is the workgroup count of the solver passes, `targets` must not be empty
+    pub fn new(device: &wgpu::Device, targets: &[LoadTarget], num_partials: u32) -> Self {
+        let infos: Vec<LoadInfo> = targets.iter().map(LoadTarget::info).collect();
+        let impulse_size = std::mem::size_of::<Impulse>() as u64;
+        let impulses_size = targets.len() as u64 * impulse_size;
+        let module_reduce = ShaderModuleBuilder::new()
+            .add_module(include_str!("./load.wgsl"))
+            .add_module(include_str!("./reduce.wgsl"))
+            .build(device, Some("Shader Module Reduce Loads"));
+        let buffer_targets = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Load Targets"),
+            contents: bytemuck::cast_slice(&infos),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let buffer_partials = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Partial Loads"),
+            size: num_partials.max(1) as u64 * impulses_size,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let buffer_impulses = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Load Impulses"),
+            size: impulses_size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_impulses = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Load Impulses"),
+            size: impulses_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Reduce Loads"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Reduce Loads"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_partials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_impulses.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Reduce Loads"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Reduce Loads"),
+            layout: Some(&pipeline_layout),
+            module: &module_reduce,
+            entry_point: Some("reduce_loads"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        LoadReduction {
+            targets: targets.to_vec(),
+            buffer_targets,
+            buffer_partials,
+            buffer_impulses,
+            staging_buffer_impulses,
+            bind_group,
+            compute_pipeline,
+        }
+    }
+
+    pub fn targets(&self) -> &[LoadTarget] {
+        &self.targets
+    }
+
+    // Add the partials of a solver pass to the impulses, one workgroup per target
+    pub fn encode(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.targets.len() as u32, 1, 1);
+    }
+
+    // Mean loads over the `elapsed` time since the last call, which clears the impulses
+    pub fn gpu2cpu_loads(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) -> Vec<Load> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Loads"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_impulses,
+            0,
+            &self.staging_buffer_impulses,
+            0,
+            self.buffer_impulses.size(),
+        );
+        encoder.clear_buffer(&self.buffer_impulses, 0, None);
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_impulses.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let impulses: Vec<Impulse> = bytemuck::pod_collect_to_vec(&output_data);
+        drop(output_data);
+        self.staging_buffer_impulses.unmap();
+        impulses
+            .iter()
+            .map(|impulse| Load {
+                force: impulse.force.map(|f| f / elapsed),
+                moment: impulse.moment.map(|m| m / elapsed),
+            })
+            .collect()
+    }
+}
+
+impl LowPass {
+    // `cutoff` in Hz
+    pub fn new(cutoff: f32) -> Self {
+        LowPass {
+            cutoff,
+            state: None,
+        }
+    }
+
+    // Next filtered value for a sample taken `dt` after the previous one, the first sample
+    // starts the filter
+    pub fn apply(&mut self, dt: f32, value: [f32; 6]) -> [f32; 6] {
+        let alpha = 1.0 - (-2.0 * std::f32::consts::PI * self.cutoff * dt).exp();
+        let state = match self.state {
+            Some(state) => std::array::from_fn(|i| state[i] + alpha * (value[i] - state[i])),
+            None => value,
+        };
+        self.state = Some(state);
+        state
+    }
+}
+
+impl LoadHistory {
+    pub fn new(targets: &[LoadTarget]) -> Self {
+        let mut names = vec![];
+        for target in targets {
+            for component in ["fx", "fy", "fz", "mx", "my", "mz"] {
+                names.push(format!("{}_{}", target.name(), component));
+            }
+        }
+        LoadHistory {
+            names,
+            filters: targets
+                .iter()
+                .map(|target| target.cutoff().map(LowPass::new))
+                .collect(),
+        }
+    }
+
+    // Six columns per target, force then moment
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // Row of the mean `loads` over the `elapsed` time since the previous row
+    pub fn row(&mut self, elapsed: f32, loads: &[Load]) -> Vec<f32> {
+        let mut row = vec![];
+        for (load, filter) in loads.iter().zip(&mut self.filters) {
+            let value = std::array::from_fn(|i| match i < 3 {
+                true => load.force[i],
+                false => load.moment[i - 3],
+            });
+            match filter {
+                Some(filter) => row.extend(filter.apply(elapsed, value)),
+                None => row.extend(value),
+            }
+        }
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_history() {
+        let targets: Vec<LoadTarget> = toml::from_str::<toml::Table>(
+            "[[loads]]\ntype = \"region\"\nname = \"floor\"\nmin = [-0.5, -0.5, -0.5]\n\
+             max = [0.5, -0.4, 0.5]\n\
+             [[loads]]\ntype = \"tag\"\nname = \"wall\"\ntag = 2\ncutoff = 10.0\n",
+        )
+        .unwrap()["loads"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(std::mem::size_of::<LoadInfo>(), 48);
+        assert_eq!(targets[1].info().kind, LOAD_TAG);
+        assert_eq!(targets[1].info().index, 2);
+        let mut history = LoadHistory::new(&targets);
+        assert_eq!(history.names().len(), 12);
+        assert_eq!(history.names()[7], "wall_fy");
+        let load = |fy| Load {
+            force: [0.0, fy, 0.0],
+            moment: [0.0; 3],
+        };
+        // The first row starts the filter, then it moves by 1 - exp(-2 pi fc dt)
+        assert_eq!(history.row(0.01, &[load(1.0), load(1.0)])[7], 1.0);
+        let row = history.row(0.01, &[load(3.0), load(3.0)]);
+        let alpha = 1.0 - (-2.0 * std::f32::consts::PI * 0.1f32).exp();
+        assert_eq!(row[1], 3.0);
+        assert!((row[7] - (1.0 + 2.0 * alpha)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,21 @@
+@group(0) @binding(0) var<storage, read> partials: array<Load>;
+@group(0) @binding(1) var<storage, read_write> impulses: array<Load>;
+
+// One workgroup per target adding the partials of the solver pass, which are laid out per
+// workgroup of that pass and then per target
+@compute @workgroup_size(128)
+fn reduce_loads(
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let num_targets = arrayLength(&impulses);
+    let target_idx = workgroup_id.x;
+    var sum = Load();
+    for (var i = local_index; i < arrayLength(&partials) / num_targets; i += REDUCE_SIZE) {
+        sum = combine_loads(sum, partials[i * num_targets + target_idx]);
+    }
+    let result = reduce_workgroup(local_index, sum);
+    if (local_index == 0u) {
+        impulses[target_idx] = combine_loads(impulses[target_idx], result);
+    }
+}
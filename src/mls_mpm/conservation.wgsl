@@ -0,0 +1,117 @@
+struct Grid {
+    vx: i32,
+    vy: i32,
+    vz: i32,
+    mass: i32,
+}
+
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f, // MLS-MPM Affine Matrix
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+struct Material {
+    color: vec4f,
+    eos_density: f32, // reference density
+    eos_threshold: f32, // negative pressure threshold
+    eos_stiffness: f32, // stiffness coefficient
+    eos_n: f32, // exponent 
+    dynamic_viscosity: f32, // viscosity coefficient
+    rigid_flag: u32,
+}
+
+struct Disturbance {
+    field: vec3f,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> grid: array<Grid>;
+@group(0) @binding(2) var<storage, read> materials: array<Material>;
+@group(0) @binding(3) var<uniform> params: SimParams;
+@group(0) @binding(4) var<uniform> disturbance: Disturbance;
+@group(0) @binding(5) var<storage, read_write> partials: array<Totals>;
+
+// Density at the particle from the node masses of the last step, as in the constitutive model.
+// It is zero before the first step.
+fn particle_density(particle: Particle) -> f32 {
+    let position = particle.position * f32(params.grid_resolution);
+    let node_coord: vec3f = floor(position);
+    let node_dist: vec3f = position - node_coord - 0.5;
+    let weights = quadratic_weights(node_dist);
+    var density = 0.0;
+    for (var gx = 0u; gx < 3; gx++) {
+        for (var gy = 0u; gy < 3; gy++) {
+            for (var gz = 0u; gz < 3; gz++) {
+                let weight = weights[gx].x * weights[gy].y * weights[gz].z;
+                let neighbor_coord = vec3f(
+                    node_coord.x + f32(gx) - 1.0,
+                    node_coord.y + f32(gy) - 1.0,
+                    node_coord.z + f32(gz) - 1.0);
+                let node_idx = get_node_index(neighbor_coord, params.grid_resolution);
+                density += i32_to_f32(grid[node_idx].mass) * weight;
+            }
+        }
+    }
+    return density;
+}
+
+// Particle masses are per grid cell and positions and velocities in the unit domain, the totals
+// are scaled to world units. The internal energy integrates p / rho^2 of the equation of state
+// without its negative pressure threshold.
+@compute @workgroup_size(128)
+fn reduce_conservation(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let index = global_id.x;
+    var totals = empty_totals();
+    if (index < params.num_particles) {
+        let particle = particles[index];
+        let material = materials[particle.material_idx];
+        let scale = params.scale_distance;
+        let cell_size = scale / f32(params.grid_resolution);
+        let mass = particle.mass * cell_size * cell_size * cell_size;
+        let density = particle_density(particle);
+        let rho0 = material.eos_density;
+        let n = material.eos_n;
+        let pressure = max(-material.eos_threshold,
+            material.eos_stiffness * (pow(density / rho0, n) - 1.0));
+        var internal_energy = 0.0;
+        if (density > 0.0) {
+            var power_term = log(density / rho0) / rho0;
+            if (abs(n - 1.0) > 1.0e-6) {
+                power_term = (pow(density / rho0, n - 1.0) - 1.0) / ((n - 1.0) * rho0);
+            }
+            internal_energy = mass * material.eos_stiffness * (power_term + 1.0 / density - 1.0 / rho0);
+        }
+        totals = particle_totals(
+            mass,
+            particle.position * scale,
+            particle.velocity * scale,
+            disturbance.field * scale,
+            density,
+            pressure,
+            internal_energy,
+        );
+    }
+    let partial = reduce_workgroup(local_index, totals);
+    if (local_index == 0u) {
+        partials[workgroup_id.x] = partial;
+    }
+}
@@ -0,0 +1,45 @@
+struct Grid {
+    vx: i32,
+    vy: i32,
+    vz: i32,
+    mass: i32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read_write> grid: array<Grid>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read> zones: array<DampingZone>;
+@group(0) @binding(3) var<storage, read> profiles: array<f32>;
+
+@compute @workgroup_size(256)
+fn grid_damping_zones(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= params.num_nodes) {
+        return;
+    }
+    let node = grid[idx];
+    if (node.mass <= 0) {
+        return;
+    }
+    // Node position in the normalized simulation frame
+    let grid_res = params.grid_resolution;
+    let x = idx / grid_res / grid_res;
+    let y = (idx / grid_res) % grid_res;
+    let z = idx % grid_res;
+    let position = (vec3f(f32(x), f32(y), f32(z)) + 0.5) / f32(grid_res);
+    // Grid holds velocity after the grid update, scaled by the domain size unlike the targets
+    let scale = params.scale_distance;
+    let velocity = vec3f(i32_to_f32(node.vx), i32_to_f32(node.vy), i32_to_f32(node.vz));
+    let relaxed = relax_velocity(position, velocity / scale, params.dt) * scale;
+    grid[idx].vx = f32_to_i32(relaxed.x);
+    grid[idx].vy = f32_to_i32(relaxed.y);
+    grid[idx].vz = f32_to_i32(relaxed.z);
+}
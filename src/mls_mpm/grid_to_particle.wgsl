@@ -12,6 +12,8 @@ struct Particle {
     velocity: vec3f,
     material_idx: u32,
     C: mat3x3f, // MLS-MPM Affine Matrix
+    id: u32,
+    tag: u32,
 }
 
 struct SimParams {
@@ -20,7 +22,7 @@ struct SimParams {
     scale_distance: f32,
     num_particles: u32,
     num_nodes: u32,
-    _padding: u32,
+    periodic_axes: u32, // bit per periodic axis
 }
 
 struct Material {
@@ -90,9 +92,14 @@ fn grid_to_particle(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
     // Advect particles
     particles[idx].position += particles[idx].velocity * params.dt;
-    particles[idx].position.x = clamp(particles[idx].position.x, 1.0 / grid_res, (grid_res - 2.0) / grid_res);
-    particles[idx].position.y = clamp(particles[idx].position.y, 1.0 / grid_res, (grid_res - 2.0) / grid_res);
-    particles[idx].position.z = clamp(particles[idx].position.z, 1.0 / grid_res, (grid_res - 2.0) / grid_res);
+    // Wrap around periodic axes of the unit domain and clamp to the walls on the others
+    for (var axis = 0u; axis < 3u; axis++) {
+        if (is_periodic(axis)) {
+            particles[idx].position[axis] = fract(particles[idx].position[axis]);
+        } else {
+            particles[idx].position[axis] = clamp(particles[idx].position[axis], 1.0 / grid_res, (grid_res - 2.0) / grid_res);
+        }
+    }
 
     // Boundary Conditions (maby have this as separate dispatch)
     // Need to use buffer for parameters insated of hard code
@@ -100,8 +107,14 @@ fn grid_to_particle(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let wallStiffness = 0.7;
     let wallFriction = 0.5;
     let x_n: vec3f = grid_res * (particles[idx].position + particles[idx].velocity * params.dt * k);
-    let wallMin: vec3f = vec3f(1.0);
-    let wallMax: vec3f = vec3f(grid_res - 2.0);
+    var wallMin: vec3f = vec3f(1.0);
+    var wallMax: vec3f = vec3f(grid_res - 2.0);
+    for (var axis = 0u; axis < 3u; axis++) {
+        if (is_periodic(axis)) {
+            wallMin[axis] = -1.0e30;
+            wallMax[axis] = 1.0e30;
+        }
+    }
     if (x_n.x < wallMin.x) {
         particles[idx].velocity.x += wallStiffness * (wallMin.x - x_n.x);
         particles[idx].velocity.y *= wallFriction;
@@ -11,7 +11,7 @@ struct SimParams {
     scale_distance: f32,
     num_particles: u32,
     num_nodes: u32,
-    _padding: u32,
+    periodic_axes: u32, // bit per periodic axis
 }
 
 struct Disturbance {
@@ -44,9 +44,9 @@ fn grid_update(@builtin(global_invocation_id) global_id: vec3<u32>) {
         let x = idx / grid_res / grid_res;
         let y = (idx / grid_res) % grid_res;
         let z = idx % grid_res;
-        if (x < 2 || x > grid_res - 3) { grid[idx].vx = 0; }
-        if (y < 2 || y > grid_res - 3) { grid[idx].vy = 0; }
-        if (z < 2 || z > grid_res - 3) { grid[idx].vz = 0; }
+        if (!is_periodic(0u) && (x < 2 || x > grid_res - 3)) { grid[idx].vx = 0; }
+        if (!is_periodic(1u) && (y < 2 || y > grid_res - 3)) { grid[idx].vy = 0; }
+        if (!is_periodic(2u) && (z < 2 || z > grid_res - 3)) { grid[idx].vz = 0; }
     }
 }
 
@@ -0,0 +1,48 @@
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f,
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> params: SimParams;
+@group(0) @binding(2) var<storage, read> emitted: array<EmittedParticle>;
+@group(0) @binding(3) var<uniform> emission: Emission;
+
+// Append emitted particles after the alive ones, dropping those beyond capacity
+@compute @workgroup_size(256)
+fn emit_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    let slot = params.num_particles + idx;
+    if (idx >= emission.num_emitted || slot >= arrayLength(&particles)) {
+        return;
+    }
+    let new_particle = emitted[idx];
+    var particle = Particle();
+    particle.position = new_particle.position;
+    particle.mass = new_particle.mass;
+    particle.velocity = new_particle.velocity;
+    particle.material_idx = new_particle.material_idx;
+    particle.id = emission.first_id + idx;
+    particle.tag = new_particle.tag;
+    particles[slot] = particle;
+}
+
+// Runs after emit_particles so that every invocation saw the same count
+@compute @workgroup_size(1)
+fn commit_emitted() {
+    params.num_particles = min(params.num_particles + emission.num_emitted, arrayLength(&particles));
+}
@@ -1,5 +1,21 @@
+use crate::checkpoint::SolverState;
+use crate::conservation::{REDUCE_SIZE, Totals, TotalsReduction};
+use crate::damping::{DampingBuffers, DampingZone};
+use crate::flow::{EmittedParticle, Inlet, Outlet};
+use crate::obstacle::{Obstacle, ObstacleBuffers};
+use crate::prefix_sum::PrefixSum;
+use crate::probe::Probes;
+use crate::rigid_body::{RigidBody, RigidBodyBuffers, RigidBodyLoad, SCHEDULE_STEPS};
 use crate::shader_module::ShaderModuleBuilder;
+use crate::simulation::{
+    Checkpointing, ConservationTotals, InstancePass, ParticleData, ProbeRecords, RigidBodyMotion,
+    RigidBodyRecords, Scene, Simulation, gpu2cpu_buffer,
+};
+use crate::sort::BitonicSort;
+use crate::tracer::{TracerBuffers, TracerSample};
+use crate::wavemaker::Wavemaker;
 use futures::executor::block_on;
+use std::cell::{Cell, RefCell};
 use std::{num::NonZeroU64, str::FromStr};
 use wgpu::{ShaderModule, util::DeviceExt};
 
@@ -11,6 +27,11 @@ pub struct Particle {
     pub velocity: [f32; 3],
     pub material_idx: u32,
     pub C: [f32; 12],
+    // Persistent across reordering, inlets continue from the initial particle count
+    pub id: u32,
+    // User defined group of the particle, e.g. tracers or the inlet it came from
+    pub tag: u32,
+    pub _padding: [u32; 2],
 }
 
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -30,9 +51,13 @@ pub struct SimParams {
     pub scale_distance: f32,
     pub num_particles: u32,
     pub num_nodes: u32,
-    pub _padding: u32,
+    pub periodic_axes: u32, // bit per periodic axis, see PERIODIC_X
 }
 
+pub const PERIODIC_X: u32 = 1;
+pub const PERIODIC_Y: u32 = 2;
+pub const PERIODIC_Z: u32 = 4;
+
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct Disturbance {
@@ -62,8 +87,14 @@ pub struct MlsMpm {
 }
 
 pub struct MlsMpmCompute {
-    num_particles: u32,
+    // Length of the particle buffer, particle dispatches cover the capacity
+    capacity: u32,
+    // Id of the next particle emitted by an inlet
+    next_id: Cell<u32>,
     num_nodes: u32,
+    // Time step and gravity of the GPU parameters, for the boundaries stepped on the CPU
+    dt: Cell<f32>,
+    gravity: Cell<[f32; 3]>,
     // Input Buffers
     pub buffer_particles: wgpu::Buffer,
     buffer_grid: wgpu::Buffer,
@@ -76,6 +107,7 @@ pub struct MlsMpmCompute {
     // Staging Buffers
     staging_buffer_particles: wgpu::Buffer,
     staging_buffer_grid: wgpu::Buffer,
+    staging_buffer_params: wgpu::Buffer,
 
     // Bind Groups
     bind_group_particle_to_grid: wgpu::BindGroup,
@@ -89,6 +121,129 @@ pub struct MlsMpmCompute {
     compute_pipeline_grid_to_particle: wgpu::ComputePipeline,
     compute_pipeline_grid_update: wgpu::ComputePipeline,
     compute_pipeline_grid_reset: wgpu::ComputePipeline,
+
+    // Optional Boundaries
+    obstacle_projection: Option<ObstacleProjection>,
+    rigid_body_coupling: Option<RigidBodyCoupling>,
+    damping: Option<Damping>,
+    flow_boundaries: Option<FlowBoundaries>,
+
+    // Optional Reordering
+    reorder: Option<Reorder>,
+
+    // Optional Tracers
+    tracers: Option<Tracers>,
+
+    // Optional Probes
+    probes: Option<ProbeSampling>,
+
+    // Optional Conservation Diagnostics
+    conservation: Option<Conservation>,
+}
+
+struct Reorder {
+    // Steps between the reorders of prepare_step, 0 for none, and the steps since the last one
+    interval: u32,
+    steps: Cell<u32>,
+    #[allow(unused)]
+    buffer_pairs: wgpu::Buffer,
+    buffer_particles_out: wgpu::Buffer,
+    sort: BitonicSort,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_morton_keys: wgpu::ComputePipeline,
+    compute_pipeline_gather_particles: wgpu::ComputePipeline,
+}
+
+impl Reorder {
+    // Steps of the next submission before a reorder is due, counting the one run by prepare_step
+    fn steps_to_next(&self) -> Option<u32> {
+        let steps = self.steps.get();
+        match self.interval {
+            0 => None,
+            interval if steps >= interval => Some(interval),
+            interval => Some(interval - steps),
+        }
+    }
+}
+
+struct Tracers {
+    buffers: TracerBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct Conservation {
+    reduction: TotalsReduction,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct ProbeSampling {
+    probes: Probes,
+    #[allow(unused)]
+    buffer_probe_material: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+// Emission runs on the CPU before each submission, up to the time of the steps recorded so far
+struct FlowBoundaries {
+    inlets: RefCell<Vec<Inlet>>,
+    outflow: Option<Outflow>,
+    time: Cell<f32>,
+    // Emitted particles are appended in chunks of the emission buffer length
+    chunk_len: usize,
+    buffer_emitted: wgpu::Buffer,
+    buffer_emission: wgpu::Buffer,
+    bind_group_inflow: wgpu::BindGroup,
+    compute_pipeline_emit_particles: wgpu::ComputePipeline,
+    compute_pipeline_commit_emitted: wgpu::ComputePipeline,
+}
+
+struct Outflow {
+    #[allow(unused)]
+    buffer_outlets: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_keep: wgpu::Buffer,
+    buffer_particles_out: wgpu::Buffer,
+    prefix_sum: PrefixSum,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_mark_outflow: wgpu::ComputePipeline,
+    compute_pipeline_compact_particles: wgpu::ComputePipeline,
+}
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Emission {
+    num_emitted: u32,
+    first_id: u32,
+    _padding: [u32; 2],
+}
+
+struct Damping {
+    #[allow(unused)]
+    buffers: DampingBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct RigidBodyCoupling {
+    buffers: RigidBodyBuffers,
+    // Integrated on the GPU, the poses are refreshed by gpu2cpu_rigid_bodies
+    bodies: RefCell<Vec<RigidBody>>,
+    // Prescribed motion of the paddle bodies, which are not integrated, see attach_wavemakers
+    wavemakers: Vec<Option<Wavemaker>>,
+    // Time of the steps scheduled since the wavemakers were attached
+    time: Cell<f64>,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct ObstacleProjection {
+    #[allow(unused)]
+    buffers: ObstacleBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
 }
 
 impl MlsMpm {
@@ -105,11 +260,85 @@ impl MlsMpm {
             materials,
         }
     }
+    // Ids of the particles with the given tag, e.g. to follow them with attach_tracers
+    pub fn ids_with_tag(&self, tag: u32) -> Vec<u32> {
+        self.particles
+            .iter()
+            .filter(|particle| particle.tag == tag)
+            .map(|particle| particle.id)
+            .collect()
+    }
+}
+
+impl Material {
+    // Pressure equation of state of the constitutive model
+    pub fn pressure(&self, density: f32) -> f32 {
+        (self.eos_stiffness * ((density / self.eos_density).powf(self.eos_n) - 1.0))
+            .max(-self.eos_threshold)
+    }
+}
+
+// Density at the particles as seen by the constitutive model, from a CPU particle to grid pass of
+// the particle masses. Particles carry no density so exporters recompute it.
+pub fn particle_densities(particles: &[Particle], params: &SimParams) -> Vec<f32> {
+    let res = params.grid_resolution as i32;
+    let node_index = |node: [i32; 3]| -> usize {
+        let node: [i32; 3] = std::array::from_fn(|a| {
+            if params.periodic_axes & (1 << a) != 0 {
+                node[a].rem_euclid(res)
+            } else {
+                node[a].clamp(0, res - 1)
+            }
+        });
+        (node[0] * res * res + node[1] * res + node[2]) as usize
+    };
+    // Base node and quadratic weights of the 3x3x3 stencil
+    let stencil = |particle: &Particle| -> ([i32; 3], [[f32; 3]; 3]) {
+        let position = particle.position.map(|x| x * res as f32);
+        let base = position.map(|x| x.floor() as i32 - 1);
+        let weights = std::array::from_fn(|a| {
+            let dist = position[a] - position[a].floor() - 0.5;
+            [
+                0.5 * (0.5 - dist) * (0.5 - dist),
+                0.75 - dist * dist,
+                0.5 * (0.5 + dist) * (0.5 + dist),
+            ]
+        });
+        (base, weights)
+    };
+    let nodes = |base: [i32; 3], weights: [[f32; 3]; 3]| {
+        (0..27).map(move |offset| {
+            let g = [offset / 9, (offset / 3) % 3, offset % 3];
+            let node = std::array::from_fn(|a| base[a] + g[a] as i32);
+            (node, weights[0][g[0]] * weights[1][g[1]] * weights[2][g[2]])
+        })
+    };
+    let mut grid_mass = vec![0.0; (res * res * res) as usize];
+    for particle in particles {
+        let (base, weights) = stencil(particle);
+        for (node, weight) in nodes(base, weights) {
+            grid_mass[node_index(node)] += weight * particle.mass;
+        }
+    }
+    particles
+        .iter()
+        .map(|particle| {
+            let (base, weights) = stencil(particle);
+            nodes(base, weights)
+                .map(|(node, weight)| weight * grid_mass[node_index(node)])
+                .sum()
+        })
+        .collect()
 }
 
 impl MlsMpmCompute {
     pub async fn new(device: &wgpu::Device, params: &SimParams) -> Self {
-        let num_particles = params.num_particles as usize;
+        Self::with_capacity(device, params, params.num_particles).await
+    }
+
+    // Leave headroom above the initial particle count for inlets
+    pub async fn with_capacity(device: &wgpu::Device, params: &SimParams, capacity: u32) -> Self {
+        let capacity = capacity.max(params.num_particles) as usize;
         let num_nodes = params.num_nodes as usize;
         const MATERIAL_MAX_LEN: usize = 4; // Hard coded, consider defining at compilation or user input
 
@@ -139,7 +368,7 @@ impl MlsMpmCompute {
         // Create Input Buffers
         let buffer_particles = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Particle"),
-            size: (num_particles * std::mem::size_of::<Particle>()) as u64,
+            size: (capacity * std::mem::size_of::<Particle>()) as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
@@ -157,7 +386,9 @@ impl MlsMpmCompute {
         let buffer_materials = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Material"),
             size: (MATERIAL_MAX_LEN * std::mem::size_of::<Material>()) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
 
@@ -165,21 +396,27 @@ impl MlsMpmCompute {
         let buffer_params = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Simulation Parameters"),
             size: std::mem::size_of::<SimParams>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            // Storage for the particle count updated by inlets
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
 
         let buffer_disturbance = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Disturbance"),
             size: std::mem::size_of::<Disturbance>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
 
         // Create Staging Buffers
         let staging_buffer_particles = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Staging Buffer Particle"),
-            size: (num_particles * std::mem::size_of::<Particle>()) as u64,
+            size: (capacity * std::mem::size_of::<Particle>()) as u64,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -191,6 +428,13 @@ impl MlsMpmCompute {
             mapped_at_creation: false,
         });
 
+        let staging_buffer_params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Simulation Parameters"),
+            size: std::mem::size_of::<SimParams>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
         // Bind Group Layouts
         let bind_group_layout_particle_to_grid =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -527,7 +771,10 @@ impl MlsMpmCompute {
             });
 
         MlsMpmCompute {
-            num_particles: num_particles as u32,
+            capacity: capacity as u32,
+            next_id: Cell::new(params.num_particles),
+            dt: Cell::new(params.dt),
+            gravity: Cell::new([0.0; 3]),
             num_nodes: num_nodes as u32,
             // Input Buffers
             buffer_particles,
@@ -541,6 +788,7 @@ impl MlsMpmCompute {
             // Staging Buffers
             staging_buffer_particles,
             staging_buffer_grid,
+            staging_buffer_params,
 
             // Bind Groups
             bind_group_particle_to_grid,
@@ -554,170 +802,1870 @@ impl MlsMpmCompute {
             compute_pipeline_grid_to_particle,
             compute_pipeline_grid_update,
             compute_pipeline_grid_reset,
-        }
-    }
-}
-
-impl MlsMpmCompute {
-    pub fn cpu2gpu_particles(&self, queue: &wgpu::Queue, particles: &Vec<Particle>) {
-        queue.write_buffer(&self.buffer_particles, 0, bytemuck::cast_slice(&particles));
-    }
-    pub fn cpu2gpu_params(&self, queue: &wgpu::Queue, params: &SimParams) {
-        queue.write_buffer(&self.buffer_params, 0, bytemuck::bytes_of(params));
-    }
-    pub fn cpu2gpu_materials(&self, queue: &wgpu::Queue, materials: &Vec<Material>) {
-        queue.write_buffer(&self.buffer_materials, 0, bytemuck::cast_slice(&materials));
-    }
-    pub fn cpu2gpu_disturbance(&self, queue: &wgpu::Queue, disturbance: &Disturbance) {
-        queue.write_buffer(&self.buffer_disturbance, 0, bytemuck::bytes_of(disturbance));
-    }
 
-    pub fn gpu2cpu_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Particle> {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder GPU to CPU Particles"),
-        });
-        encoder.copy_buffer_to_buffer(
-            &self.buffer_particles,
-            0,
-            &self.staging_buffer_particles,
-            0,
-            self.buffer_particles.size(),
-        );
-        queue.submit(std::iter::once(encoder.finish()));
-        // Read back buffer
-        let buffer_slice = self.staging_buffer_particles.slice(..);
-        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
-        // Wait for GPU to finish operation
-        _ = device.poll(wgpu::PollType::Wait);
-        // Read data from buffer
-        let output_data = buffer_slice.get_mapped_range();
-        // Convert to structure
-        let particles_out: Vec<Particle> = bytemuck::cast_slice(&output_data).to_vec();
-        // Drop output and unmap staging buffer
-        drop(output_data);
-        self.staging_buffer_particles.unmap();
-        return particles_out;
+            // Optional Boundaries
+            obstacle_projection: None,
+            rigid_body_coupling: None,
+            damping: None,
+            flow_boundaries: None,
+            reorder: None,
+            tracers: None,
+            probes: None,
+            conservation: None,
+        }
     }
 
-    pub fn gpu2cpu_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Grid> {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder GPU to CPU Grid"),
+    // Obstacles are given in the normalized simulation frame of the particle positions
+    pub fn attach_obstacles(&mut self, device: &wgpu::Device, obstacles: &[Obstacle]) {
+        if obstacles.is_empty() {
+            self.obstacle_projection = None;
+            return;
+        }
+        let buffers = ObstacleBuffers::new(device, obstacles);
+        let module_obstacle = ShaderModuleBuilder::new()
+            .add_module(include_str!("./obstacle.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .build(device, Some("Shader Module Grid Obstacle Projection"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Grid Obstacle Projection"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
-        encoder.copy_buffer_to_buffer(
-            &self.buffer_grid,
-            0,
-            &self.staging_buffer_grid,
-            0,
-            self.buffer_grid.size(),
-        );
-        queue.submit(std::iter::once(encoder.finish()));
-        // Read back buffer
-        let buffer_slice = self.staging_buffer_grid.slice(..);
-        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
-        // Wait for GPU to finish operation
-        _ = device.poll(wgpu::PollType::Wait);
-        // Read data from buffer
-        let output_data = buffer_slice.get_mapped_range();
-        // Convert to structure
-        let grid_out: Vec<Grid> = bytemuck::cast_slice(&output_data).to_vec();
-        // Drop output and unmap staging buffer
-        drop(output_data);
-        self.staging_buffer_grid.unmap();
-        return grid_out;
-    }
-
-    pub fn compute_particle_to_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Particle to Grid"),
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Grid Obstacle Projection"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.buffer_obstacles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_sdf.as_entire_binding(),
+                },
+            ],
         });
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Particle to Grid"),
-            timestamp_writes: None,
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Grid Obstacle Projection"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
         });
-        // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_particle_to_grid);
-        compute_pass.set_bind_group(0, &self.bind_group_particle_to_grid, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
-        // Drop compute pass to gain access to encoder again
-        drop(compute_pass);
-        // Submit commands to queue
-        let command_buffer = encoder.finish();
-        queue.submit([command_buffer]);
-    }
-
-    pub fn compute_particle_constitutive_model(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Particle Constitutive Model"),
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Grid Obstacle Projection"),
+            layout: Some(&pipeline_layout),
+            module: &module_obstacle,
+            entry_point: Some("grid_obstacle_projection"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
         });
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Particle Constitutive Model"),
-            timestamp_writes: None,
+        self.obstacle_projection = Some(ObstacleProjection {
+            buffers,
+            bind_group,
+            compute_pipeline,
         });
-        // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_particle_constitutive_model);
-        compute_pass.set_bind_group(0, &self.bind_group_particle_constitutive_model, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
-        // Drop compute pass to gain access to encoder again
-        drop(compute_pass);
-        // Submit commands to queue
-        let command_buffer = encoder.finish();
-        queue.submit([command_buffer]);
     }
 
-    pub fn compute_grid_to_particle(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Grid to Particle"),
+    // Bodies are given in the normalized simulation frame and replace the rigid_flag particles for
+    // two-way coupling: nodes inside a body or within a cell of its surface take its velocity and
+    // the body receives the reaction. The bodies are integrated on the GPU after each step, see
+    // RigidBodyBuffers::schedule.
+    pub fn attach_rigid_bodies(&mut self, device: &wgpu::Device, bodies: Vec<RigidBody>) {
+        if bodies.is_empty() {
+            self.rigid_body_coupling = None;
+            return;
+        }
+        let buffers = RigidBodyBuffers::new(device, &bodies);
+        let module_rigid_body = ShaderModuleBuilder::new()
+            .add_module(include_str!("./rigid_body.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("../rigid_body/state.wgsl"))
+            .add_module(include_str!("../rigid_body/rigid_body.wgsl"))
+            .build(device, Some("Shader Module Grid Rigid Body Coupling"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Grid Rigid Body Coupling"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Grid to Particle"),
-            timestamp_writes: None,
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Grid Rigid Body Coupling"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.buffer_bodies.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_impulses.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.fields.buffer_obstacles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffers.fields.buffer_sdf.as_entire_binding(),
+                },
+            ],
         });
-        // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_grid_to_particle);
-        compute_pass.set_bind_group(0, &self.bind_group_grid_to_particle, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
-        // Drop compute pass to gain access to encoder again
-        drop(compute_pass);
-        // Submit commands to queue
-        let command_buffer = encoder.finish();
-        queue.submit([command_buffer]);
-    }
-
-    pub fn compute_grid_update(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Grid Update"),
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Grid Rigid Body Coupling"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
         });
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Grid Update"),
-            timestamp_writes: None,
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Grid Rigid Body Coupling"),
+            layout: Some(&pipeline_layout),
+            module: &module_rigid_body,
+            entry_point: Some("grid_rigid_body_coupling"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.rigid_body_coupling = Some(RigidBodyCoupling {
+            buffers,
+            bodies: RefCell::new(bodies),
+            wavemakers: vec![],
+            time: Cell::new(0.0),
+            bind_group,
+            compute_pipeline,
         });
-        // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_grid_update);
-        compute_pass.set_bind_group(0, &self.bind_group_grid_update, &[]);
-        compute_pass.dispatch_workgroups((self.num_nodes + 255) / 256, 1, 1);
-        // Drop compute pass to gain access to encoder again
-        drop(compute_pass);
-        // Submit commands to queue
-        let command_buffer = encoder.finish();
-        queue.submit([command_buffer]);
     }
 
-    pub fn compute_grid_reset(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Grid Reset"),
-        });
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Grid Reset"),
+    // Relax grid velocities inside absorbing zones given in the normalized simulation frame
+    pub fn attach_damping_zones(&mut self, device: &wgpu::Device, zones: &[DampingZone]) {
+        if zones.is_empty() {
+            self.damping = None;
+            return;
+        }
+        let buffers = DampingBuffers::new(device, zones);
+        let module_damping = ShaderModuleBuilder::new()
+            .add_module(include_str!("./damping.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("../damping/damping.wgsl"))
+            .build(device, Some("Shader Module Grid Damping Zones"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Grid Damping Zones"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Grid Damping Zones"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.buffer_zones.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_profiles.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Grid Damping Zones"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Grid Damping Zones"),
+            layout: Some(&pipeline_layout),
+            module: &module_damping,
+            entry_point: Some("grid_damping_zones"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.damping = Some(Damping {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Inlets and outlets are given in the normalized simulation frame. Inlets append particles
+    // into the capacity headroom and outlets delete the particles inside them. Their time starts
+    // at zero when attached.
+    pub fn attach_flow_boundaries(
+        &mut self,
+        device: &wgpu::Device,
+        inlets: Vec<Inlet>,
+        outlets: &[Outlet],
+    ) {
+        if inlets.is_empty() && outlets.is_empty() {
+            self.flow_boundaries = None;
+            return;
+        }
+        let capacity = self.capacity as usize;
+        let outflow = (!outlets.is_empty()).then(|| {
+            let buffer_outlets = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Buffer Outlets"),
+                contents: bytemuck::cast_slice(outlets),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let buffer_keep = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer Outflow Keep"),
+                size: (capacity * std::mem::size_of::<u32>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            let buffer_particles_out = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer Particle Compacted"),
+                size: (capacity * std::mem::size_of::<Particle>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let prefix_sum = PrefixSum::new(device, &buffer_keep, self.capacity);
+            let module_outflow = ShaderModuleBuilder::new()
+                .add_module(include_str!("./outflow.wgsl"))
+                .add_module(include_str!("../flow/outlet.wgsl"))
+                .build(device, Some("Shader Module Outflow"));
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Bind Group Layout Outflow"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bind Group Outflow"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.buffer_particles.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.buffer_params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: buffer_outlets.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: buffer_keep.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: buffer_particles_out.as_entire_binding(),
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout Outflow"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let compute_pipeline_mark_outflow =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Compute Pipeline Mark Outflow"),
+                    layout: Some(&pipeline_layout),
+                    module: &module_outflow,
+                    entry_point: Some("mark_outflow"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                });
+            let compute_pipeline_compact_particles =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Compute Pipeline Compact Particles"),
+                    layout: Some(&pipeline_layout),
+                    module: &module_outflow,
+                    entry_point: Some("compact_particles"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                });
+            Outflow {
+                buffer_outlets,
+                buffer_keep,
+                buffer_particles_out,
+                prefix_sum,
+                bind_group,
+                compute_pipeline_mark_outflow,
+                compute_pipeline_compact_particles,
+            }
+        });
+        let chunk_len = inlets
+            .iter()
+            .map(|inlet| inlet.num_points())
+            .sum::<usize>()
+            .max(1);
+        let buffer_emitted = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Emitted Particles"),
+            size: (chunk_len * std::mem::size_of::<EmittedParticle>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffer_emission = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Emission"),
+            size: std::mem::size_of::<Emission>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let module_inflow = ShaderModuleBuilder::new()
+            .add_module(include_str!("./inflow.wgsl"))
+            .add_module(include_str!("../flow/emission.wgsl"))
+            .build(device, Some("Shader Module Inflow"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Inflow"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group_inflow = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Inflow"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_emitted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_emission.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Inflow"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_emit_particles =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Emit Particles"),
+                layout: Some(&pipeline_layout),
+                module: &module_inflow,
+                entry_point: Some("emit_particles"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_commit_emitted =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Commit Emitted"),
+                layout: Some(&pipeline_layout),
+                module: &module_inflow,
+                entry_point: Some("commit_emitted"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.flow_boundaries = Some(FlowBoundaries {
+            inlets: RefCell::new(inlets),
+            outflow,
+            time: Cell::new(0.0),
+            chunk_len,
+            buffer_emitted,
+            buffer_emission,
+            bind_group_inflow,
+            compute_pipeline_emit_particles,
+            compute_pipeline_commit_emitted,
+        });
+    }
+
+    // Buffers for compute_morton_reorder, which step and prepare_step then run every `interval`
+    // steps, a few hundred is worthwhile. An interval of 0 leaves the reorder to the caller.
+    pub fn attach_morton_reorder(&mut self, device: &wgpu::Device, interval: u32) {
+        let module_reorder = ShaderModuleBuilder::new()
+            .add_module(include_str!("../sort/morton.wgsl"))
+            .add_module(include_str!("./reorder.wgsl"))
+            .build(device, Some("Shader Module Morton Reorder"));
+        let buffer_pairs = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Morton Keys"),
+            size: (self.capacity as usize * 2 * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let buffer_particles_out = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Particle Reordered"),
+            size: self.buffer_particles.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let sort = BitonicSort::new(device, &buffer_pairs, self.capacity);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Morton Reorder"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Morton Reorder"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_pairs.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_particles_out.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Morton Reorder"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_morton_keys =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Morton Keys"),
+                layout: Some(&pipeline_layout),
+                module: &module_reorder,
+                entry_point: Some("morton_keys"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_gather_particles =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Gather Particles"),
+                layout: Some(&pipeline_layout),
+                module: &module_reorder,
+                entry_point: Some("gather_particles"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.reorder = Some(Reorder {
+            interval,
+            steps: Cell::new(0),
+            buffer_pairs,
+            buffer_particles_out,
+            sort,
+            bind_group,
+            compute_pipeline_morton_keys,
+            compute_pipeline_gather_particles,
+        });
+    }
+
+    // Follow the particles with the given ids, see gpu2cpu_tracers
+    pub fn attach_tracers(&mut self, device: &wgpu::Device, ids: &[u32]) {
+        let module_tracer = ShaderModuleBuilder::new()
+            .add_module(include_str!("../tracer/tracer.wgsl"))
+            .add_module(include_str!("./tracer.wgsl"))
+            .build(device, Some("Shader Module Tracers"));
+        let buffers = TracerBuffers::new(device, ids);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Tracers"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Tracers"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.buffer_ids.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_samples.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Tracers"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Gather Tracers"),
+            layout: Some(&pipeline_layout),
+            module: &module_tracer,
+            entry_point: Some("gather_tracers"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.tracers = Some(Tracers {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Probes sampled on the grid after every step, see gpu2cpu_probes. Pressure and volume
+    // fraction use the equation of state of `material_idx`.
+    pub fn attach_probes(&mut self, device: &wgpu::Device, probes: Probes, material_idx: u32) {
+        let module_probe = ShaderModuleBuilder::new()
+            .add_module(include_str!("../resample/sample.wgsl"))
+            .add_module(include_str!("../probe/probe.wgsl"))
+            .add_module(include_str!("./probe.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .build(device, Some("Shader Module Sample Probes"));
+        let buffer_probe_material = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Probe Material"),
+            contents: bytemuck::cast_slice(&[material_idx, 0, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Sample Probes"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Sample Probes"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_probe_material.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: probes.buffer_points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: probes.buffer_samples.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Sample Probes"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Sample Probes"),
+            layout: Some(&pipeline_layout),
+            module: &module_probe,
+            entry_point: Some("sample_probe_points"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.probes = Some(ProbeSampling {
+            probes,
+            buffer_probe_material,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Conservation totals reduced on demand, see gpu2cpu_totals
+    pub fn attach_conservation(&mut self, device: &wgpu::Device) {
+        let module_conservation = ShaderModuleBuilder::new()
+            .add_module(include_str!("../conservation/totals.wgsl"))
+            .add_module(include_str!("./conservation.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .build(device, Some("Shader Module Conservation"));
+        let reduction = TotalsReduction::new(device, self.capacity.div_ceil(REDUCE_SIZE));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Conservation"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Conservation"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: reduction.buffer_partials.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Conservation"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Conservation"),
+            layout: Some(&pipeline_layout),
+            module: &module_conservation,
+            entry_point: Some("reduce_conservation"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.conservation = Some(Conservation {
+            reduction,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+}
+
+impl MlsMpmCompute {
+    pub fn cpu2gpu_particles(&self, queue: &wgpu::Queue, particles: &Vec<Particle>) {
+        queue.write_buffer(&self.buffer_particles, 0, bytemuck::cast_slice(&particles));
+    }
+    pub fn cpu2gpu_params(&self, queue: &wgpu::Queue, params: &SimParams) {
+        queue.write_buffer(&self.buffer_params, 0, bytemuck::bytes_of(params));
+        self.dt.set(params.dt);
+    }
+    pub fn cpu2gpu_materials(&self, queue: &wgpu::Queue, materials: &Vec<Material>) {
+        queue.write_buffer(&self.buffer_materials, 0, bytemuck::cast_slice(&materials));
+    }
+    pub fn cpu2gpu_disturbance(&self, queue: &wgpu::Queue, disturbance: &Disturbance) {
+        queue.write_buffer(&self.buffer_disturbance, 0, bytemuck::bytes_of(disturbance));
+        self.gravity.set(disturbance.field);
+    }
+    // Replace the states of the attached bodies
+    pub fn cpu2gpu_rigid_bodies(&self, queue: &wgpu::Queue, bodies: &[RigidBody]) {
+        if let Some(rigid_body_coupling) = &self.rigid_body_coupling {
+            rigid_body_coupling.buffers.cpu2gpu_bodies(queue, bodies);
+            *rigid_body_coupling.bodies.borrow_mut() = bodies.to_vec();
+        }
+    }
+
+    // Mean grid loads on the attached bodies over the `elapsed` time since they were last
+    // advanced or uploaded
+    pub fn gpu2cpu_rigid_body_loads(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) -> Vec<RigidBodyLoad> {
+        match &self.rigid_body_coupling {
+            Some(rigid_body_coupling) => rigid_body_coupling
+                .buffers
+                .gpu2cpu_loads(device, queue, elapsed),
+            None => vec![],
+        }
+    }
+    // Attached bodies at the end of the recorded steps
+    pub fn gpu2cpu_rigid_bodies(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<RigidBody> {
+        match &self.rigid_body_coupling {
+            Some(rigid_body_coupling) => {
+                let mut bodies = rigid_body_coupling.bodies.borrow_mut();
+                rigid_body_coupling
+                    .buffers
+                    .gpu2cpu_bodies(device, queue, &mut bodies);
+                bodies.clone()
+            }
+            None => vec![],
+        }
+    }
+    // Set up the next `n_substeps` steps of the bodies on the GPU, at most SCHEDULE_STEPS with
+    // wavemakers, see RigidBodyBuffers::schedule
+    fn schedule_rigid_bodies(&self, queue: &wgpu::Queue, n_substeps: u32) {
+        let Some(rigid_body_coupling) = &self.rigid_body_coupling else {
+            return;
+        };
+        let time = rigid_body_coupling.time.get();
+        rigid_body_coupling.buffers.schedule(
+            queue,
+            &rigid_body_coupling.bodies.borrow(),
+            &rigid_body_coupling.wavemakers,
+            self.gravity.get(),
+            self.dt.get(),
+            time,
+            n_substeps,
+        );
+        rigid_body_coupling
+            .time
+            .set(time + n_substeps as f64 * self.dt.get() as f64);
+    }
+    // Drive the paddle bodies of the wavemakers, one entry per attached body and None for the
+    // free ones. The wavemaker time restarts at zero with the paddles at their initial pose.
+    pub fn attach_wavemakers(&mut self, queue: &wgpu::Queue, wavemakers: Vec<Option<Wavemaker>>) {
+        let Some(rigid_body_coupling) = &mut self.rigid_body_coupling else {
+            return;
+        };
+        let bodies = rigid_body_coupling.bodies.get_mut();
+        for (idx, wavemaker) in wavemakers.iter().enumerate().take(bodies.len()) {
+            if let Some(wavemaker) = wavemaker {
+                wavemaker.update(&mut bodies[idx], 0.0);
+                rigid_body_coupling.buffers.cpu2gpu_body(queue, bodies, idx);
+            }
+        }
+        rigid_body_coupling.wavemakers = wavemakers;
+        rigid_body_coupling.time.set(0.0);
+    }
+
+    // Alive particle count, which changes with flow boundaries
+    // Parameters with the current alive particle count
+    pub fn gpu2cpu_params(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SimParams {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Simulation Parameters"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_params,
+            0,
+            &self.staging_buffer_params,
+            0,
+            self.buffer_params.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_params.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let params: SimParams = bytemuck::pod_read_unaligned(&output_data);
+        drop(output_data);
+        self.staging_buffer_params.unmap();
+        params
+    }
+    // Alive particle count, which changes with flow boundaries
+    pub fn gpu2cpu_num_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32 {
+        self.gpu2cpu_params(device, queue).num_particles
+    }
+
+    // Alive particles only
+    pub fn gpu2cpu_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Particle> {
+        let num_particles = self.gpu2cpu_num_particles(device, queue) as usize;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Particles"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_particles,
+            0,
+            &self.staging_buffer_particles,
+            0,
+            self.buffer_particles.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_particles.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        // Read data from buffer
+        let output_data = buffer_slice.get_mapped_range();
+        // Convert to structure
+        let particles_out: Vec<Particle> =
+            bytemuck::cast_slice(&output_data)[..num_particles].to_vec();
+        // Drop output and unmap staging buffer
+        drop(output_data);
+        self.staging_buffer_particles.unmap();
+        return particles_out;
+    }
+
+    // Whole material table, slots past the uploaded materials are zeroed
+    pub fn gpu2cpu_materials(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Material> {
+        gpu2cpu_buffer(device, queue, &self.buffer_materials)
+    }
+    pub fn gpu2cpu_disturbance(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Disturbance {
+        gpu2cpu_buffer(device, queue, &self.buffer_disturbance)[0]
+    }
+
+    // Positions and velocities of the followed particles in ascending id order, in world
+    // coordinates. Particles removed by an outlet are returned with `alive` set to zero.
+    pub fn gpu2cpu_tracers(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<TracerSample> {
+        let Some(tracers) = &self.tracers else {
+            return vec![];
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Gather Tracers"),
+        });
+        encoder.clear_buffer(&tracers.buffers.buffer_samples, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Gather Tracers"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&tracers.compute_pipeline);
+        compute_pass.set_bind_group(0, &tracers.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        tracers.buffers.gpu2cpu_samples(device, queue)
+    }
+    // Probe values of every step since the last call, one row per step
+    pub fn gpu2cpu_probes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>> {
+        match &self.probes {
+            Some(probe_sampling) => probe_sampling.probes.gpu2cpu_values(device, queue),
+            None => vec![],
+        }
+    }
+    // Mass, momentum, energy and extrema over the alive particles, None without
+    // attach_conservation
+    pub fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Totals> {
+        let conservation = self.conservation.as_ref()?;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Conservation"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Conservation"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&conservation.compute_pipeline);
+        compute_pass.set_bind_group(0, &conservation.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(REDUCE_SIZE), 1, 1);
+        conservation.reduction.encode(&mut compute_pass);
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        Some(conservation.reduction.gpu2cpu_totals(device, queue))
+    }
+    pub fn gpu2cpu_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Grid> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Grid"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_grid,
+            0,
+            &self.staging_buffer_grid,
+            0,
+            self.buffer_grid.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_grid.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        // Read data from buffer
+        let output_data = buffer_slice.get_mapped_range();
+        // Convert to structure
+        let grid_out: Vec<Grid> = bytemuck::cast_slice(&output_data).to_vec();
+        // Drop output and unmap staging buffer
+        drop(output_data);
+        self.staging_buffer_grid.unmap();
+        return grid_out;
+    }
+
+    pub fn compute_particle_to_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Particle to Grid"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Particle to Grid"),
             timestamp_writes: None,
         });
         // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_grid_reset);
-        compute_pass.set_bind_group(0, &self.bind_group_grid_update, &[]);
-        compute_pass.dispatch_workgroups((self.num_nodes + 255) / 256, 1, 1);
+        self.encode_particle_to_grid(&mut compute_pass);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+
+    pub fn compute_particle_constitutive_model(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Particle Constitutive Model"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Particle Constitutive Model"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        self.encode_particle_constitutive_model(&mut compute_pass);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+
+    pub fn compute_grid_to_particle(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Grid to Particle"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Grid to Particle"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        self.encode_grid_to_particle(&mut compute_pass);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+
+    pub fn compute_grid_update(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Grid Update"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Grid Update"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        self.encode_grid_update(&mut compute_pass);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+
+    // Sort the particles along a Z-order curve of their cells so that neighbors are close in
+    // memory. Particle order changes, use the particle ids to track particles.
+    pub fn compute_morton_reorder(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(reorder) = &self.reorder else {
+            return;
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Morton Reorder"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Morton Reorder"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&reorder.compute_pipeline_morton_keys);
+        compute_pass.set_bind_group(0, &reorder.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        reorder.sort.encode(&mut compute_pass);
+        compute_pass.set_pipeline(&reorder.compute_pipeline_gather_particles);
+        compute_pass.set_bind_group(0, &reorder.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Swap in the reordered particles
+        encoder.copy_buffer_to_buffer(
+            &reorder.buffer_particles_out,
+            0,
+            &self.buffer_particles,
+            0,
+            self.buffer_particles.size(),
+        );
+        queue.submit([encoder.finish()]);
+        reorder.steps.set(0);
+    }
+    pub fn compute_grid_reset(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Grid Reset"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Grid Reset"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        self.encode_grid_reset(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
         let command_buffer = encoder.finish();
         queue.submit([command_buffer]);
     }
+
+    // Delete particles in outlets then append the particles emitted by inlets up to the time of
+    // the steps recorded so far, before `n_substeps` more are recorded
+    fn advance_flow_boundaries(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        let Some(flow_boundaries) = &self.flow_boundaries else {
+            return;
+        };
+        let time = flow_boundaries.time.get();
+        flow_boundaries
+            .time
+            .set(time + n_substeps as f32 * self.dt.get());
+        if let Some(outflow) = &flow_boundaries.outflow {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Outflow"),
+            });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass Outflow"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&outflow.compute_pipeline_mark_outflow);
+            compute_pass.set_bind_group(0, &outflow.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            outflow.prefix_sum.encode(&mut compute_pass);
+            compute_pass.set_pipeline(&outflow.compute_pipeline_compact_particles);
+            compute_pass.set_bind_group(0, &outflow.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            // Drop compute pass to gain access to encoder again
+            drop(compute_pass);
+            // Swap in the compacted particles and their count
+            encoder.copy_buffer_to_buffer(
+                &outflow.buffer_particles_out,
+                0,
+                &self.buffer_particles,
+                0,
+                self.buffer_particles.size(),
+            );
+            encoder.copy_buffer_to_buffer(
+                outflow.prefix_sum.buffer_total(),
+                0,
+                &self.buffer_params,
+                std::mem::offset_of!(SimParams, num_particles) as u64,
+                std::mem::size_of::<u32>() as u64,
+            );
+            queue.submit([encoder.finish()]);
+        }
+        let emitted: Vec<EmittedParticle> = flow_boundaries
+            .inlets
+            .borrow_mut()
+            .iter_mut()
+            .flat_map(|inlet| inlet.emit(time))
+            .collect();
+        for chunk in emitted.chunks(flow_boundaries.chunk_len) {
+            let emission = Emission {
+                num_emitted: chunk.len() as u32,
+                first_id: self.next_id.get(),
+                _padding: [0; 2],
+            };
+            self.next_id.set(emission.num_emitted + emission.first_id);
+            queue.write_buffer(
+                &flow_boundaries.buffer_emitted,
+                0,
+                bytemuck::cast_slice(chunk),
+            );
+            queue.write_buffer(
+                &flow_boundaries.buffer_emission,
+                0,
+                bytemuck::bytes_of(&emission),
+            );
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Inflow"),
+            });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass Inflow"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&flow_boundaries.compute_pipeline_emit_particles);
+            compute_pass.set_bind_group(0, &flow_boundaries.bind_group_inflow, &[]);
+            compute_pass.dispatch_workgroups(emission.num_emitted.div_ceil(256), 1, 1);
+            compute_pass.set_pipeline(&flow_boundaries.compute_pipeline_commit_emitted);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+            drop(compute_pass);
+            queue.submit([encoder.finish()]);
+        }
+    }
+}
+
+impl MlsMpmCompute {
+    // Advance `n_substeps` time steps in a single submission, see encode_step, or in submissions
+    // of at most SCHEDULE_STEPS steps with wavemakers
+    pub fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        let wavemakers = self
+            .rigid_body_coupling
+            .as_ref()
+            .is_some_and(|coupling| coupling.wavemakers.iter().any(Option::is_some));
+        let mut remaining = n_substeps;
+        while remaining > 0 {
+            // The paddle poses are scheduled for each submission, and a due reorder runs between
+            // two of them
+            let mut n_substeps = remaining;
+            if wavemakers {
+                n_substeps = n_substeps.min(SCHEDULE_STEPS);
+            }
+            if let Some(steps) = self.reorder.as_ref().and_then(Reorder::steps_to_next) {
+                n_substeps = n_substeps.min(steps);
+            }
+            remaining -= n_substeps;
+            self.prepare_step(device, queue, n_substeps);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Step"),
+            });
+            self.encode_step(&mut encoder, n_substeps);
+            queue.submit([encoder.finish()]);
+        }
+    }
+    // CPU side of the next `n_substeps` steps, to run before they are recorded with encode_step:
+    // reorder the particles when due, upload the rigid body schedule, then delete and emit the
+    // particles of the flow boundaries. These run in submissions of their own, the flow
+    // boundaries thus act once per submission.
+    pub fn prepare_step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        if let Some(reorder) = &self.reorder {
+            if reorder.interval > 0 && reorder.steps.get() >= reorder.interval {
+                self.compute_morton_reorder(device, queue);
+            }
+            reorder.steps.set(reorder.steps.get() + n_substeps);
+        }
+        self.schedule_rigid_bodies(queue, n_substeps);
+        self.advance_flow_boundaries(device, queue, n_substeps);
+    }
+    // Record `n_substeps` time steps into `encoder`: grid reset, particle to grid, constitutive
+    // model, grid update and grid to particle. Callers can record their own passes after it, e.g.
+    // the particle instances of the renderer. Rigid bodies are integrated after each step. Only
+    // records, see prepare_step for what the steps need from the CPU.
+    pub fn encode_step(&self, encoder: &mut wgpu::CommandEncoder, n_substeps: u32) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Step"),
+            timestamp_writes: None,
+        });
+        for _ in 0..n_substeps {
+            self.encode_grid_reset(&mut compute_pass);
+            self.encode_particle_to_grid(&mut compute_pass);
+            self.encode_particle_constitutive_model(&mut compute_pass);
+            self.encode_grid_update(&mut compute_pass);
+            self.encode_grid_to_particle(&mut compute_pass);
+            self.encode_probes(&mut compute_pass);
+        }
+    }
+
+    // Passes shared by the compute_* methods and step
+    fn encode_grid_reset(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_grid_reset);
+        compute_pass.set_bind_group(0, &self.bind_group_grid_update, &[]);
+        compute_pass.dispatch_workgroups(self.num_nodes.div_ceil(256), 1, 1);
+    }
+    fn encode_particle_to_grid(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_particle_to_grid);
+        compute_pass.set_bind_group(0, &self.bind_group_particle_to_grid, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_particle_constitutive_model(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_particle_constitutive_model);
+        compute_pass.set_bind_group(0, &self.bind_group_particle_constitutive_model, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_grid_update(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_grid_update);
+        compute_pass.set_bind_group(0, &self.bind_group_grid_update, &[]);
+        compute_pass.dispatch_workgroups(self.num_nodes.div_ceil(256), 1, 1);
+        // Project node velocities out of obstacles
+        if let Some(obstacle_projection) = &self.obstacle_projection {
+            compute_pass.set_pipeline(&obstacle_projection.compute_pipeline);
+            compute_pass.set_bind_group(0, &obstacle_projection.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.num_nodes.div_ceil(256), 1, 1);
+        }
+        // Exchange momentum with rigid bodies and close the impulses of the step
+        if let Some(rigid_body_coupling) = &self.rigid_body_coupling {
+            compute_pass.set_pipeline(&rigid_body_coupling.compute_pipeline);
+            compute_pass.set_bind_group(0, &rigid_body_coupling.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.num_nodes.div_ceil(256), 1, 1);
+            rigid_body_coupling.buffers.encode_flush(compute_pass);
+        }
+        // Absorb outgoing waves
+        if let Some(damping) = &self.damping {
+            compute_pass.set_pipeline(&damping.compute_pipeline);
+            compute_pass.set_bind_group(0, &damping.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.num_nodes.div_ceil(256), 1, 1);
+        }
+    }
+    fn encode_grid_to_particle(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_grid_to_particle);
+        compute_pass.set_bind_group(0, &self.bind_group_grid_to_particle, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_probes(&self, compute_pass: &mut wgpu::ComputePass) {
+        let Some(probe_sampling) = &self.probes else {
+            return;
+        };
+        compute_pass.set_pipeline(&probe_sampling.compute_pipeline);
+        compute_pass.set_bind_group(0, &probe_sampling.bind_group, &[]);
+        let num_points = probe_sampling.probes.num_points();
+        compute_pass.dispatch_workgroups(num_points.div_ceil(64), 1, 1);
+        probe_sampling.probes.encode(compute_pass);
+    }
+}
+
+impl Scene for MlsMpm {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        let compute = pollster::block_on(MlsMpmCompute::new(device, &self.params));
+        compute.cpu2gpu_params(queue, &self.params);
+        compute.cpu2gpu_disturbance(queue, &self.disturbance);
+        compute.cpu2gpu_particles(queue, &self.particles);
+        compute.cpu2gpu_materials(queue, &self.materials);
+        Box::new(compute)
+    }
+    fn dt(&self) -> f32 {
+        self.params.dt
+    }
+}
+
+impl Simulation for MlsMpmCompute {
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    fn num_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32 {
+        self.gpu2cpu_num_particles(device, queue)
+    }
+    fn prepare_step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        MlsMpmCompute::prepare_step(self, device, queue, n_substeps);
+    }
+    fn encode_step(&self, encoder: &mut wgpu::CommandEncoder, n_substeps: u32) {
+        MlsMpmCompute::encode_step(self, encoder, n_substeps);
+    }
+    fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        MlsMpmCompute::step(self, device, queue, n_substeps);
+    }
+    // Positions and velocities are scaled from the unit domain
+    fn gpu2cpu_particle_data(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> ParticleData {
+        let scale_distance = self.gpu2cpu_params(device, queue).scale_distance;
+        let particles = self.gpu2cpu_particles(device, queue);
+        ParticleData {
+            ids: particles.iter().map(|particle| particle.id).collect(),
+            positions: particles
+                .iter()
+                .map(|particle| particle.position.map(|x| x * scale_distance))
+                .collect(),
+            velocities: particles
+                .iter()
+                .map(|particle| particle.velocity.map(|v| v * scale_distance))
+                .collect(),
+            material_idx: particles
+                .iter()
+                .map(|particle| particle.material_idx)
+                .collect(),
+        }
+    }
+    fn instance_pass(
+        &self,
+        device: &wgpu::Device,
+        buffer_instances: &wgpu::Buffer,
+    ) -> InstancePass {
+        let module_instance = ShaderModuleBuilder::new()
+            .add_module(include_str!("./particle_to_instance.wgsl"))
+            .build(device, Some("Shader Module Particle Instance"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Particle Instance"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Particle Instance"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_instances.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Particle Instance"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Particle Instance"),
+            layout: Some(&pipeline_layout),
+            module: &module_instance,
+            entry_point: Some("particle_to_instance"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        InstancePass {
+            bind_group,
+            compute_pipeline,
+            num_workgroups: self.capacity.div_ceil(256),
+        }
+    }
+    fn checkpointing(&self) -> Option<&dyn Checkpointing> {
+        Some(self)
+    }
+    fn conservation(&self) -> Option<&dyn ConservationTotals> {
+        Some(self)
+    }
+    fn conservation_mut(&mut self) -> Option<&mut dyn ConservationTotals> {
+        Some(self)
+    }
+    fn probes(&self) -> Option<&dyn ProbeRecords> {
+        Some(self)
+    }
+    // The grid exchanges momentum with the boundaries, loads are only measured by the SPH solver
+    fn rigid_bodies(&self) -> Option<&dyn RigidBodyRecords> {
+        Some(self)
+    }
+}
+
+impl Checkpointing for MlsMpmCompute {
+    fn gpu2cpu_state(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SolverState {
+        SolverState::MlsMpm(MlsMpm {
+            params: self.gpu2cpu_params(device, queue),
+            disturbance: self.gpu2cpu_disturbance(device, queue),
+            particles: self.gpu2cpu_particles(device, queue),
+            materials: self.gpu2cpu_materials(device, queue),
+        })
+    }
+    fn cpu2gpu_state(&self, queue: &wgpu::Queue, state: &SolverState) {
+        let SolverState::MlsMpm(mls_mpm) = state else {
+            panic!(
+                "MLS-MPM solver restarted from {} state",
+                state.solver_name()
+            );
+        };
+        self.cpu2gpu_params(queue, &mls_mpm.params);
+        self.cpu2gpu_disturbance(queue, &mls_mpm.disturbance);
+        self.cpu2gpu_materials(queue, &mls_mpm.materials);
+        self.cpu2gpu_particles(queue, &mls_mpm.particles);
+    }
+}
+
+impl ConservationTotals for MlsMpmCompute {
+    fn attach_conservation(&mut self, device: &wgpu::Device) {
+        MlsMpmCompute::attach_conservation(self, device);
+    }
+    fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Totals> {
+        MlsMpmCompute::gpu2cpu_totals(self, device, queue)
+    }
+}
+
+impl ProbeRecords for MlsMpmCompute {
+    fn probe_names(&self) -> Vec<String> {
+        match &self.probes {
+            Some(probe_sampling) => probe_sampling.probes.names().to_vec(),
+            None => vec![],
+        }
+    }
+    fn gpu2cpu_probes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>> {
+        MlsMpmCompute::gpu2cpu_probes(self, device, queue)
+    }
+}
+
+impl RigidBodyRecords for MlsMpmCompute {
+    // Positions and velocities are scaled from the unit domain
+    fn gpu2cpu_rigid_body_motion(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<RigidBodyMotion> {
+        let scale_distance = self.gpu2cpu_params(device, queue).scale_distance;
+        self.gpu2cpu_rigid_bodies(device, queue)
+            .iter()
+            .map(|body| body.motion(scale_distance))
+            .collect()
+    }
 }
@@ -0,0 +1,51 @@
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f,
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read> outlets: array<Outlet>;
+@group(0) @binding(3) var<storage, read_write> keep: array<u32>;
+@group(0) @binding(4) var<storage, read_write> particles_out: array<Particle>;
+
+fn is_kept(idx: u32) -> bool {
+    if (idx >= params.num_particles) {
+        return false;
+    }
+    return !in_outlet(particles[idx].position);
+}
+
+// Flag surviving particles over the whole capacity, followed by a prefix sum of the flags
+@compute @workgroup_size(256)
+fn mark_outflow(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= arrayLength(&keep)) {
+        return;
+    }
+    keep[idx] = select(0u, 1u, is_kept(idx));
+}
+
+// Scatter surviving particles to their scanned slot, preserving order
+@compute @workgroup_size(256)
+fn compact_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (!is_kept(idx)) {
+        return;
+    }
+    particles_out[keep[idx]] = particles[idx];
+}
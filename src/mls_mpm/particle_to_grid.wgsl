@@ -11,6 +11,8 @@ struct Particle {
     velocity: vec3f,
     material_idx: u32,
     C: mat3x3f, // MLS-MPM Affine Matrix
+    id: u32,
+    tag: u32,
 }
 
 struct SimParams {
@@ -19,7 +21,7 @@ struct SimParams {
     scale_distance: f32,
     num_particles: u32,
     num_nodes: u32,
-    _padding: u32,
+    periodic_axes: u32, // bit per periodic axis
 }
 
 @group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
@@ -0,0 +1,56 @@
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f, // MLS-MPM Affine Matrix
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+struct Material {
+    color: vec4f,
+    eos_density: f32, // reference density
+    eos_threshold: f32, // negative pressure threshold
+    eos_stiffness: f32, // stiffness coefficient
+    eos_n: f32, // exponent 
+    dynamic_viscosity: f32, // viscosity coefficient
+    rigid_flag: u32,
+}
+
+struct Instance {
+    position: vec3f,
+    color: vec4f,
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> materials: array<Material>;
+@group(0) @binding(2) var<uniform> params: SimParams;
+@group(0) @binding(3) var<storage, read_write> instance: array<Instance>;
+
+@compute @workgroup_size(256)
+fn particle_to_instance(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= arrayLength(&instance)) {
+        return;
+    }
+    // Slots freed by outlets are moved beyond the far plane
+    if (idx >= params.num_particles) {
+        instance[idx].position = vec3f(1.0e30);
+        return;
+    }
+    let particle = particles[idx];
+    // particles coordinate frame is 0,0,0 in lower left corner
+    // shift to 0,0,0 in center of screen
+    instance[idx].position = (particle.position - 0.5) * params.scale_distance;
+    instance[idx].color = materials[particle.material_idx].color;
+}
@@ -0,0 +1,87 @@
+struct Grid {
+    vx: i32,
+    vy: i32,
+    vz: i32,
+    mass: i32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+struct Material {
+    color: vec4f,
+    eos_density: f32, // reference density
+    eos_threshold: f32, // negative pressure threshold
+    eos_stiffness: f32, // stiffness coefficient
+    eos_n: f32, // exponent 
+    dynamic_viscosity: f32, // viscosity coefficient
+    rigid_flag: u32,
+}
+
+// Material giving the pressure and volume fraction of the probes
+struct ProbeMaterial {
+    material_idx: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0) var<storage, read> grid: array<Grid>;
+@group(0) @binding(1) var<storage, read> materials: array<Material>;
+@group(0) @binding(2) var<uniform> params: SimParams;
+@group(0) @binding(3) var<uniform> probe_material: ProbeMaterial;
+@group(0) @binding(4) var<storage, read> probe_points: array<ProbePoint>;
+@group(0) @binding(5) var<storage, read_write> probe_samples: array<FieldSample>;
+
+// Quadratic interpolation of the grid of the last step at each probe point. The density is the
+// interpolated node mass, the pressure follows from the material equation of state and the
+// velocity is averaged over the nodes holding mass.
+@compute @workgroup_size(64)
+fn sample_probe_points(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&probe_points)) {
+        return;
+    }
+    let grid_res = f32(params.grid_resolution);
+    let position = probe_points[index].position / params.scale_distance * grid_res;
+    let node_coord: vec3f = floor(position);
+    let node_dist: vec3f = position - node_coord - 0.5;
+    let weights = quadratic_weights(node_dist);
+    var density = 0.0;
+    var velocity = vec3f(0.0);
+    var velocity_weight = 0.0;
+    for (var gx = 0u; gx < 3; gx++) {
+        for (var gy = 0u; gy < 3; gy++) {
+            for (var gz = 0u; gz < 3; gz++) {
+                let weight = weights[gx].x * weights[gy].y * weights[gz].z;
+                let neighbor_coord = vec3f(
+                    node_coord.x + f32(gx) - 1.0,
+                    node_coord.y + f32(gy) - 1.0,
+                    node_coord.z + f32(gz) - 1.0);
+                let node = grid[get_node_index(neighbor_coord, params.grid_resolution)];
+                if (node.mass <= 0) {
+                    continue;
+                }
+                density += i32_to_f32(node.mass) * weight;
+                velocity += vec3f(i32_to_f32(node.vx), i32_to_f32(node.vy), i32_to_f32(node.vz)) * weight;
+                velocity_weight += weight;
+            }
+        }
+    }
+    var sample = FieldSample();
+    if (velocity_weight > 0.0) {
+        let material = materials[probe_material.material_idx];
+        sample.velocity = velocity / velocity_weight;
+        sample.density = density;
+        sample.pressure = max(-material.eos_threshold,
+            material.eos_stiffness * (pow(density / material.eos_density, material.eos_n) - 1.0));
+        sample.volume_fraction = density / material.eos_density;
+    }
+    probe_samples[index] = sample;
+}
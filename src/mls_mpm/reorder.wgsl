@@ -0,0 +1,47 @@
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f,
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read_write> pairs: array<vec2u>;
+@group(0) @binding(3) var<storage, read_write> particles_out: array<Particle>;
+
+// Z-order key of the grid cell, slots past the alive particles sort last
+@compute @workgroup_size(256)
+fn morton_keys(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= arrayLength(&pairs)) {
+        return;
+    }
+    var key = 4294967295u;
+    if (idx < params.num_particles) {
+        let position = clamp(particles[idx].position, vec3f(0.0), vec3f(1.0));
+        key = morton_key(vec3u(position * f32(params.grid_resolution)));
+    }
+    pairs[idx] = vec2u(idx, key);
+}
+
+@compute @workgroup_size(256)
+fn gather_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= params.num_particles) {
+        return;
+    }
+    particles_out[idx] = particles[pairs[idx].x];
+}
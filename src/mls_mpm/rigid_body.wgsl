@@ -0,0 +1,67 @@
+struct Grid {
+    vx: i32,
+    vy: i32,
+    vz: i32,
+    mass: i32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read_write> grid: array<Grid>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read> bodies: array<RigidBodyState>;
+@group(0) @binding(3) var<storage, read_write> impulses: array<atomic<i32>>;
+@group(0) @binding(4) var<storage, read> obstacles: array<SdfObstacle>;
+@group(0) @binding(5) var<storage, read> sdf: array<f32>;
+
+@compute @workgroup_size(256)
+fn grid_rigid_body_coupling(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= params.num_nodes) {
+        return;
+    }
+    let node = grid[idx];
+    if (node.mass <= 0) {
+        return;
+    }
+    // Node position in the normalized simulation frame
+    let grid_res = params.grid_resolution;
+    let x = idx / grid_res / grid_res;
+    let y = (idx / grid_res) % grid_res;
+    let z = idx % grid_res;
+    let position = (vec3f(f32(x), f32(y), f32(z)) + 0.5) / f32(grid_res);
+    let mass = i32_to_f32(node.mass);
+    // Nodes within a cell of the surface carry the fluid in contact, the ones inside the body
+    // only get the tails of the particle weights
+    let band = 1.0 / f32(grid_res);
+    // Grid holds velocity after the grid update
+    var velocity = vec3f(i32_to_f32(node.vx), i32_to_f32(node.vy), i32_to_f32(node.vz));
+    var collided = false;
+    for (var body_idx = 0u; body_idx < arrayLength(&bodies); body_idx++) {
+        let body = bodies[body_idx];
+        let local = quat_rotate_inverse(body.orientation, position - body.position);
+        if (body_sdf(body, local) >= band) {
+            continue;
+        }
+        // Project node velocity in the frame of the body surface
+        let normal = quat_rotate(body.orientation, body_normal(body, local));
+        let surface_velocity = body_point_velocity(body, position);
+        let velocity_new = surface_velocity + collision_response(velocity - surface_velocity, normal, body.friction, body.restitution);
+        // Equal and opposite impulse on the body
+        accumulate_body_impulse(body_idx, body, position, -mass * (velocity_new - velocity));
+        velocity = velocity_new;
+        collided = true;
+    }
+    if (collided) {
+        grid[idx].vx = f32_to_i32(velocity.x);
+        grid[idx].vy = f32_to_i32(velocity.y);
+        grid[idx].vz = f32_to_i32(velocity.z);
+    }
+}
@@ -0,0 +1,44 @@
+
+struct Particle {
+    position: vec3f,
+    mass: f32,
+    velocity: vec3f,
+    material_idx: u32,
+    C: mat3x3f, // MLS-MPM Affine Matrix
+    id: u32,
+    tag: u32,
+}
+
+struct SimParams {
+    grid_resolution: u32,
+    dt: f32,
+    scale_distance: f32,
+    num_particles: u32,
+    num_nodes: u32,
+    periodic_axes: u32, // bit per periodic axis
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: SimParams;
+@group(0) @binding(2) var<storage, read> tracer_ids: array<u32>;
+@group(0) @binding(3) var<storage, read_write> samples: array<TracerSample>;
+
+// Scatter the alive tracers into their slots, in world coordinates
+@compute @workgroup_size(256)
+fn gather_tracers(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= params.num_particles) {
+        return;
+    }
+    let particle = particles[idx];
+    let slot = tracer_slot(particle.id);
+    if (slot == TRACER_NONE) {
+        return;
+    }
+    samples[slot] = TracerSample(
+        particle.position * params.scale_distance,
+        particle.id,
+        particle.velocity * params.scale_distance,
+        1u,
+    );
+}
@@ -15,11 +15,27 @@ fn i32_to_f32(integer: i32) -> f32 {
     return f32(integer) * 1.0e-5;
 }
 
+// Remainder in [0, n), the GLSL backend leaves `%` undefined for negative operands
+fn modulo(a: i32, n: i32) -> i32 {
+    let r = abs(a) % n;
+    return select(r, (n - r) % n, a < 0);
+}
+
+fn is_periodic(axis: u32) -> bool {
+    return (params.periodic_axes & (1u << axis)) != 0u;
+}
+
+// Wrap node coordinates on periodic axes and clamp them on the others
 fn get_node_index(coord: vec3f, grid_resolution: u32) -> u32 {
     let res = i32(grid_resolution);
-    let x = clamp(i32(coord.x), 0i, res - 1i);
-    let y = clamp(i32(coord.y), 0i, res - 1i);
-    let z = clamp(i32(coord.z), 0i, res - 1i);
-    return u32(x * res * res + y * res + z);
+    var node = vec3i(floor(coord));
+    for (var axis = 0u; axis < 3u; axis++) {
+        if (is_periodic(axis)) {
+            node[axis] = modulo(node[axis], res);
+        } else {
+            node[axis] = clamp(node[axis], 0i, res - 1i);
+        }
+    }
+    return u32(node.x * res * res + node.y * res + node.z);
 }
 
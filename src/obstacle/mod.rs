@@ -0,0 +1,528 @@
+use anyhow::*;
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SdfObstacle {
+    pub origin: [f32; 3],
+    pub cell_size: f32,
+    pub dims: [u32; 3],
+    pub offset: u32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub _padding: [f32; 2],
+    // 48 bytes
+}
+
+pub struct TriangleMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+#[derive(Clone)]
+pub struct SignedDistanceField {
+    pub origin: [f32; 3],
+    pub cell_size: f32,
+    pub dims: [u32; 3],
+    // Values stored on lattice nodes, x fastest
+    pub values: Vec<f32>,
+}
+
+pub struct Obstacle {
+    pub sdf: SignedDistanceField,
+    pub friction: f32,
+    pub restitution: f32,
+}
+
+pub struct ObstacleBuffers {
+    pub num_obstacles: u32,
+    pub buffer_obstacles: wgpu::Buffer,
+    pub buffer_sdf: wgpu::Buffer,
+}
+
+impl TriangleMesh {
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (models, _) = tobj::load_obj(
+            path.as_ref(),
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ignore_points: true,
+                ignore_lines: true,
+            },
+        )
+        .with_context(|| format!("failed to load obj {:?}", path.as_ref()))?;
+        // Merge all models into a single triangle soup
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+        for model in models {
+            let base = vertices.len() as u32;
+            let mesh = model.mesh;
+            for p in mesh.positions.chunks_exact(3) {
+                vertices.push([p[0], p[1], p[2]]);
+            }
+            for t in mesh.indices.chunks_exact(3) {
+                triangles.push([base + t[0], base + t[1], base + t[2]]);
+            }
+        }
+        if triangles.is_empty() {
+            bail!("obj {:?} contains no triangles", path.as_ref());
+        }
+        Ok(TriangleMesh {
+            vertices,
+            triangles,
+        })
+    }
+
+    // Scale about the origin then translate, used to place CAD exports in the domain
+    pub fn transform(&mut self, scale: f32, translation: [f32; 3]) {
+        for v in self.vertices.iter_mut() {
+            for i in 0..3 {
+                v[i] = v[i] * scale + translation[i];
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in &self.vertices {
+            for i in 0..3 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+        (min, max)
+    }
+
+    fn triangle(&self, t: usize) -> [[f64; 3]; 3] {
+        let [a, b, c] = self.triangles[t];
+        [
+            to_f64(self.vertices[a as usize]),
+            to_f64(self.vertices[b as usize]),
+            to_f64(self.vertices[c as usize]),
+        ]
+    }
+}
+
+impl SignedDistanceField {
+    // Voxelise a closed triangle mesh following Bridson's makelevelset3: exact distances in a
+    // narrow band around each triangle, fast sweeping for the rest, and the sign from the parity
+    // of ray crossings along x.
+    pub fn from_mesh(mesh: &TriangleMesh, cell_size: f32, padding: u32) -> Self {
+        let (min, max) = mesh.bounds();
+        let dx = cell_size as f64;
+        let origin = [
+            min[0] - padding as f32 * cell_size,
+            min[1] - padding as f32 * cell_size,
+            min[2] - padding as f32 * cell_size,
+        ];
+        let mut dims = [0u32; 3];
+        for i in 0..3 {
+            dims[i] = ((max[i] - min[i]) / cell_size).ceil() as u32 + 1 + 2 * padding;
+        }
+        let [ni, nj, nk] = [dims[0] as usize, dims[1] as usize, dims[2] as usize];
+        let index = |i: usize, j: usize, k: usize| i + ni * (j + nj * k);
+        let node = |i: usize, j: usize, k: usize| {
+            [
+                origin[0] as f64 + i as f64 * dx,
+                origin[1] as f64 + j as f64 * dx,
+                origin[2] as f64 + k as f64 * dx,
+            ]
+        };
+
+        let mut phi = vec![(ni + nj + nk) as f64 * dx; ni * nj * nk];
+        let mut closest = vec![u32::MAX; ni * nj * nk];
+        let mut crossings = vec![0i32; ni * nj * nk];
+        let exact_band = 1i64;
+        for t in 0..mesh.triangles.len() {
+            let tri = mesh.triangle(t);
+            // Triangle in grid coordinates
+            let g: Vec<[f64; 3]> = tri
+                .iter()
+                .map(|p| {
+                    [
+                        (p[0] - origin[0] as f64) / dx,
+                        (p[1] - origin[1] as f64) / dx,
+                        (p[2] - origin[2] as f64) / dx,
+                    ]
+                })
+                .collect();
+            let lo = |a: usize, n: usize| {
+                let m = g[0][a].min(g[1][a]).min(g[2][a]);
+                (m.floor() as i64 - exact_band).clamp(0, n as i64 - 1) as usize
+            };
+            let hi = |a: usize, n: usize| {
+                let m = g[0][a].max(g[1][a]).max(g[2][a]);
+                (m.ceil() as i64 + exact_band).clamp(0, n as i64 - 1) as usize
+            };
+            // Exact distances in a band around the triangle
+            for k in lo(2, nk)..=hi(2, nk) {
+                for j in lo(1, nj)..=hi(1, nj) {
+                    for i in lo(0, ni)..=hi(0, ni) {
+                        let d = point_triangle_distance(node(i, j, k), tri);
+                        if d < phi[index(i, j, k)] {
+                            phi[index(i, j, k)] = d;
+                            closest[index(i, j, k)] = t as u32;
+                        }
+                    }
+                }
+            }
+            // Count crossings of rays cast along +x through each (j, k) node
+            let j0 = (g[0][1].min(g[1][1]).min(g[2][1]).ceil() as i64).clamp(0, nj as i64 - 1);
+            let j1 = (g[0][1].max(g[1][1]).max(g[2][1]).floor() as i64).clamp(0, nj as i64 - 1);
+            let k0 = (g[0][2].min(g[1][2]).min(g[2][2]).ceil() as i64).clamp(0, nk as i64 - 1);
+            let k1 = (g[0][2].max(g[1][2]).max(g[2][2]).floor() as i64).clamp(0, nk as i64 - 1);
+            for k in k0..=k1 {
+                for j in j0..=j1 {
+                    let (jf, kf) = (j as f64, k as f64);
+                    if let Some((a, b, c)) = point_in_triangle_2d(
+                        jf, kf, g[0][1], g[0][2], g[1][1], g[1][2], g[2][1], g[2][2],
+                    ) {
+                        let fi = a * g[0][0] + b * g[1][0] + c * g[2][0];
+                        let i_interval = fi.ceil() as i64;
+                        if i_interval < 0 {
+                            crossings[index(0, j as usize, k as usize)] += 1;
+                        } else if (i_interval as usize) < ni {
+                            crossings[index(i_interval as usize, j as usize, k as usize)] += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Propagate closest triangles to the rest of the grid
+        for _pass in 0..2 {
+            for &(di, dj, dk) in &[
+                (1, 1, 1),
+                (-1, -1, -1),
+                (1, 1, -1),
+                (-1, -1, 1),
+                (1, -1, 1),
+                (-1, 1, -1),
+                (1, -1, -1),
+                (-1, 1, 1),
+            ] {
+                let range = |d: i64, n: usize| -> Vec<usize> {
+                    if d > 0 {
+                        (1..n).collect()
+                    } else {
+                        (0..n.saturating_sub(1)).rev().collect()
+                    }
+                };
+                for k in range(dk, nk) {
+                    for j in range(dj, nj) {
+                        for i in range(di, ni) {
+                            let gx = node(i, j, k);
+                            let (pi, pj, pk) = (
+                                (i as i64 - di) as usize,
+                                (j as i64 - dj) as usize,
+                                (k as i64 - dk) as usize,
+                            );
+                            for (a, b, c) in [
+                                (pi, j, k),
+                                (i, pj, k),
+                                (i, j, pk),
+                                (pi, pj, k),
+                                (pi, j, pk),
+                                (i, pj, pk),
+                                (pi, pj, pk),
+                            ] {
+                                let t = closest[index(a, b, c)];
+                                if t == u32::MAX {
+                                    continue;
+                                }
+                                let d = point_triangle_distance(gx, mesh.triangle(t as usize));
+                                if d < phi[index(i, j, k)] {
+                                    phi[index(i, j, k)] = d;
+                                    closest[index(i, j, k)] = t;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Odd crossing count along x means the node is inside the mesh
+        for k in 0..nk {
+            for j in 0..nj {
+                let mut total = 0;
+                for i in 0..ni {
+                    total += crossings[index(i, j, k)];
+                    if total % 2 == 1 {
+                        phi[index(i, j, k)] = -phi[index(i, j, k)];
+                    }
+                }
+            }
+        }
+
+        SignedDistanceField {
+            origin,
+            cell_size,
+            dims,
+            values: phi.iter().map(|&v| v as f32).collect(),
+        }
+    }
+
+    // Trilinear sample, mirrors sample_sdf in sdf.wgsl
+    pub fn sample(&self, position: [f32; 3]) -> f32 {
+        let mut g = [0.0f32; 3];
+        let mut outside = 0.0f32;
+        for a in 0..3 {
+            let upper = (self.dims[a] - 1) as f32;
+            let local = (position[a] - self.origin[a]) / self.cell_size;
+            let clamped = local.clamp(0.0, upper);
+            outside += ((local - clamped) * self.cell_size).powi(2);
+            g[a] = clamped;
+        }
+        let [ni, nj, _] = self.dims;
+        let value = |i: u32, j: u32, k: u32| self.values[(i + ni * (j + nj * k)) as usize];
+        let i0 = (g[0].floor() as u32).min(self.dims[0].saturating_sub(2));
+        let j0 = (g[1].floor() as u32).min(self.dims[1].saturating_sub(2));
+        let k0 = (g[2].floor() as u32).min(self.dims[2].saturating_sub(2));
+        let (fx, fy, fz) = (g[0] - i0 as f32, g[1] - j0 as f32, g[2] - k0 as f32);
+        let c00 = value(i0, j0, k0) * (1.0 - fx) + value(i0 + 1, j0, k0) * fx;
+        let c10 = value(i0, j0 + 1, k0) * (1.0 - fx) + value(i0 + 1, j0 + 1, k0) * fx;
+        let c01 = value(i0, j0, k0 + 1) * (1.0 - fx) + value(i0 + 1, j0, k0 + 1) * fx;
+        let c11 = value(i0, j0 + 1, k0 + 1) * (1.0 - fx) + value(i0 + 1, j0 + 1, k0 + 1) * fx;
+        let c0 = c00 * (1.0 - fy) + c10 * fy;
+        let c1 = c01 * (1.0 - fy) + c11 * fy;
+        c0 * (1.0 - fz) + c1 * fz + outside.sqrt()
+    }
+}
+
+impl Obstacle {
+    pub fn from_obj<P: AsRef<Path>>(
+        path: P,
+        scale: f32,
+        translation: [f32; 3],
+        cell_size: f32,
+        friction: f32,
+        restitution: f32,
+    ) -> Result<Self> {
+        let mut mesh = TriangleMesh::load_obj(path)?;
+        mesh.transform(scale, translation);
+        Ok(Obstacle {
+            sdf: SignedDistanceField::from_mesh(&mesh, cell_size, 2),
+            friction,
+            restitution,
+        })
+    }
+}
+
+impl ObstacleBuffers {
+    pub fn new(device: &wgpu::Device, obstacles: &[Obstacle]) -> Self {
+        let fields: Vec<(&SignedDistanceField, f32, f32)> = obstacles
+            .iter()
+            .map(|obstacle| (&obstacle.sdf, obstacle.friction, obstacle.restitution))
+            .collect();
+        Self::from_fields(device, &fields)
+    }
+
+    // Pack distance fields with their friction and restitution into a header and value buffer
+    pub fn from_fields(device: &wgpu::Device, fields: &[(&SignedDistanceField, f32, f32)]) -> Self {
+        let mut headers: Vec<SdfObstacle> = vec![];
+        let mut values: Vec<f32> = vec![];
+        for (sdf, friction, restitution) in fields {
+            headers.push(SdfObstacle {
+                origin: sdf.origin,
+                cell_size: sdf.cell_size,
+                dims: sdf.dims,
+                offset: values.len() as u32,
+                friction: *friction,
+                restitution: *restitution,
+                _padding: [0.0; 2],
+            });
+            values.extend_from_slice(&sdf.values);
+        }
+        // Bindings cannot be zero sized
+        if headers.is_empty() {
+            headers.push(bytemuck::Zeroable::zeroed());
+        }
+        if values.is_empty() {
+            values.push(0.0);
+        }
+        let buffer_obstacles = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Obstacles"),
+            contents: bytemuck::cast_slice(&headers),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_sdf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Signed Distance Field"),
+            contents: bytemuck::cast_slice(&values),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        ObstacleBuffers {
+            num_obstacles: fields.len() as u32,
+            buffer_obstacles,
+            buffer_sdf,
+        }
+    }
+}
+
+fn to_f64(v: [f32; 3]) -> [f64; 3] {
+    [v[0] as f64, v[1] as f64, v[2] as f64]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn point_segment_distance(x0: [f64; 3], x1: [f64; 3], x2: [f64; 3]) -> f64 {
+    let dx = sub(x2, x1);
+    let m2 = dot(dx, dx);
+    // Parameter of the closest point on the segment
+    let s = if m2 > 0.0 {
+        (dot(sub(x2, x0), dx) / m2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let closest = [
+        s * x1[0] + (1.0 - s) * x2[0],
+        s * x1[1] + (1.0 - s) * x2[1],
+        s * x1[2] + (1.0 - s) * x2[2],
+    ];
+    let d = sub(x0, closest);
+    dot(d, d).sqrt()
+}
+
+fn point_triangle_distance(x0: [f64; 3], tri: [[f64; 3]; 3]) -> f64 {
+    let [x1, x2, x3] = tri;
+    let x13 = sub(x1, x3);
+    let x23 = sub(x2, x3);
+    let x03 = sub(x0, x3);
+    let m13 = dot(x13, x13);
+    let m23 = dot(x23, x23);
+    let d = dot(x13, x23);
+    let invdet = 1.0 / (m13 * m23 - d * d).max(1e-30);
+    let a = dot(x13, x03);
+    let b = dot(x23, x03);
+    // Barycentric coordinates of the projection onto the triangle plane
+    let w23 = invdet * (m23 * a - d * b);
+    let w31 = invdet * (m13 * b - d * a);
+    let w12 = 1.0 - w23 - w31;
+    if w23 >= 0.0 && w31 >= 0.0 && w12 >= 0.0 {
+        let p = [
+            w23 * x1[0] + w31 * x2[0] + w12 * x3[0],
+            w23 * x1[1] + w31 * x2[1] + w12 * x3[1],
+            w23 * x1[2] + w31 * x2[2] + w12 * x3[2],
+        ];
+        let r = sub(x0, p);
+        return dot(r, r).sqrt();
+    }
+    // Otherwise the closest point is on an edge
+    if w23 > 0.0 {
+        point_segment_distance(x0, x1, x2).min(point_segment_distance(x0, x1, x3))
+    } else if w31 > 0.0 {
+        point_segment_distance(x0, x1, x2).min(point_segment_distance(x0, x2, x3))
+    } else {
+        point_segment_distance(x0, x1, x3).min(point_segment_distance(x0, x2, x3))
+    }
+}
+
+// Robust orientation test with consistent tie breaking, returns the sign and twice the signed area
+fn orientation(x1: f64, y1: f64, x2: f64, y2: f64) -> (i32, f64) {
+    let twice_signed_area = y1 * x2 - x1 * y2;
+    let sign = if twice_signed_area > 0.0 {
+        1
+    } else if twice_signed_area < 0.0 {
+        -1
+    } else if y2 > y1 {
+        1
+    } else if y2 < y1 {
+        -1
+    } else if x1 > x2 {
+        1
+    } else if x1 < x2 {
+        -1
+    } else {
+        0
+    };
+    (sign, twice_signed_area)
+}
+
+// Barycentric coordinates of (x0, y0) if it lies inside the 2-D triangle
+#[allow(clippy::too_many_arguments)]
+fn point_in_triangle_2d(
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+    x3: f64,
+    y3: f64,
+) -> Option<(f64, f64, f64)> {
+    let (x1, y1, x2, y2, x3, y3) = (x1 - x0, y1 - y0, x2 - x0, y2 - y0, x3 - x0, y3 - y0);
+    let (sign_a, a) = orientation(x2, y2, x3, y3);
+    if sign_a == 0 {
+        return None;
+    }
+    let (sign_b, b) = orientation(x3, y3, x1, y1);
+    if sign_b != sign_a {
+        return None;
+    }
+    let (sign_c, c) = orientation(x1, y1, x2, y2);
+    if sign_c != sign_a {
+        return None;
+    }
+    let sum = a + b + c;
+    if sum == 0.0 {
+        return None;
+    }
+    Some((a / sum, b / sum, c / sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_cube() -> TriangleMesh {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ];
+        let triangles = vec![
+            [0, 2, 1],
+            [0, 3, 2],
+            [4, 5, 6],
+            [4, 6, 7],
+            [0, 1, 5],
+            [0, 5, 4],
+            [3, 6, 2],
+            [3, 7, 6],
+            [0, 4, 7],
+            [0, 7, 3],
+            [1, 2, 6],
+            [1, 6, 5],
+        ];
+        TriangleMesh {
+            vertices,
+            triangles,
+        }
+    }
+
+    #[test]
+    fn test_cube_sdf_sign() {
+        let sdf = SignedDistanceField::from_mesh(&unit_cube(), 0.1, 2);
+
+        // Centre of the cube is half a unit from every face
+        assert!((sdf.sample([0.5, 0.5, 0.5]) + 0.5).abs() < 0.05);
+
+        // Points outside the cube are positive
+        assert!((sdf.sample([1.25, 0.5, 0.5]) - 0.25).abs() < 0.05);
+        assert!(sdf.sample([3.0, 0.5, 0.5]) > 1.5);
+    }
+}
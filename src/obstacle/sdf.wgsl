@@ -0,0 +1,68 @@
+// WGSL file for signed distance field obstacles
+// Requires `obstacles: array<SdfObstacle>` and `sdf: array<f32>` storage bindings
+
+struct SdfObstacle {
+    origin: vec3f,
+    cell_size: f32,
+    dims: vec3u,
+    offset: u32,
+    friction: f32,
+    restitution: f32,
+    _padding: vec2f,
+    // 48 bytes
+}
+
+fn sdf_value(obstacle: SdfObstacle, i: u32, j: u32, k: u32) -> f32 {
+    let dims = obstacle.dims;
+    return sdf[obstacle.offset + i + dims.x * (j + dims.y * k)];
+}
+
+// Trilinear sample of the distance field, positive outside the obstacle
+fn sample_sdf(obstacle_idx: u32, position: vec3f) -> f32 {
+    let obstacle = obstacles[obstacle_idx];
+    let upper = vec3f(obstacle.dims - 1u);
+    let local = (position - obstacle.origin) / obstacle.cell_size;
+    let clamped = clamp(local, vec3f(0.0), upper);
+    // Distance to the field bounding box for queries outside of it
+    let outside = length(local - clamped) * obstacle.cell_size;
+    let node = min(vec3u(floor(clamped)), max(obstacle.dims, vec3u(2u)) - 2u);
+    let f = clamped - vec3f(node);
+    let c00 = mix(sdf_value(obstacle, node.x, node.y, node.z), sdf_value(obstacle, node.x + 1u, node.y, node.z), f.x);
+    let c10 = mix(sdf_value(obstacle, node.x, node.y + 1u, node.z), sdf_value(obstacle, node.x + 1u, node.y + 1u, node.z), f.x);
+    let c01 = mix(sdf_value(obstacle, node.x, node.y, node.z + 1u), sdf_value(obstacle, node.x + 1u, node.y, node.z + 1u), f.x);
+    let c11 = mix(sdf_value(obstacle, node.x, node.y + 1u, node.z + 1u), sdf_value(obstacle, node.x + 1u, node.y + 1u, node.z + 1u), f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z) + outside;
+}
+
+// Outward surface normal from central differences of the field
+fn sdf_normal(obstacle_idx: u32, position: vec3f) -> vec3f {
+    let h = obstacles[obstacle_idx].cell_size;
+    let dx = vec3f(h, 0.0, 0.0);
+    let dy = vec3f(0.0, h, 0.0);
+    let dz = vec3f(0.0, 0.0, h);
+    let gradient = vec3f(
+        sample_sdf(obstacle_idx, position + dx) - sample_sdf(obstacle_idx, position - dx),
+        sample_sdf(obstacle_idx, position + dy) - sample_sdf(obstacle_idx, position - dy),
+        sample_sdf(obstacle_idx, position + dz) - sample_sdf(obstacle_idx, position - dz),
+    );
+    let norm = length(gradient);
+    if (norm < 1e-12) {
+        return vec3f(0.0, 1.0, 0.0);
+    }
+    return gradient / norm;
+}
+
+// Remove the approaching normal velocity with restitution and apply Coulomb friction
+fn collision_response(velocity: vec3f, normal: vec3f, friction: f32, restitution: f32) -> vec3f {
+    let vn = dot(velocity, normal);
+    if (vn >= 0.0) {
+        return velocity;
+    }
+    let tangential = velocity - vn * normal;
+    let vt = length(tangential);
+    var tangential_scale = 0.0;
+    if (vt > 1e-8) {
+        tangential_scale = max(0.0, 1.0 - friction * (1.0 + restitution) * abs(vn) / vt);
+    }
+    return tangential * tangential_scale - restitution * vn * normal;
+}
@@ -0,0 +1,714 @@
+use crate::mls_mpm;
+use crate::seeding::split_sph_position;
+use crate::sph;
+use crate::vtk::PointData;
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Named per point attributes read from or written to PLY and CSV files, e.g. for Houdini,
+// Blender and pandas. Values are kept as f64 so that integer attributes such as ids survive.
+#[derive(Clone, Debug, Default)]
+pub struct PointCloud {
+    pub attributes: Vec<Attribute>,
+    // Free text lines, PLY comments or `#` lines at the top of a CSV file
+    pub comments: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub kind: AttributeKind,
+    pub values: Vec<f64>,
+}
+
+// Type of an attribute in written PLY files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributeKind {
+    Float,
+    UInt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+// Columns of the point cloud that set the particle fields. Only the positions are required,
+// unmapped fields take the defaults of the importer and mapped columns must exist.
+//
+//     [point_sets.mapping]
+//     position = ["P.x", "P.y", "P.z"]
+//     velocity = ["v.x", "v.y", "v.z"]
+//     mass = "mass"
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttributeMap {
+    pub position: [String; 3],
+    pub velocity: Option<[String; 3]>,
+    pub mass: Option<String>,
+    // Index into the material list
+    pub material: Option<String>,
+    pub tag: Option<String>,
+    // SPH only
+    pub density: Option<String>,
+    pub pressure: Option<String>,
+}
+
+impl Default for AttributeMap {
+    fn default() -> Self {
+        AttributeMap {
+            position: ["x", "y", "z"].map(String::from),
+            velocity: None,
+            mass: None,
+            material: None,
+            tag: None,
+            density: None,
+            pressure: None,
+        }
+    }
+}
+
+// Per point values of the mapped fields in the units of the file
+struct MappedFields {
+    positions: Vec<[f32; 3]>,
+    velocities: Option<Vec<[f32; 3]>>,
+    masses: Option<Vec<f32>>,
+    material_idx: Option<Vec<u32>>,
+    tags: Option<Vec<u32>>,
+    densities: Option<Vec<f32>>,
+    pressures: Option<Vec<f32>>,
+}
+
+impl PointCloud {
+    // Exported columns x, y, z, vx, vy, vz, density, pressure, mass, material and id
+    pub fn from_point_data(data: &PointData) -> Self {
+        let mut cloud = PointCloud::default();
+        for (a, name) in ["x", "y", "z"].into_iter().enumerate() {
+            cloud.push_float(name, data.positions.iter().map(|p| p[a]));
+        }
+        for (a, name) in ["vx", "vy", "vz"].into_iter().enumerate() {
+            cloud.push_float(name, data.velocities.iter().map(|v| v[a]));
+        }
+        cloud.push_float("density", data.densities.iter().cloned());
+        cloud.push_float("pressure", data.pressures.iter().cloned());
+        cloud.push_float("mass", data.masses.iter().cloned());
+        cloud.push_uint("material", data.material_idx.iter().cloned());
+        cloud.push_uint("id", data.ids.iter().cloned());
+        cloud
+    }
+
+    pub fn push_float(&mut self, name: &str, values: impl Iterator<Item = f32>) {
+        self.attributes.push(Attribute {
+            name: name.to_string(),
+            kind: AttributeKind::Float,
+            values: values.map(f64::from).collect(),
+        });
+    }
+
+    pub fn push_uint(&mut self, name: &str, values: impl Iterator<Item = u32>) {
+        self.attributes.push(Attribute {
+            name: name.to_string(),
+            kind: AttributeKind::UInt,
+            values: values.map(f64::from).collect(),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.attributes.first().map_or(0, |a| a.values.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    fn column(&self, name: &str) -> Result<&[f64]> {
+        match self.attribute(name) {
+            Some(attribute) => Ok(&attribute.values),
+            None => bail!(
+                "no attribute {:?}, the point cloud has {:?}",
+                name,
+                self.attributes.iter().map(|a| &a.name).collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    // `.ply` or `.csv` by extension, PLY files are written in binary
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("ply") => Some(PlyFormat::BinaryLittleEndian),
+            Some("csv") => None,
+            _ => bail!("{} is not a .ply or .csv file", path.display()),
+        };
+        self.save_as(path, format)
+    }
+
+    // PLY in the given format, CSV without one
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, ply: Option<PlyFormat>) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        match ply {
+            Some(format) => self.write_ply(&mut writer, format),
+            None => self.write_csv(&mut writer),
+        }
+        .and_then(|_| Ok(writer.flush()?))
+        .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ply") => Self::read_ply(&mut reader),
+            Some("csv") => Self::read_csv(&mut reader),
+            _ => bail!("{} is not a .ply or .csv file", path.display()),
+        }
+        .with_context(|| format!("invalid point cloud {}", path.display()))
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for comment in &self.comments {
+            writeln!(writer, "# {}", comment)?;
+        }
+        let names: Vec<&str> = self.attributes.iter().map(|a| a.name.as_str()).collect();
+        writeln!(writer, "{}", names.join(","))?;
+        for i in 0..self.len() {
+            for (j, attribute) in self.attributes.iter().enumerate() {
+                if j > 0 {
+                    write!(writer, ",")?;
+                }
+                match attribute.kind {
+                    AttributeKind::Float => write!(writer, "{}", attribute.values[i] as f32)?,
+                    AttributeKind::UInt => write!(writer, "{}", attribute.values[i] as u32)?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    // Header row of names then one row per point, `#` lines before the header are comments
+    pub fn read_csv<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let mut cloud = PointCloud::default();
+        let mut lines = reader.lines().enumerate();
+        let header = loop {
+            let Some((_, line)) = lines.next() else {
+                bail!("missing header row");
+            };
+            let line = line?;
+            match line.strip_prefix('#') {
+                Some(comment) => cloud.comments.push(comment.trim().to_string()),
+                None if line.trim().is_empty() => continue,
+                None => break line,
+            }
+        };
+        for name in header.split(',') {
+            cloud.attributes.push(Attribute {
+                name: name.trim().to_string(),
+                kind: AttributeKind::Float,
+                values: vec![],
+            });
+        }
+        for (line_idx, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != cloud.attributes.len() {
+                bail!(
+                    "line {}: {} values for {} columns",
+                    line_idx + 1,
+                    fields.len(),
+                    cloud.attributes.len()
+                );
+            }
+            for (attribute, field) in cloud.attributes.iter_mut().zip(fields) {
+                let value = field.trim().parse().with_context(|| {
+                    format!(
+                        "line {}: invalid {} value {:?}",
+                        line_idx + 1,
+                        attribute.name,
+                        field
+                    )
+                })?;
+                attribute.values.push(value);
+            }
+        }
+        Ok(cloud)
+    }
+
+    // Single `vertex` element with float and uint properties
+    pub fn write_ply<W: Write>(&self, writer: &mut W, format: PlyFormat) -> Result<()> {
+        writeln!(writer, "ply")?;
+        match format {
+            PlyFormat::Ascii => writeln!(writer, "format ascii 1.0")?,
+            PlyFormat::BinaryLittleEndian => writeln!(writer, "format binary_little_endian 1.0")?,
+        }
+        for comment in &self.comments {
+            writeln!(writer, "comment {}", comment)?;
+        }
+        writeln!(writer, "element vertex {}", self.len())?;
+        for attribute in &self.attributes {
+            let ty = match attribute.kind {
+                AttributeKind::Float => "float",
+                AttributeKind::UInt => "uint",
+            };
+            writeln!(writer, "property {} {}", ty, attribute.name)?;
+        }
+        writeln!(writer, "end_header")?;
+        for i in 0..self.len() {
+            for (j, attribute) in self.attributes.iter().enumerate() {
+                let value = attribute.values[i];
+                match (format, attribute.kind) {
+                    (PlyFormat::Ascii, kind) => {
+                        if j > 0 {
+                            write!(writer, " ")?;
+                        }
+                        match kind {
+                            AttributeKind::Float => write!(writer, "{}", value as f32)?,
+                            AttributeKind::UInt => write!(writer, "{}", value as u32)?,
+                        }
+                    }
+                    (PlyFormat::BinaryLittleEndian, AttributeKind::Float) => {
+                        writer.write_all(&(value as f32).to_le_bytes())?
+                    }
+                    (PlyFormat::BinaryLittleEndian, AttributeKind::UInt) => {
+                        writer.write_all(&(value as u32).to_le_bytes())?
+                    }
+                }
+            }
+            if format == PlyFormat::Ascii {
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Scalar properties of the `vertex` element in any of the three PLY formats, other elements
+    // such as faces are skipped
+    pub fn read_ply<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let header = PlyHeader::read(reader)?;
+        let mut cloud = PointCloud {
+            attributes: vec![],
+            comments: header.comments.clone(),
+        };
+        for element in &header.elements {
+            let is_vertex = element.name == "vertex";
+            if is_vertex {
+                for property in &element.properties {
+                    if property.list.is_some() {
+                        bail!("list property {} of vertex is not supported", property.name);
+                    }
+                    cloud.attributes.push(Attribute {
+                        name: property.name.clone(),
+                        kind: match property.ty.is_integer() {
+                            true => AttributeKind::UInt,
+                            false => AttributeKind::Float,
+                        },
+                        values: Vec::with_capacity(element.count),
+                    });
+                }
+            }
+            let mut words = vec![];
+            for item in 0..element.count {
+                if header.format == PlyEncoding::Ascii {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 {
+                        bail!("file ends in {} {}", element.name, item);
+                    }
+                    words = line.split_whitespace().map(String::from).rev().collect();
+                }
+                let mut next = |ty: PlyType| -> Result<f64> {
+                    match header.format {
+                        PlyEncoding::Ascii => {
+                            let word = words.pop().with_context(|| {
+                                format!("too few values in {} {}", element.name, item)
+                            })?;
+                            word.parse()
+                                .with_context(|| format!("invalid value {:?}", word))
+                        }
+                        PlyEncoding::Binary { big_endian } => ty.read(reader, big_endian),
+                    }
+                };
+                for (p, property) in element.properties.iter().enumerate() {
+                    match property.list {
+                        Some(count_ty) => {
+                            let count = next(count_ty)? as usize;
+                            for _ in 0..count {
+                                next(property.ty)?;
+                            }
+                        }
+                        None => {
+                            let value = next(property.ty)?;
+                            if is_vertex {
+                                cloud.attributes[p].values.push(value);
+                            }
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                return Ok(cloud);
+            }
+        }
+        bail!("no vertex element")
+    }
+
+    // Particle fields from the mapped columns
+    fn map(&self, map: &AttributeMap) -> Result<MappedFields> {
+        let floats = |name: &String| -> Result<Vec<f32>> {
+            Ok(self.column(name)?.iter().map(|&x| x as f32).collect())
+        };
+        let uints = |name: &String| -> Result<Vec<u32>> {
+            self.column(name)?
+                .iter()
+                .map(
+                    |&x| match x >= 0.0 && x.fract() == 0.0 && x <= u32::MAX as f64 {
+                        true => Ok(x as u32),
+                        false => bail!("{} value {} is not a non-negative integer", name, x),
+                    },
+                )
+                .collect()
+        };
+        let vectors = |names: &[String; 3]| -> Result<Vec<[f32; 3]>> {
+            let [x, y, z] = [floats(&names[0])?, floats(&names[1])?, floats(&names[2])?];
+            Ok((0..x.len()).map(|i| [x[i], y[i], z[i]]).collect())
+        };
+        Ok(MappedFields {
+            positions: vectors(&map.position)?,
+            velocities: map.velocity.as_ref().map(vectors).transpose()?,
+            masses: map.mass.as_ref().map(floats).transpose()?,
+            material_idx: map.material.as_ref().map(uints).transpose()?,
+            tags: map.tag.as_ref().map(uints).transpose()?,
+            densities: map.density.as_ref().map(floats).transpose()?,
+            pressures: map.pressure.as_ref().map(floats).transpose()?,
+        })
+    }
+
+    // SPH particles at world positions with ids from `first_id`. Unmapped fields are at rest
+    // with `material_idx`, `mass`, zero tag and zero density and pressure.
+    pub fn sph_particles(
+        &self,
+        map: &AttributeMap,
+        material_idx: u32,
+        mass: f32,
+        smoothing_length: f32,
+        grid_size: f32,
+        first_id: u32,
+    ) -> Result<(Vec<sph::Particle>, Vec<sph::ParticleMotion>)> {
+        let fields = self.map(map)?;
+        let mut particles = vec![];
+        let mut motion = vec![];
+        for (i, &point) in fields.positions.iter().enumerate() {
+            let (coord, position) = split_sph_position(point, grid_size);
+            let velocity = fields.velocities.as_ref().map_or([0.0; 3], |v| v[i]);
+            particles.push(sph::Particle {
+                coord,
+                mass: fields.masses.as_ref().map_or(mass, |m| m[i]),
+                position,
+                density: fields.densities.as_ref().map_or(0.0, |d| d[i]),
+                pressure: fields.pressures.as_ref().map_or(0.0, |p| p[i]),
+                smoothing_length,
+                material_idx: fields.material_idx.as_ref().map_or(material_idx, |m| m[i]),
+                id: first_id + i as u32,
+            });
+            motion.push(sph::ParticleMotion {
+                velocity,
+                drho_dt: 0.0,
+                acceleration: [0.0; 3],
+                _padding: 0.0,
+                velocity_p: velocity,
+                tag: fields.tags.as_ref().map_or(0, |t| t[i]),
+            });
+        }
+        Ok((particles, motion))
+    }
+
+    // MLS-MPM particles from world positions, velocities and masses, scaled into the unit domain
+    // and grid cells as `Seeder::mpm_particles`. Density and pressure are not particle fields.
+    pub fn mpm_particles(
+        &self,
+        map: &AttributeMap,
+        material_idx: u32,
+        mass: f32,
+        params: &mls_mpm::SimParams,
+        first_id: u32,
+    ) -> Result<Vec<mls_mpm::Particle>> {
+        let fields = self.map(map)?;
+        let scale = params.scale_distance;
+        let cell_volume = (scale / params.grid_resolution as f32).powi(3);
+        Ok(fields
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, point)| mls_mpm::Particle {
+                position: point.map(|x| x / scale),
+                mass: fields.masses.as_ref().map_or(mass, |m| m[i]) / cell_volume,
+                velocity: fields
+                    .velocities
+                    .as_ref()
+                    .map_or([0.0; 3], |v| v[i].map(|v| v / scale)),
+                material_idx: fields.material_idx.as_ref().map_or(material_idx, |m| m[i]),
+                C: [0.0; 12],
+                id: first_id + i as u32,
+                tag: fields.tags.as_ref().map_or(0, |t| t[i]),
+                _padding: [0; 2],
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlyEncoding {
+    Ascii,
+    Binary { big_endian: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    // Type of the count of list properties
+    list: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyHeader {
+    format: PlyEncoding,
+    comments: Vec<String>,
+    elements: Vec<PlyElement>,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => PlyType::Int8,
+            "uchar" | "uint8" => PlyType::UInt8,
+            "short" | "int16" => PlyType::Int16,
+            "ushort" | "uint16" => PlyType::UInt16,
+            "int" | "int32" => PlyType::Int32,
+            "uint" | "uint32" => PlyType::UInt32,
+            "float" | "float32" => PlyType::Float32,
+            "double" | "float64" => PlyType::Float64,
+            _ => bail!("unknown property type {:?}", name),
+        })
+    }
+
+    fn is_integer(&self) -> bool {
+        !matches!(self, PlyType::Float32 | PlyType::Float64)
+    }
+
+    fn read<R: Read>(&self, reader: &mut R, big_endian: bool) -> Result<f64> {
+        fn bytes<R: Read, const N: usize>(reader: &mut R, big_endian: bool) -> Result<[u8; N]> {
+            let mut bytes = [0u8; N];
+            reader.read_exact(&mut bytes).context("file ends early")?;
+            if big_endian {
+                bytes.reverse();
+            }
+            Ok(bytes)
+        }
+        Ok(match self {
+            PlyType::Int8 => i8::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::UInt8 => u8::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::Int16 => i16::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::UInt16 => u16::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::Int32 => i32::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::UInt32 => u32::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::Float32 => f32::from_le_bytes(bytes(reader, big_endian)?) as f64,
+            PlyType::Float64 => f64::from_le_bytes(bytes(reader, big_endian)?),
+        })
+    }
+}
+
+impl PlyHeader {
+    fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let mut header = PlyHeader {
+            format: PlyEncoding::Ascii,
+            comments: vec![],
+            elements: vec![],
+        };
+        let next_line = |reader: &mut R| -> Result<String> {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                bail!("missing end_header");
+            }
+            Ok(line.trim_end().to_string())
+        };
+        let mut line_idx = 1;
+        if next_line(reader)? != "ply" {
+            bail!("not a PLY file");
+        }
+        loop {
+            let line = next_line(reader)?;
+            line_idx += 1;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["end_header"] => break,
+                ["format", format, _version] => {
+                    header.format = match *format {
+                        "ascii" => PlyEncoding::Ascii,
+                        "binary_little_endian" => PlyEncoding::Binary { big_endian: false },
+                        "binary_big_endian" => PlyEncoding::Binary { big_endian: true },
+                        _ => bail!("line {}: unknown format {:?}", line_idx, format),
+                    }
+                }
+                ["comment", ..] => header
+                    .comments
+                    .push(line["comment".len()..].trim().to_string()),
+                ["obj_info", ..] | [] => {}
+                ["element", name, count] => header.elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .with_context(|| format!("line {}: invalid count", line_idx))?,
+                    properties: vec![],
+                }),
+                ["property", rest @ ..] => {
+                    let element = header
+                        .elements
+                        .last_mut()
+                        .with_context(|| format!("line {}: property before element", line_idx))?;
+                    let property = match rest {
+                        ["list", count_ty, ty, name] => PlyProperty {
+                            name: name.to_string(),
+                            ty: PlyType::parse(ty)?,
+                            list: Some(PlyType::parse(count_ty)?),
+                        },
+                        [ty, name] => PlyProperty {
+                            name: name.to_string(),
+                            ty: PlyType::parse(ty)?,
+                            list: None,
+                        },
+                        _ => bail!("line {}: invalid property {:?}", line_idx, line),
+                    };
+                    element.properties.push(property);
+                }
+                _ => bail!("line {}: unexpected {:?}", line_idx, line),
+            }
+        }
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> PointCloud {
+        let mut cloud = PointCloud {
+            attributes: vec![],
+            comments: vec!["time 0.25".to_string()],
+        };
+        cloud.push_float("x", [0.0, 0.5, -0.25].into_iter());
+        cloud.push_float("y", [1.0, 0.0, 0.125].into_iter());
+        cloud.push_float("z", [0.0, 0.0, 0.3].into_iter());
+        cloud.push_float("speed", [1.5, 0.0, 2.0].into_iter());
+        cloud.push_uint("id", [16_777_217, 1, 2].into_iter());
+        cloud
+    }
+
+    fn assert_same(a: &PointCloud, b: &PointCloud) {
+        assert_eq!(a.comments, b.comments);
+        assert_eq!(a.attributes.len(), b.attributes.len());
+        for (a, b) in a.attributes.iter().zip(&b.attributes) {
+            assert_eq!(a.name, b.name);
+            for (x, y) in a.values.iter().zip(&b.values) {
+                assert_eq!(*x as f32, *y as f32, "{}", a.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_point_cloud_round_trip() {
+        let cloud = sample();
+        let mut csv = vec![];
+        cloud.write_csv(&mut csv).unwrap();
+        assert_same(&cloud, &PointCloud::read_csv(&mut csv.as_slice()).unwrap());
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian] {
+            let mut ply = vec![];
+            cloud.write_ply(&mut ply, format).unwrap();
+            let read = PointCloud::read_ply(&mut ply.as_slice()).unwrap();
+            assert_same(&cloud, &read);
+            // Ids above 2^24 are exact in uint properties
+            assert_eq!(read.attribute("id").unwrap().values[0], 16_777_217.0);
+        }
+
+        // Big-endian doubles followed by a face element, as written by other tools
+        let mut ply = b"ply\nformat binary_big_endian 1.0\nelement vertex 1\n\
+            property double x\nproperty double y\nproperty double z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n"
+            .to_vec();
+        for x in [1.0f64, 2.0, 3.0] {
+            ply.extend(x.to_be_bytes());
+        }
+        ply.push(0);
+        let read = PointCloud::read_ply(&mut ply.as_slice()).unwrap();
+        assert_eq!(read.attribute("z").unwrap().values, [3.0]);
+    }
+
+    #[test]
+    fn test_attribute_mapping() {
+        let cloud = sample();
+        let map = AttributeMap {
+            velocity: Some(["speed", "y", "z"].map(String::from)),
+            tag: Some("id".to_string()),
+            ..Default::default()
+        };
+        let (particles, motion) = cloud.sph_particles(&map, 1, 0.5, 0.1, 0.2, 10).unwrap();
+        assert_eq!(particles.len(), 3);
+        assert_eq!(particles[1].coord, [2, 0, 0]);
+        assert_eq!(particles[2].mass, 0.5);
+        assert_eq!(particles[2].material_idx, 1);
+        assert_eq!(particles[2].id, 12);
+        assert_eq!(motion[0].velocity, [1.5, 1.0, 0.0]);
+        assert_eq!(motion[1].tag, 1);
+
+        // Mapped columns must exist and hold the right kind of values
+        let map = AttributeMap {
+            mass: Some("mass".to_string()),
+            ..Default::default()
+        };
+        let error = cloud
+            .sph_particles(&map, 0, 0.5, 0.1, 0.2, 0)
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("no attribute \"mass\""),
+            "{}",
+            error
+        );
+        let map = AttributeMap {
+            material: Some("x".to_string()),
+            ..Default::default()
+        };
+        assert!(cloud.sph_particles(&map, 0, 0.5, 0.1, 0.2, 0).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::shader_module::ShaderModuleBuilder;
+use wgpu::util::DeviceExt;
+
+const BLOCK_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct ScanParams {
+    len: u32,
+    _padding: [u32; 3],
+}
+
+struct ScanLevel {
+    len: u32,
+    #[allow(unused)]
+    buffer_params: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+// In-place exclusive prefix sum of a u32 buffer, recorded into a compute pass
+pub struct PrefixSum {
+    levels: Vec<ScanLevel>,
+    // Block sums of every level, the last one holds the total in its first element
+    buffer_block_sums: Vec<wgpu::Buffer>,
+    compute_pipeline_scan_blocks: wgpu::ComputePipeline,
+    compute_pipeline_add_block_offsets: wgpu::ComputePipeline,
+}
+
+impl PrefixSum {
+    pub fn new(device: &wgpu::Device, buffer_data: &wgpu::Buffer, len: u32) -> Self {
+        let module_prefix_sum = ShaderModuleBuilder::new()
+            .add_module(include_str!("./prefix_sum.wgsl"))
+            .build(device, Some("Shader Module Prefix Sum"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Prefix Sum"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Prefix Sum"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_scan_blocks =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Prefix Sum Scan Blocks"),
+                layout: Some(&pipeline_layout),
+                module: &module_prefix_sum,
+                entry_point: Some("scan_blocks"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_add_block_offsets =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Prefix Sum Add Block Offsets"),
+                layout: Some(&pipeline_layout),
+                module: &module_prefix_sum,
+                entry_point: Some("add_block_offsets"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+
+        // Reduce until a single block remains
+        let mut lens = vec![len.max(1)];
+        while lens[lens.len() - 1] > BLOCK_SIZE {
+            lens.push(lens[lens.len() - 1].div_ceil(BLOCK_SIZE));
+        }
+        let buffer_block_sums: Vec<wgpu::Buffer> = lens
+            .iter()
+            .map(|len| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Buffer Prefix Sum Block Sums"),
+                    size: (len.div_ceil(BLOCK_SIZE) as usize * std::mem::size_of::<u32>()) as u64,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        let levels = lens
+            .iter()
+            .enumerate()
+            .map(|(level, &len)| {
+                let buffer_params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Buffer Prefix Sum Parameters"),
+                    contents: bytemuck::bytes_of(&ScanParams {
+                        len,
+                        _padding: [0; 3],
+                    }),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let buffer_level_data = match level {
+                    0 => buffer_data,
+                    _ => &buffer_block_sums[level - 1],
+                };
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Bind Group Prefix Sum"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer_level_data.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: buffer_block_sums[level].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: buffer_params.as_entire_binding(),
+                        },
+                    ],
+                });
+                ScanLevel {
+                    len,
+                    buffer_params,
+                    bind_group,
+                }
+            })
+            .collect();
+
+        PrefixSum {
+            levels,
+            buffer_block_sums,
+            compute_pipeline_scan_blocks,
+            compute_pipeline_add_block_offsets,
+        }
+    }
+
+    // Buffer whose first element is the sum of all values after the scan
+    pub fn buffer_total(&self) -> &wgpu::Buffer {
+        &self.buffer_block_sums[self.buffer_block_sums.len() - 1]
+    }
+
+    pub fn encode(&self, compute_pass: &mut wgpu::ComputePass) {
+        // Scan blocks on the way down
+        compute_pass.set_pipeline(&self.compute_pipeline_scan_blocks);
+        for level in &self.levels {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups(level.len.div_ceil(BLOCK_SIZE), 1, 1);
+        }
+        // Propagate the scanned block sums on the way up
+        compute_pass.set_pipeline(&self.compute_pipeline_add_block_offsets);
+        for level in self.levels.iter().rev().skip(1) {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups(level.len.div_ceil(BLOCK_SIZE), 1, 1);
+        }
+    }
+
+    // Workgroups of each level, for the arguments of encode_indirect
+    pub fn num_workgroups(&self) -> Vec<u32> {
+        self.levels
+            .iter()
+            .map(|level| level.len.div_ceil(BLOCK_SIZE))
+            .collect()
+    }
+
+    // Same as encode with the levels dispatched from consecutive arguments starting at
+    // `indirect_offset`, one per level in the order of num_workgroups
+    pub fn encode_indirect(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        indirect_buffer: &wgpu::Buffer,
+        indirect_offset: u64,
+    ) {
+        let args_size = std::mem::size_of::<wgpu::util::DispatchIndirectArgs>();
+        let offset = |level: usize| indirect_offset + (level * args_size) as u64;
+        compute_pass.set_pipeline(&self.compute_pipeline_scan_blocks);
+        for (idx, level) in self.levels.iter().enumerate() {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups_indirect(indirect_buffer, offset(idx));
+        }
+        compute_pass.set_pipeline(&self.compute_pipeline_add_block_offsets);
+        for (idx, level) in self.levels.iter().enumerate().rev().skip(1) {
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch_workgroups_indirect(indirect_buffer, offset(idx));
+        }
+    }
+}
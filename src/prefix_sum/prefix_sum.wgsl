@@ -0,0 +1,63 @@
+// WGSL file for the exclusive prefix sum of u32 arrays
+// Each level scans blocks of 256 values and writes the block totals to the next level
+
+const BLOCK_SIZE: u32 = 256u;
+
+struct ScanParams {
+    len: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+    // 16 bytes
+}
+
+@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+@group(0) @binding(1) var<storage, read_write> block_sums: array<u32>;
+@group(0) @binding(2) var<uniform> params: ScanParams;
+
+var<workgroup> shared_data: array<u32, BLOCK_SIZE>;
+
+@compute @workgroup_size(256)
+fn scan_blocks(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let idx = global_id.x;
+    let lid = local_id.x;
+    var value = 0u;
+    if (idx < params.len) {
+        value = data[idx];
+    }
+    shared_data[lid] = value;
+    workgroupBarrier();
+    // Hillis-Steele inclusive scan in shared memory
+    for (var offset = 1u; offset < BLOCK_SIZE; offset *= 2u) {
+        var addend = 0u;
+        if (lid >= offset) {
+            addend = shared_data[lid - offset];
+        }
+        workgroupBarrier();
+        shared_data[lid] += addend;
+        workgroupBarrier();
+    }
+    // Convert to exclusive
+    if (idx < params.len) {
+        data[idx] = shared_data[lid] - value;
+    }
+    if (lid == BLOCK_SIZE - 1u) {
+        block_sums[workgroup_id.x] = shared_data[lid];
+    }
+}
+
+@compute @workgroup_size(256)
+fn add_block_offsets(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let idx = global_id.x;
+    if (idx >= params.len) {
+        return;
+    }
+    data[idx] += block_sums[workgroup_id.x];
+}
@@ -0,0 +1,474 @@
+use crate::shader_module::ShaderModuleBuilder;
+use anyhow::*;
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+// Steps kept on the GPU between two readbacks of the probe values
+pub const MAX_RECORDS: u32 = 256;
+
+const PROBE_PRESSURE: u32 = 0;
+const PROBE_WAVE_GAUGE: u32 = 1;
+const PROBE_FLUX_SECTION: u32 = 2;
+
+// Time series at fixed locations in world units, sampled after every time step. Each probe gives
+// one value per step.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Probe {
+    // Pressure interpolated at a point
+    Pressure {
+        name: String,
+        position: [f32; 3],
+    },
+    // Free surface elevation along a vertical line going up `height` from `position`, sampled
+    // every `spacing`
+    WaveGauge {
+        name: String,
+        position: [f32; 3],
+        height: f32,
+        spacing: f32,
+    },
+    // Volume flow rate through the rectangle between `min` and `max`, which must be flat along
+    // one axis. The flow is positive along that axis, sampled on a grid of `spacing`.
+    FluxSection {
+        name: String,
+        min: [f32; 3],
+        max: [f32; 3],
+        spacing: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct ProbePoint {
+    pub position: [f32; 3],
+    pub probe: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct ProbeInfo {
+    pub kind: u32,
+    pub first_point: u32,
+    pub num_points: u32,
+    pub _padding: u32,
+    // Section normal, unused by the other kinds
+    pub normal: [f32; 3],
+    // Point spacing of a wave gauge or area per point of a flux section
+    pub weight: f32,
+}
+
+// Sample points and history of the probes of a solver. The solver writes the fields at the points
+// into `buffer_samples` and then records `encode`, see attach_probes of the solvers.
+pub struct Probes {
+    names: Vec<String>,
+    max_records: u32,
+    pub buffer_points: wgpu::Buffer,
+    pub buffer_samples: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_probes: wgpu::Buffer,
+    buffer_history: wgpu::Buffer,
+    buffer_records: wgpu::Buffer,
+    staging_buffer_history: wgpu::Buffer,
+    staging_buffer_records: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_reduce: wgpu::ComputePipeline,
+    compute_pipeline_advance: wgpu::ComputePipeline,
+}
+
+impl Probe {
+    pub fn name(&self) -> &str {
+        match self {
+            Probe::Pressure { name, .. }
+            | Probe::WaveGauge { name, .. }
+            | Probe::FluxSection { name, .. } => name,
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        let name = self.name();
+        if name.is_empty() || name.contains([',', '"', '\n']) {
+            bail!("probe name {:?} can't be a CSV column", name);
+        }
+        match self {
+            Probe::Pressure { .. } => {}
+            Probe::WaveGauge {
+                height, spacing, ..
+            } => {
+                if !(positive(*height) && positive(*spacing)) {
+                    bail!("wave gauge {:?} needs a positive height and spacing", name);
+                }
+            }
+            Probe::FluxSection {
+                min, max, spacing, ..
+            } => {
+                if !positive(*spacing) {
+                    bail!("flux section {:?} needs a positive spacing", name);
+                }
+                let flat = (0..3).filter(|&a| min[a] == max[a]).count();
+                if flat != 1 || (0..3).any(|a| min[a] > max[a]) {
+                    bail!(
+                        "flux section {:?} must be a rectangle with min = max along one axis",
+                        name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Kind, normal and weight of the probe with its sample points
+    pub fn points(&self) -> (ProbeInfo, Vec<[f32; 3]>) {
+        let info = |kind, normal, weight| ProbeInfo {
+            kind,
+            normal,
+            weight,
+            ..Default::default()
+        };
+        match *self {
+            Probe::Pressure { position, .. } => {
+                (info(PROBE_PRESSURE, [0.0; 3], 0.0), vec![position])
+            }
+            Probe::WaveGauge {
+                position,
+                height,
+                spacing,
+                ..
+            } => {
+                let num_points = (height / spacing + 1e-4).floor() as u32 + 1;
+                let points = (0..num_points)
+                    .map(|i| [position[0], position[1] + i as f32 * spacing, position[2]])
+                    .collect();
+                (info(PROBE_WAVE_GAUGE, [0.0; 3], spacing), points)
+            }
+            Probe::FluxSection {
+                min, max, spacing, ..
+            } => {
+                let axis = (0..3).find(|&a| min[a] == max[a]).unwrap_or(0);
+                let mut normal = [0.0; 3];
+                normal[axis] = 1.0;
+                // Cell centres of the section
+                let dims: [u32; 3] = std::array::from_fn(|a| match a == axis {
+                    true => 1,
+                    false => (((max[a] - min[a]) / spacing).ceil() as u32).max(1),
+                });
+                let step: [f32; 3] = std::array::from_fn(|a| (max[a] - min[a]) / dims[a] as f32);
+                let area = (0..3).filter(|&a| a != axis).map(|a| step[a]).product();
+                let mut points = vec![];
+                for i in 0..dims[0] {
+                    for j in 0..dims[1] {
+                        for k in 0..dims[2] {
+                            let node = [i, j, k];
+                            points.push(std::array::from_fn(|a| match a == axis {
+                                true => min[a],
+                                false => min[a] + (node[a] as f32 + 0.5) * step[a],
+                            }));
+                        }
+                    }
+                }
+                (info(PROBE_FLUX_SECTION, normal, area), points)
+            }
+        }
+    }
+}
+
+impl Probes {
+    pub fn new(device: &wgpu::Device, probes: &[Probe], max_records: u32) -> Self {
+        let mut infos = vec![];
+        let mut points = vec![];
+        for (idx, probe) in probes.iter().enumerate() {
+            let (mut info, probe_points) = probe.points();
+            info.first_point = points.len() as u32;
+            info.num_points = probe_points.len() as u32;
+            infos.push(info);
+            points.extend(probe_points.into_iter().map(|position| ProbePoint {
+                position,
+                probe: idx as u32,
+            }));
+        }
+        // Bindings can't be empty
+        if infos.is_empty() {
+            infos.push(ProbeInfo::default());
+            points.push(ProbePoint::default());
+        }
+        let module_reduce = ShaderModuleBuilder::new()
+            .add_module(include_str!("../resample/sample.wgsl"))
+            .add_module(include_str!("./probe.wgsl"))
+            .add_module(include_str!("./reduce.wgsl"))
+            .build(device, Some("Shader Module Probes"));
+        let buffer_probes = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Probes"),
+            contents: bytemuck::cast_slice(&infos),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let buffer_points = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Probe Points"),
+            contents: bytemuck::cast_slice(&points),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let buffer_samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Probe Samples"),
+            size: (points.len() * 32) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let history_size = (max_records as usize * infos.len() * std::mem::size_of::<f32>()) as u64;
+        let buffer_history = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Probe History"),
+            size: history_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let buffer_records = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Probe Records"),
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_history = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Probe History"),
+            size: history_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_records = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Probe Records"),
+            size: 16,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Probes"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Probes"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_probes.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_samples.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_history.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_records.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Probes"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_reduce =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Reduce Probes"),
+                layout: Some(&pipeline_layout),
+                module: &module_reduce,
+                entry_point: Some("reduce_probes"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_advance =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Advance Probe Records"),
+                layout: Some(&pipeline_layout),
+                module: &module_reduce,
+                entry_point: Some("advance_probe_records"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        Probes {
+            names: probes
+                .iter()
+                .map(|probe| probe.name().to_string())
+                .collect(),
+            max_records,
+            buffer_points,
+            buffer_samples,
+            buffer_probes,
+            buffer_history,
+            buffer_records,
+            staging_buffer_history,
+            staging_buffer_records,
+            bind_group,
+            compute_pipeline_reduce,
+            compute_pipeline_advance,
+        }
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn num_points(&self) -> u32 {
+        (self.buffer_points.size() / std::mem::size_of::<ProbePoint>() as u64) as u32
+    }
+
+    // Reduce the samples of the step into the next record, after the solver sampled the points
+    pub fn encode(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_reduce);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups((self.names.len() as u32).div_ceil(64), 1, 1);
+        compute_pass.set_pipeline(&self.compute_pipeline_advance);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    // Values of every step recorded since the last call, one row per step with one value per
+    // probe. Steps beyond `max_records` are dropped, so read at least that often.
+    pub fn gpu2cpu_values(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Probes"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_records,
+            0,
+            &self.staging_buffer_records,
+            0,
+            self.buffer_records.size(),
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_history,
+            0,
+            &self.staging_buffer_history,
+            0,
+            self.buffer_history.size(),
+        );
+        encoder.clear_buffer(&self.buffer_records, 0, None);
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffers
+        let records_slice = self.staging_buffer_records.slice(..);
+        records_slice.map_async(wgpu::MapMode::Read, |_| {});
+        let history_slice = self.staging_buffer_history.slice(..);
+        history_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let records_data = records_slice.get_mapped_range();
+        let count: u32 = bytemuck::cast_slice(&records_data)[0];
+        drop(records_data);
+        self.staging_buffer_records.unmap();
+        let history_data = history_slice.get_mapped_range();
+        let history: &[f32] = bytemuck::cast_slice(&history_data);
+        let num_probes = self.names.len();
+        let rows = match num_probes {
+            0 => vec![],
+            _ => history
+                .chunks(num_probes)
+                .take(count.min(self.max_records) as usize)
+                .map(|row| row.to_vec())
+                .collect(),
+        };
+        drop(history_data);
+        self.staging_buffer_history.unmap();
+        rows
+    }
+}
+
+fn positive(value: f32) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_points() {
+        let gauge = Probe::WaveGauge {
+            name: "gauge".to_string(),
+            position: [0.1, -0.5, 0.0],
+            height: 1.0,
+            spacing: 0.1,
+        };
+        let (info, points) = gauge.points();
+        assert_eq!(info.kind, PROBE_WAVE_GAUGE);
+        assert_eq!(points.len(), 11);
+        assert!((points[10][1] - 0.5).abs() < 1e-5);
+        let section = Probe::FluxSection {
+            name: "section".to_string(),
+            min: [0.2, -0.5, -0.5],
+            max: [0.2, 0.0, 0.5],
+            spacing: 0.2,
+        };
+        section.check().unwrap();
+        let (info, points) = section.points();
+        assert_eq!(info.normal, [1.0, 0.0, 0.0]);
+        // 3 by 5 cells of 1/6 by 1/5
+        assert_eq!(points.len(), 15);
+        assert!((info.weight * points.len() as f32 - 0.5).abs() < 1e-5);
+        assert!(points.iter().all(|point| point[0] == 0.2));
+        assert!((points[0][1] + 0.5 - 1.0 / 12.0).abs() < 1e-5);
+        let skewed = Probe::FluxSection {
+            name: "skewed".to_string(),
+            min: [0.0; 3],
+            max: [1.0; 3],
+            spacing: 0.2,
+        };
+        assert!(skewed.check().is_err());
+    }
+}
@@ -0,0 +1,9 @@
+// WGSL file for probes sampled every time step
+// Each probe is expanded into sample points, the solver interpolates its fields at the points and
+// reduce.wgsl turns the samples of each probe into one value
+
+struct ProbePoint {
+    position: vec3f,
+    probe: u32,
+    // 16 bytes
+}
@@ -0,0 +1,105 @@
+struct ProbeInfo {
+    kind: u32,
+    first_point: u32,
+    num_points: u32,
+    _padding: u32,
+    // Section normal, unused by the other kinds
+    normal: vec3f,
+    // Point spacing of a wave gauge or area per point of a flux section
+    weight: f32,
+    // 32 bytes
+}
+
+struct ProbeRecords {
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+    // 16 bytes
+}
+
+const PROBE_PRESSURE: u32 = 0u;
+const PROBE_WAVE_GAUGE: u32 = 1u;
+const PROBE_FLUX_SECTION: u32 = 2u;
+
+// Wet points of a wave gauge, the volume fraction is about one inside the fluid
+const WET_FRACTION: f32 = 0.5;
+
+@group(0) @binding(0)
+var<storage, read> probes: array<ProbeInfo>;
+
+@group(0) @binding(1)
+var<storage, read> probe_points: array<ProbePoint>;
+
+@group(0) @binding(2)
+var<storage, read> probe_samples: array<FieldSample>;
+
+@group(0) @binding(3)
+var<storage, read_write> probe_history: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> probe_records: ProbeRecords;
+
+// Highest point of the gauge where the fluid crosses the wet fraction, interpolated between the
+// points around it. Gauge points go upwards along y.
+fn wave_elevation(probe: ProbeInfo) -> f32 {
+    let first = probe.first_point;
+    for (var i = probe.num_points; i > 0u; i--) {
+        let point = first + i - 1u;
+        let fraction = probe_samples[point].volume_fraction;
+        if (fraction >= WET_FRACTION) {
+            let elevation = probe_points[point].position.y;
+            if (i == probe.num_points) {
+                return elevation;
+            }
+            let fraction_above = probe_samples[point + 1u].volume_fraction;
+            return elevation + probe.weight * (fraction - WET_FRACTION) / (fraction - fraction_above);
+        }
+    }
+    // Dry gauge
+    return probe_points[first].position.y;
+}
+
+// Volume flow rate through the section along its normal
+fn flow_rate(probe: ProbeInfo) -> f32 {
+    var rate = 0.0;
+    for (var point = probe.first_point; point < probe.first_point + probe.num_points; point++) {
+        let sample = probe_samples[point];
+        rate += sample.volume_fraction * dot(sample.velocity, probe.normal);
+    }
+    return rate * probe.weight;
+}
+
+// One value per probe into the record of the current step, records past the history are dropped
+@compute @workgroup_size(64)
+fn reduce_probes(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let num_probes = arrayLength(&probes);
+    if (index >= num_probes) {
+        return;
+    }
+    let record = probe_records.count;
+    if (record >= arrayLength(&probe_history) / num_probes) {
+        return;
+    }
+    let probe = probes[index];
+    var value = 0.0;
+    switch probe.kind {
+        case PROBE_PRESSURE: {
+            value = probe_samples[probe.first_point].pressure;
+        }
+        case PROBE_WAVE_GAUGE: {
+            value = wave_elevation(probe);
+        }
+        case PROBE_FLUX_SECTION: {
+            value = flow_rate(probe);
+        }
+        default: {}
+    }
+    probe_history[record * num_probes + index] = value;
+}
+
+@compute @workgroup_size(1)
+fn advance_probe_records() {
+    probe_records.count += 1u;
+}
@@ -1,11 +1,10 @@
 use crate::camera;
+use crate::checkpoint::SolverState;
 use crate::geometry::{SphereGeometry, SphereVertex};
-// use crate::mls_mpm::{MlsMpm, MlsMpmCompute};
-use crate::sph::{Sph, SphCompute};
+use crate::simulation::{InstancePass, Scene, Simulation};
 use crate::{shader_module::ShaderModuleBuilder, texture};
 use std::sync::Arc;
 use texture::Texture;
-use wgpu::ShaderStages;
 use wgpu::util::DeviceExt;
 use winit::event::DeviceEvent;
 use winit::{
@@ -65,8 +64,10 @@ impl Instance {
 }
 
 pub struct Renderer {
-    sim: Option<Sph>,
-    compute: Option<SphCompute>,
+    sim: Option<Box<dyn Scene>>,
+    compute: Option<Box<dyn Simulation>>,
+    // State right after init, uploaded again on reset
+    initial_state: Option<SolverState>,
     surface: Option<wgpu::Surface<'static>>,
     device: Option<wgpu::Device>,
     queue: Option<wgpu::Queue>,
@@ -90,8 +91,7 @@ pub struct Renderer {
     depth_texture: Option<texture::Texture>,
     // Particle Instances, only need position, no rotation
     instance_buffer: Option<wgpu::Buffer>,
-    particle_instance_bind_group: Option<wgpu::BindGroup>,
-    particle_instance_pipeline: Option<wgpu::ComputePipeline>,
+    particle_instance_pass: Option<InstancePass>,
 }
 
 impl Default for Renderer {
@@ -99,6 +99,7 @@ impl Default for Renderer {
         Self {
             sim: None,
             compute: None,
+            initial_state: None,
 
             surface: None,
             device: None,
@@ -123,8 +124,7 @@ impl Default for Renderer {
             depth_texture: None,
 
             instance_buffer: None,
-            particle_instance_bind_group: None,
-            particle_instance_pipeline: None,
+            particle_instance_pass: None,
         }
     }
 }
@@ -220,8 +220,9 @@ impl ApplicationHandler for Renderer {
 }
 
 impl Renderer {
-    pub fn attach_sim(&mut self, sim: Sph) {
-        self.sim = Some(sim);
+    // Either solver, e.g. `Sph` or `MlsMpm`
+    pub fn attach_sim(&mut self, sim: impl Scene + 'static) {
+        self.sim = Some(Box::new(sim));
         println!("Sim attached successfully");
     }
 
@@ -274,12 +275,10 @@ impl Renderer {
 
         // Initialize Compute Buffers and Pipelines
         let sim = self.sim.as_ref().expect("Sim not initialized");
-        let compute = pollster::block_on(SphCompute::new(&device, &sim.params));
-        // write buffers to compute
-        compute.cpu2gpu_params(&queue, &sim.params);
-        compute.cpu2gpu_disturbance(&queue, &sim.disturbance);
-        compute.cpu2gpu_particles(&queue, &sim.particles, &sim.motion);
-        compute.cpu2gpu_materials(&queue, &sim.materials);
+        let compute = sim.init(&device, &queue);
+        let initial_state = compute
+            .checkpointing()
+            .map(|checkpointing| checkpointing.gpu2cpu_state(&device, &queue));
 
         // Initialize Camera
         let camera = camera::Camera::new((0.0, 0.0, 2.5), cgmath::Deg(-90.0), cgmath::Deg(0.0));
@@ -331,11 +330,9 @@ impl Renderer {
         let vertex_shader = ShaderModuleBuilder::new()
             .add_module(include_str!("./vertex_shader.wgsl"))
             .build(&device, Some("Vertex Shader"));
-        let particle_to_instance_shader = ShaderModuleBuilder::new()
-            .add_module(include_str!("./particle_to_instance.wgsl"))
-            .build(&device, Some("Particle to Instance Shader"));
 
-        let num_particles = sim.params.num_particles;
+        // One instance per particle slot
+        let num_particles = compute.capacity();
         let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Instance Buffer"),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
@@ -343,91 +340,6 @@ impl Renderer {
             mapped_at_creation: false,
         });
 
-        let particle_instance_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("Particle Instance Bind Group Layout"),
-            });
-
-        let particle_instance_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &particle_instance_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: compute.buffer_particles.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: compute.buffer_materials.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: compute.buffer_params.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: instance_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("Particle Instance Bind Group"),
-        });
-
-        let particle_instance_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Particle Instance Pipeline Layout"),
-                bind_group_layouts: &[&particle_instance_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let particle_instance_pipeline =
-            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Particle Instance Pipeline"),
-                layout: Some(&particle_instance_pipeline_layout),
-                module: &particle_to_instance_shader,
-                entry_point: Some("particle_to_instance"),
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                cache: None,
-            });
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
@@ -478,8 +390,11 @@ impl Renderer {
             cache: None,
         });
 
+        let particle_instance_pass = compute.instance_pass(&device, &instance_buffer);
+
         // Set fields
         self.compute = Some(compute);
+        self.initial_state = initial_state;
         self.surface = Some(surface);
         self.device = Some(device);
         self.queue = Some(queue);
@@ -501,8 +416,7 @@ impl Renderer {
         self.depth_texture = Some(depth_texture);
 
         self.instance_buffer = Some(instance_buffer);
-        self.particle_instance_bind_group = Some(particle_instance_bind_group);
-        self.particle_instance_pipeline = Some(particle_instance_pipeline);
+        self.particle_instance_pass = Some(particle_instance_pass);
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -538,10 +452,11 @@ impl Renderer {
                 let physical_key = key.physical_key;
                 match physical_key {
                     PhysicalKey::Code(KeyCode::KeyR) => {
-                        if let (Some(compute), Some(sim), Some(queue)) =
-                            (&self.compute, &self.sim, &self.queue)
+                        if let (Some(compute), Some(initial_state), Some(queue)) =
+                            (&self.compute, &self.initial_state, &self.queue)
+                            && let Some(checkpointing) = compute.checkpointing()
                         {
-                            compute.cpu2gpu_particles(queue, &sim.particles, &sim.motion);
+                            checkpointing.cpu2gpu_state(queue, initial_state);
                             true
                         } else {
                             false
@@ -578,8 +493,6 @@ impl Renderer {
             Some(camera_uniform),
             Some(projection),
             Some(queue),
-            Some(device),
-            Some(compute),
         ) = (
             &mut self.camera,
             &self.camera_buffer,
@@ -587,8 +500,6 @@ impl Renderer {
             &mut self.camera_uniform,
             &self.projection,
             &self.queue,
-            &self.device,
-            &self.compute,
         ) {
             // Camera Controller Update
             camera_controller.update_camera(camera, dt);
@@ -598,78 +509,34 @@ impl Renderer {
                 0,
                 bytemuck::cast_slice(&[self.camera_uniform.expect("Camera uniform not init")]),
             );
-
-            // Hydrodynamics Update
-            compute.compute_hash_grid(device, queue);
-            //TODO: Sort spatial on GPU side
-            // Get out the scattered spatial lookup
-            let mut spatial = compute.gpu2cpu_spatial_scattered(device, queue);
-            let mut start_indices = compute.gpu2cpu_start_indices(device, queue);
-            let n = compute.num_particles as usize;
-            let mut spatial_lookup = vec![(0, 0); n];
-            for i in 0..n {
-                spatial_lookup[i] = (spatial[i].index, spatial[i].key);
-            }
-            spatial_lookup.sort_by_cached_key(|k| k.1);
-            let mut key_prev = spatial_lookup[0].1;
-            start_indices[key_prev as usize] = 0;
-            for i in 0..n {
-                let key = spatial_lookup[i].1;
-                if key != key_prev {
-                    start_indices[key as usize] = i as u32;
-                }
-                key_prev = key;
-                spatial[i].index = spatial_lookup[i].0;
-                spatial[i].key = spatial_lookup[i].1;
-            }
-            // map sorted spatial and start indices back to gpu
-            compute.cpu2gpu_spatial_sorted(queue, &spatial);
-            compute.cpu2gpu_start_indices(queue, &start_indices);
-            // continue with dynammics
-            compute.compute_density_interpolant(device, queue);
-            compute.compute_pressure_equation_of_state(device, queue);
-            compute.compute_equation_of_motion(device, queue);
-            compute.compute_leap_frog(device, queue);
         }
-        self.compute_particle_to_instance();
+        self.compute_step();
     }
 
-    fn compute_particle_to_instance(&self) {
-        if let (Some(device), Some(queue), Some(instance_pipeline)) =
-            (&self.device, &self.queue, &self.particle_instance_pipeline)
+    // Hydrodynamics update and particle instances in a single submission
+    fn compute_step(&self) {
+        if let (Some(device), Some(queue), Some(compute)) =
+            (&self.device, &self.queue, &self.compute)
         {
+            compute.prepare_step(device, queue, 1);
             let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Comput Command Encoder"),
             });
+            compute.encode_step(&mut encoder, 1);
+            self.encode_particle_to_instance(&mut encoder);
+            queue.submit([encoder.finish()]);
+        }
+    }
+
+    fn encode_particle_to_instance(&self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(instance_pass) = &self.particle_instance_pass {
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("Compute Pass Particle Instance"),
                 timestamp_writes: None,
             });
-            compute_pass.set_pipeline(instance_pipeline);
-            compute_pass.set_bind_group(
-                0,
-                self.particle_instance_bind_group
-                    .as_ref()
-                    .expect("Particle Instance not init"),
-                &[],
-            );
-            compute_pass.dispatch_workgroups(
-                (self
-                    .sim
-                    .as_ref()
-                    .expect("sim not init")
-                    .params
-                    .num_particles
-                    + 255)
-                    / 256,
-                1,
-                1,
-            );
-            // Drop compute pass
-            drop(compute_pass);
-            // Submit commands to queue
-            let command_buffer = encoder.finish();
-            queue.submit([command_buffer]);
+            compute_pass.set_pipeline(&instance_pass.compute_pipeline);
+            compute_pass.set_bind_group(0, &instance_pass.bind_group, &[]);
+            compute_pass.dispatch_workgroups(instance_pass.num_workgroups, 1, 1);
         }
     }
 
@@ -760,12 +627,7 @@ impl Renderer {
             render_pass.draw_indexed(
                 0..self.num_indices.expect("No vertex indices"),
                 0,
-                0..self
-                    .sim
-                    .as_ref()
-                    .expect("sim not init")
-                    .params
-                    .num_particles,
+                0..self.compute.as_ref().expect("sim not init").capacity(),
             );
         }
 
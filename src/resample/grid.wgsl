@@ -0,0 +1,24 @@
+// WGSL file for fields resampled on a regular grid of nodes
+// Requires a `field_grid: FieldGrid` uniform binding, the samples are in sample.wgsl
+
+struct FieldGrid {
+    origin: vec3f,
+    num_nodes: u32,
+    spacing: vec3f,
+    _padding: u32,
+    dims: vec3u,
+    _padding2: u32,
+    // 48 bytes
+}
+
+// Nodes are numbered with x varying fastest, as in VTK image data
+fn grid_node(index: u32) -> vec3f {
+    let dims = field_grid.dims;
+    let node = vec3u(index % dims.x, (index / dims.x) % dims.y, index / (dims.x * dims.y));
+    return field_grid.origin + vec3f(node) * field_grid.spacing;
+}
+
+// Node index of a 2-D dispatch, the node count can exceed the workgroups of one dimension
+fn grid_node_index(global_id: vec3u, num_workgroups: vec3u) -> u32 {
+    return global_id.x + global_id.y * num_workgroups.x * 256u;
+}
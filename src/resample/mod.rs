@@ -0,0 +1,309 @@
+use crate::vtk::{self, AppendedArray};
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+// Regular grid of nodes in world units, numbered with x varying fastest. A plane is a grid with
+// a single node along one axis.
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct FieldGrid {
+    pub origin: [f32; 3],
+    pub num_nodes: u32,
+    pub spacing: [f32; 3],
+    pub _padding: u32,
+    pub dims: [u32; 3],
+    pub _padding2: u32,
+}
+
+// Particle quantities interpolated at a node. Density, pressure and velocity are kernel averages
+// weighted by the particle volumes, the volume fraction is the kernel sum of those volumes, about
+// one inside the fluid and zero away from it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct FieldSample {
+    pub velocity: [f32; 3],
+    pub density: f32,
+    pub pressure: f32,
+    pub volume_fraction: f32,
+    pub _padding: [f32; 2],
+    // 32 bytes
+}
+
+pub struct FieldBuffers {
+    pub grid: FieldGrid,
+    pub buffer_grid: wgpu::Buffer,
+    pub buffer_samples: wgpu::Buffer,
+    pub staging_buffer_samples: wgpu::Buffer,
+}
+
+// Samples of one resampling pass with the grid they belong to
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub grid: FieldGrid,
+    pub samples: Vec<FieldSample>,
+}
+
+impl FieldGrid {
+    pub fn new(origin: [f32; 3], spacing: [f32; 3], dims: [u32; 3]) -> Self {
+        let dims = dims.map(|n| n.max(1));
+        FieldGrid {
+            origin,
+            num_nodes: dims.iter().product(),
+            spacing,
+            _padding: 0,
+            dims,
+            _padding2: 0,
+        }
+    }
+
+    // Nodes `spacing` apart from `min`, up to `max` included
+    pub fn from_bounds(min: [f32; 3], max: [f32; 3], spacing: f32) -> Self {
+        let dims = std::array::from_fn(|a| ((max[a] - min[a]) / spacing + 1e-4).floor() as u32 + 1);
+        Self::new(min, [spacing; 3], dims)
+    }
+
+    // Nodes in the plane normal to `axis` at `offset`, within the bounds along the other axes
+    pub fn plane(axis: usize, offset: f32, min: [f32; 3], max: [f32; 3], spacing: f32) -> Self {
+        let mut grid = Self::from_bounds(min, max, spacing);
+        grid.origin[axis] = offset;
+        grid.dims[axis] = 1;
+        grid.num_nodes = grid.dims.iter().product();
+        grid
+    }
+
+    pub fn node(&self, index: u32) -> [f32; 3] {
+        let [nx, ny, _] = self.dims;
+        let node = [index % nx, (index / nx) % ny, index / (nx * ny)];
+        std::array::from_fn(|a| self.origin[a] + node[a] as f32 * self.spacing[a])
+    }
+
+    // 2-D dispatch of 256 node workgroups, see grid_node_index
+    pub fn workgroups(&self, max_per_dimension: u32) -> (u32, u32) {
+        let groups = self.num_nodes.div_ceil(256);
+        let x = groups.min(max_per_dimension);
+        (x, groups.div_ceil(x))
+    }
+}
+
+impl FieldBuffers {
+    pub fn new(device: &wgpu::Device, grid: FieldGrid) -> Self {
+        let buffer_grid = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Field Grid"),
+            contents: bytemuck::bytes_of(&grid),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let samples_size = (grid.num_nodes as usize * std::mem::size_of::<FieldSample>()) as u64;
+        let buffer_samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Field Samples"),
+            size: samples_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Field Samples"),
+            size: samples_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        FieldBuffers {
+            grid,
+            buffer_grid,
+            buffer_samples,
+            staging_buffer_samples,
+        }
+    }
+
+    // Read back the samples written by the resampling pass
+    pub fn gpu2cpu_fields(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Fields {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Field Samples"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_samples,
+            0,
+            &self.staging_buffer_samples,
+            0,
+            self.buffer_samples.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_samples.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let samples = bytemuck::cast_slice(&output_data).to_vec();
+        drop(output_data);
+        self.staging_buffer_samples.unmap();
+        Fields {
+            grid: self.grid,
+            samples,
+        }
+    }
+}
+
+impl Fields {
+    pub fn densities(&self) -> Vec<f32> {
+        self.samples.iter().map(|sample| sample.density).collect()
+    }
+
+    pub fn pressures(&self) -> Vec<f32> {
+        self.samples.iter().map(|sample| sample.pressure).collect()
+    }
+
+    pub fn velocities(&self) -> Vec<[f32; 3]> {
+        self.samples.iter().map(|sample| sample.velocity).collect()
+    }
+
+    pub fn volume_fractions(&self) -> Vec<f32> {
+        self.samples
+            .iter()
+            .map(|sample| sample.volume_fraction)
+            .collect()
+    }
+
+    // VTK image data `.vti` or headerless `.raw` arrays by extension
+    pub fn save<P: AsRef<Path>>(&self, path: P, time: f64) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("vti") => self.write_vti(&mut writer, time)?,
+            Some("raw") => self.write_raw(&mut writer)?,
+            _ => bail!("{} is not a .vti or .raw file", path.display()),
+        }
+        writer
+            .flush()
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    // XML image data with the point arrays appended as raw little-endian binary
+    pub fn write_vti<W: Write>(&self, writer: &mut W, time: f64) -> Result<()> {
+        let time_value = [time];
+        let densities = self.densities();
+        let pressures = self.pressures();
+        let velocities = self.velocities();
+        let volume_fractions = self.volume_fractions();
+        let arrays: [AppendedArray; 5] = [
+            ("TimeValue", "Float64", 1, bytemuck::cast_slice(&time_value)),
+            ("density", "Float32", 1, bytemuck::cast_slice(&densities)),
+            ("pressure", "Float32", 1, bytemuck::cast_slice(&pressures)),
+            ("velocity", "Float32", 3, bytemuck::cast_slice(&velocities)),
+            (
+                "volume_fraction",
+                "Float32",
+                1,
+                bytemuck::cast_slice(&volume_fractions),
+            ),
+        ];
+        let elements = vtk::appended_elements(&arrays);
+        let [nx, ny, nz] = self.grid.dims;
+        let extent = format!("0 {} 0 {} 0 {}", nx - 1, ny - 1, nz - 1);
+        let [ox, oy, oz] = self.grid.origin;
+        let [dx, dy, dz] = self.grid.spacing;
+        writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            writer,
+            r#"<VTKFile type="ImageData" version="1.0" byte_order="LittleEndian" header_type="UInt64">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <ImageData WholeExtent="{}" Origin="{} {} {}" Spacing="{} {} {}">"#,
+            extent, ox, oy, oz, dx, dy, dz
+        )?;
+        writeln!(writer, "    <FieldData>")?;
+        writeln!(
+            writer,
+            "      {}",
+            elements[0].replace("/>", r#" NumberOfTuples="1"/>"#)
+        )?;
+        writeln!(writer, "    </FieldData>")?;
+        writeln!(writer, r#"    <Piece Extent="{}">"#, extent)?;
+        writeln!(
+            writer,
+            r#"      <PointData Scalars="density" Vectors="velocity">"#
+        )?;
+        for element in &elements[1..] {
+            writeln!(writer, "        {}", element)?;
+        }
+        writeln!(writer, "      </PointData>")?;
+        writeln!(writer, "    </Piece>")?;
+        writeln!(writer, "  </ImageData>")?;
+        vtk::write_appended_data(writer, &arrays)?;
+        writeln!(writer, "</VTKFile>")?;
+        Ok(())
+    }
+
+    // Little-endian f32 arrays one after the other: density, pressure, velocity with interleaved
+    // components and volume fraction, in node order. With numpy a field reads as
+    // `fromfile(path, "<f4", n, offset=...).reshape(nz, ny, nx)`.
+    pub fn write_raw<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(bytemuck::cast_slice(&self.densities()))?;
+        writer.write_all(bytemuck::cast_slice(&self.pressures()))?;
+        writer.write_all(bytemuck::cast_slice(&self.velocities()))?;
+        writer.write_all(bytemuck::cast_slice(&self.volume_fractions()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_grid_layout() {
+        let grid = FieldGrid::from_bounds([-0.5, 0.0, 0.0], [0.5, 0.2, 0.1], 0.1);
+        assert_eq!(grid.dims, [11, 3, 2]);
+        assert_eq!(grid.num_nodes, 66);
+        // x varies fastest
+        assert_eq!(grid.node(1), [-0.4, 0.0, 0.0]);
+        let node = grid.node(11 * 3 + 11 + 2);
+        assert!((node[0] + 0.3).abs() < 1e-6 && (node[1] - 0.1).abs() < 1e-6);
+        assert!((node[2] - 0.1).abs() < 1e-6);
+        let plane = FieldGrid::plane(2, 0.05, [-0.5, 0.0, -1.0], [0.5, 0.2, 1.0], 0.1);
+        assert_eq!(plane.dims, [11, 3, 1]);
+        assert_eq!(plane.node(0), [-0.5, 0.0, 0.05]);
+        assert_eq!(grid.workgroups(65535), (1, 1));
+        assert_eq!(
+            FieldGrid::new([0.0; 3], [1.0; 3], [1024, 1024, 64]).workgroups(65535),
+            (65535, 5)
+        );
+
+        let fields = Fields {
+            grid: plane,
+            samples: (0..plane.num_nodes)
+                .map(|i| FieldSample {
+                    velocity: [i as f32, 0.0, -1.0],
+                    density: 1000.0,
+                    pressure: i as f32,
+                    volume_fraction: 1.0,
+                    _padding: [0.0; 2],
+                })
+                .collect(),
+        };
+        let mut raw = vec![];
+        fields.write_raw(&mut raw).unwrap();
+        let values: Vec<f32> = bytemuck::pod_collect_to_vec(&raw);
+        assert_eq!(values.len(), 6 * 33);
+        assert_eq!(values[33 + 5], 5.0);
+        assert_eq!(values[66 + 3 * 5..66 + 3 * 6], [5.0, 0.0, -1.0]);
+        let mut vti = vec![];
+        fields.write_vti(&mut vti, 0.5).unwrap();
+        let text = String::from_utf8_lossy(&vti);
+        assert!(text.contains(r#"WholeExtent="0 10 0 2 0 0" Origin="-0.5 0 0.05""#));
+        // Time, density, pressure, velocity and volume fraction blocks with their byte counts
+        assert!(text.contains(
+            r#"Name="volume_fraction" NumberOfComponents="1" format="appended" offset="700""#
+        ));
+        let data_start = text.find("\n   _").unwrap() + 5;
+        let closing = "\n  </AppendedData>\n</VTKFile>\n";
+        assert_eq!(
+            vti.len(),
+            data_start + 5 * 8 + 8 + 6 * 33 * 4 + closing.len()
+        );
+    }
+}
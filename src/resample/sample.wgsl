@@ -0,0 +1,11 @@
+// WGSL file for particle quantities interpolated at a point, shared by the grid resampling and
+// the probes
+
+struct FieldSample {
+    velocity: vec3f,
+    density: f32,
+    pressure: f32,
+    volume_fraction: f32,
+    _padding: vec2f,
+    // 32 bytes
+}
@@ -0,0 +1,19 @@
+// WGSL file adding the fixed point impulses of a step to the float totals of the rigid bodies
+// The accumulators are cleared for the next step
+
+@group(0) @binding(0)
+var<storage, read_write> impulses: array<atomic<i32>>;
+
+// Counts per unit impulse of each accumulator
+@group(0) @binding(1)
+var<storage, read> scales: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> totals: array<f32>;
+
+@compute @workgroup_size(64)
+fn flush_impulses(@builtin(local_invocation_index) local_index: u32) {
+    for (var idx = local_index; idx < arrayLength(&totals); idx += 64u) {
+        totals[idx] += f32(atomicExchange(&impulses[idx], 0)) / scales[idx];
+    }
+}
@@ -0,0 +1,95 @@
+// WGSL file advancing the rigid bodies over a step, before flush_impulses clears the impulses the
+// fluid exchanged with them. Free bodies take a semi-implicit Euler step of the Newton-Euler
+// equations, see RigidBody::integrate, and driven bodies take their pose from the schedule.
+// Requires state.wgsl
+
+struct RigidBodyMass {
+    // Principal moments of inertia in the body frame
+    inertia: vec3f,
+    mass: f32,
+    driven: u32,
+    _padding: array<u32, 3>,
+}
+
+struct RigidBodyIntegration {
+    gravity: vec3f,
+    dt: f32,
+    // Steps advanced since the schedule was uploaded
+    step: u32,
+    num_steps: u32,
+    _padding: vec2u,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> bodies: array<RigidBodyState>;
+
+@group(0) @binding(1)
+var<storage, read_write> impulses: array<atomic<i32>>;
+
+@group(0) @binding(2)
+var<storage, read> masses: array<RigidBodyMass>;
+
+@group(0) @binding(3)
+var<storage, read_write> integration: RigidBodyIntegration;
+
+// States of the bodies at the end of each scheduled step, only read for the driven bodies
+@group(0) @binding(4)
+var<storage, read> schedule: array<RigidBodyState>;
+
+// Hamilton product of quaternions (x, y, z, w)
+fn quat_multiply(a: vec4f, b: vec4f) -> vec4f {
+    return vec4f(a.w * b.xyz + b.w * a.xyz + cross(a.xyz, b.xyz), a.w * b.w - dot(a.xyz, b.xyz));
+}
+
+fn body_impulses(body_idx: u32, body: RigidBodyState) -> array<vec3f, 2> {
+    let base = body_idx * IMPULSE_STRIDE;
+    let impulse = vec3f(
+        f32(atomicLoad(&impulses[base + 0u])),
+        f32(atomicLoad(&impulses[base + 1u])),
+        f32(atomicLoad(&impulses[base + 2u])),
+    );
+    let angular_impulse = vec3f(
+        f32(atomicLoad(&impulses[base + 3u])),
+        f32(atomicLoad(&impulses[base + 4u])),
+        f32(atomicLoad(&impulses[base + 5u])),
+    );
+    return array(impulse / body.linear_scale, angular_impulse / body.angular_scale);
+}
+
+@compute @workgroup_size(64)
+fn integrate_bodies(@builtin(local_invocation_index) local_index: u32) {
+    let step = integration.step;
+    let dt = integration.dt;
+    let num_bodies = arrayLength(&masses);
+    for (var idx = local_index; idx < num_bodies; idx += 64u) {
+        var body = bodies[idx];
+        let mass = masses[idx];
+        if (mass.driven != 0u) {
+            // Steps past the schedule hold its last pose
+            let scheduled = schedule[min(step, max(integration.num_steps, 1u) - 1u) * num_bodies + idx];
+            body.position = scheduled.position;
+            body.orientation = scheduled.orientation;
+            body.linear_velocity = scheduled.linear_velocity;
+            body.angular_velocity = scheduled.angular_velocity;
+        } else {
+            let exchanged = body_impulses(idx, body);
+            body.linear_velocity += exchanged[0] / mass.mass + integration.gravity * dt;
+            // Angular momentum balance in the body frame, including the gyroscopic term
+            let omega_body = quat_rotate_inverse(body.orientation, body.angular_velocity);
+            let angular_impulse_body = quat_rotate_inverse(body.orientation, exchanged[1]);
+            let momentum_body = omega_body * mass.inertia;
+            let domega_body = (angular_impulse_body - cross(omega_body, momentum_body) * dt) / mass.inertia;
+            body.angular_velocity += quat_rotate(body.orientation, domega_body);
+            // Advance position and orientation with the updated velocities
+            body.position += body.linear_velocity * dt;
+            let spin = quat_multiply(vec4f(body.angular_velocity, 0.0), body.orientation) * 0.5;
+            body.orientation = normalize(body.orientation + spin * dt);
+        }
+        bodies[idx] = body;
+    }
+    // Every invocation has read the step
+    storageBarrier();
+    if (local_index == 0u) {
+        integration.step = step + 1u;
+    }
+}
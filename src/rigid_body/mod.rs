@@ -0,0 +1,719 @@
+use crate::obstacle::{ObstacleBuffers, SignedDistanceField, TriangleMesh};
+use crate::shader_module::ShaderModuleBuilder;
+use crate::simulation::{RigidBodyMotion, gpu2cpu_buffer};
+use crate::wavemaker::Wavemaker;
+use cgmath::*;
+use wgpu::util::DeviceExt;
+
+// Fixed point counts of the impulse accumulators per unit change of the body velocity, matches
+// impulse_to_i32 in the shaders. The accumulators are flushed to floats every step, so they
+// overflow only if a single step changes the velocity of a body by more than 2e3.
+pub const IMPULSE_SCALE: f32 = 1.0e6;
+// Accumulator slots per body: impulse xyz, angular impulse xyz, padding
+pub const IMPULSE_STRIDE: usize = 8;
+// Steps of the driven bodies scheduled per upload, see RigidBodyBuffers::schedule
+pub const SCHEDULE_STEPS: u32 = 64;
+
+pub const SHAPE_SPHERE: u32 = 0;
+pub const SHAPE_CUBOID: u32 = 1;
+pub const SHAPE_MESH: u32 = 2;
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct RigidBodyState {
+    pub position: [f32; 3],
+    pub shape: u32,
+    pub orientation: [f32; 4], // quaternion (x, y, z, w)
+    pub linear_velocity: [f32; 3],
+    pub friction: f32,
+    pub angular_velocity: [f32; 3],
+    pub restitution: f32,
+    pub half_extents: [f32; 3],
+    pub sdf_index: u32,
+    // Accumulator counts per unit impulse and angular impulse
+    pub linear_scale: f32,
+    pub angular_scale: f32,
+    pub _padding: [f32; 2],
+    // 96 bytes
+}
+
+// Mass properties of a body on the GPU, see integrate.wgsl
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct RigidBodyMass {
+    pub inertia: [f32; 3],
+    pub mass: f32,
+    // Non-zero for the paddles of wavemakers, which follow the schedule
+    pub driven: u32,
+    pub _padding: [u32; 3],
+}
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct RigidBodyIntegration {
+    pub gravity: [f32; 3],
+    pub dt: f32,
+    // Steps advanced since the schedule was uploaded, counted on the GPU
+    pub step: u32,
+    pub num_steps: u32,
+    pub _padding: [u32; 2],
+}
+
+#[derive(Clone)]
+pub enum RigidShape {
+    Sphere { radius: f32 },
+    Cuboid { half_extents: [f32; 3] },
+    // Distance field in the body frame, centred on the centre of mass
+    Mesh { sdf: SignedDistanceField },
+}
+
+#[derive(Clone)]
+pub struct RigidBody {
+    pub shape: RigidShape,
+    pub mass: f32,
+    // Principal moments of inertia in the body frame
+    pub inertia: Vector3<f32>,
+    pub position: Vector3<f32>,
+    pub orientation: Quaternion<f32>,
+    pub linear_velocity: Vector3<f32>,
+    pub angular_velocity: Vector3<f32>,
+    pub friction: f32,
+    pub restitution: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RigidBodyLoad {
+    pub force: [f32; 3],
+    pub torque: [f32; 3],
+}
+
+// The solver passes accumulate the impulses of a step into `buffer_impulses`, with which
+// `encode_flush` integrates the bodies on the GPU before adding the impulses to the totals read
+// back by gpu2cpu_loads
+pub struct RigidBodyBuffers {
+    pub num_bodies: u32,
+    pub buffer_bodies: wgpu::Buffer,
+    pub buffer_impulses: wgpu::Buffer,
+    buffer_scales: wgpu::Buffer,
+    buffer_totals: wgpu::Buffer,
+    staging_buffer_totals: wgpu::Buffer,
+    buffer_masses: wgpu::Buffer,
+    buffer_integration: wgpu::Buffer,
+    // SCHEDULE_STEPS states of every body
+    buffer_schedule: wgpu::Buffer,
+    // Distance fields of mesh shapes, indexed by sdf_index
+    pub fields: ObstacleBuffers,
+    bind_group_flush: wgpu::BindGroup,
+    compute_pipeline_flush: wgpu::ComputePipeline,
+    bind_group_integrate: wgpu::BindGroup,
+    compute_pipeline_integrate: wgpu::ComputePipeline,
+}
+
+impl RigidBody {
+    pub fn sphere(radius: f32, density: f32, position: [f32; 3]) -> Self {
+        let mass = density * 4.0 / 3.0 * std::f32::consts::PI * radius.powi(3);
+        let moment = 0.4 * mass * radius * radius;
+        Self::with_shape(
+            RigidShape::Sphere { radius },
+            mass,
+            Vector3::new(moment, moment, moment),
+            position,
+        )
+    }
+
+    pub fn cuboid(half_extents: [f32; 3], density: f32, position: [f32; 3]) -> Self {
+        let [hx, hy, hz] = half_extents;
+        let mass = density * 8.0 * hx * hy * hz;
+        let inertia = Vector3::new(
+            mass * (hy * hy + hz * hz) / 3.0,
+            mass * (hx * hx + hz * hz) / 3.0,
+            mass * (hx * hx + hy * hy) / 3.0,
+        );
+        Self::with_shape(RigidShape::Cuboid { half_extents }, mass, inertia, position)
+    }
+
+    // Mass properties are integrated over the interior nodes of the distance field. Products of
+    // inertia are neglected, so meshes should be exported aligned with their principal axes.
+    pub fn mesh(mesh: &TriangleMesh, cell_size: f32, density: f32) -> Self {
+        let mut sdf = SignedDistanceField::from_mesh(mesh, cell_size, 2);
+        let [ni, nj, nk] = sdf.dims;
+        let cell_volume = cell_size.powi(3);
+        let node = |i: u32, j: u32, k: u32| {
+            Vector3::new(
+                sdf.origin[0] + i as f32 * cell_size,
+                sdf.origin[1] + j as f32 * cell_size,
+                sdf.origin[2] + k as f32 * cell_size,
+            )
+        };
+        let mut mass = 0.0;
+        let mut first_moment = Vector3::zero();
+        for k in 0..nk {
+            for j in 0..nj {
+                for i in 0..ni {
+                    if sdf.values[(i + ni * (j + nj * k)) as usize] < 0.0 {
+                        mass += density * cell_volume;
+                        first_moment += node(i, j, k) * density * cell_volume;
+                    }
+                }
+            }
+        }
+        let centroid = first_moment / mass.max(f32::EPSILON);
+        let mut inertia = Vector3::zero();
+        for k in 0..nk {
+            for j in 0..nj {
+                for i in 0..ni {
+                    if sdf.values[(i + ni * (j + nj * k)) as usize] < 0.0 {
+                        let r = node(i, j, k) - centroid;
+                        let dm = density * cell_volume;
+                        inertia += Vector3::new(
+                            r.y * r.y + r.z * r.z,
+                            r.x * r.x + r.z * r.z,
+                            r.x * r.x + r.y * r.y,
+                        ) * dm;
+                    }
+                }
+            }
+        }
+        // Move the field into the body frame
+        for a in 0..3 {
+            sdf.origin[a] -= centroid[a];
+        }
+        Self::with_shape(RigidShape::Mesh { sdf }, mass, inertia, centroid.into())
+    }
+
+    fn with_shape(shape: RigidShape, mass: f32, inertia: Vector3<f32>, position: [f32; 3]) -> Self {
+        RigidBody {
+            shape,
+            mass,
+            inertia,
+            position: position.into(),
+            orientation: Quaternion::one(),
+            linear_velocity: Vector3::zero(),
+            angular_velocity: Vector3::zero(),
+            friction: 0.2,
+            restitution: 0.0,
+        }
+    }
+
+    // Semi-implicit Euler step of the Newton-Euler equations
+    pub fn integrate(&mut self, load: &RigidBodyLoad, gravity: [f32; 3], dt: f32) {
+        let force = Vector3::from(load.force);
+        let torque = Vector3::from(load.torque);
+        self.linear_velocity += (force / self.mass + Vector3::from(gravity)) * dt;
+        // Angular momentum balance in the body frame, including the gyroscopic term
+        let rotation = Matrix3::from(self.orientation);
+        let omega_body = rotation.transpose() * self.angular_velocity;
+        let torque_body = rotation.transpose() * torque;
+        let momentum_body = omega_body.mul_element_wise(self.inertia);
+        let domega_body =
+            (torque_body - omega_body.cross(momentum_body)).div_element_wise(self.inertia);
+        self.angular_velocity += rotation * domega_body * dt;
+        // Advance position and orientation with the updated velocities
+        self.position += self.linear_velocity * dt;
+        let spin = Quaternion::from_sv(0.0, self.angular_velocity) * self.orientation * 0.5;
+        self.orientation = (self.orientation + spin * dt).normalize();
+    }
+
+    // Signed distance of a point to the surface, negative inside
+    pub fn distance(&self, point: [f32; 3]) -> f32 {
+        let rotation = Matrix3::from(self.orientation);
+        let local = rotation.transpose() * (Vector3::from(point) - self.position);
+        match &self.shape {
+            RigidShape::Sphere { radius } => local.magnitude() - radius,
+            RigidShape::Cuboid { half_extents } => {
+                let q = local.map(f32::abs) - Vector3::from(*half_extents);
+                q.map(|x| x.max(0.0)).magnitude() + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            RigidShape::Mesh { sdf } => sdf.sample(local.into()),
+        }
+    }
+
+    // Pose and velocities with lengths multiplied by `scale_distance`, e.g. the size of the
+    // MLS-MPM domain
+    pub fn motion(&self, scale_distance: f32) -> RigidBodyMotion {
+        let q = self.orientation;
+        RigidBodyMotion {
+            position: (self.position * scale_distance).into(),
+            orientation: [q.v.x, q.v.y, q.v.z, q.s],
+            linear_velocity: (self.linear_velocity * scale_distance).into(),
+            angular_velocity: self.angular_velocity.into(),
+        }
+    }
+
+    pub fn state(&self, sdf_index: u32) -> RigidBodyState {
+        let (shape, half_extents) = match &self.shape {
+            RigidShape::Sphere { radius } => (SHAPE_SPHERE, [*radius; 3]),
+            RigidShape::Cuboid { half_extents } => (SHAPE_CUBOID, *half_extents),
+            RigidShape::Mesh { sdf } => {
+                let half_extents = sdf.dims.map(|n| 0.5 * (n - 1) as f32 * sdf.cell_size);
+                (SHAPE_MESH, half_extents)
+            }
+        };
+        let q = self.orientation;
+        let [linear_scale, angular_scale] = self.impulse_scales();
+        RigidBodyState {
+            position: self.position.into(),
+            shape,
+            orientation: [q.v.x, q.v.y, q.v.z, q.s],
+            linear_velocity: self.linear_velocity.into(),
+            friction: self.friction,
+            angular_velocity: self.angular_velocity.into(),
+            restitution: self.restitution,
+            half_extents,
+            sdf_index,
+            linear_scale,
+            angular_scale,
+            _padding: [0.0; 2],
+        }
+    }
+
+    // Counts per unit impulse and angular impulse, relative to the mass and mean moment of inertia
+    fn impulse_scales(&self) -> [f32; 2] {
+        let moment = self.inertia.sum() / 3.0;
+        [
+            IMPULSE_SCALE / self.mass.max(f32::EPSILON),
+            IMPULSE_SCALE / moment.max(f32::EPSILON),
+        ]
+    }
+}
+
+impl RigidBodyBuffers {
+    pub fn new(device: &wgpu::Device, bodies: &[RigidBody]) -> Self {
+        let fields: Vec<(&SignedDistanceField, f32, f32)> = bodies
+            .iter()
+            .filter_map(|body| match &body.shape {
+                RigidShape::Mesh { sdf } => Some((sdf, body.friction, body.restitution)),
+                _ => None,
+            })
+            .collect();
+        let fields = ObstacleBuffers::from_fields(device, &fields);
+        let states = rigid_body_states(bodies);
+        let buffer_bodies = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Rigid Bodies"),
+            contents: bytemuck::cast_slice(&states),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let impulse_size = (bodies.len() * IMPULSE_STRIDE * std::mem::size_of::<i32>()) as u64;
+        let buffer_impulses = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Rigid Body Impulses"),
+            size: impulse_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffer_scales = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Rigid Body Impulse Scales"),
+            contents: bytemuck::cast_slice(&impulse_scales(bodies)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_totals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Rigid Body Impulse Totals"),
+            size: impulse_size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_totals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Rigid Body Impulse Totals"),
+            size: impulse_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffer_masses = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Rigid Body Masses"),
+            contents: bytemuck::cast_slice(&rigid_body_masses(bodies, &[])),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_integration = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Rigid Body Integration"),
+            contents: bytemuck::bytes_of(&RigidBodyIntegration {
+                gravity: [0.0; 3],
+                dt: 0.0,
+                step: 0,
+                num_steps: 0,
+                _padding: [0; 2],
+            }),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_schedule = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Rigid Body Schedule"),
+            size: (SCHEDULE_STEPS as usize * states.len() * std::mem::size_of::<RigidBodyState>())
+                as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let module_flush = ShaderModuleBuilder::new()
+            .add_module(include_str!("./flush.wgsl"))
+            .build(device, Some("Shader Module Flush Rigid Body Impulses"));
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Flush Rigid Body Impulses"),
+            entries: &[
+                storage_entry(0, false),
+                storage_entry(1, true),
+                storage_entry(2, false),
+            ],
+        });
+        let bind_group_flush = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Flush Rigid Body Impulses"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_impulses.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_scales.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_totals.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Flush Rigid Body Impulses"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_flush =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Flush Rigid Body Impulses"),
+                layout: Some(&pipeline_layout),
+                module: &module_flush,
+                entry_point: Some("flush_impulses"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let module_integrate = ShaderModuleBuilder::new()
+            .add_module(include_str!("./state.wgsl"))
+            .add_module(include_str!("./integrate.wgsl"))
+            .build(device, Some("Shader Module Integrate Rigid Bodies"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Integrate Rigid Bodies"),
+            entries: &[
+                storage_entry(0, false),
+                storage_entry(1, false),
+                storage_entry(2, true),
+                storage_entry(3, false),
+                storage_entry(4, true),
+            ],
+        });
+        let bind_group_integrate = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Integrate Rigid Bodies"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_bodies.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_impulses.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_masses.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_integration.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_schedule.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Integrate Rigid Bodies"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_integrate =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Integrate Rigid Bodies"),
+                layout: Some(&pipeline_layout),
+                module: &module_integrate,
+                entry_point: Some("integrate_bodies"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        RigidBodyBuffers {
+            num_bodies: bodies.len() as u32,
+            buffer_bodies,
+            buffer_impulses,
+            buffer_scales,
+            buffer_totals,
+            staging_buffer_totals,
+            buffer_masses,
+            buffer_integration,
+            buffer_schedule,
+            fields,
+            bind_group_flush,
+            compute_pipeline_flush,
+            bind_group_integrate,
+            compute_pipeline_integrate,
+        }
+    }
+
+    // Upload the body states and clear the impulse accumulators
+    pub fn cpu2gpu_bodies(&self, queue: &wgpu::Queue, bodies: &[RigidBody]) {
+        let states = rigid_body_states(bodies);
+        queue.write_buffer(&self.buffer_bodies, 0, bytemuck::cast_slice(&states));
+        queue.write_buffer(
+            &self.buffer_scales,
+            0,
+            bytemuck::cast_slice(&impulse_scales(bodies)),
+        );
+        let zeros = vec![0i32; bodies.len() * IMPULSE_STRIDE];
+        queue.write_buffer(&self.buffer_impulses, 0, bytemuck::cast_slice(&zeros));
+        queue.write_buffer(&self.buffer_totals, 0, bytemuck::cast_slice(&zeros));
+    }
+
+    // Upload the state of the body `idx` of `bodies`, leaving the others and the impulses as they are
+    pub fn cpu2gpu_body(&self, queue: &wgpu::Queue, bodies: &[RigidBody], idx: usize) {
+        let state = rigid_body_states(bodies)[idx];
+        queue.write_buffer(
+            &self.buffer_bodies,
+            (idx * std::mem::size_of::<RigidBodyState>()) as u64,
+            bytemuck::bytes_of(&state),
+        );
+    }
+
+    // Integrate the bodies with the impulses of the step and add these to the totals, after the
+    // last coupling pass of each step
+    pub fn encode_flush(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_integrate);
+        compute_pass.set_bind_group(0, &self.bind_group_integrate, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+        compute_pass.set_pipeline(&self.compute_pipeline_flush);
+        compute_pass.set_bind_group(0, &self.bind_group_flush, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    // Read back the body states into `bodies`, which keep their shapes and mass properties
+    pub fn gpu2cpu_bodies(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bodies: &mut [RigidBody],
+    ) {
+        let states: Vec<RigidBodyState> = gpu2cpu_buffer(device, queue, &self.buffer_bodies);
+        for (body, state) in bodies.iter_mut().zip(&states) {
+            let [x, y, z, w] = state.orientation;
+            body.position = state.position.into();
+            body.orientation = Quaternion::new(w, x, y, z);
+            body.linear_velocity = state.linear_velocity.into();
+            body.angular_velocity = state.angular_velocity.into();
+        }
+    }
+
+    // Read back the impulses exchanged with the fluid since the last call or upload and convert
+    // them to mean loads over `elapsed`
+    pub fn gpu2cpu_loads(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) -> Vec<RigidBodyLoad> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Rigid Body Impulses"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_totals,
+            0,
+            &self.staging_buffer_totals,
+            0,
+            self.buffer_totals.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_totals.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let totals: Vec<f32> = bytemuck::cast_slice(&output_data).to_vec();
+        drop(output_data);
+        self.staging_buffer_totals.unmap();
+        queue.write_buffer(
+            &self.buffer_totals,
+            0,
+            bytemuck::cast_slice(&vec![0.0f32; totals.len()]),
+        );
+        let to_load = |v: f32| v / elapsed;
+        totals
+            .chunks_exact(IMPULSE_STRIDE)
+            .map(|c| RigidBodyLoad {
+                force: [to_load(c[0]), to_load(c[1]), to_load(c[2])],
+                torque: [to_load(c[3]), to_load(c[4]), to_load(c[5])],
+            })
+            .collect()
+    }
+
+    // Prepare the integration of the next `steps` steps of `dt`, at most SCHEDULE_STEPS, on the
+    // GPU. The paddles of `wavemakers`, one entry per body, are scheduled to their poses at the
+    // end of each step from `time` on. The other bodies are held over each step and integrated
+    // after it with the impulses the fluid exchanged with them, see integrate.wgsl. This explicit
+    // coupling lags the fluid by one step, which is stable while a step moves a body by much less
+    // than a particle spacing and the body is not much lighter than the fluid it displaces.
+    #[allow(clippy::too_many_arguments)]
+    pub fn schedule(
+        &self,
+        queue: &wgpu::Queue,
+        bodies: &[RigidBody],
+        wavemakers: &[Option<Wavemaker>],
+        gravity: [f32; 3],
+        dt: f32,
+        time: f64,
+        steps: u32,
+    ) {
+        if wavemakers.iter().any(Option::is_some) {
+            assert!(steps <= SCHEDULE_STEPS, "{} steps past the schedule", steps);
+            let mut poses = bodies.to_vec();
+            let mut schedule = vec![];
+            for step in 1..=steps {
+                for (body, wavemaker) in poses.iter_mut().zip(wavemakers) {
+                    if let Some(wavemaker) = wavemaker {
+                        wavemaker.update(body, time + step as f64 * dt as f64);
+                    }
+                }
+                schedule.extend(rigid_body_states(&poses));
+            }
+            queue.write_buffer(&self.buffer_schedule, 0, bytemuck::cast_slice(&schedule));
+        }
+        queue.write_buffer(
+            &self.buffer_masses,
+            0,
+            bytemuck::cast_slice(&rigid_body_masses(bodies, wavemakers)),
+        );
+        let integration = RigidBodyIntegration {
+            gravity,
+            dt,
+            step: 0,
+            num_steps: steps,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(
+            &self.buffer_integration,
+            0,
+            bytemuck::bytes_of(&integration),
+        );
+    }
+}
+
+fn rigid_body_states(bodies: &[RigidBody]) -> Vec<RigidBodyState> {
+    let mut sdf_index = 0;
+    let mut states = vec![];
+    for body in bodies {
+        states.push(body.state(sdf_index));
+        if let RigidShape::Mesh { .. } = body.shape {
+            sdf_index += 1;
+        }
+    }
+    states
+}
+
+// Driven bodies are the ones with an entry in `wavemakers`
+fn rigid_body_masses(bodies: &[RigidBody], wavemakers: &[Option<Wavemaker>]) -> Vec<RigidBodyMass> {
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(idx, body)| RigidBodyMass {
+            inertia: body.inertia.map(|i| i.max(f32::EPSILON)).into(),
+            mass: body.mass.max(f32::EPSILON),
+            driven: matches!(wavemakers.get(idx), Some(Some(_))) as u32,
+            _padding: [0; 3],
+        })
+        .collect()
+}
+
+// Counts per unit impulse of every accumulator slot, see IMPULSE_SCALE
+fn impulse_scales(bodies: &[RigidBody]) -> Vec<f32> {
+    bodies
+        .iter()
+        .flat_map(|body| {
+            let [linear, angular] = body.impulse_scales();
+            [linear, linear, linear, angular, angular, angular, 1.0, 1.0]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rigid_body_free_motion() {
+        let mut body = RigidBody::sphere(0.1, 1000.0, [0.0, 1.0, 0.0]);
+        body.angular_velocity = Vector3::new(0.0, 2.0, 0.0);
+        let dt = 1.0e-3;
+        for _ in 0..1000 {
+            body.integrate(&RigidBodyLoad::default(), [0.0, -9.81, 0.0], dt);
+        }
+
+        // One second of free fall
+        assert!((body.linear_velocity.y + 9.81).abs() < 1e-3);
+        assert!((body.position.y - (1.0 - 0.5 * 9.81)).abs() < 1e-2);
+
+        // Spin about a principal axis is preserved and the orientation stays normalized
+        assert!((body.angular_velocity.y - 2.0).abs() < 1e-5);
+        assert!((body.orientation.magnitude() - 1.0).abs() < 1e-5);
+        let expected = Quaternion::from(Euler::new(Rad(0.0), Rad(2.0), Rad(0.0)));
+        assert!(body.orientation.dot(expected).abs() > 0.999);
+    }
+
+    #[test]
+    fn test_rigid_body_gpu_integration() {
+        // Without impulses the GPU follows the CPU integration, gyroscopic term included
+        let mut body = RigidBody::cuboid([0.1, 0.2, 0.3], 1000.0, [0.0, 1.0, 0.0]);
+        body.angular_velocity = Vector3::new(1.0, 2.0, 0.5);
+        let gravity = [0.0, -9.81, 0.0];
+        let dt = 1.0e-3;
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the test needs a wgpu adapter");
+        let buffers = RigidBodyBuffers::new(&device, std::slice::from_ref(&body));
+        buffers.schedule(
+            &queue,
+            std::slice::from_ref(&body),
+            &[],
+            gravity,
+            dt,
+            0.0,
+            100,
+        );
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Rigid Body Integration"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Rigid Body Integration"),
+            timestamp_writes: None,
+        });
+        for _ in 0..100 {
+            buffers.encode_flush(&mut compute_pass);
+        }
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        let mut integrated = vec![body.clone()];
+        buffers.gpu2cpu_bodies(&device, &queue, &mut integrated);
+        for _ in 0..100 {
+            body.integrate(&RigidBodyLoad::default(), gravity, dt);
+        }
+        assert!((integrated[0].position - body.position).magnitude() < 1e-5);
+        assert!((integrated[0].angular_velocity - body.angular_velocity).magnitude() < 1e-4);
+        assert!(integrated[0].orientation.dot(body.orientation).abs() > 1.0 - 1e-6);
+    }
+}
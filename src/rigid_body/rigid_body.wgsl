@@ -0,0 +1,59 @@
+// WGSL file for rigid bodies coupled to the fluid
+// Requires state.wgsl, `bodies`, `impulses` and the `obstacles`/`sdf` bindings used by mesh shapes
+
+// Signed distance to the body surface in the body frame
+fn body_sdf(body: RigidBodyState, local: vec3f) -> f32 {
+    switch body.shape {
+        case SHAPE_SPHERE: {
+            return length(local) - body.half_extents.x;
+        }
+        case SHAPE_CUBOID: {
+            let q = abs(local) - body.half_extents;
+            return length(max(q, vec3f(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+        }
+        default: {
+            return sample_sdf(body.sdf_index, local);
+        }
+    }
+}
+
+// Outward normal in the body frame
+fn body_normal(body: RigidBodyState, local: vec3f) -> vec3f {
+    if (body.shape != SHAPE_SPHERE && body.shape != SHAPE_CUBOID) {
+        return sdf_normal(body.sdf_index, local);
+    }
+    let h = 1e-3 * length(body.half_extents);
+    let gradient = vec3f(
+        body_sdf(body, local + vec3f(h, 0.0, 0.0)) - body_sdf(body, local - vec3f(h, 0.0, 0.0)),
+        body_sdf(body, local + vec3f(0.0, h, 0.0)) - body_sdf(body, local - vec3f(0.0, h, 0.0)),
+        body_sdf(body, local + vec3f(0.0, 0.0, h)) - body_sdf(body, local - vec3f(0.0, 0.0, h)),
+    );
+    let norm = length(gradient);
+    if (norm < 1e-12) {
+        return vec3f(0.0, 1.0, 0.0);
+    }
+    return gradient / norm;
+}
+
+// Velocity of the body surface at a world position
+fn body_point_velocity(body: RigidBodyState, position: vec3f) -> vec3f {
+    return body.linear_velocity + cross(body.angular_velocity, position - body.position);
+}
+
+// Single contributions saturate instead of wrapping around
+fn impulse_to_i32(value: f32, scale: f32) -> i32 {
+    return i32(clamp(value * scale, -2.0e9, 2.0e9));
+}
+
+// Accumulate the impulse received by the body at a world position, in counts of the body
+// velocity change so that the precision follows the size of the body
+fn accumulate_body_impulse(body_idx: u32, body: RigidBodyState, position: vec3f, impulse: vec3f) {
+    let angular_impulse = cross(position - body.position, impulse);
+    let base = body_idx * IMPULSE_STRIDE;
+    atomicAdd(&impulses[base + 0u], impulse_to_i32(impulse.x, body.linear_scale));
+    atomicAdd(&impulses[base + 1u], impulse_to_i32(impulse.y, body.linear_scale));
+    atomicAdd(&impulses[base + 2u], impulse_to_i32(impulse.z, body.linear_scale));
+    atomicAdd(&impulses[base + 3u], impulse_to_i32(angular_impulse.x, body.angular_scale));
+    atomicAdd(&impulses[base + 4u], impulse_to_i32(angular_impulse.y, body.angular_scale));
+    atomicAdd(&impulses[base + 5u], impulse_to_i32(angular_impulse.z, body.angular_scale));
+}
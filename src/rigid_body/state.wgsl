@@ -0,0 +1,31 @@
+// WGSL file for the rigid body states shared by the coupling and integration passes
+
+struct RigidBodyState {
+    position: vec3f,
+    shape: u32,
+    orientation: vec4f, // quaternion (x, y, z, w)
+    linear_velocity: vec3f,
+    friction: f32,
+    angular_velocity: vec3f,
+    restitution: f32,
+    half_extents: vec3f,
+    sdf_index: u32,
+    // Accumulator counts per unit impulse and angular impulse
+    linear_scale: f32,
+    angular_scale: f32,
+    _padding: vec2f,
+    // 96 bytes
+}
+
+const SHAPE_SPHERE: u32 = 0u;
+const SHAPE_CUBOID: u32 = 1u;
+const IMPULSE_STRIDE: u32 = 8u;
+
+fn quat_rotate(q: vec4f, v: vec3f) -> vec3f {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn quat_rotate_inverse(q: vec4f, v: vec3f) -> vec3f {
+    return quat_rotate(vec4f(-q.xyz, q.w), v);
+}
@@ -0,0 +1,1610 @@
+use crate::checkpoint::SolverState;
+use crate::damping::DampingZone;
+use crate::loads::LoadTarget;
+use crate::mls_mpm::{self, MlsMpm, MlsMpmCompute};
+use crate::obstacle::{Obstacle, TriangleMesh};
+use crate::point_cloud::{AttributeMap, PointCloud};
+use crate::probe::{MAX_RECORDS, Probe, Probes};
+use crate::rigid_body::RigidBody;
+use crate::seeding::{Lattice, Seeder, Shape, split_sph_position};
+use crate::simulation::{CpuSimulation, Scene, Simulation};
+use crate::sph::{self, Integrator, Periodicity, Sph, SphCompute, SphCpu, Walls};
+use crate::wavemaker::{MotionSignal, StokesOrder, Wavemaker, WavemakerMotion};
+use anyhow::*;
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+// Declarative description of a run, read from TOML or RON. All lengths, velocities and forces
+// are in world units, the loader converts them for the MLS-MPM unit domain.
+//
+//     solver = "sph"
+//     dt = 0.001                     # or cfl = 0.3 for SPH
+//     end_time = 2.0
+//     gravity = [0.0, -9.81, 0.0]
+//     reorder_interval = 200         # steps between Morton reorders of the particles, 0 never
+//
+//     [domain]
+//     periodic = [false, false, true]
+//
+//     [[materials]]
+//     name = "water"
+//     density = 1000.0
+//
+//     [[blocks]]
+//     material = "water"
+//     min = [-0.5, -0.5, -0.5]
+//     max = [0.0, 0.0, 0.5]
+//     spacing = 0.02
+//
+//     [[point_sets]]                 # PLY or CSV particles, see point_cloud::AttributeMap
+//     file = "drop.ply"
+//     material = "water"
+//     spacing = 0.02
+//
+//     [[probes]]                     # or wave_gauge and flux_section, see probe::Probe
+//     type = "pressure"
+//     name = "p1"
+//     position = [0.45, -0.45, 0.0]
+//
+//     [[loads]]                      # or tag and obstacle, SPH only, see loads::LoadTarget
+//     type = "region"
+//     name = "floor"
+//     min = [-0.5, -0.5, -0.5]
+//     max = [0.5, -0.5, 0.5]
+//
+//     [[rigid_bodies]]               # or sphere and mesh, see RigidBodyShape
+//     shape = { type = "cuboid", half_extents = [0.1, 0.1, 0.1] }
+//     density = 500.0
+//     position = [0.0, 0.0, 0.0]
+//
+//     [rigid_bodies.wavemaker]       # optional prescribed motion, see WavemakerDescription
+//     motion = { type = "piston", direction = [1.0, 0.0, 0.0] }
+//     signal = { type = "stokes", height = 0.02, period = 1.0, depth = 0.5 }
+//
+//     [output]
+//     interval = 100
+//
+// The SPH domain is the unit box around the origin, the MLS-MPM domain is [0, size]^3.
+// Inlets and outlets need capacity headroom and are attached in code, the step loop drives them.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    pub solver: SolverKind,
+    #[serde(default)]
+    pub dt: Option<Positive>,
+    // Time step as a fraction of smoothing length over sound speed, SPH only
+    #[serde(default)]
+    pub cfl: Option<Positive>,
+    #[serde(default)]
+    pub end_time: Option<Positive>,
+    #[serde(default)]
+    pub gravity: [f32; 3],
+    // Steps between the Morton reorders of the particle buffers, see attach_morton_reorder
+    #[serde(default)]
+    pub reorder_interval: u32,
+    #[serde(default)]
+    pub domain: Domain,
+    #[serde(default)]
+    pub sph: SphOptions,
+    #[serde(default)]
+    pub mpm: MpmOptions,
+    pub materials: Vec<MaterialDescription>,
+    #[serde(default)]
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub point_sets: Vec<PointSet>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDescription>,
+    #[serde(default)]
+    pub damping_zones: Vec<DampingDescription>,
+    #[serde(default)]
+    pub probes: Vec<Probe>,
+    #[serde(default)]
+    pub loads: Vec<LoadTarget>,
+    #[serde(default)]
+    pub rigid_bodies: Vec<RigidBodyDescription>,
+    #[serde(default)]
+    pub output: Output,
+    // Source text and directory, for error lines and relative paths
+    #[serde(skip)]
+    source: String,
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolverKind {
+    Sph,
+    Mpm,
+}
+
+// Strictly positive number, checked while parsing so that errors point at the value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Positive(pub f32);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Domain {
+    // Edge length of the MLS-MPM domain
+    #[serde(default = "default_size")]
+    pub size: Positive,
+    #[serde(default)]
+    pub periodic: [bool; 3],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SphOptions {
+    // Smoothing length over particle spacing
+    #[serde(default = "default_smoothing_factor")]
+    pub smoothing_factor: Positive,
+    #[serde(default)]
+    pub integrator: Integrator,
+    // Mirror the fluid across the walls, see Walls::mirrored
+    #[serde(default)]
+    pub mirror_walls: bool,
+    // Start from hydrostatic pressure below this height, see Sph::hydrostatic_init
+    #[serde(default)]
+    pub free_surface: Option<f32>,
+    // Damped steps run before t = 0, see SphCompute::relax
+    #[serde(default)]
+    pub relaxation_steps: u32,
+    #[serde(default = "default_relaxation_damping")]
+    pub relaxation_damping: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MpmOptions {
+    #[serde(default = "default_grid_resolution")]
+    pub grid_resolution: u32,
+}
+
+// Union of the SPH and MLS-MPM material parameters, fields of the other solver are ignored
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDescription {
+    pub name: String,
+    #[serde(default = "default_color")]
+    pub color: [f32; 4],
+    pub density: Positive,
+    // Fraction of the reference density below which pressure is clipped
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    // SPH
+    #[serde(default = "default_compressibility")]
+    pub compressibility: f32,
+    #[serde(default = "default_boundary_damping")]
+    pub boundary_damping: f32,
+    #[serde(default = "default_sound_speed")]
+    pub sound_speed: Positive,
+    #[serde(default = "default_alpha")]
+    pub alpha: f32,
+    #[serde(default = "default_beta")]
+    pub beta: f32,
+    #[serde(default = "default_eps")]
+    pub eps: f32,
+    // MLS-MPM
+    #[serde(default = "default_stiffness")]
+    pub stiffness: f32,
+    #[serde(default = "default_exponent")]
+    pub exponent: f32,
+    #[serde(default = "default_viscosity")]
+    pub viscosity: f32,
+    #[serde(default)]
+    pub rigid: bool,
+}
+
+// Box of particles seeded on a lattice
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Block {
+    pub material: String,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub spacing: Positive,
+    #[serde(default)]
+    pub lattice: Lattice,
+    // Random displacement as a fraction of the spacing
+    #[serde(default)]
+    pub jitter: f32,
+    #[serde(default)]
+    pub velocity: [f32; 3],
+    #[serde(default)]
+    pub tag: u32,
+}
+
+// Particles read from a PLY or CSV point cloud in world units, after the blocks
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PointSet {
+    // Relative to the scene file
+    pub file: PathBuf,
+    pub material: String,
+    // Nominal particle spacing, sets the smoothing length and the masses not read from the file
+    pub spacing: Positive,
+    #[serde(default)]
+    pub mapping: AttributeMap,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleDescription {
+    // OBJ file, relative to the scene file
+    pub obj: PathBuf,
+    #[serde(default = "default_scale")]
+    pub scale: Positive,
+    #[serde(default)]
+    pub translation: [f32; 3],
+    pub cell_size: Positive,
+    #[serde(default)]
+    pub friction: f32,
+    #[serde(default)]
+    pub restitution: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DampingDescription {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub direction: [f32; 3],
+    pub strength: Positive,
+    #[serde(default)]
+    pub target_velocity: [f32; 3],
+    // Exponent of the power profile
+    #[serde(default = "default_damping_exponent")]
+    pub exponent: Positive,
+}
+
+// Free body coupled with the fluid, block particles inside it are left out
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RigidBodyDescription {
+    pub shape: RigidBodyShape,
+    pub density: Positive,
+    // Centre of a sphere or cuboid, added to the mesh vertices
+    #[serde(default)]
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub velocity: [f32; 3],
+    #[serde(default)]
+    pub angular_velocity: [f32; 3],
+    #[serde(default = "default_body_friction")]
+    pub friction: f32,
+    #[serde(default)]
+    pub restitution: f32,
+    // Drives the body as a paddle, which then ignores the loads of the fluid
+    #[serde(default)]
+    pub wavemaker: Option<WavemakerDescription>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WavemakerDescription {
+    pub motion: PaddleMotion,
+    pub signal: PaddleSignal,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PaddleMotion {
+    // Translation along `direction`, the signal is the displacement
+    Piston { direction: [f32; 3] },
+    // Rotation about `axis` through `hinge`, the signal is the angle in radians
+    Flap { hinge: [f32; 3], axis: [f32; 3] },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PaddleSignal {
+    // Time and value columns relative to the scene file, see MotionSignal::from_csv
+    TimeSeries {
+        file: PathBuf,
+    },
+    // Regular waves of `height` and `period` in water of `depth`, flaps are first order only
+    Stokes {
+        height: Positive,
+        period: Positive,
+        depth: Positive,
+        #[serde(default)]
+        order: StokesOrder,
+    },
+    // Constant displacement or angle rate
+    Rate {
+        rate: f32,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RigidBodyShape {
+    Sphere {
+        radius: Positive,
+    },
+    Cuboid {
+        half_extents: [Positive; 3],
+    },
+    // OBJ file relative to the scene file, aligned with its principal axes
+    Mesh {
+        obj: PathBuf,
+        #[serde(default = "default_scale")]
+        scale: Positive,
+        cell_size: Positive,
+    },
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    // Steps between snapshots, none without
+    #[serde(default)]
+    pub interval: Option<u32>,
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    // Steps between conservation totals, see conservation::Totals
+    #[serde(default)]
+    pub conservation_interval: Option<u32>,
+    // Steps averaged into one row of loads
+    #[serde(default)]
+    pub load_interval: Option<u32>,
+}
+
+// Solver state built from a scene file, with the boundaries attached on init
+pub enum LoadedScene {
+    Sph(SphScene),
+    MlsMpm(MlsMpmScene),
+}
+
+pub struct SphScene {
+    pub sph: Sph,
+    pub integrator: Integrator,
+    pub relaxation_steps: u32,
+    pub relaxation_damping: f32,
+    pub reorder_interval: u32,
+    pub periodicity: Periodicity,
+    pub walls: Walls,
+    pub obstacles: Vec<Obstacle>,
+    pub damping_zones: Vec<DampingZone>,
+    pub probes: Vec<Probe>,
+    pub loads: Vec<LoadTarget>,
+    pub rigid_bodies: Vec<RigidBody>,
+    // One per rigid body, None for the free ones
+    pub wavemakers: Vec<Option<Wavemaker>>,
+}
+
+pub struct MlsMpmScene {
+    pub mls_mpm: MlsMpm,
+    pub reorder_interval: u32,
+    pub obstacles: Vec<Obstacle>,
+    pub damping_zones: Vec<DampingZone>,
+    pub probes: Vec<Probe>,
+    // Material whose equation of state gives the probe pressures
+    pub probe_material: u32,
+    // In the unit domain, see SceneFile::load_rigid_bodies
+    pub rigid_bodies: Vec<RigidBody>,
+    pub wavemakers: Vec<Option<Wavemaker>>,
+}
+
+fn default_size() -> Positive {
+    Positive(1.0)
+}
+fn default_smoothing_factor() -> Positive {
+    Positive(2.0)
+}
+fn default_relaxation_damping() -> f32 {
+    0.05
+}
+fn default_grid_resolution() -> u32 {
+    64
+}
+fn default_color() -> [f32; 4] {
+    [0.0, 0.0, 1.0, 1.0]
+}
+fn default_threshold() -> f32 {
+    0.7
+}
+fn default_compressibility() -> f32 {
+    0.1
+}
+fn default_boundary_damping() -> f32 {
+    0.8
+}
+fn default_sound_speed() -> Positive {
+    Positive(5.0)
+}
+fn default_alpha() -> f32 {
+    1.0
+}
+fn default_beta() -> f32 {
+    2.0
+}
+fn default_eps() -> f32 {
+    0.01
+}
+fn default_stiffness() -> f32 {
+    10.0
+}
+fn default_exponent() -> f32 {
+    4.0
+}
+fn default_viscosity() -> f32 {
+    0.1
+}
+fn default_scale() -> Positive {
+    Positive(1.0)
+}
+fn default_damping_exponent() -> Positive {
+    Positive(2.0)
+}
+fn default_body_friction() -> f32 {
+    0.2
+}
+
+impl Block {
+    // Blocks are jittered independently, seeded by their index
+    fn seeder(&self, block_idx: usize) -> Seeder {
+        Seeder::new(self.lattice, self.spacing.0).with_jitter(self.jitter, block_idx as u64)
+    }
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Domain {
+            size: default_size(),
+            periodic: [false; 3],
+        }
+    }
+}
+
+impl Default for SphOptions {
+    fn default() -> Self {
+        SphOptions {
+            smoothing_factor: default_smoothing_factor(),
+            integrator: Integrator::default(),
+            mirror_walls: false,
+            free_surface: None,
+            relaxation_steps: 0,
+            relaxation_damping: default_relaxation_damping(),
+        }
+    }
+}
+
+impl Default for MpmOptions {
+    fn default() -> Self {
+        MpmOptions {
+            grid_resolution: default_grid_resolution(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Positive {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f32::deserialize(deserializer)?;
+        match value > 0.0 && value.is_finite() {
+            true => std::result::Result::Ok(Positive(value)),
+            false => Err(serde::de::Error::custom(format!(
+                "expected a positive number, found {}",
+                value
+            ))),
+        }
+    }
+}
+
+// 1-based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+impl SceneFile {
+    // Format from the extension, `.toml` or `.ron`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut scene = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::parse_toml(&text),
+            Some("ron") => Self::parse_ron(&text),
+            _ => bail!("{}: expected a .toml or .ron scene", path.display()),
+        }
+        .with_context(|| format!("invalid scene {}", path.display()))?;
+        scene.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scene)
+    }
+
+    pub fn parse_toml(text: &str) -> Result<Self> {
+        let mut scene: SceneFile = toml::from_str(text).map_err(|error| {
+            let offset = error.span().map_or(0, |span| span.start);
+            let (line, column) = line_column(text, offset);
+            anyhow!("line {}, column {}: {}", line, column, error.message())
+        })?;
+        scene.source = text.to_string();
+        Ok(scene)
+    }
+
+    pub fn parse_ron(text: &str) -> Result<Self> {
+        // Optional fields are written without Some(...) as in TOML
+        let options = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+        let mut scene: SceneFile = options.from_str(text).map_err(|error| {
+            anyhow!(
+                "line {}, column {}: {}",
+                error.position.line,
+                error.position.col,
+                error.code
+            )
+        })?;
+        scene.source = text.to_string();
+        Ok(scene)
+    }
+
+    // Line of the first occurrence of `needle`, to place errors found after parsing
+    fn line_of(&self, needle: &str) -> usize {
+        self.source
+            .find(needle)
+            .map_or(0, |offset| line_column(&self.source, offset).0)
+    }
+
+    // `what` names the referencing block or point set in errors
+    fn material_index(&self, what: &str, material: &str) -> Result<u32> {
+        match self.materials.iter().position(|m| m.name == material) {
+            Some(idx) => Ok(idx as u32),
+            None => bail!(
+                "line {}: {} references unknown material {:?}",
+                self.line_of(&format!("{:?}", material)),
+                what,
+                material
+            ),
+        }
+    }
+
+    // Point clouds of the point sets with their material indices
+    fn load_point_sets(&self) -> Result<Vec<(PointCloud, u32)>> {
+        self.point_sets
+            .iter()
+            .enumerate()
+            .map(|(idx, set)| {
+                let what = format!("point set {}", idx);
+                let material_idx = self.material_index(&what, &set.material)?;
+                let cloud = PointCloud::load(self.base_dir.join(&set.file))
+                    .with_context(|| format!("line {}: {}", self.point_set_line(idx), what))?;
+                Ok((cloud, material_idx))
+            })
+            .collect()
+    }
+
+    fn point_set_line(&self, idx: usize) -> usize {
+        self.line_of(&self.point_sets[idx].file.display().to_string())
+    }
+
+    // Imported particles must lie in the domain and use known materials
+    fn check_point_set(
+        &self,
+        idx: usize,
+        positions: impl Iterator<Item = [f32; 3]>,
+        material_idx: impl Iterator<Item = u32>,
+        min: [f32; 3],
+        max: [f32; 3],
+    ) -> Result<()> {
+        let line = self.point_set_line(idx);
+        for (i, point) in positions.enumerate() {
+            if !(0..3).all(|a| min[a] <= point[a] && point[a] <= max[a]) {
+                bail!(
+                    "line {}: point {} {:?} of point set {} leaves the domain [{:?}, {:?}]",
+                    line,
+                    i,
+                    point,
+                    idx,
+                    min,
+                    max
+                );
+            }
+        }
+        if let Some(material_idx) = material_idx
+            .into_iter()
+            .find(|&m| m as usize >= self.materials.len())
+        {
+            bail!(
+                "line {}: point set {} uses material index {} of {} materials",
+                line,
+                idx,
+                material_idx,
+                self.materials.len()
+            );
+        }
+        Ok(())
+    }
+
+    // Time step from `dt` or from `cfl` with the smallest smoothing length and largest sound speed
+    fn time_step(&self, smoothing_length: f32) -> Result<f32> {
+        match (self.dt, self.cfl) {
+            (Some(dt), None) => Ok(dt.0),
+            (None, Some(cfl)) if self.solver == SolverKind::Sph => {
+                let sound_speed = self
+                    .materials
+                    .iter()
+                    .map(|m| m.sound_speed.0)
+                    .fold(0.0, f32::max);
+                Ok(cfl.0 * smoothing_length / sound_speed)
+            }
+            (None, Some(_)) => bail!(
+                "line {}: cfl is only supported by the sph solver, set dt",
+                self.line_of("cfl")
+            ),
+            (Some(_), Some(_)) => bail!("line {}: set either dt or cfl", self.line_of("cfl")),
+            (None, None) => bail!("scene sets neither dt nor cfl"),
+        }
+    }
+
+    // Lattice points of each block with their block index
+    fn lattice(&self, min: [f32; 3], max: [f32; 3]) -> Result<Vec<(usize, [f32; 3])>> {
+        let mut points = vec![];
+        for (block_idx, block) in self.blocks.iter().enumerate() {
+            let inside = (0..3).all(|a| {
+                min[a] <= block.min[a] && block.min[a] < block.max[a] && block.max[a] <= max[a]
+            });
+            if !inside {
+                bail!(
+                    "line {}: block {} [{:?}, {:?}] is empty or leaves the domain [{:?}, {:?}]",
+                    self.line_of(&format!("{:?}", block.material)),
+                    block_idx,
+                    block.min,
+                    block.max,
+                    min,
+                    max
+                );
+            }
+            let shape = Shape::Box {
+                min: block.min,
+                max: block.max,
+            };
+            let seeder = block.seeder(block_idx);
+            points.extend(seeder.points(&shape).into_iter().map(|p| (block_idx, p)));
+        }
+        Ok(points)
+    }
+
+    fn load_obstacles(&self, scale: f32) -> Result<Vec<Obstacle>> {
+        self.obstacles
+            .iter()
+            .map(|obstacle| {
+                Obstacle::from_obj(
+                    self.base_dir.join(&obstacle.obj),
+                    obstacle.scale.0 / scale,
+                    obstacle.translation.map(|x| x / scale),
+                    obstacle.cell_size.0 / scale,
+                    obstacle.friction,
+                    obstacle.restitution,
+                )
+            })
+            .collect()
+    }
+
+    // Bodies with lengths divided by `scale` and densities multiplied by `density_scale`, the
+    // MLS-MPM masses being in grid cells
+    fn load_rigid_bodies(
+        &self,
+        scale: f32,
+        density_scale: f32,
+        min: [f32; 3],
+        max: [f32; 3],
+    ) -> Result<Vec<RigidBody>> {
+        self.rigid_bodies
+            .iter()
+            .enumerate()
+            .map(|(idx, description)| {
+                let density = description.density.0 * density_scale;
+                let position = description.position.map(|x| x / scale);
+                let mut body = match &description.shape {
+                    RigidBodyShape::Sphere { radius } => {
+                        RigidBody::sphere(radius.0 / scale, density, position)
+                    }
+                    RigidBodyShape::Cuboid { half_extents } => {
+                        RigidBody::cuboid(half_extents.map(|h| h.0 / scale), density, position)
+                    }
+                    RigidBodyShape::Mesh {
+                        obj,
+                        scale: mesh_scale,
+                        cell_size,
+                    } => {
+                        let mut mesh = TriangleMesh::load_obj(self.base_dir.join(obj))
+                            .with_context(|| format!("rigid body {}", idx))?;
+                        mesh.transform(mesh_scale.0 / scale, position);
+                        RigidBody::mesh(&mesh, cell_size.0 / scale, density)
+                    }
+                };
+                let centre: [f32; 3] = (body.position * scale).into();
+                if !(0..3).all(|a| min[a] < centre[a] && centre[a] < max[a]) {
+                    bail!(
+                        "rigid body {} centred at {:?} leaves the domain [{:?}, {:?}]",
+                        idx,
+                        centre,
+                        min,
+                        max
+                    );
+                }
+                body.linear_velocity = description.velocity.map(|v| v / scale).into();
+                body.angular_velocity = description.angular_velocity.into();
+                body.friction = description.friction;
+                body.restitution = description.restitution;
+                Ok(body)
+            })
+            .collect()
+    }
+
+    // Paddle motion of the bodies with lengths divided by `scale`
+    fn load_wavemakers(&self, bodies: &[RigidBody], scale: f32) -> Result<Vec<Option<Wavemaker>>> {
+        let gravity = self.gravity.iter().map(|g| g * g).sum::<f32>().sqrt() / scale;
+        self.rigid_bodies
+            .iter()
+            .zip(bodies)
+            .enumerate()
+            .map(|(idx, (description, body))| {
+                let Some(wavemaker) = &description.wavemaker else {
+                    return Ok(None);
+                };
+                let what = format!("wavemaker of rigid body {}", idx);
+                let unit = |v: [f32; 3]| -> Result<Vector3<f32>> {
+                    let v = Vector3::from(v);
+                    match v.magnitude() > 0.0 {
+                        true => Ok(v.normalize()),
+                        false => bail!("{} has a zero direction or axis", what),
+                    }
+                };
+                if let PaddleSignal::Stokes { .. } = wavemaker.signal
+                    && gravity == 0.0
+                {
+                    bail!("{} needs gravity for stokes waves", what);
+                }
+                let wavemaker = match (&wavemaker.motion, &wavemaker.signal) {
+                    (
+                        PaddleMotion::Piston { direction },
+                        PaddleSignal::Stokes {
+                            height,
+                            period,
+                            depth,
+                            order,
+                        },
+                    ) => Wavemaker::piston_stokes(
+                        body,
+                        unit(*direction)?.into(),
+                        height.0 / scale,
+                        period.0,
+                        depth.0 / scale,
+                        gravity,
+                        *order,
+                    ),
+                    (
+                        PaddleMotion::Flap { hinge, axis },
+                        PaddleSignal::Stokes {
+                            height,
+                            period,
+                            depth,
+                            order,
+                        },
+                    ) => {
+                        if *order != StokesOrder::First {
+                            bail!("{} only has first order waves as a flap", what);
+                        }
+                        Wavemaker::flap_stokes(
+                            body,
+                            hinge.map(|x| x / scale),
+                            unit(*axis)?.into(),
+                            height.0 / scale,
+                            period.0,
+                            depth.0 / scale,
+                            gravity,
+                        )
+                    }
+                    (motion, signal) => {
+                        let signal = match signal {
+                            PaddleSignal::TimeSeries { file } => {
+                                MotionSignal::from_csv(self.base_dir.join(file))
+                                    .with_context(|| what.clone())?
+                            }
+                            PaddleSignal::Rate { rate } => MotionSignal::Rate { rate: *rate },
+                            PaddleSignal::Stokes { .. } => unreachable!(),
+                        };
+                        // Displacements are scaled with the direction, angles are kept
+                        let motion = match motion {
+                            PaddleMotion::Piston { direction } => WavemakerMotion::Piston {
+                                direction: unit(*direction)? / scale,
+                            },
+                            PaddleMotion::Flap { hinge, axis } => WavemakerMotion::Flap {
+                                hinge: Vector3::from(*hinge) / scale,
+                                axis: unit(*axis)?,
+                            },
+                        };
+                        Wavemaker::new(motion, signal, body)
+                    }
+                };
+                Ok(Some(wavemaker))
+            })
+            .collect()
+    }
+
+    fn damping(&self, scale: f32) -> Vec<DampingZone> {
+        self.damping_zones
+            .iter()
+            .map(|zone| {
+                DampingZone::new(
+                    zone.min.map(|x| x / scale),
+                    zone.max.map(|x| x / scale),
+                    zone.direction,
+                    zone.strength.0,
+                )
+                .with_target_velocity(zone.target_velocity.map(|v| v / scale))
+                .with_profile(crate::damping::DampingProfile::Power(zone.exponent.0))
+            })
+            .collect()
+    }
+
+    pub fn build(&self) -> Result<LoadedScene> {
+        if self.materials.is_empty() {
+            bail!("scene has no materials");
+        }
+        for (idx, material) in self.materials.iter().enumerate() {
+            if self.materials[..idx]
+                .iter()
+                .any(|m| m.name == material.name)
+            {
+                bail!(
+                    "line {}: duplicate material {:?}",
+                    self.line_of(&format!("{:?}", material.name)),
+                    material.name
+                );
+            }
+        }
+        for (idx, probe) in self.probes.iter().enumerate() {
+            let line = self.line_of(&format!("{:?}", probe.name()));
+            probe
+                .check()
+                .with_context(|| format!("line {}: probe {}", line, idx))?;
+            if self.probes[..idx].iter().any(|p| p.name() == probe.name()) {
+                bail!("line {}: duplicate probe {:?}", line, probe.name());
+            }
+        }
+        for (idx, load) in self.loads.iter().enumerate() {
+            let line = self.line_of(&format!("{:?}", load.name()));
+            load.check()
+                .with_context(|| format!("line {}: load {}", line, idx))?;
+            if self.loads[..idx].iter().any(|l| l.name() == load.name()) {
+                bail!("line {}: duplicate load {:?}", line, load.name());
+            }
+            if let LoadTarget::Obstacle { obstacle, .. } = load
+                && *obstacle as usize >= self.obstacles.len()
+            {
+                bail!(
+                    "line {}: load {:?} references obstacle {} of {}",
+                    line,
+                    load.name(),
+                    obstacle,
+                    self.obstacles.len()
+                );
+            }
+            if self.solver != SolverKind::Sph {
+                bail!("line {}: loads are only measured by the SPH solver", line);
+            }
+        }
+        match self.solver {
+            SolverKind::Sph => self.build_sph().map(LoadedScene::Sph),
+            SolverKind::Mpm => self.build_mpm().map(LoadedScene::MlsMpm),
+        }
+    }
+
+    fn build_sph(&self) -> Result<SphScene> {
+        use sph::*;
+        let (min, max) = ([-0.5; 3], [0.5; 3]);
+        let rigid_bodies = self.load_rigid_bodies(1.0, 1.0, min, max)?;
+        let mut points = self.lattice(min, max)?;
+        points.retain(|(_, point)| rigid_bodies.iter().all(|body| body.distance(*point) > 0.0));
+        let point_sets = self.load_point_sets()?;
+        let smoothing_factor = self.sph.smoothing_factor.0;
+        // Smoothing lengths of the blocks then of the point sets
+        let smoothing_lengths: Vec<f32> = self
+            .blocks
+            .iter()
+            .map(|block| smoothing_factor * block.spacing.0)
+            .chain(
+                self.point_sets
+                    .iter()
+                    .map(|set| smoothing_factor * set.spacing.0),
+            )
+            .collect();
+        let grid_size = smoothing_lengths.iter().cloned().fold(0.0, f32::max);
+        let min_smoothing_length = smoothing_lengths.iter().cloned().fold(f32::MAX, f32::min);
+        let material_indices: Vec<u32> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| self.material_index(&format!("block {}", idx), &block.material))
+            .collect::<Result<_>>()?;
+        let mut particles = vec![];
+        let mut motion = vec![];
+        for (id, (block_idx, point)) in points.into_iter().enumerate() {
+            let block = &self.blocks[block_idx];
+            let material_idx = material_indices[block_idx];
+            let density = self.materials[material_idx as usize].density.0;
+            let (coord, position) = split_sph_position(point, grid_size);
+            particles.push(Particle {
+                coord,
+                mass: block.seeder(block_idx).particle_mass(density),
+                position,
+                density: 0.0,
+                pressure: 0.0,
+                smoothing_length: smoothing_lengths[block_idx],
+                material_idx,
+                id: id as u32,
+            });
+            motion.push(ParticleMotion {
+                velocity: block.velocity,
+                drho_dt: 0.0,
+                acceleration: [0.0; 3],
+                _padding: 0.0,
+                velocity_p: block.velocity,
+                tag: block.tag,
+            });
+        }
+        for (idx, (cloud, material_idx)) in point_sets.iter().enumerate() {
+            let set = &self.point_sets[idx];
+            let density = self.materials[*material_idx as usize].density.0;
+            let (set_particles, set_motion) = cloud
+                .sph_particles(
+                    &set.mapping,
+                    *material_idx,
+                    density * set.spacing.0.powi(3),
+                    smoothing_lengths[self.blocks.len() + idx],
+                    grid_size,
+                    particles.len() as u32,
+                )
+                .with_context(|| format!("point set {}", idx))?;
+            self.check_point_set(
+                idx,
+                set_particles.iter().map(|particle| {
+                    std::array::from_fn(|a| {
+                        (particle.coord[a] as f32 + particle.position[a]) * grid_size
+                    })
+                }),
+                set_particles.iter().map(|particle| particle.material_idx),
+                min,
+                max,
+            )?;
+            particles.extend(set_particles);
+            motion.extend(set_motion);
+        }
+        if particles.is_empty() {
+            bail!("scene has no particles");
+        }
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: self.time_step(min_smoothing_length)?,
+            grid_size,
+            num_particles: particles.len() as u32,
+            _padding: [0.0; 2],
+        };
+        let materials = self
+            .materials
+            .iter()
+            .map(|m| Material {
+                density_reference: m.density.0,
+                density_ref_threshold: m.threshold,
+                compressibility: m.compressibility,
+                boundary_damping: m.boundary_damping,
+                cs: m.sound_speed.0,
+                alpha: m.alpha,
+                beta: m.beta,
+                eps: m.eps,
+                color: m.color,
+            })
+            .collect();
+        let disturbance = Disturbance {
+            field: self.gravity,
+            _padding: 0.0,
+        };
+        let mut sph = Sph::new(params, disturbance, particles, motion, materials);
+        let periodicity = Periodicity::new(min, max, self.domain.periodic, grid_size);
+        if let Some(free_surface) = self.sph.free_surface {
+            // The reference density is that of one lattice
+            let lattices: Vec<Seeder> = self
+                .blocks
+                .iter()
+                .enumerate()
+                .map(|(idx, block)| block.seeder(idx))
+                .collect();
+            let Some(lattice) = lattices.first() else {
+                bail!("free_surface needs blocks to take the particle lattice from");
+            };
+            if !self.point_sets.is_empty()
+                || lattices.iter().any(|other| {
+                    other.lattice != lattice.lattice || other.spacing != lattice.spacing
+                })
+            {
+                bail!("free_surface needs blocks of one lattice and spacing and no point sets");
+            }
+            sph.hydrostatic_init(free_surface, lattice);
+        }
+        Ok(SphScene {
+            sph,
+            integrator: self.sph.integrator,
+            relaxation_steps: self.sph.relaxation_steps,
+            relaxation_damping: self.sph.relaxation_damping,
+            reorder_interval: self.reorder_interval,
+            periodicity,
+            walls: match self.sph.mirror_walls {
+                true => Walls::mirrored(),
+                false => Walls::default(),
+            },
+            obstacles: self.load_obstacles(1.0)?,
+            damping_zones: self.damping(1.0),
+            probes: self.probes.clone(),
+            loads: self.loads.clone(),
+            wavemakers: self.load_wavemakers(&rigid_bodies, 1.0)?,
+            rigid_bodies,
+        })
+    }
+
+    fn build_mpm(&self) -> Result<MlsMpmScene> {
+        use mls_mpm::*;
+        let size = self.domain.size.0;
+        let grid_resolution = self.mpm.grid_resolution;
+        if grid_resolution < 4 {
+            bail!(
+                "line {}: grid_resolution must be at least 4",
+                self.line_of("grid_resolution")
+            );
+        }
+        let cell_size = size / grid_resolution as f32;
+        let rigid_bodies =
+            self.load_rigid_bodies(size, (grid_resolution as f32).powi(3), [0.0; 3], [size; 3])?;
+        let mut points = self.lattice([0.0; 3], [size; 3])?;
+        points.retain(|(_, point)| {
+            let point = point.map(|x| x / size);
+            rigid_bodies.iter().all(|body| body.distance(point) > 0.0)
+        });
+        let point_sets = self.load_point_sets()?;
+        let mut particles = vec![];
+        for (id, (block_idx, point)) in points.into_iter().enumerate() {
+            let block = &self.blocks[block_idx];
+            let material_idx =
+                self.material_index(&format!("block {}", block_idx), &block.material)?;
+            let density = self.materials[material_idx as usize].density.0;
+            particles.push(Particle {
+                position: point.map(|x| x / size),
+                // Masses are in grid cells, the grid density is mass per cell
+                mass: block.seeder(block_idx).particle_mass(density) / cell_size.powi(3),
+                velocity: block.velocity.map(|v| v / size),
+                material_idx,
+                C: [0.0; 12],
+                id: id as u32,
+                tag: block.tag,
+                _padding: [0; 2],
+            });
+        }
+        let periodic_axes = [PERIODIC_X, PERIODIC_Y, PERIODIC_Z]
+            .iter()
+            .zip(self.domain.periodic)
+            .filter(|(_, periodic)| *periodic)
+            .fold(0, |axes, (axis, _)| axes | axis);
+        let mut params = SimParams {
+            grid_resolution,
+            dt: self.time_step(cell_size)?,
+            scale_distance: size,
+            num_particles: 0,
+            num_nodes: grid_resolution.pow(3),
+            periodic_axes,
+        };
+        for (idx, (cloud, material_idx)) in point_sets.iter().enumerate() {
+            let set = &self.point_sets[idx];
+            let density = self.materials[*material_idx as usize].density.0;
+            let set_particles = cloud
+                .mpm_particles(
+                    &set.mapping,
+                    *material_idx,
+                    density * set.spacing.0.powi(3),
+                    &params,
+                    particles.len() as u32,
+                )
+                .with_context(|| format!("point set {}", idx))?;
+            self.check_point_set(
+                idx,
+                set_particles
+                    .iter()
+                    .map(|particle| particle.position.map(|x| x * size)),
+                set_particles.iter().map(|particle| particle.material_idx),
+                [0.0; 3],
+                [size; 3],
+            )?;
+            particles.extend(set_particles);
+        }
+        if particles.is_empty() {
+            bail!("scene has no particles");
+        }
+        params.num_particles = particles.len() as u32;
+        let materials = self
+            .materials
+            .iter()
+            .map(|m| Material {
+                color: m.color,
+                eos_density: m.density.0,
+                eos_threshold: m.threshold,
+                eos_stiffness: m.stiffness,
+                eos_n: m.exponent,
+                dynamic_viscosity: m.viscosity,
+                rigid_flag: m.rigid as u32,
+                _padding: [0; 2],
+            })
+            .collect();
+        let disturbance = Disturbance {
+            field: self.gravity.map(|g| g / size),
+            _padding: 0,
+        };
+        Ok(MlsMpmScene {
+            mls_mpm: MlsMpm::new(params, disturbance, particles, materials),
+            reorder_interval: self.reorder_interval,
+            obstacles: self.load_obstacles(size)?,
+            damping_zones: self.damping(size),
+            probes: self.probes.clone(),
+            probe_material: self.materials.iter().position(|m| !m.rigid).unwrap_or(0) as u32,
+            wavemakers: self.load_wavemakers(&rigid_bodies, size)?,
+            rigid_bodies,
+        })
+    }
+}
+
+impl LoadedScene {
+    // Continue from a checkpoint, the scene keeps its boundaries and the SPH relaxation is skipped
+    // as the saved particles have already settled
+    pub fn restore(&mut self, state: SolverState) -> Result<()> {
+        let rigid_bodies = match self {
+            LoadedScene::Sph(scene) => &scene.rigid_bodies,
+            LoadedScene::MlsMpm(scene) => &scene.rigid_bodies,
+        };
+        if !rigid_bodies.is_empty() {
+            bail!("checkpoints don't hold the rigid bodies, the scene can't be restarted");
+        }
+        match (self, state) {
+            (LoadedScene::Sph(scene), SolverState::Sph(sph)) => {
+                scene.sph = sph;
+                scene.relaxation_steps = 0;
+            }
+            (LoadedScene::MlsMpm(scene), SolverState::MlsMpm(mls_mpm)) => scene.mls_mpm = mls_mpm,
+            (_, state) => bail!(
+                "checkpoint of an {} run does not match the scene solver",
+                state.solver_name()
+            ),
+        }
+        Ok(())
+    }
+}
+
+impl Scene for SphScene {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        let sph = &self.sph;
+        let mut compute = pollster::block_on(SphCompute::new(device, &sph.params));
+        compute.cpu2gpu_params(queue, &sph.params);
+        compute.cpu2gpu_disturbance(queue, &sph.disturbance);
+        compute.cpu2gpu_particles(queue, &sph.particles, &sph.motion);
+        compute.cpu2gpu_materials(queue, &sph.materials);
+        compute.cpu2gpu_periodicity(queue, &self.periodicity);
+        compute.cpu2gpu_walls(queue, &self.walls);
+        compute.set_integrator(device, self.integrator);
+        if self.reorder_interval > 0 {
+            compute.attach_morton_reorder(device, self.reorder_interval);
+        }
+        if !self.obstacles.is_empty() {
+            compute.attach_obstacles(device, &self.obstacles);
+        }
+        if !self.damping_zones.is_empty() {
+            compute.attach_damping_zones(device, &self.damping_zones);
+        }
+        if !self.rigid_bodies.is_empty() {
+            compute.attach_rigid_bodies(device, self.rigid_bodies.clone());
+        }
+        if self.relaxation_steps > 0 {
+            // Paddles are held at their rest pose
+            let held = self
+                .wavemakers
+                .iter()
+                .map(|wavemaker| {
+                    wavemaker.clone().map(|mut wavemaker| {
+                        wavemaker.signal = MotionSignal::Rate { rate: 0.0 };
+                        wavemaker
+                    })
+                })
+                .collect();
+            compute.attach_wavemakers(queue, held);
+            compute.relax(
+                device,
+                queue,
+                self.relaxation_steps,
+                self.relaxation_damping,
+            );
+            // The bodies move during the relaxation and start the run from their initial state
+            compute.cpu2gpu_rigid_bodies(queue, &self.rigid_bodies);
+        }
+        if self.wavemakers.iter().any(Option::is_some) {
+            compute.attach_wavemakers(queue, self.wavemakers.clone());
+        }
+        // After the relaxation, which would fill the probe records and load impulses
+        if !self.probes.is_empty() {
+            compute.attach_probes(device, Probes::new(device, &self.probes, MAX_RECORDS));
+        }
+        compute.attach_loads(device, &self.loads);
+        Box::new(compute)
+    }
+    fn dt(&self) -> f32 {
+        self.sph.params.dt
+    }
+    // SphCpu covers the leap frog integrator and the walls of the unit box
+    fn init_cpu(&self) -> Result<Box<dyn CpuSimulation>> {
+        if self.integrator != Integrator::LeapFrog {
+            bail!("the CPU solver only has the leap frog integrator");
+        }
+        if self.relaxation_steps > 0 {
+            bail!("the CPU solver has no relaxation");
+        }
+        if !self.obstacles.is_empty()
+            || !self.damping_zones.is_empty()
+            || !self.probes.is_empty()
+            || !self.loads.is_empty()
+            || !self.rigid_bodies.is_empty()
+        {
+            bail!("obstacles, damping zones, probes, loads and rigid bodies need the GPU solver");
+        }
+        Ok(Box::new(SphCpu::new(
+            self.sph.clone(),
+            self.periodicity,
+            self.walls,
+        )))
+    }
+}
+
+impl Scene for MlsMpmScene {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        let mls_mpm = &self.mls_mpm;
+        let mut compute = pollster::block_on(MlsMpmCompute::new(device, &mls_mpm.params));
+        compute.cpu2gpu_params(queue, &mls_mpm.params);
+        compute.cpu2gpu_disturbance(queue, &mls_mpm.disturbance);
+        compute.cpu2gpu_particles(queue, &mls_mpm.particles);
+        compute.cpu2gpu_materials(queue, &mls_mpm.materials);
+        if self.reorder_interval > 0 {
+            compute.attach_morton_reorder(device, self.reorder_interval);
+        }
+        if !self.obstacles.is_empty() {
+            compute.attach_obstacles(device, &self.obstacles);
+        }
+        if !self.damping_zones.is_empty() {
+            compute.attach_damping_zones(device, &self.damping_zones);
+        }
+        if !self.probes.is_empty() {
+            let probes = Probes::new(device, &self.probes, MAX_RECORDS);
+            compute.attach_probes(device, probes, self.probe_material);
+        }
+        if !self.rigid_bodies.is_empty() {
+            compute.attach_rigid_bodies(device, self.rigid_bodies.clone());
+        }
+        if self.wavemakers.iter().any(Option::is_some) {
+            compute.attach_wavemakers(queue, self.wavemakers.clone());
+        }
+        Box::new(compute)
+    }
+    fn dt(&self) -> f32 {
+        self.mls_mpm.params.dt
+    }
+}
+
+impl Scene for LoadedScene {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        match self {
+            LoadedScene::Sph(scene) => scene.init(device, queue),
+            LoadedScene::MlsMpm(scene) => scene.init(device, queue),
+        }
+    }
+    fn dt(&self) -> f32 {
+        match self {
+            LoadedScene::Sph(scene) => scene.dt(),
+            LoadedScene::MlsMpm(scene) => scene.dt(),
+        }
+    }
+    fn init_cpu(&self) -> Result<Box<dyn CpuSimulation>> {
+        match self {
+            LoadedScene::Sph(scene) => scene.init_cpu(),
+            LoadedScene::MlsMpm(scene) => scene.init_cpu(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAM_BREAK: &str = r#"
+solver = "sph"
+cfl = 0.25
+gravity = [0.0, -9.81, 0.0]
+
+[sph]
+integrator = "velocity_verlet"
+
+[[materials]]
+name = "water"
+density = 1000.0
+
+[[blocks]]
+material = "water"
+min = [-0.5, -0.5, -0.5]
+max = [-0.3, -0.3, -0.3]
+spacing = 0.05
+"#;
+
+    #[test]
+    fn test_scene_toml() {
+        let scene = SceneFile::parse_toml(DAM_BREAK).unwrap();
+        let LoadedScene::Sph(sph) = scene.build().unwrap() else {
+            panic!("expected an sph scene");
+        };
+        assert_eq!(sph.sph.particles.len(), 4 * 4 * 4);
+        assert_eq!(sph.integrator, Integrator::VelocityVerlet);
+        // cfl * h / cs
+        assert!((sph.sph.params.dt - 0.25 * 0.1 / 5.0).abs() < 1e-7);
+        assert!((sph.sph.particles[0].mass - 0.125).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_example_scenes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for name in ["dam_break.toml", "dam_break_mpm.ron"] {
+            let scene = SceneFile::load(dir.join(name)).unwrap();
+            scene.build().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scene_point_set() {
+        let dir = std::env::temp_dir().join(format!("hydrocode_point_set_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("drop.csv"),
+            "# exported frame\nid,px,py,pz,vy\n7,0.1,0.2,0.0,-1.0\n8,0.1,0.25,0.0,-1.0\n",
+        )
+        .unwrap();
+        let scene = format!(
+            "{}\n[[point_sets]]\nfile = \"drop.csv\"\nmaterial = \"water\"\nspacing = 0.05\n\
+             [point_sets.mapping]\nposition = [\"px\", \"py\", \"pz\"]\nvelocity = [\"pz\", \"vy\", \"pz\"]\n",
+            DAM_BREAK
+        );
+        std::fs::write(dir.join("scene.toml"), &scene).unwrap();
+        let LoadedScene::Sph(built) = SceneFile::load(dir.join("scene.toml"))
+            .unwrap()
+            .build()
+            .unwrap()
+        else {
+            panic!("expected an SPH scene");
+        };
+        // Imported particles follow the block particles with the mass of their spacing
+        let sph = &built.sph;
+        let n = sph.particles.len();
+        assert_eq!(sph.particles[n - 1].id, n as u32 - 1);
+        assert!((sph.particles[n - 1].mass - 1000.0 * 0.05f32.powi(3)).abs() < 1e-4);
+        assert_eq!(sph.motion[n - 1].velocity, [0.0, -1.0, 0.0]);
+
+        // Points outside the domain are reported with the point set line
+        std::fs::write(dir.join("drop.csv"), "px,py,pz,vy\n0.1,0.7,0.0,0.0\n").unwrap();
+        let error = SceneFile::load(dir.join("scene.toml"))
+            .unwrap()
+            .build()
+            .err()
+            .unwrap();
+        let line = scene.lines().position(|l| l.contains("drop.csv")).unwrap() + 1;
+        assert!(
+            error.to_string().starts_with(&format!("line {}:", line)),
+            "{}",
+            error
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scene_probes() {
+        let scene = format!(
+            "{}\n[[probes]]\ntype = \"pressure\"\nname = \"p1\"\nposition = [-0.45, -0.45, 0.0]\n\
+             [[probes]]\ntype = \"wave_gauge\"\nname = \"gauge\"\nposition = [0.0, -0.5, 0.0]\n\
+             height = 0.5\nspacing = 0.01\n",
+            DAM_BREAK
+        );
+        let LoadedScene::Sph(built) = SceneFile::parse_toml(&scene).unwrap().build().unwrap()
+        else {
+            panic!("expected an sph scene");
+        };
+        let names: Vec<_> = built.probes.iter().map(|probe| probe.name()).collect();
+        assert_eq!(names, ["p1", "gauge"]);
+
+        // Duplicate names would give ambiguous CSV columns
+        let duplicate = scene.replace("\"gauge\"", "\"p1\"");
+        let error = SceneFile::parse_toml(&duplicate)
+            .unwrap()
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("duplicate probe"), "{}", error);
+        let flat = scene.replace("height = 0.5", "height = 0.0");
+        let error = SceneFile::parse_toml(&flat).unwrap().build().err().unwrap();
+        let line = scene.lines().position(|l| l.contains("\"gauge\"")).unwrap() + 1;
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("line {}: probe 1", line)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_scene_loads() {
+        let scene = format!(
+            "{}\n[[loads]]\ntype = \"region\"\nname = \"floor\"\nmin = [-0.5, -0.5, -0.5]\n\
+             max = [0.5, -0.45, 0.5]\nreference = [0.0, -0.5, 0.0]\ncutoff = 20.0\n",
+            DAM_BREAK
+        );
+        let LoadedScene::Sph(built) = SceneFile::parse_toml(&scene).unwrap().build().unwrap()
+        else {
+            panic!("expected an sph scene");
+        };
+        assert_eq!(built.loads[0].cutoff(), Some(20.0));
+
+        // Obstacle targets index the obstacles of the scene
+        let obstacle = scene.replace(
+            "type = \"region\"\nname = \"floor\"\nmin = [-0.5, -0.5, -0.5]\n\
+             max = [0.5, -0.45, 0.5]",
+            "type = \"obstacle\"\nname = \"floor\"\nobstacle = 0",
+        );
+        let error = SceneFile::parse_toml(&obstacle)
+            .unwrap()
+            .build()
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("references obstacle 0 of 0"),
+            "{}",
+            error
+        );
+        let mpm = scene.replace("solver = \"sph\"", "solver = \"mpm\"");
+        let error = SceneFile::parse_toml(&mpm).unwrap().build().err().unwrap();
+        assert!(
+            error.to_string().contains("only measured by the SPH"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_scene_rigid_bodies() {
+        let scene = format!(
+            "{}\n[[rigid_bodies]]\nshape = {{ type = \"cuboid\", half_extents = [0.05, 0.05, 0.05] }}\n\
+             density = 500.0\nposition = [-0.4, -0.4, -0.4]\nvelocity = [0.0, -1.0, 0.0]\n",
+            DAM_BREAK
+        );
+        let LoadedScene::Sph(built) = SceneFile::parse_toml(&scene).unwrap().build().unwrap()
+        else {
+            panic!("expected an sph scene");
+        };
+        // The block particles inside the body are left out
+        assert_eq!(built.sph.particles.len(), 4 * 4 * 4 - 2 * 2 * 2);
+        let body = &built.rigid_bodies[0];
+        assert!((body.mass - 500.0 * 0.1f32.powi(3)).abs() < 1e-5);
+        assert_eq!(body.linear_velocity.y, -1.0);
+        assert!(built.init_cpu().is_err());
+
+        // MLS-MPM bodies are in the unit domain with their masses in grid cells
+        let mpm = scene
+            .replace("solver = \"sph\"", "solver = \"mpm\"\ndt = 0.001")
+            .replace("cfl = 0.25\n", "")
+            .replace(
+                "[sph]\nintegrator = \"velocity_verlet\"",
+                "[domain]\nsize = 2.0",
+            )
+            .replace("-0.5", "0.1")
+            .replace("-0.4", "0.2")
+            .replace("-0.3", "0.3");
+        let LoadedScene::MlsMpm(built) = SceneFile::parse_toml(&mpm).unwrap().build().unwrap()
+        else {
+            panic!("expected an mpm scene");
+        };
+        let body = &built.rigid_bodies[0];
+        assert!((body.position.x - 0.1).abs() < 1e-6);
+        assert!((body.linear_velocity.y + 0.5).abs() < 1e-6);
+        let cell_size: f32 = 2.0 / 64.0;
+        let mass = 500.0 * 0.1f32.powi(3) / cell_size.powi(3);
+        assert!((body.mass / mass - 1.0).abs() < 1e-4, "{}", body.mass);
+
+        let outside = scene.replace(
+            "position = [-0.4, -0.4, -0.4]",
+            "position = [0.0, 0.7, 0.0]",
+        );
+        let error = SceneFile::parse_toml(&outside)
+            .unwrap()
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("leaves the domain"), "{}", error);
+    }
+
+    #[test]
+    fn test_scene_wavemakers() {
+        let scene = format!(
+            "{}\n[[rigid_bodies]]\nshape = {{ type = \"cuboid\", half_extents = [0.05, 0.5, 0.5] }}\n\
+             density = 1000.0\nposition = [-0.4, 0.0, 0.0]\n\
+             [rigid_bodies.wavemaker]\nmotion = {{ type = \"piston\", direction = [2.0, 0.0, 0.0] }}\n\
+             signal = {{ type = \"rate\", rate = 0.5 }}\n",
+            DAM_BREAK
+        );
+        let LoadedScene::Sph(built) = SceneFile::parse_toml(&scene).unwrap().build().unwrap()
+        else {
+            panic!("expected an sph scene");
+        };
+        // The paddle follows the signal along the normalized direction
+        let wavemaker = built.wavemakers[0].as_ref().unwrap();
+        let mut paddle = built.rigid_bodies[0].clone();
+        wavemaker.update(&mut paddle, 0.2);
+        assert!((paddle.position.x + 0.3).abs() < 1e-6);
+        assert!((paddle.linear_velocity.x - 0.5).abs() < 1e-6);
+
+        let flap = scene
+            .replace(
+                "type = \"piston\", direction = [2.0, 0.0, 0.0]",
+                "type = \"flap\", hinge = [-0.4, -0.5, 0.0], axis = [0.0, 0.0, 1.0]",
+            )
+            .replace(
+                "type = \"rate\", rate = 0.5",
+                "type = \"stokes\", height = 0.05, period = 1.0, depth = 0.5, order = \"second\"",
+            );
+        let error = SceneFile::parse_toml(&flap).unwrap().build().err().unwrap();
+        assert!(error.to_string().contains("first order"), "{}", error);
+        let still = scene.replace("[2.0, 0.0, 0.0]", "[0.0, 0.0, 0.0]");
+        let error = SceneFile::parse_toml(&still)
+            .unwrap()
+            .build()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("zero direction"), "{}", error);
+    }
+
+    #[test]
+    fn test_scene_cpu() {
+        let scene = SceneFile::parse_toml(DAM_BREAK).unwrap().build().unwrap();
+        let error = scene.init_cpu().err().unwrap();
+        assert!(error.to_string().contains("leap frog"), "{}", error);
+        let leap_frog = DAM_BREAK.replace("velocity_verlet", "leap_frog");
+        let scene = SceneFile::parse_toml(&leap_frog).unwrap().build().unwrap();
+        assert_eq!(scene.init_cpu().unwrap().capacity(), 4 * 4 * 4);
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let scene = SceneFile::load(dir.join("dam_break_mpm.ron"))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(scene.init_cpu().is_err());
+    }
+
+    #[test]
+    fn test_scene_errors_have_lines() {
+        let error = SceneFile::parse_toml(&DAM_BREAK.replace("spacing = 0.05", "spacing = -1"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("line 17,"), "{}", error);
+
+        let scene =
+            SceneFile::parse_toml(&DAM_BREAK.replace("material = \"water\"", "material = \"oil\""))
+                .unwrap();
+        let error = scene.build().err().unwrap().to_string();
+        assert!(error.starts_with("line 14:"), "{}", error);
+
+        let ron = "(\n    solver: sph,\n    dt: 0.001,\n    materials: [(name: \"water\", density: 0.0)],\n)";
+        let error = SceneFile::parse_ron(ron).unwrap_err().to_string();
+        assert!(error.starts_with("line 4,"), "{}", error);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::mls_mpm;
+use crate::obstacle::{SignedDistanceField, TriangleMesh};
+use crate::sph;
+use anyhow::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::path::Path;
+
+// Arrangement of the seeded particles, `spacing` is the nearest neighbour distance
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lattice {
+    #[default]
+    Cubic,
+    // ABAB stacking of triangular layers along y, densest packing for a given spacing
+    HexagonalClosePacked,
+}
+
+// Region filled with particles, in world units
+pub enum Shape {
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    // Capped cylinder between the centres of its end faces
+    Cylinder {
+        base: [f32; 3],
+        top: [f32; 3],
+        radius: f32,
+    },
+    // Interior of a closed triangle mesh
+    Mesh(SignedDistanceField),
+}
+
+// Places particles on a lattice inside a shape, optionally displaced by a random jitter
+#[derive(Clone, Copy, Debug)]
+pub struct Seeder {
+    pub lattice: Lattice,
+    pub spacing: f32,
+    // Largest displacement per axis as a fraction of the spacing
+    pub jitter: f32,
+    pub seed: u64,
+}
+
+impl Shape {
+    // Closed OBJ mesh scaled about the origin then translated, the distance field is sampled at
+    // `cell_size` which should not exceed the particle spacing
+    pub fn from_obj<P: AsRef<Path>>(
+        path: P,
+        scale: f32,
+        translation: [f32; 3],
+        cell_size: f32,
+    ) -> Result<Self> {
+        let mut mesh = TriangleMesh::load_obj(path)?;
+        mesh.transform(scale, translation);
+        Ok(Shape::Mesh(SignedDistanceField::from_mesh(
+            &mesh, cell_size, 2,
+        )))
+    }
+
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        match self {
+            Shape::Box { min, max } => (*min, *max),
+            Shape::Sphere { center, radius } => {
+                (center.map(|c| c - radius), center.map(|c| c + radius))
+            }
+            Shape::Cylinder { base, top, radius } => (
+                std::array::from_fn(|a| base[a].min(top[a]) - radius),
+                std::array::from_fn(|a| base[a].max(top[a]) + radius),
+            ),
+            Shape::Mesh(sdf) => (
+                sdf.origin,
+                std::array::from_fn(|a| sdf.origin[a] + (sdf.dims[a] - 1) as f32 * sdf.cell_size),
+            ),
+        }
+    }
+
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        match self {
+            Shape::Box { min, max } => (0..3).all(|a| point[a] >= min[a] && point[a] <= max[a]),
+            Shape::Sphere { center, radius } => {
+                (0..3).map(|a| (point[a] - center[a]).powi(2)).sum::<f32>() <= radius * radius
+            }
+            Shape::Cylinder { base, top, radius } => {
+                let axis: [f32; 3] = std::array::from_fn(|a| top[a] - base[a]);
+                let length2: f32 = axis.iter().map(|x| x * x).sum();
+                let offset: [f32; 3] = std::array::from_fn(|a| point[a] - base[a]);
+                let t =
+                    (0..3).map(|a| offset[a] * axis[a]).sum::<f32>() / length2.max(f32::EPSILON);
+                let radial2: f32 = (0..3).map(|a| (offset[a] - t * axis[a]).powi(2)).sum();
+                (0.0..=1.0).contains(&t) && radial2 <= radius * radius
+            }
+            Shape::Mesh(sdf) => sdf.sample(point) < 0.0,
+        }
+    }
+}
+
+impl Seeder {
+    pub fn new(lattice: Lattice, spacing: f32) -> Self {
+        Seeder {
+            lattice,
+            spacing,
+            jitter: 0.0,
+            seed: 0,
+        }
+    }
+
+    // Jitter breaks the lattice symmetry, the same seed gives the same particles
+    pub fn with_jitter(mut self, jitter: f32, seed: u64) -> Self {
+        self.jitter = jitter;
+        self.seed = seed;
+        self
+    }
+
+    // Volume represented by one particle
+    pub fn particle_volume(&self) -> f32 {
+        match self.lattice {
+            Lattice::Cubic => self.spacing.powi(3),
+            Lattice::HexagonalClosePacked => self.spacing.powi(3) / 2f32.sqrt(),
+        }
+    }
+
+    pub fn particle_mass(&self, density: f32) -> f32 {
+        density * self.particle_volume()
+    }
+
+    // Lattice points inside the shape, the lattice starts half a spacing inside the bounds
+    pub fn points(&self, shape: &Shape) -> Vec<[f32; 3]> {
+        let (min, max) = shape.bounds();
+        let d = self.spacing;
+        // Step between lattice lines and the offset of odd rows and layers
+        let (step, row_offset, layer_offset) = match self.lattice {
+            Lattice::Cubic => ([d, d, d], 0.0, [0.0, 0.0]),
+            Lattice::HexagonalClosePacked => (
+                [d, d * (2.0f32 / 3.0).sqrt(), d * 3f32.sqrt() / 2.0],
+                0.5 * d,
+                [0.5 * d, d * 3f32.sqrt() / 6.0],
+            ),
+        };
+        let counts: [u32; 3] =
+            std::array::from_fn(|a| ((max[a] - min[a]) / step[a] + 1e-3).floor() as u32 + 1);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut points = vec![];
+        for j in 0..counts[1] {
+            for k in 0..counts[2] {
+                for i in 0..counts[0] {
+                    let mut point = [
+                        min[0] + 0.5 * d + i as f32 * step[0],
+                        min[1] + 0.5 * d + j as f32 * step[1],
+                        min[2] + 0.5 * d + k as f32 * step[2],
+                    ];
+                    if k % 2 == 1 {
+                        point[0] += row_offset;
+                    }
+                    if j % 2 == 1 {
+                        point[0] += layer_offset[0];
+                        point[2] += layer_offset[1];
+                    }
+                    if !shape.contains(point) {
+                        continue;
+                    }
+                    if self.jitter > 0.0 {
+                        let amplitude = self.jitter * d;
+                        for x in point.iter_mut() {
+                            *x += rng.random_range(-amplitude..=amplitude);
+                        }
+                    }
+                    points.push(point);
+                }
+            }
+        }
+        points
+    }
+
+    // SPH particles at rest with ids from `first_id`, `grid_size` is the hash grid cell of the
+    // solver parameters
+    pub fn sph_particles(
+        &self,
+        shape: &Shape,
+        material: &sph::Material,
+        material_idx: u32,
+        smoothing_length: f32,
+        grid_size: f32,
+        first_id: u32,
+    ) -> (Vec<sph::Particle>, Vec<sph::ParticleMotion>) {
+        let mass = self.particle_mass(material.density_reference);
+        let mut particles = vec![];
+        let mut motion = vec![];
+        for (i, point) in self.points(shape).into_iter().enumerate() {
+            let (coord, position) = split_sph_position(point, grid_size);
+            particles.push(sph::Particle {
+                coord,
+                mass,
+                position,
+                density: 0.0,
+                pressure: 0.0,
+                smoothing_length,
+                material_idx,
+                id: first_id + i as u32,
+            });
+            motion.push(sph::ParticleMotion {
+                velocity: [0.0; 3],
+                drho_dt: 0.0,
+                acceleration: [0.0; 3],
+                _padding: 0.0,
+                velocity_p: [0.0; 3],
+                tag: 0,
+            });
+        }
+        (particles, motion)
+    }
+
+    // MLS-MPM particles at rest, positions are scaled into the unit domain and masses are in grid
+    // cells as the grid density is mass per cell
+    pub fn mpm_particles(
+        &self,
+        shape: &Shape,
+        material: &mls_mpm::Material,
+        material_idx: u32,
+        params: &mls_mpm::SimParams,
+        first_id: u32,
+    ) -> Vec<mls_mpm::Particle> {
+        let cell_size = params.scale_distance / params.grid_resolution as f32;
+        let mass = material.eos_density * self.particle_volume() / cell_size.powi(3);
+        self.points(shape)
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| mls_mpm::Particle {
+                position: point.map(|x| x / params.scale_distance),
+                mass,
+                velocity: [0.0; 3],
+                material_idx,
+                C: [0.0; 12],
+                id: first_id + i as u32,
+                tag: 0,
+                _padding: [0; 2],
+            })
+            .collect()
+    }
+}
+
+// Hash grid cell and the fractional position within it of an SPH particle at a world point
+pub fn split_sph_position(point: [f32; 3], grid_size: f32) -> ([i32; 3], [f32; 3]) {
+    let mut coord = [0; 3];
+    let mut position = [0.0; 3];
+    for a in 0..3 {
+        let cell = (point[a] / grid_size).floor();
+        coord[a] = cell as i32;
+        position[a] = point[a] / grid_size - cell;
+    }
+    (coord, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lattice_fill() {
+        let unit_box = Shape::Box {
+            min: [0.0; 3],
+            max: [1.0; 3],
+        };
+        let cubic = Seeder::new(Lattice::Cubic, 0.1);
+        assert_eq!(cubic.points(&unit_box).len(), 1000);
+
+        // Filled volume matches the shape volume to within the surface layer
+        let hcp = Seeder::new(Lattice::HexagonalClosePacked, 0.05);
+        let volume = hcp.points(&unit_box).len() as f32 * hcp.particle_volume();
+        assert!((volume - 1.0).abs() < 0.1, "{}", volume);
+
+        let sphere = Shape::Sphere {
+            center: [0.0; 3],
+            radius: 0.5,
+        };
+        let volume = hcp.points(&sphere).len() as f32 * hcp.particle_volume();
+        let exact = 4.0 / 3.0 * std::f32::consts::PI * 0.125;
+        assert!((volume / exact - 1.0).abs() < 0.1, "{}", volume);
+
+        let cylinder = Shape::Cylinder {
+            base: [0.0; 3],
+            top: [0.0, 0.0, 1.0],
+            radius: 0.5,
+        };
+        let volume = cubic.points(&cylinder).len() as f32 * cubic.particle_volume();
+        let exact = std::f32::consts::PI * 0.25;
+        assert!((volume / exact - 1.0).abs() < 0.1, "{}", volume);
+    }
+
+    #[test]
+    fn test_jitter_is_bounded_and_repeatable() {
+        let shape = Shape::Box {
+            min: [0.0; 3],
+            max: [0.5; 3],
+        };
+        let lattice = Seeder::new(Lattice::Cubic, 0.1).points(&shape);
+        let seeder = Seeder::new(Lattice::Cubic, 0.1).with_jitter(0.2, 7);
+        let jittered = seeder.points(&shape);
+        assert_eq!(jittered, seeder.points(&shape));
+        for (a, b) in lattice.iter().zip(&jittered) {
+            assert!((0..3).all(|i| (a[i] - b[i]).abs() <= 0.02 + 1e-6));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Solver independent access to the particle simulations, implemented by the SPH and MLS-MPM
+// solvers so that the renderer, exporters and headless drivers can run either of them
+
+use crate::checkpoint::SolverState;
+use crate::conservation::Totals;
+use crate::loads::{Load, LoadTarget};
+use anyhow::*;
+
+// Particle state in world coordinates, one entry per alive particle
+#[derive(Clone, Debug, Default)]
+pub struct ParticleData {
+    pub ids: Vec<u32>,
+    pub positions: Vec<[f32; 3]>,
+    pub velocities: Vec<[f32; 3]>,
+    pub material_idx: Vec<u32>,
+}
+
+// Pose and velocities of a rigid body in world coordinates, orientation as a quaternion
+// (x, y, z, w)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RigidBodyMotion {
+    pub position: [f32; 3],
+    pub orientation: [f32; 4],
+    pub linear_velocity: [f32; 3],
+    pub angular_velocity: [f32; 3],
+}
+
+// Compute pass writing one render instance (position and color) per particle slot, slots past
+// the alive count are moved out of view
+pub struct InstancePass {
+    pub bind_group: wgpu::BindGroup,
+    pub compute_pipeline: wgpu::ComputePipeline,
+    pub num_workgroups: u32,
+}
+
+// Initial state of a solver, e.g. `Sph` or `MlsMpm`
+pub trait Scene {
+    // Build the solver and upload the particles, parameters and materials
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation>;
+    // Physical time of one step
+    fn dt(&self) -> f32;
+    // Same state stepped on the CPU, for the solvers and boundaries that have a CPU reference
+    fn init_cpu(&self) -> Result<Box<dyn CpuSimulation>> {
+        bail!("the solver has no CPU implementation")
+    }
+}
+
+pub trait Simulation {
+    // Length of the particle buffers, the alive count never exceeds it
+    fn capacity(&self) -> u32;
+    // Alive particle count
+    fn num_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32;
+    // CPU side of the next `n_substeps` steps, run before they are recorded with encode_step,
+    // e.g. boundary uploads, or the whole steps of a CPU solver
+    fn prepare_step(&self, _device: &wgpu::Device, _queue: &wgpu::Queue, _n_substeps: u32) {}
+    // Record `n_substeps` complete time steps into `encoder`, without touching the queue
+    fn encode_step(&self, encoder: &mut wgpu::CommandEncoder, n_substeps: u32);
+    fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        self.prepare_step(device, queue, n_substeps);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Step"),
+        });
+        self.encode_step(&mut encoder, n_substeps);
+        queue.submit([encoder.finish()]);
+    }
+    fn gpu2cpu_particle_data(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> ParticleData;
+    // `buffer_instances` holds `capacity` instances of a position and a color, 32 bytes each
+    fn instance_pass(&self, device: &wgpu::Device, buffer_instances: &wgpu::Buffer)
+    -> InstancePass;
+    // Optional capabilities, None for the solvers that do not have them
+    fn checkpointing(&self) -> Option<&dyn Checkpointing> {
+        None
+    }
+    fn conservation(&self) -> Option<&dyn ConservationTotals> {
+        None
+    }
+    fn conservation_mut(&mut self) -> Option<&mut dyn ConservationTotals> {
+        None
+    }
+    fn probes(&self) -> Option<&dyn ProbeRecords> {
+        None
+    }
+    fn loads(&self) -> Option<&dyn BoundaryLoads> {
+        None
+    }
+    fn rigid_bodies(&self) -> Option<&dyn RigidBodyRecords> {
+        None
+    }
+}
+
+pub trait Checkpointing {
+    // Everything needed to continue the run, see `checkpoint::Checkpoint`
+    fn gpu2cpu_state(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SolverState;
+    // Replace the particles, parameters and materials, e.g. to restart from a state read before.
+    // Attached boundaries keep their own state
+    fn cpu2gpu_state(&self, queue: &wgpu::Queue, state: &SolverState);
+}
+
+pub trait ConservationTotals {
+    // Build the conservation reduction, see gpu2cpu_totals
+    fn attach_conservation(&mut self, device: &wgpu::Device);
+    // Conservation totals of the current state, None before attach_conservation
+    fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Totals>;
+}
+
+pub trait ProbeRecords {
+    // Column names of the probe values, empty without attached probes
+    fn probe_names(&self) -> Vec<String>;
+    // Probe values of every step since the last call, one row per step in `probe_names` order
+    fn gpu2cpu_probes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>>;
+}
+
+pub trait BoundaryLoads {
+    // Targets of the boundary loads, empty without attached loads
+    fn load_targets(&self) -> Vec<LoadTarget>;
+    // Mean loads over the `elapsed` time since the last call, one per target
+    fn gpu2cpu_loads(&self, device: &wgpu::Device, queue: &wgpu::Queue, elapsed: f32) -> Vec<Load>;
+}
+
+pub trait RigidBodyRecords {
+    // Attached rigid bodies at the end of the recorded steps, empty without
+    fn gpu2cpu_rigid_body_motion(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<RigidBodyMotion>;
+}
+
+// Solver stepped on the CPU without any wgpu device, see Scene::init_cpu
+pub trait CpuSimulation {
+    fn capacity(&self) -> u32;
+    fn step(&mut self, n_substeps: u32);
+    fn particle_data(&self) -> ParticleData;
+    // Conservation totals of the current state
+    fn totals(&self) -> Totals;
+    // Everything needed to continue the run, see `checkpoint::Checkpoint`
+    fn state(&self) -> SolverState;
+}
+
+// Whole content of a `COPY_SRC` buffer through a temporary staging buffer, for readbacks that are
+// too rare to keep a staging buffer around
+pub fn gpu2cpu_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Vec<T> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging Buffer Readback"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Command Encoder GPU to CPU Readback"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    queue.submit(std::iter::once(encoder.finish()));
+    let buffer_slice = staging_buffer.slice(..);
+    buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+    _ = device.poll(wgpu::PollType::Wait);
+    let output_data = buffer_slice.get_mapped_range();
+    let data = bytemuck::pod_collect_to_vec(&output_data);
+    drop(output_data);
+    staging_buffer.unmap();
+    data
+}
@@ -0,0 +1,34 @@
+// WGSL file for the bitonic sort of (value, key) u32 pairs by key
+// Every merge starts by comparing mirrored elements so that all comparisons are ascending, which
+// lets lengths that are not a power of two behave as if padded with maximal keys
+
+struct SortStep {
+    len: u32,
+    // Half the size of the blocks being merged
+    stride: u32,
+    // 1 for the mirrored first comparison of a merge
+    flip: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> pairs: array<vec2u>;
+@group(0) @binding(1) var<uniform> step: SortStep;
+
+@compute @workgroup_size(256)
+fn bitonic_step(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let low = global_id.x % step.stride;
+    let i = 2u * step.stride * (global_id.x / step.stride) + low;
+    var j = i + step.stride;
+    if (step.flip == 1u) {
+        j = i - low + 2u * step.stride - 1u - low;
+    }
+    if (j >= step.len) {
+        return;
+    }
+    let a = pairs[i];
+    let b = pairs[j];
+    if (a.y > b.y) {
+        pairs[i] = b;
+        pairs[j] = a;
+    }
+}
@@ -0,0 +1,214 @@
+use crate::shader_module::ShaderModuleBuilder;
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct SortStep {
+    len: u32,
+    stride: u32,
+    flip: u32,
+    _padding: u32,
+}
+
+struct SortPass {
+    #[allow(unused)]
+    buffer_step: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+// In-place ascending sort of (value, key) u32 pairs by key, recorded into a compute pass. The
+// layout matches the SPH spatial lookup. The order of equal keys is not preserved.
+pub struct BitonicSort {
+    num_workgroups: u32,
+    passes: Vec<SortPass>,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+impl BitonicSort {
+    pub fn new(device: &wgpu::Device, buffer_pairs: &wgpu::Buffer, len: u32) -> Self {
+        let module_sort = ShaderModuleBuilder::new()
+            .add_module(include_str!("./bitonic_sort.wgsl"))
+            .build(device, Some("Shader Module Bitonic Sort"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Bitonic Sort"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Bitonic Sort"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Bitonic Sort"),
+            layout: Some(&pipeline_layout),
+            module: &module_sort,
+            entry_point: Some("bitonic_step"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        let passes = sort_steps(len)
+            .iter()
+            .map(|step| {
+                let buffer_step = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Buffer Bitonic Sort Step"),
+                    contents: bytemuck::bytes_of(step),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Bind Group Bitonic Sort"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer_pairs.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: buffer_step.as_entire_binding(),
+                        },
+                    ],
+                });
+                SortPass {
+                    buffer_step,
+                    bind_group,
+                }
+            })
+            .collect();
+
+        BitonicSort {
+            // One invocation per compared pair
+            num_workgroups: (len.max(2).next_power_of_two() / 2).div_ceil(WORKGROUP_SIZE),
+            passes,
+            compute_pipeline,
+        }
+    }
+
+    pub fn encode(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        for pass in &self.passes {
+            compute_pass.set_bind_group(0, &pass.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.num_workgroups, 1, 1);
+        }
+    }
+
+    // Workgroups of every pass, for the arguments of encode_indirect
+    pub fn num_workgroups(&self) -> u32 {
+        self.num_workgroups
+    }
+
+    // Same as encode with every pass dispatched from the arguments at `indirect_offset`, which
+    // skip the sort when they are zero
+    pub fn encode_indirect(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        indirect_buffer: &wgpu::Buffer,
+        indirect_offset: u64,
+    ) {
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        for pass in &self.passes {
+            compute_pass.set_bind_group(0, &pass.bind_group, &[]);
+            compute_pass.dispatch_workgroups_indirect(indirect_buffer, indirect_offset);
+        }
+    }
+}
+
+// Merges of doubling block size, each a mirrored comparison followed by halving strides
+fn sort_steps(len: u32) -> Vec<SortStep> {
+    let padded_len = len.max(2).next_power_of_two();
+    let mut steps = vec![];
+    let mut block = 2;
+    while block <= padded_len {
+        let mut stride = block / 2;
+        let mut flip = 1;
+        while stride > 0 {
+            steps.push(SortStep {
+                len,
+                stride,
+                flip,
+                _padding: 0,
+            });
+            stride /= 2;
+            flip = 0;
+        }
+        block *= 2;
+    }
+    steps
+}
+
+// Z-order key of a cell, matching morton.wgsl
+pub fn morton_key(cell: [u32; 3]) -> u32 {
+    let spread_bits = |value: u32| {
+        let mut x = value & 0x3ff;
+        x = (x | (x << 16)) & 0x030000ff;
+        x = (x | (x << 8)) & 0x0300f00f;
+        x = (x | (x << 4)) & 0x030c30c3;
+        x = (x | (x << 2)) & 0x09249249;
+        x
+    };
+    spread_bits(cell[0]) | (spread_bits(cell[1]) << 1) | (spread_bits(cell[2]) << 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_bitonic_sort_steps() {
+        let mut rng = rand::rng();
+        for len in [1, 2, 5, 64, 100, 1000] {
+            let mut pairs: Vec<[u32; 2]> = (0..len).map(|i| [i, rng.random_range(0..50)]).collect();
+            let mut expected: Vec<u32> = pairs.iter().map(|pair| pair[1]).collect();
+            expected.sort();
+            // Emulate the invocations of every pass
+            for step in sort_steps(len) {
+                let invocations = len.max(2).next_power_of_two() / 2;
+                for id in 0..invocations {
+                    let low = id % step.stride;
+                    let i = 2 * step.stride * (id / step.stride) + low;
+                    let j = match step.flip {
+                        1 => i - low + 2 * step.stride - 1 - low,
+                        _ => i + step.stride,
+                    };
+                    if j < len && pairs[i as usize][1] > pairs[j as usize][1] {
+                        pairs.swap(i as usize, j as usize);
+                    }
+                }
+            }
+            let keys: Vec<u32> = pairs.iter().map(|pair| pair[1]).collect();
+            assert_eq!(keys, expected);
+        }
+    }
+
+    #[test]
+    fn test_morton_key() {
+        assert_eq!(morton_key([1, 0, 0]), 1);
+        assert_eq!(morton_key([0, 1, 0]), 2);
+        assert_eq!(morton_key([0, 0, 1]), 4);
+        assert_eq!(morton_key([3, 3, 3]), 63);
+        assert_eq!(morton_key([1023, 1023, 1023]), (1 << 30) - 1);
+    }
+}
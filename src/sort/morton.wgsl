@@ -0,0 +1,16 @@
+// WGSL file for Z-order curve keys of grid cells
+
+// Spread the low 10 bits over every third bit
+fn spread_bits(value: u32) -> u32 {
+    var x = value & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+// 30 bit key interleaving the low 10 bits of each cell coordinate
+fn morton_key(cell: vec3u) -> u32 {
+    return spread_bits(cell.x) | (spread_bits(cell.y) << 1u) | (spread_bits(cell.z) << 2u);
+}
@@ -0,0 +1,17 @@
+// WGSL file for the dense cell list neighbor search backend
+// Requires a `cell_grid: CellGrid` uniform binding
+
+// Dense index of a cell, U32MAX outside the bounded domain
+fn cell_key(coord: vec3i) -> u32 {
+    let local = coord - cell_grid.min;
+    if (any(local < vec3i(0)) || any(vec3u(local) >= cell_grid.dims)) {
+        return U32MAX;
+    }
+    let cell = vec3u(local);
+    return (cell.x * cell_grid.dims.y + cell.y) * cell_grid.dims.z + cell.z;
+}
+
+// Particles that leave the domain are binned into the nearest boundary cell
+fn clamp_cell(coord: vec3i) -> vec3i {
+    return clamp(coord, cell_grid.min, cell_grid.min + vec3i(cell_grid.dims) - vec3i(1));
+}
@@ -0,0 +1,50 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> spatial: array<SpatialLookup>;
+
+@group(0) @binding(2)
+var<storage, read_write> cell_start: array<atomic<u32>>;
+
+@group(0) @binding(3)
+var<storage, read_write> cell_ranks: array<u32>;
+
+@group(0) @binding(4)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(5)
+var<uniform> cell_grid: CellGrid;
+
+// The table holds one entry past the last cell so that cell c spans [start[c], start[c + 1])
+@compute @workgroup_size(256)
+fn clear_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index > cell_grid.num_cells) {
+        return;
+    }
+    atomicStore(&cell_start[index], 0u);
+}
+
+// Count particles per cell, the rank orders particles within their cell
+@compute @workgroup_size(256)
+fn count_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let key = cell_key(clamp_cell(particles[index].coord));
+    cell_ranks[index] = atomicAdd(&cell_start[key], 1u);
+}
+
+// Place particles after the exclusive prefix sum of the counts
+@compute @workgroup_size(256)
+fn scatter_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let key = cell_key(clamp_cell(particles[index].coord));
+    let spatial_idx = atomicLoad(&cell_start[key]) + cell_ranks[index];
+    spatial[spatial_idx] = SpatialLookup(index, key);
+}
@@ -0,0 +1,53 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> materials: array<Material>;
+
+@group(0) @binding(3)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(4)
+var<uniform> disturbance: Disturbance;
+
+@group(0) @binding(5)
+var<storage, read_write> partials: array<Totals>;
+
+// Internal energy of the linear equation of state, the integral of p / rho^2 from the reference
+// density. Below the threshold the pressure is zero and the energy stays at its threshold value.
+fn internal_energy(particle: Particle) -> f32 {
+    let material = materials[particle.material_idx];
+    let rho0 = material.density_reference;
+    let density = max(particle.density, material.density_reference_threshold * rho0);
+    return particle.mass * material.compressibility * (log(density / rho0) + rho0 / density - 1.0);
+}
+
+@compute @workgroup_size(128)
+fn reduce_conservation(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let index = global_id.x;
+    var totals = empty_totals();
+    if (index < params.num_particles) {
+        let particle = particles[index];
+        let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+        totals = particle_totals(
+            particle.mass,
+            position,
+            particles_motion[index].velocity,
+            disturbance.field,
+            particle.density,
+            particle.pressure,
+            internal_energy(particle),
+        );
+    }
+    let partial = reduce_workgroup(local_index, totals);
+    if (local_index == 0u) {
+        partials[workgroup_id.x] = partial;
+    }
+}
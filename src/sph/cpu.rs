@@ -0,0 +1,678 @@
+// CPU reference of the SPH step, parallel over the particles with rayon. Each pass follows its
+// WGSL counterpart so that the GPU results can be checked against it, `headless --cpu` also runs
+// scenes with it through CpuSimulation. Covers the leap frog integrator and the walls of the
+// unit box, the optional boundaries are GPU only. Neighbors come from the exact cells as with
+// NeighborSearch::CellList, the spatial hash also walks the particles of distant cells sharing a
+// key and visits a cell twice when two neighbor cells share one.
+
+use super::{Particle, ParticleMotion, Periodicity, Sph, Walls, kernel_cubic_bspline};
+use crate::checkpoint::SolverState;
+use crate::conservation::Totals;
+use crate::simulation::{CpuSimulation, ParticleData};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+// Half width of the unit box, WALL_BOUNDS of wall.wgsl
+const WALL_BOUNDS: f32 = 0.5;
+
+pub struct SphCpu {
+    pub sph: Sph,
+    pub periodicity: Periodicity,
+    pub walls: Walls,
+    // Particles of each occupied cell, rebuilt by spatial_lookup
+    cells: HashMap<[i32; 3], Vec<usize>>,
+}
+
+// Derivative of the cubic B-spline of kernel.wgsl
+fn dkernel_cubic_bspline(r: f32, h: f32) -> f32 {
+    let normalization = h * h * h * std::f32::consts::PI / 8.0;
+    let q = r / h;
+    if q < 0.5 {
+        6.0 * (3.0 * q * q - 2.0 * q) / normalization
+    } else if q <= 1.0 {
+        -6.0 * (1.0 - q) * (1.0 - q) / normalization
+    } else {
+        0.0
+    }
+}
+
+// Derivative of the spiky kernel of kernel.wgsl
+fn dkernel_spiky(r: f32, h: f32) -> f32 {
+    let normalization = h.powi(6) * std::f32::consts::PI / 15.0;
+    if r < h {
+        -3.0 * (h - r) * (h - r) / normalization
+    } else {
+        0.0
+    }
+}
+
+// sign of WGSL, zero at zero
+fn sign(x: f32) -> f32 {
+    if x == 0.0 { 0.0 } else { x.signum() }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl SphCpu {
+    // Materials, parameters and disturbance are taken from `sph` as uploaded by Sph::init
+    pub fn new(sph: Sph, periodicity: Periodicity, walls: Walls) -> Self {
+        SphCpu {
+            sph,
+            periodicity,
+            walls,
+            cells: HashMap::new(),
+        }
+    }
+
+    // Advance `n_substeps` time steps in the pass order of SphCompute::encode_step
+    pub fn step(&mut self, n_substeps: u32) {
+        for _ in 0..n_substeps {
+            self.spatial_lookup();
+            self.density_interpolant();
+            self.pressure_equation_of_state();
+            self.equation_of_motion();
+            self.leap_frog();
+            self.wall_boundaries();
+        }
+    }
+
+    pub fn particle_data(&self) -> ParticleData {
+        let grid_size = self.sph.params.grid_size;
+        let particles = &self.sph.particles;
+        ParticleData {
+            ids: particles.iter().map(|particle| particle.id).collect(),
+            positions: particles
+                .iter()
+                .map(|particle| {
+                    [0, 1, 2].map(|a| (particle.coord[a] as f32 + particle.position[a]) * grid_size)
+                })
+                .collect(),
+            velocities: self
+                .sph
+                .motion
+                .iter()
+                .map(|motion| motion.velocity)
+                .collect(),
+            material_idx: particles
+                .iter()
+                .map(|particle| particle.material_idx)
+                .collect(),
+        }
+    }
+
+    pub fn state(&self) -> SolverState {
+        SolverState::Sph(self.sph.clone())
+    }
+
+    // Conservation totals as reduced by conservation.wgsl
+    pub fn totals(&self) -> Totals {
+        let grid_size = self.sph.params.grid_size;
+        let gravity = self.sph.disturbance.field;
+        let mut totals = Totals {
+            min_density: f32::MAX,
+            max_density: -f32::MAX,
+            min_pressure: f32::MAX,
+            max_pressure: -f32::MAX,
+            ..Default::default()
+        };
+        for (particle, motion) in self.sph.particles.iter().zip(&self.sph.motion) {
+            let m = particle.mass;
+            let x: [f32; 3] = std::array::from_fn(|a| {
+                (particle.coord[a] as f32 + particle.position[a]) * grid_size
+            });
+            let v = motion.velocity;
+            for (total, v) in totals.momentum.iter_mut().zip(v) {
+                *total += m * v;
+            }
+            let angular_momentum = [
+                x[1] * v[2] - x[2] * v[1],
+                x[2] * v[0] - x[0] * v[2],
+                x[0] * v[1] - x[1] * v[0],
+            ];
+            for (total, l) in totals.angular_momentum.iter_mut().zip(angular_momentum) {
+                *total += m * l;
+            }
+            totals.mass += m;
+            totals.kinetic_energy += 0.5 * m * dot(v, v);
+            totals.potential_energy -= m * dot(gravity, x);
+            // internal_energy of conservation.wgsl
+            let material = &self.sph.materials[particle.material_idx as usize];
+            let rho0 = material.density_reference;
+            let density = particle.density.max(material.density_ref_threshold * rho0);
+            totals.internal_energy +=
+                m * material.compressibility * ((density / rho0).ln() + rho0 / density - 1.0);
+            totals.min_density = totals.min_density.min(particle.density);
+            totals.max_density = totals.max_density.max(particle.density);
+            totals.min_pressure = totals.min_pressure.min(particle.pressure);
+            totals.max_pressure = totals.max_pressure.max(particle.pressure);
+            totals.max_speed = totals.max_speed.max(dot(v, v).sqrt());
+            totals.count += 1;
+        }
+        totals
+    }
+
+    // Particles of each cell, the CPU counterpart of the hash grid and its start indices
+    pub fn spatial_lookup(&mut self) {
+        self.cells.clear();
+        for (idx, particle) in self.sph.particles.iter().enumerate() {
+            self.cells.entry(particle.coord).or_default().push(idx);
+        }
+    }
+
+    pub fn density_interpolant(&mut self) {
+        let densities: Vec<f32> = (0..self.sph.particles.len())
+            .into_par_iter()
+            .map(|index| {
+                let particle = &self.sph.particles[index];
+                let h_a = particle.smoothing_length;
+                let mut density = particle.mass / (std::f32::consts::PI * h_a * h_a);
+                let walls = self.near_walls(index);
+                for neighbor_idx in self.neighbors(index) {
+                    density += self.density_contribution(index, neighbor_idx);
+                    for (image, _, rvec_ab) in self.mirror_images(index, walls, neighbor_idx) {
+                        density += self.pair_density(index, &image, rvec_ab);
+                    }
+                }
+                density
+            })
+            .collect();
+        for (particle, density) in self.sph.particles.iter_mut().zip(densities) {
+            particle.density = density;
+        }
+    }
+
+    pub fn pressure_equation_of_state(&mut self) {
+        let materials = &self.sph.materials;
+        self.sph.particles.par_iter_mut().for_each(|particle| {
+            let material = &materials[particle.material_idx as usize];
+            let rho0 = material.density_reference;
+            particle.pressure = match particle.density / rho0 >= material.density_ref_threshold {
+                true => material.compressibility * (particle.density - rho0),
+                false => 0.0,
+            };
+        });
+    }
+
+    pub fn equation_of_motion(&mut self) {
+        let accelerations: Vec<[f32; 3]> = (0..self.sph.particles.len())
+            .into_par_iter()
+            .map(|index| {
+                let mut acceleration = [0.0; 3];
+                let walls = self.near_walls(index);
+                for neighbor_idx in self.neighbors(index) {
+                    let images = self.mirror_images(index, walls, neighbor_idx).map(
+                        |(image, image_motion, rvec_ab)| {
+                            self.pair_acceleration(index, &image, &image_motion, rvec_ab)
+                        },
+                    );
+                    let direct = self.acceleration_contribution(index, neighbor_idx);
+                    for contribution in std::iter::once(direct).chain(images) {
+                        for a in 0..3 {
+                            acceleration[a] += contribution[a];
+                        }
+                    }
+                }
+                acceleration
+            })
+            .collect();
+        for (motion, acceleration) in self.sph.motion.iter_mut().zip(accelerations) {
+            for (total, a) in motion.acceleration.iter_mut().zip(acceleration) {
+                *total += a;
+            }
+        }
+    }
+
+    // velocity_p holds the velocity half a step behind, velocity is the mean of the two half steps
+    pub fn leap_frog(&mut self) {
+        let dt = self.sph.params.dt;
+        let grid_size = self.sph.params.grid_size;
+        let field = self.sph.disturbance.field;
+        let periodicity = self.periodicity;
+        self.sph
+            .particles
+            .par_iter_mut()
+            .zip(self.sph.motion.par_iter_mut())
+            .for_each(|(particle, motion)| {
+                let acceleration: [f32; 3] =
+                    std::array::from_fn(|a| motion.acceleration[a] + field[a]);
+                motion.acceleration = [0.0; 3];
+                let velocity_ph: [f32; 3] =
+                    std::array::from_fn(|a| motion.velocity_p[a] + acceleration[a] * dt);
+                let pos: [f32; 3] = std::array::from_fn(|a| {
+                    particle.coord[a] as f32
+                        + particle.position[a]
+                        + velocity_ph[a] * dt / grid_size
+                });
+                particle.coord = wrap_coord(&periodicity, pos.map(|x| x.floor() as i32));
+                particle.position = pos.map(|x| x - x.floor());
+                motion.velocity =
+                    std::array::from_fn(|a| 0.5 * (motion.velocity_p[a] + velocity_ph[a]));
+                motion.velocity_p = velocity_ph;
+            });
+    }
+
+    // Reflect the particles leaving the unit box along the non-periodic axes
+    pub fn wall_boundaries(&mut self) {
+        let grid_size = self.sph.params.grid_size;
+        let periodicity = self.periodicity;
+        self.sph
+            .particles
+            .par_iter_mut()
+            .zip(self.sph.motion.par_iter_mut())
+            .for_each(|(particle, motion)| {
+                let position: [f32; 3] = std::array::from_fn(|a| {
+                    (particle.coord[a] as f32 + particle.position[a]) * grid_size
+                });
+                let boundary_damping = 0.7;
+                let bounds = 0.5;
+                for (axis, &position) in position.iter().enumerate() {
+                    if periodicity.cells[axis] == 0
+                        && position.abs() > bounds
+                        && sign(position) == sign(motion.velocity_p[axis])
+                    {
+                        let velocity = -motion.velocity_p[axis] * boundary_damping;
+                        motion.velocity[axis] = velocity;
+                        motion.velocity_p[axis] = velocity;
+                        // Place the particle on the wall
+                        let wall = sign(position) * bounds / grid_size;
+                        particle.coord[axis] = wall.floor() as i32;
+                        particle.position[axis] = wall - wall.floor();
+                    }
+                }
+            });
+    }
+
+    // Particles of the 27 cells around a particle, in the visiting order of the WGSL loops
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let coord = self.sph.particles[index].coord;
+        (0..27).flat_map(move |offset| {
+            let cell = std::array::from_fn(|a| coord[a] + offset / 3i32.pow(2 - a as u32) % 3 - 1);
+            let cell = wrap_coord(&self.periodicity, cell);
+            self.cells.get(&cell).into_iter().flatten().copied()
+        })
+    }
+
+    // get_particle_distance of util.wgsl
+    fn particle_distance(&self, index: usize, neighbor_idx: usize) -> [f32; 3] {
+        let particle = &self.sph.particles[index];
+        let neighbor = &self.sph.particles[neighbor_idx];
+        let coord_dist = minimum_image(
+            &self.periodicity,
+            std::array::from_fn(|a| particle.coord[a] - neighbor.coord[a]),
+        );
+        std::array::from_fn(|a| {
+            (coord_dist[a] as f32 + particle.position[a] - neighbor.position[a])
+                * self.sph.params.grid_size
+        })
+    }
+
+    // Kernel weighted mass of a neighbor, density_contribution of interaction.wgsl
+    fn density_contribution(&self, index: usize, neighbor_idx: usize) -> f32 {
+        let rvec_ab = self.particle_distance(index, neighbor_idx);
+        let neighbor = &self.sph.particles[neighbor_idx];
+        self.pair_density(index, neighbor, rvec_ab)
+    }
+
+    // Pressure and viscous acceleration due to a neighbor, acceleration_contribution of
+    // interaction.wgsl
+    fn acceleration_contribution(&self, index: usize, neighbor_idx: usize) -> [f32; 3] {
+        let rvec_ab = self.particle_distance(index, neighbor_idx);
+        let neighbor = &self.sph.particles[neighbor_idx];
+        let neighbor_motion = &self.sph.motion[neighbor_idx];
+        self.pair_acceleration(index, neighbor, neighbor_motion, rvec_ab)
+    }
+
+    fn pair_density(&self, index: usize, neighbor: &Particle, rvec_ab: [f32; 3]) -> f32 {
+        let particle = &self.sph.particles[index];
+        let r_ab = dot(rvec_ab, rvec_ab).sqrt();
+        let h_ab = 0.5 * (particle.smoothing_length + neighbor.smoothing_length);
+        neighbor.mass * kernel_cubic_bspline(r_ab, h_ab)
+    }
+
+    fn pair_acceleration(
+        &self,
+        index: usize,
+        neighbor: &Particle,
+        neighbor_motion: &ParticleMotion,
+        rvec_ab: [f32; 3],
+    ) -> [f32; 3] {
+        let particle = &self.sph.particles[index];
+        let motion = &self.sph.motion[index];
+        let r2_ab = dot(rvec_ab, rvec_ab);
+        let r_ab = r2_ab.sqrt();
+        let h_ab = 0.5 * (particle.smoothing_length + neighbor.smoothing_length);
+        let h2_ab = h_ab * h_ab;
+        let dkernel_viscosity = dkernel_cubic_bspline(r_ab, h_ab);
+        let dkernel_pressure = dkernel_spiky(r_ab, h_ab);
+        // Pressure
+        let rho_a = particle.density;
+        let rho_b = neighbor.density;
+        let pressure_on_rho2_delta =
+            particle.pressure / (rho_a * rho_a) + neighbor.pressure / (rho_b * rho_b);
+        // Monaghan & Gingold viscosity
+        let material_a = &self.sph.materials[particle.material_idx as usize];
+        let material_b = &self.sph.materials[neighbor.material_idx as usize];
+        let vvec_ab: [f32; 3] =
+            std::array::from_fn(|a| motion.velocity[a] - neighbor_motion.velocity[a]);
+        let v_dot_r_ab = dot(vvec_ab, rvec_ab);
+        let cs_ab = 0.5 * (material_a.cs + material_b.cs);
+        let alpha_ab = 0.5 * (material_a.alpha + material_b.alpha);
+        let beta_ab = 0.5 * (material_a.beta + material_b.beta);
+        let eps_ab = 0.5 * (material_a.eps + material_b.eps);
+        let rho_ab = 0.5 * (rho_a + rho_b);
+        let eta2 = eps_ab * h2_ab;
+        let nu_ab = h_ab * v_dot_r_ab / (r2_ab + eta2);
+        let mut viscosity = 0.0;
+        if v_dot_r_ab < 0.0 && r2_ab > 1e-8 {
+            viscosity = (-alpha_ab * cs_ab * nu_ab + beta_ab * nu_ab * nu_ab) / rho_ab;
+        }
+        let scale = -neighbor.mass
+            * (pressure_on_rho2_delta * dkernel_pressure + viscosity * dkernel_viscosity);
+        rvec_ab.map(|x| scale * x / (r_ab + eta2))
+    }
+
+    fn position(&self, index: usize) -> [f32; 3] {
+        let particle = &self.sph.particles[index];
+        std::array::from_fn(|a| {
+            (particle.coord[a] as f32 + particle.position[a]) * self.sph.params.grid_size
+        })
+    }
+
+    // near_walls of wall.wgsl
+    fn near_walls(&self, index: usize) -> u32 {
+        if self.walls.mirror == 0 {
+            return 0;
+        }
+        let h = self.sph.particles[index].smoothing_length;
+        let position = self.position(index);
+        (0..3)
+            .filter(|&a| self.periodicity.cells[a] == 0 && WALL_BOUNDS - position[a].abs() < h)
+            .fold(0, |walls, a| walls | 1 << a)
+    }
+
+    // Images of a neighbor across each combination of the near walls, mirror_image of wall.wgsl
+    fn mirror_images(
+        &self,
+        index: usize,
+        walls: u32,
+        neighbor_idx: usize,
+    ) -> impl Iterator<Item = (Particle, ParticleMotion, [f32; 3])> + '_ {
+        let position = self.position(index);
+        let rvec_ab = self.particle_distance(index, neighbor_idx);
+        let neighbor = self.sph.particles[neighbor_idx];
+        let neighbor_motion = self.sph.motion[neighbor_idx];
+        let material = &self.sph.materials[neighbor.material_idx as usize];
+        let field = self.sph.disturbance.field;
+        (1..8u32)
+            .filter(move |axes| axes & walls == *axes)
+            .map(move |axes| {
+                let mut image = neighbor;
+                let mut image_motion = neighbor_motion;
+                let mut image_rvec_ab = rvec_ab;
+                for a in (0..3).filter(|a| axes & 1 << a != 0) {
+                    let wall = sign(position[a]) * WALL_BOUNDS;
+                    image_rvec_ab[a] = 2.0 * (position[a] - wall) - rvec_ab[a];
+                    image_motion.velocity[a] = -neighbor_motion.velocity[a];
+                }
+                let rho0 = material.density_reference;
+                let offset = std::array::from_fn(|a| rvec_ab[a] - image_rvec_ab[a]);
+                let pressure_delta = rho0 * dot(field, offset);
+                let density_delta = pressure_delta / material.compressibility;
+                image.pressure += pressure_delta;
+                image.density += density_delta;
+                image.mass *= 1.0 + density_delta / rho0;
+                (image, image_motion, image_rvec_ab)
+            })
+    }
+}
+
+// `headless --cpu` steps scenes through this interface, without creating a device
+impl CpuSimulation for SphCpu {
+    fn capacity(&self) -> u32 {
+        self.sph.particles.len() as u32
+    }
+    fn step(&mut self, n_substeps: u32) {
+        SphCpu::step(self, n_substeps);
+    }
+    fn particle_data(&self) -> ParticleData {
+        SphCpu::particle_data(self)
+    }
+    fn totals(&self) -> Totals {
+        SphCpu::totals(self)
+    }
+    fn state(&self) -> SolverState {
+        SphCpu::state(self)
+    }
+}
+
+// wrap_coord of periodic.wgsl
+fn wrap_coord(periodicity: &Periodicity, coord: [i32; 3]) -> [i32; 3] {
+    std::array::from_fn(|a| {
+        let cells = periodicity.cells[a] as i32;
+        match cells > 0 {
+            true => periodicity.min[a] + (coord[a] - periodicity.min[a]).rem_euclid(cells),
+            false => coord[a],
+        }
+    })
+}
+
+// minimum_image of periodic.wgsl
+fn minimum_image(periodicity: &Periodicity, coord_dist: [i32; 3]) -> [i32; 3] {
+    std::array::from_fn(|a| {
+        let cells = periodicity.cells[a] as i32;
+        if cells == 0 {
+            return coord_dist[a];
+        }
+        let d = coord_dist[a].rem_euclid(cells);
+        if 2 * d > cells { d - cells } else { d }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeding::{Lattice, Seeder, Shape};
+    use crate::simulation::Checkpointing;
+    use crate::sph::{
+        Disturbance, Material, NeighborSearch, Particle, ParticleMotion, SimParams, SphCompute,
+    };
+
+    // Block of water thrown into the lower corner of the box, so that the walls, pressure and
+    // viscosity all take part within a few steps
+    fn splash(periodic: bool) -> (Sph, Periodicity) {
+        let water = Material {
+            density_reference: 1000.0,
+            density_ref_threshold: 0.7,
+            compressibility: 1000.0,
+            boundary_damping: 0.8,
+            cs: 20.0,
+            alpha: 1.0,
+            beta: 2.0,
+            eps: 0.01,
+            color: [0.0, 0.0, 1.0, 1.0],
+        };
+        let block = Shape::Box {
+            min: [-0.5, -0.5, -0.2],
+            max: [-0.2, -0.2, 0.1],
+        };
+        let grid_size = 0.06;
+        let (particles, mut motion) =
+            Seeder::new(Lattice::Cubic, 0.03).sph_particles(&block, &water, 0, 0.06, grid_size, 0);
+        for (i, motion) in motion.iter_mut().enumerate() {
+            let i = i as f32;
+            motion.velocity = [-(7.0 * i).sin(), (5.0 * i).cos() - 1.0, (3.0 * i).sin()];
+            motion.velocity_p = motion.velocity;
+        }
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.0005,
+            grid_size,
+            num_particles: particles.len() as u32,
+            _padding: [0.0; 2],
+        };
+        let disturbance = Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        };
+        let periodicity =
+            Periodicity::new([-0.5; 3], [0.5; 3], [false, false, periodic], grid_size);
+        let sph = Sph::new(params, disturbance, particles, motion, vec![water]);
+        (sph, periodicity)
+    }
+
+    // The software adapter runs the WGSL passes on machines without a GPU
+    fn gpu(
+        sph: &Sph,
+        periodicity: &Periodicity,
+        walls: &Walls,
+    ) -> (wgpu::Device, wgpu::Queue, SphCompute) {
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the GPU comparison needs a wgpu adapter");
+        let neighbor_search = NeighborSearch::CellList {
+            min: [-0.5; 3],
+            max: [0.5; 3],
+        };
+        let params = &sph.params;
+        let compute = pollster::block_on(SphCompute::with_neighbor_search(
+            &device,
+            params,
+            params.num_particles,
+            neighbor_search,
+        ));
+        compute.cpu2gpu_params(&queue, params);
+        compute.cpu2gpu_disturbance(&queue, &sph.disturbance);
+        compute.cpu2gpu_particles(&queue, &sph.particles, &sph.motion);
+        compute.cpu2gpu_materials(&queue, &sph.materials);
+        compute.cpu2gpu_periodicity(&queue, periodicity);
+        compute.cpu2gpu_walls(&queue, walls);
+        (device, queue, compute)
+    }
+
+    // Largest difference relative to the largest CPU value
+    fn assert_close(name: &str, gpu: &[f32], cpu: &[f32], tolerance: f32) {
+        assert_eq!(gpu.len(), cpu.len());
+        let scale = cpu.iter().fold(f32::EPSILON, |m, x| m.max(x.abs()));
+        let error = gpu
+            .iter()
+            .zip(cpu)
+            .fold(0.0f32, |m, (g, c)| m.max((g - c).abs()));
+        assert!(
+            error <= tolerance * scale,
+            "{}: error {} for values up to {}",
+            name,
+            error,
+            scale
+        );
+    }
+
+    fn positions(sph: &Sph) -> Vec<f32> {
+        let grid_size = sph.params.grid_size;
+        sph.particles
+            .iter()
+            .flat_map(|p| [0, 1, 2].map(|a| (p.coord[a] as f32 + p.position[a]) * grid_size))
+            .collect()
+    }
+
+    #[test]
+    fn test_cpu_passes_match_gpu() {
+        let (sph, periodicity) = splash(true);
+        // The block fills a corner, within reach of the walls of both non-periodic axes
+        let walls = Walls::mirrored();
+        let (device, queue, compute) = gpu(&sph, &periodicity, &walls);
+        let mut cpu = SphCpu::new(sph, periodicity, walls);
+        let gpu_particles = || compute.gpu2cpu_particles(&device, &queue);
+        let gpu_motion = || compute.gpu2cpu_motion(&device, &queue);
+
+        let density =
+            |particles: &[Particle]| -> Vec<f32> { particles.iter().map(|p| p.density).collect() };
+        compute.compute_spatial_lookup(&device, &queue);
+        compute.compute_density_interpolant(&device, &queue);
+        cpu.spatial_lookup();
+        cpu.density_interpolant();
+        assert_close(
+            "density",
+            &density(&gpu_particles()),
+            &density(&cpu.sph.particles),
+            1e-5,
+        );
+
+        compute.compute_pressure_equation_of_state(&device, &queue);
+        cpu.pressure_equation_of_state();
+        let pressure =
+            |particles: &[Particle]| -> Vec<f32> { particles.iter().map(|p| p.pressure).collect() };
+        assert_close(
+            "pressure",
+            &pressure(&gpu_particles()),
+            &pressure(&cpu.sph.particles),
+            1e-4,
+        );
+
+        compute.compute_equation_of_motion(&device, &queue);
+        cpu.equation_of_motion();
+        let acceleration = |motion: &[ParticleMotion]| -> Vec<f32> {
+            motion.iter().flat_map(|m| m.acceleration).collect()
+        };
+        assert_close(
+            "acceleration",
+            &acceleration(&gpu_motion()),
+            &acceleration(&cpu.sph.motion),
+            1e-4,
+        );
+
+        compute.compute_integration(&device, &queue, 0);
+        compute.compute_boundaries(&device, &queue);
+        cpu.leap_frog();
+        cpu.wall_boundaries();
+        let velocity = |motion: &[ParticleMotion]| -> Vec<f32> {
+            motion.iter().flat_map(|m| m.velocity).collect()
+        };
+        assert_close(
+            "velocity",
+            &velocity(&gpu_motion()),
+            &velocity(&cpu.sph.motion),
+            1e-5,
+        );
+        let state = compute.gpu2cpu_state(&device, &queue);
+        let SolverState::Sph(gpu_sph) = state else {
+            unreachable!();
+        };
+        assert_close("position", &positions(&gpu_sph), &positions(&cpu.sph), 1e-5);
+    }
+
+    #[test]
+    fn test_cpu_steps_match_gpu() {
+        let (sph, periodicity) = splash(false);
+        let (device, queue, compute) = gpu(&sph, &periodicity, &Walls::default());
+        let mut cpu = SphCpu::new(sph, periodicity, Walls::default());
+        compute.step(&device, &queue, 20);
+        cpu.step(20);
+        let SolverState::Sph(gpu_sph) = compute.gpu2cpu_state(&device, &queue) else {
+            unreachable!();
+        };
+        // Some particles have hit the walls
+        let data = cpu.particle_data();
+        assert!(data.positions.iter().any(|x| x[1] == -0.5));
+        assert_close("position", &positions(&gpu_sph), &positions(&cpu.sph), 1e-4);
+        let velocity =
+            |sph: &Sph| -> Vec<f32> { sph.motion.iter().flat_map(|m| m.velocity).collect() };
+        assert_close("velocity", &velocity(&gpu_sph), &velocity(&cpu.sph), 1e-3);
+    }
+
+    #[test]
+    fn test_cpu_totals_match_gpu() {
+        let (sph, periodicity) = splash(false);
+        let (device, queue, mut compute) = gpu(&sph, &periodicity, &Walls::default());
+        // Through the interface of `headless --cpu`
+        let mut cpu: Box<dyn CpuSimulation> =
+            Box::new(SphCpu::new(sph, periodicity, Walls::default()));
+        compute.attach_conservation(&device);
+        compute.step(&device, &queue, 10);
+        cpu.step(10);
+        let gpu_totals = compute.gpu2cpu_totals(&device, &queue).unwrap();
+        let cpu_totals = cpu.totals();
+        assert_eq!(cpu_totals.count, gpu_totals.count);
+        assert_close("totals", &gpu_totals.values(), &cpu_totals.values(), 1e-3);
+    }
+}
@@ -0,0 +1,29 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> zones: array<DampingZone>;
+
+@group(0) @binding(4)
+var<storage, read> profiles: array<f32>;
+
+@compute @workgroup_size(256)
+fn damping_zones(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    // Relax both the velocity and the predicted velocity
+    let motion = particles_motion[index];
+    particles_motion[index].velocity = relax_velocity(position, motion.velocity, params.dt);
+    particles_motion[index].velocity_p = relax_velocity(position, motion.velocity_p, params.dt);
+}
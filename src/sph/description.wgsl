@@ -7,7 +7,7 @@ struct Particle {
     pressure: f32,
     smoothing_length: f32,
     material_idx: u32,
-    _padding: f32,
+    id: u32,
     // 48 bytes
 }
 struct ParticleMotion {
@@ -16,7 +16,7 @@ struct ParticleMotion {
     acceleration: vec3f,
     _padding: f32,
     velocity_p: vec3f,
-    _padding2: f32,
+    tag: u32,
     // 48 bytes
 }
 struct Material {
@@ -52,6 +52,40 @@ struct SimParams{
     _padding: vec2f,
     // 32 bytes
 }
+struct Periodicity {
+    min: vec3i,
+    _padding: u32,
+    cells: vec3u, // 0 for non-periodic axes
+    _padding2: u32,
+    // 32 bytes
+}
+struct Walls {
+    mirror: u32, // 1 when the walls of the non-periodic axes reflect the fluid
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+    // 16 bytes
+}
+struct CellGrid {
+    min: vec3i,
+    num_cells: u32,
+    dims: vec3u,
+    _padding: u32,
+    // 32 bytes
+}
+struct NeighborListParams {
+    max_neighbors: u32,
+    skin: f32,
+    _padding: vec2f,
+    // 16 bytes
+}
+struct ReferencePosition {
+    coord: vec3i,
+    _padding: u32,
+    position: vec3f,
+    _padding2: f32,
+    // 32 bytes
+}
 struct Disturbance {
     field: vec3f,
     _padding: f32
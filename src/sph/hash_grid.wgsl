@@ -10,10 +10,18 @@ var<storage, read_write> start_indices: array<u32>;
 @group(0) @binding(3)
 var<storage, read> params: SimParams;
 
+@group(0) @binding(4)
+var<storage, read_write> spatial_sorted: array<SpatialLookup>;
+
 @compute @workgroup_size(256)
 fn spatial_lookup(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
     if (index >= params.num_particles) {
+        // Unused slots sort after the alive particles
+        if (index < arrayLength(&spatial_scattered)) {
+            spatial_scattered[index].key = U32MAX;
+            spatial_scattered[index].index = index;
+        }
         return;
     }
     // Get particle
@@ -39,3 +47,18 @@ fn spatial_lookup(@builtin(global_invocation_id) global_id: vec3<u32>) {
     start_indices[index] = U32MAX;
     
 }
+
+// After sorting spatial_scattered by key, record where each key starts and publish the sorted
+// lookup
+@compute @workgroup_size(256)
+fn find_start_indices(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let spatial = spatial_scattered[index];
+    if (index == 0u || spatial_scattered[index - 1u].key != spatial.key) {
+        start_indices[spatial.key] = index;
+    }
+    spatial_sorted[index] = spatial;
+}
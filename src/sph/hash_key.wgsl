@@ -0,0 +1,6 @@
+// WGSL file for the spatial hash neighbor search backend
+// Distant cells can share a key, their particles are rejected by the kernel support
+
+fn cell_key(coord: vec3i) -> u32 {
+    return get_coord_hash_key(coord, params.num_particles);
+}
@@ -17,6 +17,18 @@ var<storage, read> start_indices: array<u32>;
 @group(0) @binding(5)
 var<storage, read_write> params: SimParams;
 
+@group(0) @binding(6)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(7)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(8)
+var<uniform> disturbance: Disturbance;
+
+@group(0) @binding(9)
+var<uniform> walls: Walls;
+
 @compute @workgroup_size(256)
 fn density_interpolant(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
@@ -26,26 +38,24 @@ fn density_interpolant(@builtin(global_invocation_id) global_id: vec3<u32>) {
     }
     // Get particle
     let particle = particles[index];
-    // Get prime values
-    let prime = params.grid_prime;
     // Get paticle parameters
     let h_a = particle.smoothing_length;
     let mass_a = particle.mass;
     // Initialize Density
     var density: f32 = mass_a / (PI * h_a * h_a);
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let walls = near_walls(position, h_a);
     // Loop through all adjacent grid coordinates to particle
     for (var gx = -1i; gx < 2; gx++) {
         for (var gy = -1i; gy < 2; gy++) {
             for (var gz = -1i; gz < 2; gz++) {
             // let gz = 0i;
-                // Calculate hash key
-                let grid_coord_x = particle.coord.x + gx;
-                let grid_coord_y = particle.coord.y + gy;
-                let grid_coord_z = particle.coord.z + gz;
-                let key_x = u32(grid_coord_x) * prime.x;
-                let key_y = u32(grid_coord_y) * prime.y;
-                let key_z = u32(grid_coord_z) * prime.z;
-                let key = (key_x + key_y + key_z) % num_particles;
+                // Calculate cell key
+                let grid_coord = wrap_coord(particle.coord + vec3i(gx, gy, gz));
+                let key = cell_key(grid_coord);
+                if (key == U32MAX) {
+                    continue;
+                }
                 // Find start index in particle list and loop through neihbors
                 let idx0 = start_indices[key];
                 var spatial_idx: u32 = u32(0);
@@ -57,16 +67,10 @@ fn density_interpolant(@builtin(global_invocation_id) global_id: vec3<u32>) {
                     }
                     let neighbor_idx = spatial[spatial_idx].index;
                     let neighbor = particles[neighbor_idx];
-                    // Compute distance to neighbor
-                    let rvec_ab = get_particle_distance(particle,neighbor,params.grid_size);
-                    // let rvec_ab = (vec3f(particle.coord - neighbor.coord) + particle.position - neighbor.position) * params.grid_size;
-                    let r2_ab = dot(rvec_ab,rvec_ab);
-                    let r_ab = sqrt(r2_ab);
-                    // Check if neighbor is within smoothing length
-                    let h_ab = 0.5 * (h_a + neighbor.smoothing_length);
-                    let h2_ab = h_ab * h_ab;
-                    let kernel = kernel_cubic_bspline(r_ab, r2_ab, h_ab, h2_ab);
-                    density += neighbor.mass * kernel;
+                    density += density_contribution(particle, neighbor);
+                    if (walls != 0u) {
+                        density += mirror_density_contribution(particle, position, walls, neighbor, particles_motion[neighbor_idx]);
+                    }
                 }
             }
         }
@@ -108,28 +112,23 @@ fn equation_of_motion(@builtin(global_invocation_id) global_id: vec3<u32>) {
     }
     // Get particle
     let particle = particles[index];
-    // Get prime values
-    let prime = params.grid_prime;
     // Get particle motion
     let motion = particles_motion[index];
-    // Get paticle parameters
-    let h_a = particle.smoothing_length;
-    let mass_a = particle.mass;
     // Initialize Accerleration, (TODO: initialize as disturbance)
     var acceleration = vec3f(0.0,0.0,0.0);
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let walls = near_walls(position, particle.smoothing_length);
     // Loop through all adjacent grid coordinates to particle
     for (var gx = -1i; gx < 2; gx++) {
         for (var gy = -1i; gy < 2; gy++) {
             for (var gz = -1i; gz < 2; gz++) {
             // let gz = 0i;
-                // Calculate hash key
-                let grid_coord_x = particle.coord.x + gx;
-                let grid_coord_y = particle.coord.y + gy;
-                let grid_coord_z = particle.coord.z + gz;
-                let key_x = u32(grid_coord_x) * prime.x;
-                let key_y = u32(grid_coord_y) * prime.y;
-                let key_z = u32(grid_coord_z) * prime.z;
-                let key = (key_x + key_y + key_z) % num_particles;
+                // Calculate cell key
+                let grid_coord = wrap_coord(particle.coord + vec3i(gx, gy, gz));
+                let key = cell_key(grid_coord);
+                if (key == U32MAX) {
+                    continue;
+                }
                 // Find start index in particle list and loop through neihbors
                 let idx0 = start_indices[key];
                 var spatial_idx: u32 = u32(0);
@@ -142,40 +141,10 @@ fn equation_of_motion(@builtin(global_invocation_id) global_id: vec3<u32>) {
                     let neighbor_idx = spatial[spatial_idx].index;
                     let neighbor = particles[neighbor_idx];
                     let neighbor_motion = particles_motion[neighbor_idx];
-                    // Compute distance to neighbor
-                    let rvec_ab = get_particle_distance(particle,neighbor,params.grid_size);
-                    // let rvec_ab = (vec3f(particle.coord - neighbor.coord) + particle.position - neighbor.position) * params.grid_size;
-                    let r2_ab = dot(rvec_ab,rvec_ab);
-                    let r_ab = sqrt(r2_ab);
-                    // Check if neighbor is within smoothing length
-                    let h_ab = 0.5 * (h_a + neighbor.smoothing_length);
-                    let h2_ab = h_ab * h_ab;
-                    let dkernel_viscosity = dkernel_cubic_bspline(r_ab, r2_ab, h_ab, h2_ab);
-                    let dkernel_pressure = dkernel_spiky(r_ab, h_ab, h2_ab);
-                    // Calculate Influence from pressure
-                    let rho_a = particle.density;
-                    let rho_b = neighbor.density;
-                    let pressure_a = particle.pressure;
-                    let pressure_b = neighbor.pressure;
-                    let pressure_on_rho2_delta = pressure_a / (rho_a * rho_a) + pressure_b / (rho_b * rho_b);
-                        // Calculate Viscosity using Monaghan & Gingold from 1982
-                    let material_a = material[particle.material_idx];
-                    let material_b = material[neighbor.material_idx];
-                    let vvec_ab = motion.velocity - neighbor_motion.velocity;
-                    let v_dot_r_ab = dot(vvec_ab,rvec_ab);
-                    var viscosity = 0.0;
-                    let cs_ab = 0.5 * (material_a.cs + material_b.cs);
-                    let alpha_ab = 0.5 * (material_a.alpha + material_b.alpha);
-                    let beta_ab = 0.5 * (material_a.beta + material_b.beta);
-                    let eps_ab = 0.5 * (material_a.eps + material_b.eps);
-                    let rho_ab = 0.5 * (particle.density + neighbor.density);
-                    let eta2 = eps_ab * h2_ab;
-                    let nu_ab = h_ab * v_dot_r_ab / (r2_ab + eta2);
-                    if (v_dot_r_ab < 0.0 && r2_ab > 1e-8) {
-                        viscosity = (-alpha_ab * cs_ab * nu_ab + beta_ab * nu_ab * nu_ab) / rho_ab;
+                    acceleration += acceleration_contribution(particle, motion, neighbor, neighbor_motion);
+                    if (walls != 0u) {
+                        acceleration += mirror_acceleration_contribution(particle, motion, position, walls, neighbor, neighbor_motion);
                     }
-                    let rhat_ab = rvec_ab / (r_ab + eta2);
-                    acceleration += -neighbor.mass * (pressure_on_rho2_delta * dkernel_pressure + viscosity * dkernel_viscosity) * rhat_ab;       
                 }
             }
         }
@@ -0,0 +1,46 @@
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read_write> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> emitted: array<EmittedParticle>;
+
+@group(0) @binding(4)
+var<uniform> emission: Emission;
+
+// Append emitted particles after the alive ones, dropping those beyond capacity
+@compute @workgroup_size(256)
+fn emit_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let slot = params.num_particles + index;
+    if (index >= emission.num_emitted || slot >= arrayLength(&particles)) {
+        return;
+    }
+    let new_particle = emitted[index];
+    let pos = new_particle.position / params.grid_size;
+    var particle = Particle();
+    particle.coord = vec3i(floor(pos));
+    particle.position = pos - floor(pos);
+    particle.mass = new_particle.mass;
+    particle.density = new_particle.density;
+    particle.smoothing_length = new_particle.smoothing_length;
+    particle.material_idx = new_particle.material_idx;
+    particle.id = emission.first_id + index;
+    var motion = ParticleMotion();
+    motion.velocity = new_particle.velocity;
+    motion.velocity_p = new_particle.velocity;
+    motion.tag = new_particle.tag;
+    particles[slot] = particle;
+    particles_motion[slot] = motion;
+}
+
+// Runs after emit_particles so that every invocation saw the same count
+@compute @workgroup_size(1)
+fn commit_emitted() {
+    params.num_particles = min(params.num_particles + emission.num_emitted, arrayLength(&particles));
+}
@@ -0,0 +1,60 @@
+// WGSL file for the pairwise SPH interactions shared by the neighbour search backends
+// Requires `material` and `params` bindings
+
+// Kernel weighted mass of a neighbor
+fn density_contribution(particle: Particle, neighbor: Particle) -> f32 {
+    let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+    return pair_density(particle, neighbor, rvec_ab);
+}
+
+// Pressure and viscous acceleration of a particle due to a neighbor
+fn acceleration_contribution(particle: Particle, motion: ParticleMotion, neighbor: Particle, neighbor_motion: ParticleMotion) -> vec3f {
+    let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+    return pair_acceleration(particle, motion, neighbor, neighbor_motion, rvec_ab);
+}
+
+// Kernel weighted mass of a neighbor at `rvec_ab` from the particle
+fn pair_density(particle: Particle, neighbor: Particle, rvec_ab: vec3f) -> f32 {
+    let r2_ab = dot(rvec_ab, rvec_ab);
+    let r_ab = sqrt(r2_ab);
+    // Check if neighbor is within smoothing length
+    let h_ab = 0.5 * (particle.smoothing_length + neighbor.smoothing_length);
+    let h2_ab = h_ab * h_ab;
+    let kernel = kernel_cubic_bspline(r_ab, r2_ab, h_ab, h2_ab);
+    return neighbor.mass * kernel;
+}
+
+// Pressure and viscous acceleration of a particle due to a neighbor at `rvec_ab` from it
+fn pair_acceleration(particle: Particle, motion: ParticleMotion, neighbor: Particle, neighbor_motion: ParticleMotion, rvec_ab: vec3f) -> vec3f {
+    let r2_ab = dot(rvec_ab, rvec_ab);
+    let r_ab = sqrt(r2_ab);
+    // Check if neighbor is within smoothing length
+    let h_ab = 0.5 * (particle.smoothing_length + neighbor.smoothing_length);
+    let h2_ab = h_ab * h_ab;
+    let dkernel_viscosity = dkernel_cubic_bspline(r_ab, r2_ab, h_ab, h2_ab);
+    let dkernel_pressure = dkernel_spiky(r_ab, h_ab, h2_ab);
+    // Calculate Influence from pressure
+    let rho_a = particle.density;
+    let rho_b = neighbor.density;
+    let pressure_a = particle.pressure;
+    let pressure_b = neighbor.pressure;
+    let pressure_on_rho2_delta = pressure_a / (rho_a * rho_a) + pressure_b / (rho_b * rho_b);
+    // Calculate Viscosity using Monaghan & Gingold from 1982
+    let material_a = material[particle.material_idx];
+    let material_b = material[neighbor.material_idx];
+    let vvec_ab = motion.velocity - neighbor_motion.velocity;
+    let v_dot_r_ab = dot(vvec_ab, rvec_ab);
+    var viscosity = 0.0;
+    let cs_ab = 0.5 * (material_a.cs + material_b.cs);
+    let alpha_ab = 0.5 * (material_a.alpha + material_b.alpha);
+    let beta_ab = 0.5 * (material_a.beta + material_b.beta);
+    let eps_ab = 0.5 * (material_a.eps + material_b.eps);
+    let rho_ab = 0.5 * (particle.density + neighbor.density);
+    let eta2 = eps_ab * h2_ab;
+    let nu_ab = h_ab * v_dot_r_ab / (r2_ab + eta2);
+    if (v_dot_r_ab < 0.0 && r2_ab > 1e-8) {
+        viscosity = (-alpha_ab * cs_ab * nu_ab + beta_ab * nu_ab * nu_ab) / rho_ab;
+    }
+    let rhat_ab = rvec_ab / (r_ab + eta2);
+    return -neighbor.mass * (pressure_on_rho2_delta * dkernel_pressure + viscosity * dkernel_viscosity) * rhat_ab;
+}
@@ -0,0 +1,50 @@
+// WGSL file for the kernel interpolation of the particles at arbitrary points
+// Requires the `particles`, `particles_motion`, `spatial`, `start_indices`, `params` and
+// `periodicity` bindings and a `cell_key` function
+
+// Density, pressure and velocity are normalised by the kernel sum of the particle volumes, which
+// is the volume fraction. Points away from the fluid are left at zero.
+fn interpolate_fields(point: vec3f) -> FieldSample {
+    let num_particles = params.num_particles;
+    // Point in hash grid coordinates
+    let scaled = point / params.grid_size;
+    let point_coord = wrap_coord(vec3i(floor(scaled)));
+    let point_position = scaled - floor(scaled);
+    var volume_fraction = 0.0;
+    var density = 0.0;
+    var pressure = 0.0;
+    var velocity = vec3f(0.0);
+    for (var gx = -1i; gx < 2; gx++) {
+        for (var gy = -1i; gy < 2; gy++) {
+            for (var gz = -1i; gz < 2; gz++) {
+                let key = cell_key(wrap_coord(point_coord + vec3i(gx, gy, gz)));
+                if (key == U32MAX) {
+                    continue;
+                }
+                for (var spatial_idx = start_indices[key]; spatial_idx < num_particles; spatial_idx++) {
+                    if (spatial[spatial_idx].key != key) {
+                        break;
+                    }
+                    let neighbor_idx = spatial[spatial_idx].index;
+                    let neighbor = particles[neighbor_idx];
+                    let coord_dist = minimum_image(point_coord - neighbor.coord);
+                    let rvec = (vec3f(coord_dist) + point_position - neighbor.position) * params.grid_size;
+                    let r2 = dot(rvec, rvec);
+                    let h = neighbor.smoothing_length;
+                    let kernel = kernel_cubic_bspline(sqrt(r2), r2, h, h * h);
+                    let volume = neighbor.mass / neighbor.density * kernel;
+                    volume_fraction += volume;
+                    density += volume * neighbor.density;
+                    pressure += volume * neighbor.pressure;
+                    velocity += volume * particles_motion[neighbor_idx].velocity;
+                }
+            }
+        }
+    }
+    if (volume_fraction > 0.0) {
+        density /= volume_fraction;
+        pressure /= volume_fraction;
+        velocity /= volume_fraction;
+    }
+    return FieldSample(velocity, density, pressure, volume_fraction, vec2f(0.0));
+}
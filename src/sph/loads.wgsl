@@ -0,0 +1,200 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> targets: array<LoadTarget>;
+
+@group(0) @binding(4)
+var<storage, read_write> snapshots: array<Snapshot>;
+
+@group(0) @binding(5)
+var<storage, read> obstacles: array<SdfObstacle>;
+
+@group(0) @binding(6)
+var<storage, read> sdf: array<f32>;
+
+@group(0) @binding(7)
+var<storage, read_write> partials: array<Load>;
+
+@group(0) @binding(8)
+var<storage, read> material: array<Material>;
+
+@group(0) @binding(9)
+var<storage, read> spatial: array<SpatialLookup>;
+
+@group(0) @binding(10)
+var<storage, read> start_indices: array<u32>;
+
+@group(0) @binding(11)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(12)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(13)
+var<uniform> disturbance: Disturbance;
+
+@group(0) @binding(14)
+var<uniform> walls: Walls;
+
+// Particle before a boundary pass, in world units
+struct Snapshot {
+    position: vec3f,
+    velocity_p: vec3f,
+    // 32 bytes
+}
+
+const PASS_WALLS: u32 = 0u;
+const PASS_OBSTACLES: u32 = 1u;
+const PASS_FORCES: u32 = 2u;
+// One force per combination of the walls, see mirror_acceleration_contribution
+const WALL_IMAGES: u32 = 7u;
+
+fn particle_position(index: u32) -> vec3f {
+    let particle = particles[index];
+    return (vec3f(particle.coord) + particle.position) * params.grid_size;
+}
+
+fn take_snapshot(index: u32) {
+    snapshots[index] = Snapshot(particle_position(index), particles_motion[index].velocity_p);
+}
+
+// Pressure and viscous forces of the particle on the walls, as the reaction to the acceleration
+// due to its neighbors mirrored across each combination of the near walls. Each force acts at
+// the projection of the particle on its walls.
+fn wall_forces(index: u32) -> array<vec3f, WALL_IMAGES> {
+    var forces: array<vec3f, WALL_IMAGES>;
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let position = particle_position(index);
+    let walls = near_walls(position, particle.smoothing_length);
+    if (walls == 0u) {
+        return forces;
+    }
+    var walk = neighbor_walk(index);
+    while (next_neighbor(&walk)) {
+        let neighbor_idx = walk.neighbor;
+        let neighbor = particles[neighbor_idx];
+        let neighbor_motion = particles_motion[neighbor_idx];
+        let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+        for (var axes = 1u; axes <= WALL_IMAGES; axes++) {
+            if ((axes & walls) == axes) {
+                let image = mirror_image(position, axes, neighbor, neighbor_motion, rvec_ab);
+                forces[axes - 1u] -= particle.mass * pair_acceleration(particle, motion, image.neighbor, image.motion, image.rvec_ab);
+            }
+        }
+    }
+    return forces;
+}
+
+// Point of the walls of `axes` nearest to the particle
+fn wall_point(position: vec3f, axes: u32) -> vec3f {
+    var point = position;
+    for (var axis = 0u; axis < 3u; axis++) {
+        if ((axes & (1u << axis)) != 0u) {
+            point[axis] = sign(position[axis]) * WALL_BOUNDS;
+        }
+    }
+    return point;
+}
+
+// Impulse of a particle on a target over the boundary pass or, for the forces, the time step
+fn particle_load(boundary_pass: u32, load_target: LoadTarget, index: u32, forces: array<vec3f, WALL_IMAGES>) -> Load {
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let position = particle_position(index);
+    if (boundary_pass == PASS_FORCES) {
+        if (load_target.kind == LOAD_REGION) {
+            var load = Load();
+            for (var axes = 1u; axes <= WALL_IMAGES; axes++) {
+                let point = wall_point(position, axes);
+                if (any(forces[axes - 1u] != vec3f(0.0)) && in_region(load_target, point)) {
+                    load = combine_loads(load, target_load(load_target, point, forces[axes - 1u] * params.dt));
+                }
+            }
+            return load;
+        }
+        if (load_target.kind != LOAD_TAG || motion.tag != load_target.index) {
+            return Load();
+        }
+        // Only the pair forces are accumulated so far, gravity is added by the integrator
+        return target_load(load_target, position, particle.mass * motion.acceleration * params.dt);
+    }
+    // The boundary takes the momentum it gives to the particle when it reflects or projects it
+    let snapshot = snapshots[index];
+    let impulse = particle.mass * (snapshot.velocity_p - motion.velocity_p);
+    var hit = load_target.kind == LOAD_REGION && in_region(load_target, position);
+    if (boundary_pass == PASS_OBSTACLES && load_target.kind == LOAD_OBSTACLE && load_target.index < arrayLength(&obstacles)) {
+        hit = sample_sdf(load_target.index, snapshot.position) < 0.0;
+    }
+    if (!hit) {
+        return Load();
+    }
+    return target_load(load_target, position, impulse);
+}
+
+// One partial per target for the workgroup
+fn reduce_targets(boundary_pass: u32, index: u32, local_index: u32, workgroup_idx: u32) {
+    let num_targets = arrayLength(&targets);
+    var forces: array<vec3f, WALL_IMAGES>;
+    if (boundary_pass == PASS_FORCES && index < params.num_particles) {
+        forces = wall_forces(index);
+    }
+    for (var target_idx = 0u; target_idx < num_targets; target_idx++) {
+        var load = Load();
+        if (index < params.num_particles) {
+            load = particle_load(boundary_pass, targets[target_idx], index, forces);
+        }
+        let partial = reduce_workgroup(local_index, load);
+        if (local_index == 0u) {
+            partials[workgroup_idx * num_targets + target_idx] = partial;
+        }
+    }
+}
+
+@compute @workgroup_size(128)
+fn snapshot_loads(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index < params.num_particles) {
+        take_snapshot(index);
+    }
+}
+
+// After the wall pass, the snapshot is then taken again for the obstacle pass
+@compute @workgroup_size(128)
+fn wall_loads(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let index = global_id.x;
+    reduce_targets(PASS_WALLS, index, local_index, workgroup_id.x);
+    if (index < params.num_particles) {
+        take_snapshot(index);
+    }
+}
+
+@compute @workgroup_size(128)
+fn obstacle_loads(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    reduce_targets(PASS_OBSTACLES, global_id.x, local_index, workgroup_id.x);
+}
+
+// After the equation of motion of the last integrator stage, before the acceleration is taken
+@compute @workgroup_size(128)
+fn force_loads(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    reduce_targets(PASS_FORCES, global_id.x, local_index, workgroup_id.x);
+}
@@ -1,9 +1,32 @@
+use crate::checkpoint::SolverState;
+use crate::conservation::{REDUCE_SIZE, Totals, TotalsReduction};
+use crate::damping::{DampingBuffers, DampingZone};
+use crate::flow::{EmittedParticle, Inlet, Outlet};
+use crate::loads::{Load, LoadReduction, LoadTarget};
+use crate::obstacle::{Obstacle, ObstacleBuffers};
+use crate::prefix_sum::PrefixSum;
+use crate::probe::Probes;
+use crate::resample::{FieldBuffers, FieldGrid, Fields};
+use crate::rigid_body::{RigidBody, RigidBodyBuffers, RigidBodyLoad, SCHEDULE_STEPS};
+use crate::seeding::{Seeder, Shape};
 use crate::shader_module::ShaderModuleBuilder;
+use crate::simulation::{
+    BoundaryLoads, Checkpointing, ConservationTotals, CpuSimulation, InstancePass, ParticleData,
+    ProbeRecords, RigidBodyMotion, RigidBodyRecords, Scene, Simulation, gpu2cpu_buffer,
+};
+use crate::sort::BitonicSort;
+use crate::tracer::{TracerBuffers, TracerSample};
+use crate::wavemaker::Wavemaker;
 use futures::executor::block_on;
 use iced::widget::Shader;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::{num::NonZeroU64, str::FromStr};
 use wgpu::{ShaderModule, util::DeviceExt};
 
+mod cpu;
+pub use cpu::SphCpu;
+
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct Particle {
@@ -14,7 +37,8 @@ pub struct Particle {
     pub pressure: f32,
     pub smoothing_length: f32,
     pub material_idx: u32,
-    pub _padding: f32,
+    // Persistent across reordering, inlets continue from the initial particle count
+    pub id: u32,
     // 48 bytes
 }
 
@@ -26,7 +50,8 @@ pub struct ParticleMotion {
     pub acceleration: [f32; 3],
     pub _padding: f32,
     pub velocity_p: [f32; 3],
-    pub _padding2: f32,
+    // User defined group of the particle, e.g. tracers or the inlet it came from
+    pub tag: u32,
     // 48 bytes
 }
 
@@ -72,6 +97,98 @@ pub struct Disturbance {
     pub _padding: f32,
 }
 
+// Periodic axes in hash grid cells, the default has none
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Periodicity {
+    pub min: [i32; 3],
+    pub _padding: u32,
+    pub cells: [u32; 3], // 0 for non-periodic axes
+    pub _padding2: u32,
+}
+
+// Walls of the unit box on the non-periodic axes, the default only reflects the particles
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Walls {
+    // 1 when the walls also mirror the fluid, see Walls::mirrored
+    pub mirror: u32,
+    pub _padding: [u32; 3],
+}
+
+// Dense cells of a bounded domain, in hash grid cells
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct CellGrid {
+    pub min: [i32; 3],
+    pub num_cells: u32,
+    pub dims: [u32; 3],
+    pub _padding: u32,
+}
+
+// How the neighbors of a particle are found each step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NeighborSearch {
+    // Prime hash of the cell coordinates modulo the particle count, bitonic sorted on the GPU.
+    // Works for unbounded domains but distant cells can share a key.
+    SpatialHash,
+    // Dense cell index over [min, max) with a cell start table built on the GPU. Particles that
+    // leave the domain are binned into the nearest boundary cell.
+    CellList { min: [f32; 3], max: [f32; 3] },
+}
+
+// Time integration of the SPH equations of motion, see compute_integration
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    // Staggered velocities, the reported velocity is the mean of the two half steps
+    #[default]
+    LeapFrog,
+    // Kick-drift-kick, the closing kick takes a second force evaluation at the new positions
+    VelocityVerlet,
+    // Semi-implicit Euler, velocity first and then position
+    SymplecticEuler,
+    // Monaghan's predictor-corrector, two force evaluations per step
+    PredictorCorrector,
+    // Midpoint Runge-Kutta, two force evaluations per step
+    MidpointRk2,
+}
+
+// Hash key sharing between distinct occupied cells for the spatial hash backend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HashCollisions {
+    pub occupied_cells: usize,
+    pub occupied_keys: usize,
+    // Keys shared by more than one occupied cell
+    pub colliding_keys: usize,
+    // Fraction of the particles walked for a cell lookup that lie in other cells
+    pub foreign_fraction: f32,
+}
+
+// Cached neighbor lists hold `max_neighbors` entries per particle
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct NeighborListParams {
+    pub max_neighbors: u32,
+    pub skin: f32,
+    pub _padding: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct NeighborListStatus {
+    // Non-zero once a particle moved more than half the skin since the last build
+    pub expired: u32,
+    // Most neighbors found for a particle in the last build, above `max_neighbors` lists were
+    // truncated
+    pub max_count: u32,
+    // Non-zero while the list of the current step is rebuilt, latched from `expired`
+    pub building: u32,
+    // Rebuilds since the list was attached
+    pub builds: u32,
+}
+
+#[derive(Clone)]
 pub struct Sph {
     pub params: SimParams,
     pub disturbance: Disturbance,
@@ -82,7 +199,15 @@ pub struct Sph {
 }
 
 pub struct SphCompute {
+    // Particle count at construction, the alive count is kept in the GPU parameters
     pub num_particles: u32,
+    // Length of the particle buffers, dispatches cover the capacity
+    pub capacity: u32,
+    // Id of the next particle emitted by an inlet
+    next_id: Cell<u32>,
+    // Time step and gravity of the GPU parameters, for the boundaries stepped on the CPU
+    dt: Cell<f32>,
+    gravity: Cell<[f32; 3]>,
 
     // Input Buffers
     pub buffer_particles: wgpu::Buffer,
@@ -95,10 +220,16 @@ pub struct SphCompute {
 
     // Uniform Buffers
     buffer_disturbance: wgpu::Buffer,
+    buffer_periodicity: wgpu::Buffer,
+    buffer_walls: wgpu::Buffer,
+    buffer_cell_grid: wgpu::Buffer,
 
     // Staging Buffers
     staging_buffer_spatial: wgpu::Buffer,
     staging_buffer_start_indices: wgpu::Buffer,
+    staging_buffer_params: wgpu::Buffer,
+    staging_buffer_particles: wgpu::Buffer,
+    staging_buffer_motion: wgpu::Buffer,
 
     // Bind Groups
     bind_group_hash_grid: wgpu::BindGroup,
@@ -107,10 +238,231 @@ pub struct SphCompute {
 
     // Compute Pipeline
     compute_pipeline_hash_grid: wgpu::ComputePipeline,
+    compute_pipeline_start_indices: wgpu::ComputePipeline,
     compute_pipeline_density_interpolant: wgpu::ComputePipeline,
     compute_pipeline_pressure_equation_of_state: wgpu::ComputePipeline,
     compute_pipeline_equation_of_motion: wgpu::ComputePipeline,
-    compute_pipeline_leap_frog: wgpu::ComputePipeline,
+    // One pipeline per stage of the selected integrator
+    compute_pipelines_integrator: Vec<wgpu::ComputePipeline>,
+    compute_pipeline_wall_boundaries: wgpu::ComputePipeline,
+
+    // Time Integration
+    integrator: Integrator,
+    module_solver: ShaderModule,
+    pipeline_layout_solver: wgpu::PipelineLayout,
+    #[allow(unused)]
+    buffer_start_positions: wgpu::Buffer,
+
+    // Optional Boundaries
+    obstacle_collision: Option<ObstacleCollision>,
+    rigid_body_coupling: Option<RigidBodyCoupling>,
+    damping: Option<Damping>,
+    flow_boundaries: Option<FlowBoundaries>,
+
+    // Neighbor Search, the spatial hash is sorted on the GPU
+    spatial_sort: Option<BitonicSort>,
+    cell_list: Option<CellList>,
+    neighbor_list: Option<NeighborList>,
+    // Cached lists walked by the boundary passes as their second bind group, see
+    // neighbor_walk_list.wgsl
+    bind_group_layout_neighbor_walk: wgpu::BindGroupLayout,
+
+    // Optional Reordering
+    reorder: Option<Reorder>,
+
+    // Optional Tracers
+    tracers: Option<Tracers>,
+
+    // Optional Probes
+    probes: Option<ProbeSampling>,
+
+    // Optional Conservation Diagnostics
+    conservation: Option<Conservation>,
+
+    // Optional Boundary Loads
+    loads: Option<LoadMeasurement>,
+}
+
+struct ObstacleCollision {
+    buffers: ObstacleBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct Damping {
+    #[allow(unused)]
+    buffers: DampingBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+// Emission runs on the CPU before each submission, up to the time of the steps recorded so far
+struct FlowBoundaries {
+    inlets: RefCell<Vec<Inlet>>,
+    outflow: Option<Outflow>,
+    time: Cell<f32>,
+    // Emitted particles are appended in chunks of the emission buffer length
+    chunk_len: usize,
+    buffer_emitted: wgpu::Buffer,
+    buffer_emission: wgpu::Buffer,
+    bind_group_inflow: wgpu::BindGroup,
+    compute_pipeline_emit_particles: wgpu::ComputePipeline,
+    compute_pipeline_commit_emitted: wgpu::ComputePipeline,
+}
+
+struct Outflow {
+    #[allow(unused)]
+    buffer_outlets: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_keep: wgpu::Buffer,
+    buffer_particles_out: wgpu::Buffer,
+    buffer_motion_out: wgpu::Buffer,
+    prefix_sum: PrefixSum,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_mark_outflow: wgpu::ComputePipeline,
+    compute_pipeline_compact_particles: wgpu::ComputePipeline,
+}
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Emission {
+    num_emitted: u32,
+    first_id: u32,
+    _padding: [u32; 2],
+}
+
+struct CellList {
+    num_cells: u32,
+    #[allow(unused)]
+    buffer_cell_ranks: wgpu::Buffer,
+    prefix_sum: PrefixSum,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_clear_cells: wgpu::ComputePipeline,
+    compute_pipeline_count_cells: wgpu::ComputePipeline,
+    compute_pipeline_scatter_cells: wgpu::ComputePipeline,
+}
+
+struct Reorder {
+    // Steps between the reorders of prepare_step, 0 for none, and the steps since the last one
+    interval: u32,
+    steps: Cell<u32>,
+    buffer_particles_out: wgpu::Buffer,
+    buffer_motion_out: wgpu::Buffer,
+    sort: BitonicSort,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_morton_keys: wgpu::ComputePipeline,
+    compute_pipeline_gather_particles: wgpu::ComputePipeline,
+}
+
+impl Reorder {
+    // Steps of the next submission before a reorder is due, counting the one run by prepare_step
+    fn steps_to_next(&self) -> Option<u32> {
+        let steps = self.steps.get();
+        match self.interval {
+            0 => None,
+            interval if steps >= interval => Some(interval),
+            interval => Some(interval - steps),
+        }
+    }
+}
+
+struct NeighborList {
+    #[allow(unused)]
+    buffer_list_params: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_counts: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_indices: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_references: wgpu::Buffer,
+    buffer_status: wgpu::Buffer,
+    staging_buffer_status: wgpu::Buffer,
+    #[allow(unused)]
+    buffer_dispatch_sizes: wgpu::Buffer,
+    // Indirect arguments of the spatial lookup, empty while the list is valid
+    buffer_dispatch: wgpu::Buffer,
+    bind_group_build: wgpu::BindGroup,
+    bind_group_hydrodynamics: wgpu::BindGroup,
+    bind_group_walk: wgpu::BindGroup,
+    compute_pipeline_gate: wgpu::ComputePipeline,
+    compute_pipeline_build: wgpu::ComputePipeline,
+    compute_pipeline_check: wgpu::ComputePipeline,
+    compute_pipeline_density_interpolant: wgpu::ComputePipeline,
+    compute_pipeline_equation_of_motion: wgpu::ComputePipeline,
+}
+
+struct Tracers {
+    buffers: TracerBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct Conservation {
+    reduction: TotalsReduction,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct ProbeSampling {
+    probes: Probes,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BodyPass {
+    Density,
+    Forces,
+    // Last integrator stage
+    ForcesReaction,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LoadPass {
+    Snapshot,
+    Walls,
+    Obstacles,
+    Forces,
+}
+
+struct LoadMeasurement {
+    reduction: LoadReduction,
+    // Which passes have targets
+    boundaries: bool,
+    forces: bool,
+    #[allow(unused)]
+    buffer_snapshots: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline_snapshot: wgpu::ComputePipeline,
+    compute_pipeline_walls: wgpu::ComputePipeline,
+    compute_pipeline_obstacles: wgpu::ComputePipeline,
+    compute_pipeline_forces: wgpu::ComputePipeline,
+    // Variant of the force pass walking the neighbor list, see attach_neighbor_list
+    compute_pipeline_forces_list: wgpu::ComputePipeline,
+}
+
+// Resampling pass of one grid, made by SphCompute::field_sampler
+pub struct FieldSampler {
+    buffers: FieldBuffers,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+struct RigidBodyCoupling {
+    buffers: RigidBodyBuffers,
+    // Integrated on the GPU, the poses are refreshed by gpu2cpu_rigid_bodies
+    bodies: RefCell<Vec<RigidBody>>,
+    // Prescribed motion of the paddle bodies, which are not integrated, see attach_wavemakers
+    wavemakers: Vec<Option<Wavemaker>>,
+    // Time of the steps scheduled since the wavemakers were attached
+    time: Cell<f64>,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_pipeline_density: wgpu::ComputePipeline,
+    compute_pipeline_forces: wgpu::ComputePipeline,
+    compute_pipeline_forces_reaction: wgpu::ComputePipeline,
+    // Variants of the image passes walking the neighbor list, see attach_neighbor_list
+    compute_pipelines_list: [wgpu::ComputePipeline; 3],
 }
 
 impl Sph {
@@ -131,9 +483,239 @@ impl Sph {
     }
 }
 
+impl Integrator {
+    // Force evaluations per time step
+    pub fn num_stages(&self) -> u32 {
+        self.entry_points().len() as u32
+    }
+
+    fn entry_points(&self) -> &'static [&'static str] {
+        match self {
+            Integrator::LeapFrog => &["leap_frog"],
+            Integrator::VelocityVerlet => &["kick_drift", "close_kick"],
+            Integrator::SymplecticEuler => &["symplectic_euler"],
+            Integrator::PredictorCorrector => &["predict_half_step", "correct_predictor_corrector"],
+            Integrator::MidpointRk2 => &["predict_half_step", "correct_midpoint"],
+        }
+    }
+}
+
+fn integrator_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &ShaderModule,
+    integrator: Integrator,
+) -> Vec<wgpu::ComputePipeline> {
+    integrator
+        .entry_points()
+        .iter()
+        .map(|&entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Integrator"),
+                layout: Some(layout),
+                module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        })
+        .collect()
+}
+
+// Cubic B-spline of kernel.wgsl
+fn kernel_cubic_bspline(r: f32, h: f32) -> f32 {
+    let normalization = h * h * h * std::f32::consts::PI / 8.0;
+    let q = r / h;
+    if q < 0.5 {
+        (6.0 * q * q * q - 6.0 * q * q + 1.0) / normalization
+    } else if q <= 1.0 {
+        2.0 * (1.0 - q).powi(3) / normalization
+    } else {
+        0.0
+    }
+}
+
+// Density summation of density_interpolant per unit mass, including the self contribution, for
+// a particle with a full neighbourhood of the unjittered lattice of `seeder`
+fn lattice_number_density(seeder: &Seeder, h: f32) -> f32 {
+    let lattice = Seeder::new(seeder.lattice, seeder.spacing);
+    // Wide enough for the point next to the centre to see a full smoothing length
+    let reach = h + 2.0 * seeder.spacing;
+    let points = lattice.points(&Shape::Box {
+        min: [-reach; 3],
+        max: [reach; 3],
+    });
+    let distance = |a: [f32; 3], b: [f32; 3]| {
+        (0..3)
+            .map(|axis| (a[axis] - b[axis]).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+    let center = points
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(*a, [0.0; 3]).total_cmp(&distance(*b, [0.0; 3])))
+        .expect("the lattice box holds points");
+    points
+        .iter()
+        .fold(1.0 / (std::f32::consts::PI * h * h), |sum, point| {
+            sum + kernel_cubic_bspline(distance(center, *point), h)
+        })
+}
+
+impl Sph {
+    // Fluid at rest below a free surface at height `free_surface` against gravity. Pressure is
+    // hydrostatic, p = rho0 |g| depth, the density follows from the equation of state,
+    // rho = rho0 + p / compressibility, and masses are scaled so that the density summation of a
+    // particle with a full neighbourhood of the `lattice` the fluid was seeded on returns that
+    // density. The fluid only stays at rest with Walls::mirrored, which completes the
+    // neighbourhoods at the walls, and a density threshold of 1 against the surface tension.
+    pub fn hydrostatic_init(&mut self, free_surface: f32, lattice: &Seeder) {
+        let gravity = self.disturbance.field;
+        let g = gravity.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let up = gravity.map(|x| -x / g.max(f32::EPSILON));
+        let grid_size = self.params.grid_size;
+        // Kernel sum per unit mass of each smoothing length
+        let mut number_densities: HashMap<u32, f32> = HashMap::new();
+        for particle in self.particles.iter_mut() {
+            let material = &self.materials[particle.material_idx as usize];
+            let height: f32 = (0..3)
+                .map(|a| (particle.coord[a] as f32 + particle.position[a]) * grid_size * up[a])
+                .sum();
+            let depth = (free_surface - height).max(0.0);
+            let pressure = material.density_reference * g * depth;
+            let density = material.density_reference + pressure / material.compressibility;
+            particle.pressure = pressure;
+            particle.density = density;
+            let h = particle.smoothing_length;
+            let full = *number_densities
+                .entry(h.to_bits())
+                .or_insert_with(|| lattice_number_density(lattice, h));
+            particle.mass = density / full;
+        }
+    }
+
+    // Ids of the particles with the given tag, e.g. to follow them with attach_tracers
+    pub fn ids_with_tag(&self, tag: u32) -> Vec<u32> {
+        self.particles
+            .iter()
+            .zip(&self.motion)
+            .filter(|(_, motion)| motion.tag == tag)
+            .map(|(particle, _)| particle.id)
+            .collect()
+    }
+}
+
+impl CellGrid {
+    // Cells covering [min, max), at least one per axis
+    pub fn new(min: [f32; 3], max: [f32; 3], grid_size: f32) -> Self {
+        let mut cell_grid = CellGrid::default();
+        for a in 0..3 {
+            cell_grid.min[a] = (min[a] / grid_size).floor() as i32;
+            let end = (max[a] / grid_size).ceil() as i32;
+            cell_grid.dims[a] = (end - cell_grid.min[a]).max(1) as u32;
+        }
+        cell_grid.num_cells = cell_grid.dims.iter().product();
+        cell_grid
+    }
+}
+
+impl HashCollisions {
+    // Hash the particles as the spatial lookup kernel does
+    pub fn from_particles(particles: &[Particle], params: &SimParams) -> Self {
+        let mut cells: HashMap<[i32; 3], usize> = HashMap::new();
+        for particle in particles {
+            *cells.entry(particle.coord).or_default() += 1;
+        }
+        // Occupied cells and particles per key
+        let mut keys: HashMap<u32, (usize, usize)> = HashMap::new();
+        let key_of = |coord: &[i32; 3]| {
+            let key = coord
+                .iter()
+                .zip(params.grid_prime)
+                .fold(0u32, |key, (&c, prime)| {
+                    key.wrapping_add((c as u32).wrapping_mul(prime))
+                });
+            key % params.num_particles
+        };
+        for (coord, count) in &cells {
+            let entry = keys.entry(key_of(coord)).or_default();
+            entry.0 += 1;
+            entry.1 += count;
+        }
+        let mut walked = 0;
+        let mut foreign = 0;
+        for (coord, count) in &cells {
+            let key_count = keys[&key_of(coord)].1;
+            walked += key_count;
+            foreign += key_count - count;
+        }
+        HashCollisions {
+            occupied_cells: cells.len(),
+            occupied_keys: keys.len(),
+            colliding_keys: keys.values().filter(|(cells, _)| *cells > 1).count(),
+            foreign_fraction: if walked > 0 {
+                foreign as f32 / walked as f32
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl Periodicity {
+    // Periodic axes span [min, max) rounded to whole grid cells, and need at least 3 cells so
+    // that the neighbour search does not visit a cell twice
+    pub fn new(min: [f32; 3], max: [f32; 3], axes: [bool; 3], grid_size: f32) -> Self {
+        let mut periodicity = Periodicity::default();
+        for a in 0..3 {
+            if axes[a] {
+                periodicity.min[a] = (min[a] / grid_size).round() as i32;
+                periodicity.cells[a] = (((max[a] - min[a]) / grid_size).round() as u32).max(3);
+            }
+        }
+        periodicity
+    }
+}
+
+impl Walls {
+    // Particles within a smoothing length of a wall also interact with the fluid mirrored across
+    // it, see wall.wgsl. Their neighbourhood is complete and the mirrored pressure is continued
+    // hydrostatically, so that fluid at rest stays at rest against the walls.
+    pub fn mirrored() -> Self {
+        Walls {
+            mirror: 1,
+            ..Default::default()
+        }
+    }
+}
+
 impl SphCompute {
     pub async fn new(device: &wgpu::Device, params: &SimParams) -> Self {
-        let num_particles = params.num_particles as usize;
+        Self::with_capacity(device, params, params.num_particles).await
+    }
+
+    // Leave headroom above the initial particle count for inlets
+    pub async fn with_capacity(device: &wgpu::Device, params: &SimParams, capacity: u32) -> Self {
+        Self::with_neighbor_search(device, params, capacity, NeighborSearch::SpatialHash).await
+    }
+
+    pub async fn with_neighbor_search(
+        device: &wgpu::Device,
+        params: &SimParams,
+        capacity: u32,
+        neighbor_search: NeighborSearch,
+    ) -> Self {
+        let capacity = capacity.max(params.num_particles) as usize;
+        let cell_grid = match neighbor_search {
+            NeighborSearch::SpatialHash => CellGrid::default(),
+            NeighborSearch::CellList { min, max } => CellGrid::new(min, max, params.grid_size),
+        };
+        // One start index per hash key, or per cell plus the end of the last cell
+        let start_indices_len = match neighbor_search {
+            NeighborSearch::SpatialHash => capacity,
+            NeighborSearch::CellList { .. } => cell_grid.num_cells as usize + 1,
+        };
         const MATERIAL_MAX_LEN: usize = 4; // Hard coded, consider defining at compilation or user input
 
         // Create shader modules
@@ -143,6 +725,12 @@ impl SphCompute {
         let hash_grid = include_str!("./hash_grid.wgsl");
         let hydrodynamics = include_str!("./hydrodynamics.wgsl");
         let solver = include_str!("./solver.wgsl");
+        let periodic = include_str!("./periodic.wgsl");
+        let interaction = include_str!("./interaction.wgsl");
+        let key = match neighbor_search {
+            NeighborSearch::SpatialHash => include_str!("./hash_key.wgsl"),
+            NeighborSearch::CellList { .. } => include_str!("./cell_key.wgsl"),
+        };
         let module_hash_grid = ShaderModuleBuilder::new()
             .add_module(description)
             .add_module(hash_grid)
@@ -150,18 +738,23 @@ impl SphCompute {
         let module_hydrodynamics = ShaderModuleBuilder::new()
             .add_module(util)
             .add_module(description)
+            .add_module(periodic)
             .add_module(kernel)
+            .add_module(interaction)
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(key)
             .add_module(hydrodynamics)
             .build(&device, Some("Shader Module Hydrodynamics"));
         let module_solver = ShaderModuleBuilder::new()
             .add_module(description)
+            .add_module(periodic)
             .add_module(solver)
             .build(&device, Some("Shader Module Solver"));
 
         // Create Input Buffers
         let buffer_particles = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Particle"),
-            size: (num_particles * std::mem::size_of::<Particle>()) as u64,
+            size: (capacity * std::mem::size_of::<Particle>()) as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
@@ -169,7 +762,7 @@ impl SphCompute {
         });
         let buffer_motion = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Motion"),
-            size: (num_particles * std::mem::size_of::<ParticleMotion>()) as u64,
+            size: (capacity * std::mem::size_of::<ParticleMotion>()) as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
@@ -178,12 +771,14 @@ impl SphCompute {
         let buffer_materials = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Material"),
             size: (MATERIAL_MAX_LEN * std::mem::size_of::<Material>()) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
         let buffer_spatial_scattered = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Spatial Lookup Scattered"),
-            size: (num_particles * std::mem::size_of::<SpatialLookup>()) as u64,
+            size: (capacity * std::mem::size_of::<SpatialLookup>()) as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
@@ -191,13 +786,13 @@ impl SphCompute {
         });
         let buffer_spatial_sorted = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Spatial Lookup Sorted"),
-            size: (num_particles * std::mem::size_of::<SpatialLookup>()) as u64,
+            size: (capacity * std::mem::size_of::<SpatialLookup>()) as u64,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let buffer_start_indices = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Start Indices"),
-            size: (num_particles * std::mem::size_of::<u32>()) as u64,
+            size: (start_indices_len * std::mem::size_of::<u32>()) as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
@@ -206,7 +801,18 @@ impl SphCompute {
         let buffer_params = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Simulation Parameters"),
             size: std::mem::size_of::<SimParams>() as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // Start of the step for the predictor-corrector and midpoint integrators, same layout as the neighbor list
+        // references
+        let buffer_start_positions = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Start Positions"),
+            size: (capacity * 8 * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
 
@@ -214,20 +820,56 @@ impl SphCompute {
         let buffer_disturbance = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Buffer Disturbance"),
             size: std::mem::size_of::<Disturbance>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
 
+        let buffer_periodicity = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Periodicity"),
+            contents: bytemuck::bytes_of(&Periodicity::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_walls = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Walls"),
+            contents: bytemuck::bytes_of(&Walls::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let buffer_cell_grid = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Cell Grid"),
+            contents: bytemuck::bytes_of(&cell_grid),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
         // Create Staging Buffers
         let staging_buffer_spatial = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Staging Buffer Spatial"),
-            size: (num_particles * std::mem::size_of::<SpatialLookup>()) as u64,
+            size: (capacity * std::mem::size_of::<SpatialLookup>()) as u64,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let staging_buffer_start_indices = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Staging Buffer Start Indices"),
-            size: (num_particles * std::mem::size_of::<u32>()) as u64,
+            size: (start_indices_len * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Simulation Parameters"),
+            size: std::mem::size_of::<SimParams>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_particles = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Particles"),
+            size: (capacity * std::mem::size_of::<Particle>()) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_motion = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Motion"),
+            size: (capacity * std::mem::size_of::<ParticleMotion>()) as u64,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -277,6 +919,16 @@ impl SphCompute {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
         let bind_group_layout_hydrodynamics =
@@ -343,6 +995,46 @@ impl SphCompute {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
         let bind_group_layout_solver =
@@ -389,6 +1081,26 @@ impl SphCompute {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -413,6 +1125,10 @@ impl SphCompute {
                     binding: 3,
                     resource: buffer_params.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_spatial_sorted.as_entire_binding(),
+                },
             ],
         });
         let bind_group_hydrodynamics = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -443,6 +1159,22 @@ impl SphCompute {
                     binding: 5,
                     resource: buffer_params.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: buffer_walls.as_entire_binding(),
+                },
             ],
         });
         let bind_group_solver = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -465,6 +1197,14 @@ impl SphCompute {
                     binding: 3,
                     resource: buffer_disturbance.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffer_start_positions.as_entire_binding(),
+                },
             ],
         });
 
@@ -525,47 +1265,2791 @@ impl SphCompute {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 cache: None,
             });
-        let compute_pipeline_leap_frog =
+        let compute_pipelines_integrator = integrator_pipelines(
+            device,
+            &pipeline_layout_solver,
+            &module_solver,
+            Integrator::default(),
+        );
+        let compute_pipeline_wall_boundaries =
             device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Compute Pipeline Leap Frog"),
+                label: Some("Compute Pipeline Wall Boundaries"),
                 layout: Some(&pipeline_layout_solver),
                 module: &module_solver,
-                entry_point: Some("leap_frog"),
+                entry_point: Some("wall_boundaries"),
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 cache: None,
             });
-
-        SphCompute {
-            num_particles: num_particles as u32,
-
-            // Input Buffers
-            buffer_particles,
-            buffer_motion,
-            buffer_materials,
-            buffer_spatial_scattered,
-            buffer_spatial_sorted,
-            buffer_start_indices,
-
-            // Uniform Buffers
-            buffer_params,
-            buffer_disturbance,
-
-            // Staging Buffers
-            staging_buffer_spatial,
+        let compute_pipeline_start_indices =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Start Indices"),
+                layout: Some(&pipeline_layout_hash_grid),
+                module: &module_hash_grid,
+                entry_point: Some("find_start_indices"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        // Sort of the hashed spatial lookup, the cell list needs none
+        let spatial_sort = match neighbor_search {
+            NeighborSearch::SpatialHash => Some(BitonicSort::new(
+                device,
+                &buffer_spatial_scattered,
+                capacity as u32,
+            )),
+            NeighborSearch::CellList { .. } => None,
+        };
+        // Counting sort into dense cells
+        let cell_list = match neighbor_search {
+            NeighborSearch::SpatialHash => None,
+            NeighborSearch::CellList { .. } => {
+                let module_cell_list = ShaderModuleBuilder::new()
+                    .add_module(description)
+                    .add_module(include_str!("./cell_key.wgsl"))
+                    .add_module(include_str!("./cell_list.wgsl"))
+                    .build(device, Some("Shader Module Cell List"));
+                let buffer_cell_ranks = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Buffer Cell Ranks"),
+                    size: (capacity * std::mem::size_of::<u32>()) as u64,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                let prefix_sum =
+                    PrefixSum::new(device, &buffer_start_indices, start_indices_len as u32);
+                let bind_group_layout =
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some("Bind Group Layout Cell List"),
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 4,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 5,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Bind Group Cell List"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer_particles.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: buffer_spatial_sorted.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: buffer_start_indices.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: buffer_cell_ranks.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: buffer_params.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 5,
+                            resource: buffer_cell_grid.as_entire_binding(),
+                        },
+                    ],
+                });
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Pipeline Layout Cell List"),
+                        bind_group_layouts: &[&bind_group_layout],
+                        push_constant_ranges: &[],
+                    });
+                let compute_pipeline_clear_cells =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Compute Pipeline Clear Cells"),
+                        layout: Some(&pipeline_layout),
+                        module: &module_cell_list,
+                        entry_point: Some("clear_cells"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache: None,
+                    });
+                let compute_pipeline_count_cells =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Compute Pipeline Count Cells"),
+                        layout: Some(&pipeline_layout),
+                        module: &module_cell_list,
+                        entry_point: Some("count_cells"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache: None,
+                    });
+                let compute_pipeline_scatter_cells =
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some("Compute Pipeline Scatter Cells"),
+                        layout: Some(&pipeline_layout),
+                        module: &module_cell_list,
+                        entry_point: Some("scatter_cells"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        cache: None,
+                    });
+                Some(CellList {
+                    num_cells: cell_grid.num_cells,
+                    buffer_cell_ranks,
+                    prefix_sum,
+                    bind_group,
+                    compute_pipeline_clear_cells,
+                    compute_pipeline_count_cells,
+                    compute_pipeline_scatter_cells,
+                })
+            }
+        };
+        let bind_group_layout_neighbor_walk =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bind Group Layout Neighbor Walk"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        SphCompute {
+            num_particles: params.num_particles,
+            capacity: capacity as u32,
+            next_id: Cell::new(params.num_particles),
+            dt: Cell::new(params.dt),
+            gravity: Cell::new([0.0; 3]),
+
+            // Input Buffers
+            buffer_particles,
+            buffer_motion,
+            buffer_materials,
+            buffer_spatial_scattered,
+            buffer_spatial_sorted,
+            buffer_start_indices,
+
+            // Uniform Buffers
+            buffer_params,
+            buffer_disturbance,
+            buffer_periodicity,
+            buffer_walls,
+            buffer_cell_grid,
+
+            // Staging Buffers
+            staging_buffer_spatial,
             staging_buffer_start_indices,
+            staging_buffer_params,
+            staging_buffer_particles,
+            staging_buffer_motion,
 
             // Bind Groups
             bind_group_hash_grid,
             bind_group_hydrodynamics,
             bind_group_solver,
 
-            // Compute Pipeline
-            compute_pipeline_hash_grid,
-            compute_pipeline_density_interpolant,
-            compute_pipeline_pressure_equation_of_state,
-            compute_pipeline_equation_of_motion,
-            compute_pipeline_leap_frog,
+            // Compute Pipeline
+            compute_pipeline_hash_grid,
+            compute_pipeline_start_indices,
+            spatial_sort,
+            compute_pipeline_density_interpolant,
+            compute_pipeline_pressure_equation_of_state,
+            compute_pipeline_equation_of_motion,
+            compute_pipelines_integrator,
+            compute_pipeline_wall_boundaries,
+            integrator: Integrator::default(),
+            module_solver,
+            pipeline_layout_solver,
+            buffer_start_positions,
+
+            // Optional Boundaries
+            obstacle_collision: None,
+            rigid_body_coupling: None,
+            damping: None,
+            flow_boundaries: None,
+            cell_list,
+            neighbor_list: None,
+            bind_group_layout_neighbor_walk,
+            reorder: None,
+            tracers: None,
+            probes: None,
+            conservation: None,
+            loads: None,
+        }
+    }
+
+    pub fn attach_obstacles(&mut self, device: &wgpu::Device, obstacles: &[Obstacle]) {
+        if obstacles.is_empty() {
+            self.obstacle_collision = None;
+            return;
+        }
+        let buffers = ObstacleBuffers::new(device, obstacles);
+        let module_obstacle = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("./obstacle.wgsl"))
+            .build(device, Some("Shader Module Obstacle Collision"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Obstacle Collision"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Obstacle Collision"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_obstacles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.buffer_sdf.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Obstacle Collision"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Obstacle Collision"),
+            layout: Some(&pipeline_layout),
+            module: &module_obstacle,
+            entry_point: Some("obstacle_collision"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.obstacle_collision = Some(ObstacleCollision {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Bodies act as moving boundaries for the fluid. The fluid within reach of a body is mirrored
+    // across its surface, which gives the pressure and viscous forces on both, and particles that
+    // still enter a body are projected out of it. The bodies are integrated on the GPU after each
+    // step, see RigidBodyBuffers::schedule.
+    pub fn attach_rigid_bodies(&mut self, device: &wgpu::Device, bodies: Vec<RigidBody>) {
+        if bodies.is_empty() {
+            self.rigid_body_coupling = None;
+            return;
+        }
+        let buffers = RigidBodyBuffers::new(device, &bodies);
+        let module_key = match self.cell_list {
+            Some(_) => include_str!("./cell_key.wgsl"),
+            None => include_str!("./hash_key.wgsl"),
+        };
+        let module_rigid_body = ShaderModuleBuilder::new()
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(include_str!("./interaction.wgsl"))
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(module_key)
+            .add_module(include_str!("./neighbor_walk.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("../rigid_body/state.wgsl"))
+            .add_module(include_str!("../rigid_body/rigid_body.wgsl"))
+            .add_module(include_str!("./rigid_body.wgsl"))
+            .build(device, Some("Shader Module Rigid Body Coupling"));
+        let module_rigid_body_list = ShaderModuleBuilder::new()
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(include_str!("./interaction.wgsl"))
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(module_key)
+            .add_module(include_str!("./neighbor_walk_list.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("../rigid_body/state.wgsl"))
+            .add_module(include_str!("../rigid_body/rigid_body.wgsl"))
+            .add_module(include_str!("./rigid_body.wgsl"))
+            .build(device, Some("Shader Module Rigid Body Coupling List"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Rigid Body Coupling"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Rigid Body Coupling"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_bodies.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.buffer_impulses.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffers.fields.buffer_obstacles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffers.fields.buffer_sdf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: self.buffer_spatial_sorted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: self.buffer_start_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: self.buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: self.buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: self.buffer_walls.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Rigid Body Coupling"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Rigid Body Coupling"),
+            layout: Some(&pipeline_layout),
+            module: &module_rigid_body,
+            entry_point: Some("rigid_body_coupling"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        let compute_pipeline_density =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Rigid Body Density"),
+                layout: Some(&pipeline_layout),
+                module: &module_rigid_body,
+                entry_point: Some("body_density"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_forces =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Rigid Body Forces"),
+                layout: Some(&pipeline_layout),
+                module: &module_rigid_body,
+                entry_point: Some("body_forces"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_forces_reaction =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Rigid Body Forces Reaction"),
+                layout: Some(&pipeline_layout),
+                module: &module_rigid_body,
+                entry_point: Some("body_forces_reaction"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let pipeline_layout_list = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Rigid Body Coupling List"),
+            bind_group_layouts: &[&bind_group_layout, &self.bind_group_layout_neighbor_walk],
+            push_constant_ranges: &[],
+        });
+        let compute_pipelines_list =
+            ["body_density", "body_forces", "body_forces_reaction"].map(|entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Compute Pipeline Rigid Body Images List"),
+                    layout: Some(&pipeline_layout_list),
+                    module: &module_rigid_body_list,
+                    entry_point: Some(entry_point),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                })
+            });
+        self.rigid_body_coupling = Some(RigidBodyCoupling {
+            buffers,
+            bodies: RefCell::new(bodies),
+            wavemakers: vec![],
+            time: Cell::new(0.0),
+            bind_group,
+            compute_pipeline,
+            compute_pipeline_density,
+            compute_pipeline_forces,
+            compute_pipeline_forces_reaction,
+            compute_pipelines_list,
+        });
+    }
+
+    // Relax particle velocities inside absorbing zones, e.g. sponge layers at the end of wave tanks
+    pub fn attach_damping_zones(&mut self, device: &wgpu::Device, zones: &[DampingZone]) {
+        if zones.is_empty() {
+            self.damping = None;
+            return;
+        }
+        let buffers = DampingBuffers::new(device, zones);
+        let module_damping = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../damping/damping.wgsl"))
+            .add_module(include_str!("./damping.wgsl"))
+            .build(device, Some("Shader Module Damping Zones"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Damping Zones"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Damping Zones"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_zones.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.buffer_profiles.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Damping Zones"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Damping Zones"),
+            layout: Some(&pipeline_layout),
+            module: &module_damping,
+            entry_point: Some("damping_zones"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.damping = Some(Damping {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Inlets append particles into the capacity headroom and outlets delete the particles inside
+    // them, compacting the particle buffers on the GPU. Their time starts at zero when attached,
+    // so attach them after any relaxation.
+    pub fn attach_flow_boundaries(
+        &mut self,
+        device: &wgpu::Device,
+        inlets: Vec<Inlet>,
+        outlets: &[Outlet],
+    ) {
+        if inlets.is_empty() && outlets.is_empty() {
+            self.flow_boundaries = None;
+            return;
+        }
+        let capacity = self.capacity as usize;
+        let outflow = (!outlets.is_empty()).then(|| {
+            let buffer_outlets = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Buffer Outlets"),
+                contents: bytemuck::cast_slice(outlets),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let buffer_keep = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer Outflow Keep"),
+                size: (capacity * std::mem::size_of::<u32>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            let buffer_particles_out = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer Particle Compacted"),
+                size: (capacity * std::mem::size_of::<Particle>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let buffer_motion_out = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Buffer Motion Compacted"),
+                size: (capacity * std::mem::size_of::<ParticleMotion>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let prefix_sum = PrefixSum::new(device, &buffer_keep, self.capacity);
+            let module_outflow = ShaderModuleBuilder::new()
+                .add_module(include_str!("./description.wgsl"))
+                .add_module(include_str!("../flow/outlet.wgsl"))
+                .add_module(include_str!("./outflow.wgsl"))
+                .build(device, Some("Shader Module Outflow"));
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Bind Group Layout Outflow"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bind Group Outflow"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.buffer_particles.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.buffer_motion.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.buffer_params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: buffer_outlets.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: buffer_keep.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: buffer_particles_out.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: buffer_motion_out.as_entire_binding(),
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout Outflow"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let compute_pipeline_mark_outflow =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Compute Pipeline Mark Outflow"),
+                    layout: Some(&pipeline_layout),
+                    module: &module_outflow,
+                    entry_point: Some("mark_outflow"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                });
+            let compute_pipeline_compact_particles =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Compute Pipeline Compact Particles"),
+                    layout: Some(&pipeline_layout),
+                    module: &module_outflow,
+                    entry_point: Some("compact_particles"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    cache: None,
+                });
+            Outflow {
+                buffer_outlets,
+                buffer_keep,
+                buffer_particles_out,
+                buffer_motion_out,
+                prefix_sum,
+                bind_group,
+                compute_pipeline_mark_outflow,
+                compute_pipeline_compact_particles,
+            }
+        });
+        let chunk_len = inlets
+            .iter()
+            .map(|inlet| inlet.num_points())
+            .sum::<usize>()
+            .max(1);
+        let buffer_emitted = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Emitted Particles"),
+            size: (chunk_len * std::mem::size_of::<EmittedParticle>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffer_emission = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Emission"),
+            size: std::mem::size_of::<Emission>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let module_inflow = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../flow/emission.wgsl"))
+            .add_module(include_str!("./inflow.wgsl"))
+            .build(device, Some("Shader Module Inflow"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Inflow"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group_inflow = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Inflow"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_emitted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_emission.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Inflow"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_emit_particles =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Emit Particles"),
+                layout: Some(&pipeline_layout),
+                module: &module_inflow,
+                entry_point: Some("emit_particles"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_commit_emitted =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Commit Emitted"),
+                layout: Some(&pipeline_layout),
+                module: &module_inflow,
+                entry_point: Some("commit_emitted"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.flow_boundaries = Some(FlowBoundaries {
+            inlets: RefCell::new(inlets),
+            outflow,
+            time: Cell::new(0.0),
+            chunk_len,
+            buffer_emitted,
+            buffer_emission,
+            bind_group_inflow,
+            compute_pipeline_emit_particles,
+            compute_pipeline_commit_emitted,
+        });
+    }
+
+    // Cache the neighbors within one grid size of each particle and reuse them until a particle
+    // has moved more than half of `skin`. Interactions must then reach no further than the grid
+    // size less the skin. The list takes `max_neighbors` indices per particle of capacity.
+    pub fn attach_neighbor_list(&mut self, device: &wgpu::Device, max_neighbors: u32, skin: f32) {
+        if max_neighbors == 0 {
+            self.neighbor_list = None;
+            return;
+        }
+        let capacity = self.capacity as u64;
+        let list_params = NeighborListParams {
+            max_neighbors,
+            skin,
+            _padding: [0.0; 2],
+        };
+        let module_description = include_str!("./description.wgsl");
+        let module_util = include_str!("./util.wgsl");
+        let module_periodic = include_str!("./periodic.wgsl");
+        let module_key = match self.cell_list {
+            Some(_) => include_str!("./cell_key.wgsl"),
+            None => include_str!("./hash_key.wgsl"),
+        };
+        let module_build = ShaderModuleBuilder::new()
+            .add_module(module_util)
+            .add_module(module_description)
+            .add_module(module_periodic)
+            .add_module(module_key)
+            .add_module(include_str!("./neighbor_list.wgsl"))
+            .build(device, Some("Shader Module Neighbor List"));
+        let module_hydrodynamics = ShaderModuleBuilder::new()
+            .add_module(module_util)
+            .add_module(module_description)
+            .add_module(module_periodic)
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(include_str!("./interaction.wgsl"))
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(include_str!("./neighbor_hydrodynamics.wgsl"))
+            .build(device, Some("Shader Module Neighbor List Hydrodynamics"));
+        let buffer_list_params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Neighbor List Parameters"),
+            contents: bytemuck::bytes_of(&list_params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let buffer_counts = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Neighbor Counts"),
+            size: capacity * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let buffer_indices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Neighbor Indices"),
+            size: capacity * max_neighbors as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // Particle coord and position at the last build
+        let buffer_references = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Neighbor List References"),
+            size: capacity * 8 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // Starts expired so that the first step builds the list
+        let buffer_status = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Neighbor List Status"),
+            contents: bytemuck::bytes_of(&NeighborListStatus {
+                expired: 1,
+                max_count: 0,
+                building: 0,
+                builds: 0,
+            }),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let staging_buffer_status = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Neighbor List Status"),
+            size: std::mem::size_of::<NeighborListStatus>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // The spatial lookup of a rebuild runs in the same submission as the steps, its
+        // dispatches are sized on the GPU from the expiry of the list
+        let dispatch_sizes = self.spatial_lookup_workgroups();
+        let buffer_dispatch_sizes = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Neighbor List Dispatch Sizes"),
+            contents: bytemuck::cast_slice(&dispatch_sizes),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let buffer_dispatch = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Neighbor List Dispatch"),
+            size: (dispatch_sizes.len() * std::mem::size_of::<wgpu::util::DispatchIndirectArgs>())
+                as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout_build =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bind Group Layout Neighbor List"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 11,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 12,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let bind_group_layout_hydrodynamics =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bind Group Layout Neighbor List Hydrodynamics"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let bind_group_build = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Neighbor List"),
+            layout: &bind_group_layout_build,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_spatial_sorted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_start_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffer_list_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffer_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: buffer_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: buffer_references.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: buffer_status.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: self.buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: buffer_dispatch_sizes.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: buffer_dispatch.as_entire_binding(),
+                },
+            ],
+        });
+        let bind_group_walk = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Neighbor Walk"),
+            layout: &self.bind_group_layout_neighbor_walk,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer_list_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_indices.as_entire_binding(),
+                },
+            ],
+        });
+        let bind_group_hydrodynamics = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Neighbor List Hydrodynamics"),
+            layout: &bind_group_layout_hydrodynamics,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffer_list_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffer_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: buffer_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: self.buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: self.buffer_walls.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout_build =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout Neighbor List"),
+                bind_group_layouts: &[&bind_group_layout_build],
+                push_constant_ranges: &[],
+            });
+        let pipeline_layout_hydrodynamics =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Pipeline Layout Neighbor List Hydrodynamics"),
+                bind_group_layouts: &[&bind_group_layout_hydrodynamics],
+                push_constant_ranges: &[],
+            });
+        let compute_pipeline_gate =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Gate Neighbor List"),
+                layout: Some(&pipeline_layout_build),
+                module: &module_build,
+                entry_point: Some("gate_neighbor_list"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_build =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Build Neighbor List"),
+                layout: Some(&pipeline_layout_build),
+                module: &module_build,
+                entry_point: Some("build_neighbor_list"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_check =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Check Neighbor List"),
+                layout: Some(&pipeline_layout_build),
+                module: &module_build,
+                entry_point: Some("check_neighbor_list"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_density_interpolant =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Density Interpolant Neighbor List"),
+                layout: Some(&pipeline_layout_hydrodynamics),
+                module: &module_hydrodynamics,
+                entry_point: Some("density_interpolant_list"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_equation_of_motion =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Equation of Motion Neighbor List"),
+                layout: Some(&pipeline_layout_hydrodynamics),
+                module: &module_hydrodynamics,
+                entry_point: Some("equation_of_motion_list"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.neighbor_list = Some(NeighborList {
+            buffer_list_params,
+            buffer_counts,
+            buffer_indices,
+            buffer_references,
+            buffer_status,
+            staging_buffer_status,
+            buffer_dispatch_sizes,
+            buffer_dispatch,
+            bind_group_build,
+            bind_group_hydrodynamics,
+            bind_group_walk,
+            compute_pipeline_gate,
+            compute_pipeline_build,
+            compute_pipeline_check,
+            compute_pipeline_density_interpolant,
+            compute_pipeline_equation_of_motion,
+        });
+    }
+
+    // Buffers for compute_morton_reorder, which step and prepare_step then run every `interval`
+    // steps, a few hundred is worthwhile. An interval of 0 leaves the reorder to the caller.
+    pub fn attach_morton_reorder(&mut self, device: &wgpu::Device, interval: u32) {
+        let module_reorder = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../sort/morton.wgsl"))
+            .add_module(include_str!("./reorder.wgsl"))
+            .build(device, Some("Shader Module Morton Reorder"));
+        let buffer_particles_out = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Particle Reordered"),
+            size: self.buffer_particles.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let buffer_motion_out = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Motion Reordered"),
+            size: self.buffer_motion.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        // The scattered spatial lookup is scratch space between hash grid passes
+        let sort = BitonicSort::new(device, &self.buffer_spatial_scattered, self.capacity);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Morton Reorder"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Morton Reorder"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_spatial_scattered.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_particles_out.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffer_motion_out.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Morton Reorder"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_morton_keys =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Morton Keys"),
+                layout: Some(&pipeline_layout),
+                module: &module_reorder,
+                entry_point: Some("morton_keys"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_gather_particles =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Gather Particles"),
+                layout: Some(&pipeline_layout),
+                module: &module_reorder,
+                entry_point: Some("gather_particles"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.reorder = Some(Reorder {
+            interval,
+            steps: Cell::new(0),
+            buffer_particles_out,
+            buffer_motion_out,
+            sort,
+            bind_group,
+            compute_pipeline_morton_keys,
+            compute_pipeline_gather_particles,
+        });
+    }
+
+    // Follow the particles with the given ids, see gpu2cpu_tracers
+    pub fn attach_tracers(&mut self, device: &wgpu::Device, ids: &[u32]) {
+        let module_tracer = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../tracer/tracer.wgsl"))
+            .add_module(include_str!("./tracer.wgsl"))
+            .build(device, Some("Shader Module Tracers"));
+        let buffers = TracerBuffers::new(device, ids);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Tracers"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Tracers"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.buffer_ids.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.buffer_samples.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Tracers"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Gather Tracers"),
+            layout: Some(&pipeline_layout),
+            module: &module_tracer,
+            entry_point: Some("gather_tracers"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.tracers = Some(Tracers {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Pass interpolating the particles onto the nodes of `grid`, see gpu2cpu_fields. Several
+    // samplers can be kept, e.g. for a volume and a few planes.
+    pub fn field_sampler(&self, device: &wgpu::Device, grid: FieldGrid) -> FieldSampler {
+        let key = match self.cell_list {
+            Some(_) => include_str!("./cell_key.wgsl"),
+            None => include_str!("./hash_key.wgsl"),
+        };
+        let module_resample = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(key)
+            .add_module(include_str!("../resample/sample.wgsl"))
+            .add_module(include_str!("../resample/grid.wgsl"))
+            .add_module(include_str!("./interpolate.wgsl"))
+            .add_module(include_str!("./resample.wgsl"))
+            .build(device, Some("Shader Module Resample Fields"));
+        let buffers = FieldBuffers::new(device, grid);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Resample Fields"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Resample Fields"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_spatial_sorted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_start_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: self.buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: buffers.buffer_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: buffers.buffer_samples.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Resample Fields"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Resample Fields"),
+            layout: Some(&pipeline_layout),
+            module: &module_resample,
+            entry_point: Some("resample_fields"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        FieldSampler {
+            buffers,
+            bind_group,
+            compute_pipeline,
+        }
+    }
+
+    // Probes sampled after every step, see gpu2cpu_probes. Like field_sampler, the lookup
+    // follows the neighbor search attached at this point.
+    pub fn attach_probes(&mut self, device: &wgpu::Device, probes: Probes) {
+        let key = match self.cell_list {
+            Some(_) => include_str!("./cell_key.wgsl"),
+            None => include_str!("./hash_key.wgsl"),
+        };
+        let module_probe = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(key)
+            .add_module(include_str!("../resample/sample.wgsl"))
+            .add_module(include_str!("../probe/probe.wgsl"))
+            .add_module(include_str!("./interpolate.wgsl"))
+            .add_module(include_str!("./probe.wgsl"))
+            .build(device, Some("Shader Module Sample Probes"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Sample Probes"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Sample Probes"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_spatial_sorted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_start_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: self.buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: probes.buffer_points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: probes.buffer_samples.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Sample Probes"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Sample Probes"),
+            layout: Some(&pipeline_layout),
+            module: &module_probe,
+            entry_point: Some("sample_probe_points"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.probes = Some(ProbeSampling {
+            probes,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Conservation totals reduced on demand, see gpu2cpu_totals
+    pub fn attach_conservation(&mut self, device: &wgpu::Device) {
+        let module_conservation = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("../conservation/totals.wgsl"))
+            .add_module(include_str!("./conservation.wgsl"))
+            .build(device, Some("Shader Module Conservation"));
+        let reduction = TotalsReduction::new(device, self.capacity.div_ceil(REDUCE_SIZE));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Conservation"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Conservation"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: reduction.buffer_partials.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Conservation"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Conservation"),
+            layout: Some(&pipeline_layout),
+            module: &module_conservation,
+            entry_point: Some("reduce_conservation"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        self.conservation = Some(Conservation {
+            reduction,
+            bind_group,
+            compute_pipeline,
+        });
+    }
+
+    // Loads of the particles on the targets, accumulated every step and read back with
+    // gpu2cpu_loads. Obstacle targets index the obstacles of attach_obstacles, which has to come
+    // first. Rigid bodies report their own loads, see gpu2cpu_rigid_body_loads.
+    pub fn attach_loads(&mut self, device: &wgpu::Device, targets: &[LoadTarget]) {
+        if targets.is_empty() {
+            self.loads = None;
+            return;
         }
+        let module_key = match self.cell_list {
+            Some(_) => include_str!("./cell_key.wgsl"),
+            None => include_str!("./hash_key.wgsl"),
+        };
+        let module_loads = ShaderModuleBuilder::new()
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(include_str!("./interaction.wgsl"))
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(module_key)
+            .add_module(include_str!("./neighbor_walk.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("../loads/load.wgsl"))
+            .add_module(include_str!("./loads.wgsl"))
+            .build(device, Some("Shader Module Loads"));
+        let module_loads_list = ShaderModuleBuilder::new()
+            .add_module(include_str!("./util.wgsl"))
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./periodic.wgsl"))
+            .add_module(include_str!("./kernel.wgsl"))
+            .add_module(include_str!("./interaction.wgsl"))
+            .add_module(include_str!("./wall.wgsl"))
+            .add_module(module_key)
+            .add_module(include_str!("./neighbor_walk_list.wgsl"))
+            .add_module(include_str!("../obstacle/sdf.wgsl"))
+            .add_module(include_str!("../loads/load.wgsl"))
+            .add_module(include_str!("./loads.wgsl"))
+            .build(device, Some("Shader Module Loads List"));
+        let reduction = LoadReduction::new(device, targets, self.capacity.div_ceil(REDUCE_SIZE));
+        let buffer_snapshots = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Load Snapshots"),
+            size: self.capacity as u64 * 32,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // Placeholders without obstacles, bindings can't be empty
+        let (buffer_obstacles, buffer_sdf) = match &self.obstacle_collision {
+            Some(obstacle_collision) => (
+                obstacle_collision.buffers.buffer_obstacles.clone(),
+                obstacle_collision.buffers.buffer_sdf.clone(),
+            ),
+            None => (
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Buffer Load Obstacles"),
+                    contents: &[0; 48],
+                    usage: wgpu::BufferUsages::STORAGE,
+                }),
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Buffer Load SDF"),
+                    contents: &[0; 4],
+                    usage: wgpu::BufferUsages::STORAGE,
+                }),
+            ),
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Loads"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Loads"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: reduction.buffer_targets.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer_snapshots.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: buffer_obstacles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: buffer_sdf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: reduction.buffer_partials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: self.buffer_spatial_sorted.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: self.buffer_start_indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: self.buffer_periodicity.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: self.buffer_cell_grid.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: self.buffer_disturbance.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 14,
+                    resource: self.buffer_walls.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Loads"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_snapshot =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Load Snapshots"),
+                layout: Some(&pipeline_layout),
+                module: &module_loads,
+                entry_point: Some("snapshot_loads"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_walls =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Wall Loads"),
+                layout: Some(&pipeline_layout),
+                module: &module_loads,
+                entry_point: Some("wall_loads"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_obstacles =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Obstacle Loads"),
+                layout: Some(&pipeline_layout),
+                module: &module_loads,
+                entry_point: Some("obstacle_loads"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let compute_pipeline_forces =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Force Loads"),
+                layout: Some(&pipeline_layout),
+                module: &module_loads,
+                entry_point: Some("force_loads"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        let pipeline_layout_list = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Loads List"),
+            bind_group_layouts: &[&bind_group_layout, &self.bind_group_layout_neighbor_walk],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline_forces_list =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline Force Loads List"),
+                layout: Some(&pipeline_layout_list),
+                module: &module_loads_list,
+                entry_point: Some("force_loads"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+        self.loads = Some(LoadMeasurement {
+            reduction,
+            boundaries: targets.iter().any(LoadTarget::is_boundary),
+            forces: targets.iter().any(LoadTarget::has_forces),
+            buffer_snapshots,
+            bind_group,
+            compute_pipeline_snapshot,
+            compute_pipeline_walls,
+            compute_pipeline_obstacles,
+            compute_pipeline_forces,
+            compute_pipeline_forces_list,
+        });
     }
 }
 
@@ -578,15 +4062,37 @@ impl SphCompute {
     ) {
         queue.write_buffer(&self.buffer_particles, 0, bytemuck::cast_slice(&particles));
         queue.write_buffer(&self.buffer_motion, 0, bytemuck::cast_slice(&motion));
+        self.expire_neighbor_list(queue);
+    }
+    // Force a rebuild of the neighbor list, e.g. after particles were added, removed or moved
+    pub fn expire_neighbor_list(&self, queue: &wgpu::Queue) {
+        if let Some(neighbor_list) = &self.neighbor_list {
+            queue.write_buffer(&neighbor_list.buffer_status, 0, bytemuck::bytes_of(&1u32));
+        }
     }
     pub fn cpu2gpu_params(&self, queue: &wgpu::Queue, params: &SimParams) {
         queue.write_buffer(&self.buffer_params, 0, bytemuck::bytes_of(params));
+        self.dt.set(params.dt);
     }
     pub fn cpu2gpu_materials(&self, queue: &wgpu::Queue, materials: &Vec<Material>) {
         queue.write_buffer(&self.buffer_materials, 0, bytemuck::cast_slice(&materials));
     }
     pub fn cpu2gpu_disturbance(&self, queue: &wgpu::Queue, disturbance: &Disturbance) {
         queue.write_buffer(&self.buffer_disturbance, 0, bytemuck::bytes_of(disturbance));
+        self.gravity.set(disturbance.field);
+    }
+    pub fn cpu2gpu_periodicity(&self, queue: &wgpu::Queue, periodicity: &Periodicity) {
+        queue.write_buffer(&self.buffer_periodicity, 0, bytemuck::bytes_of(periodicity));
+    }
+    pub fn cpu2gpu_walls(&self, queue: &wgpu::Queue, walls: &Walls) {
+        queue.write_buffer(&self.buffer_walls, 0, bytemuck::bytes_of(walls));
+    }
+    // Replace the states of the attached bodies
+    pub fn cpu2gpu_rigid_bodies(&self, queue: &wgpu::Queue, bodies: &[RigidBody]) {
+        if let Some(rigid_body_coupling) = &self.rigid_body_coupling {
+            rigid_body_coupling.buffers.cpu2gpu_bodies(queue, bodies);
+            *rigid_body_coupling.bodies.borrow_mut() = bodies.to_vec();
+        }
     }
     pub fn cpu2gpu_spatial_sorted(&self, queue: &wgpu::Queue, spatial: &Vec<SpatialLookup>) {
         queue.write_buffer(
@@ -633,6 +4139,285 @@ impl SphCompute {
         self.staging_buffer_spatial.unmap();
         return spatial_out;
     }
+    // Mean fluid loads on the attached bodies over the `elapsed` time since they were last
+    // advanced or uploaded
+    pub fn gpu2cpu_rigid_body_loads(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) -> Vec<RigidBodyLoad> {
+        match &self.rigid_body_coupling {
+            Some(rigid_body_coupling) => rigid_body_coupling
+                .buffers
+                .gpu2cpu_loads(device, queue, elapsed),
+            None => vec![],
+        }
+    }
+    // Attached bodies at the end of the recorded steps
+    pub fn gpu2cpu_rigid_bodies(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<RigidBody> {
+        match &self.rigid_body_coupling {
+            Some(rigid_body_coupling) => {
+                let mut bodies = rigid_body_coupling.bodies.borrow_mut();
+                rigid_body_coupling
+                    .buffers
+                    .gpu2cpu_bodies(device, queue, &mut bodies);
+                bodies.clone()
+            }
+            None => vec![],
+        }
+    }
+    // Set up the next `n_substeps` steps of the bodies on the GPU, at most SCHEDULE_STEPS with
+    // wavemakers, see RigidBodyBuffers::schedule
+    fn schedule_rigid_bodies(&self, queue: &wgpu::Queue, n_substeps: u32) {
+        let Some(rigid_body_coupling) = &self.rigid_body_coupling else {
+            return;
+        };
+        let time = rigid_body_coupling.time.get();
+        rigid_body_coupling.buffers.schedule(
+            queue,
+            &rigid_body_coupling.bodies.borrow(),
+            &rigid_body_coupling.wavemakers,
+            self.gravity.get(),
+            self.dt.get(),
+            time,
+            n_substeps,
+        );
+        rigid_body_coupling
+            .time
+            .set(time + n_substeps as f64 * self.dt.get() as f64);
+    }
+    // Drive the paddle bodies of the wavemakers, one entry per attached body and None for the
+    // free ones. The wavemaker time restarts at zero with the paddles at their initial pose.
+    pub fn attach_wavemakers(&mut self, queue: &wgpu::Queue, wavemakers: Vec<Option<Wavemaker>>) {
+        let Some(rigid_body_coupling) = &mut self.rigid_body_coupling else {
+            return;
+        };
+        let bodies = rigid_body_coupling.bodies.get_mut();
+        for (idx, wavemaker) in wavemakers.iter().enumerate().take(bodies.len()) {
+            if let Some(wavemaker) = wavemaker {
+                wavemaker.update(&mut bodies[idx], 0.0);
+                rigid_body_coupling.buffers.cpu2gpu_body(queue, bodies, idx);
+            }
+        }
+        rigid_body_coupling.wavemakers = wavemakers;
+        rigid_body_coupling.time.set(0.0);
+    }
+    // Parameters with the current alive particle count
+    pub fn gpu2cpu_params(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SimParams {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Simulation Parameters"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_params,
+            0,
+            &self.staging_buffer_params,
+            0,
+            self.buffer_params.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_params.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let params: SimParams = bytemuck::pod_read_unaligned(&output_data);
+        drop(output_data);
+        self.staging_buffer_params.unmap();
+        params
+    }
+    // Alive particle count, which changes with flow boundaries
+    pub fn gpu2cpu_num_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32 {
+        self.gpu2cpu_params(device, queue).num_particles
+    }
+    pub fn gpu2cpu_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Particle> {
+        let num_particles = self.gpu2cpu_num_particles(device, queue) as usize;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Particles"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_particles,
+            0,
+            &self.staging_buffer_particles,
+            0,
+            self.buffer_particles.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_particles.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let particles_out: Vec<Particle> =
+            bytemuck::cast_slice(&output_data)[..num_particles].to_vec();
+        drop(output_data);
+        self.staging_buffer_particles.unmap();
+        particles_out
+    }
+    pub fn gpu2cpu_motion(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<ParticleMotion> {
+        let num_particles = self.gpu2cpu_num_particles(device, queue) as usize;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Motion"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_motion,
+            0,
+            &self.staging_buffer_motion,
+            0,
+            self.buffer_motion.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_motion.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let motion_out: Vec<ParticleMotion> =
+            bytemuck::cast_slice(&output_data)[..num_particles].to_vec();
+        drop(output_data);
+        self.staging_buffer_motion.unmap();
+        motion_out
+    }
+    // Whole material table, slots past the uploaded materials are zeroed
+    pub fn gpu2cpu_materials(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Material> {
+        gpu2cpu_buffer(device, queue, &self.buffer_materials)
+    }
+    pub fn gpu2cpu_disturbance(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Disturbance {
+        gpu2cpu_buffer(device, queue, &self.buffer_disturbance)[0]
+    }
+    // None without a neighbor list, the spatial lookup is then needed every step
+    pub fn gpu2cpu_neighbor_list_status(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<NeighborListStatus> {
+        let neighbor_list = self.neighbor_list.as_ref()?;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Neighbor List Status"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &neighbor_list.buffer_status,
+            0,
+            &neighbor_list.staging_buffer_status,
+            0,
+            neighbor_list.buffer_status.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = neighbor_list.staging_buffer_status.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let status: NeighborListStatus = bytemuck::pod_read_unaligned(&output_data);
+        drop(output_data);
+        neighbor_list.staging_buffer_status.unmap();
+        Some(status)
+    }
+    // Positions and velocities of the followed particles in ascending id order, in world
+    // coordinates. Particles removed by an outlet are returned with `alive` set to zero.
+    pub fn gpu2cpu_tracers(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<TracerSample> {
+        let Some(tracers) = &self.tracers else {
+            return vec![];
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Gather Tracers"),
+        });
+        encoder.clear_buffer(&tracers.buffers.buffer_samples, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Gather Tracers"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&tracers.compute_pipeline);
+        compute_pass.set_bind_group(0, &tracers.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        tracers.buffers.gpu2cpu_samples(device, queue)
+    }
+    // Density, pressure, velocity and volume fraction at the nodes of the sampler grid. The
+    // spatial lookup is rebuilt first as the particles have moved since the last step.
+    pub fn gpu2cpu_fields(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sampler: &FieldSampler,
+    ) -> Fields {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Resample Fields"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Resample Fields"),
+            timestamp_writes: None,
+        });
+        self.encode_spatial_lookup(&mut compute_pass);
+        let (x, y) = sampler
+            .buffers
+            .grid
+            .workgroups(device.limits().max_compute_workgroups_per_dimension);
+        compute_pass.set_pipeline(&sampler.compute_pipeline);
+        compute_pass.set_bind_group(0, &sampler.bind_group, &[]);
+        compute_pass.dispatch_workgroups(x, y, 1);
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        sampler.buffers.gpu2cpu_fields(device, queue)
+    }
+    // Probe values of every step since the last call, one row per step
+    pub fn gpu2cpu_probes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>> {
+        match &self.probes {
+            Some(probe_sampling) => probe_sampling.probes.gpu2cpu_values(device, queue),
+            None => vec![],
+        }
+    }
+    // Targets of attach_loads, empty without
+    pub fn load_targets(&self) -> Vec<LoadTarget> {
+        match &self.loads {
+            Some(loads) => loads.reduction.targets().to_vec(),
+            None => vec![],
+        }
+    }
+    // Mean loads on the targets over the `elapsed` time since the last call, in target order
+    pub fn gpu2cpu_loads(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) -> Vec<Load> {
+        match &self.loads {
+            Some(loads) => loads.reduction.gpu2cpu_loads(device, queue, elapsed),
+            None => vec![],
+        }
+    }
+    // Mass, momentum, energy and extrema over the alive particles, None without
+    // attach_conservation
+    pub fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Totals> {
+        let conservation = self.conservation.as_ref()?;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Conservation"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Conservation"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&conservation.compute_pipeline);
+        compute_pass.set_bind_group(0, &conservation.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(REDUCE_SIZE), 1, 1);
+        conservation.reduction.encode(&mut compute_pass);
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+        Some(conservation.reduction.gpu2cpu_totals(device, queue))
+    }
     pub fn gpu2cpu_start_indices(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u32> {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Command Encoder GPU to CPU Start Indices"),
@@ -659,24 +4444,98 @@ impl SphCompute {
         self.staging_buffer_start_indices.unmap();
         return start_indices_out;
     }
-
-    pub fn compute_hash_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+
+    pub fn compute_hash_grid(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Hash Grid"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Hash Grid"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        compute_pass.set_pipeline(&self.compute_pipeline_hash_grid);
+        compute_pass.set_bind_group(0, &self.bind_group_hash_grid, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+    // Hash and sort the particles, or bin them into the cell list
+    pub fn compute_spatial_lookup(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Spatial Lookup"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Spatial Lookup"),
+            timestamp_writes: None,
+        });
+        self.encode_spatial_lookup(&mut compute_pass);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        queue.submit([encoder.finish()]);
+    }
+    // Sort the particles along a Z-order curve of their cells so that neighbors are close in
+    // memory. Particle order changes, use the particle ids to track particles.
+    pub fn compute_morton_reorder(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(reorder) = &self.reorder else {
+            return;
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Morton Reorder"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Morton Reorder"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&reorder.compute_pipeline_morton_keys);
+        compute_pass.set_bind_group(0, &reorder.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        reorder.sort.encode(&mut compute_pass);
+        compute_pass.set_pipeline(&reorder.compute_pipeline_gather_particles);
+        compute_pass.set_bind_group(0, &reorder.bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Swap in the reordered particles
+        encoder.copy_buffer_to_buffer(
+            &reorder.buffer_particles_out,
+            0,
+            &self.buffer_particles,
+            0,
+            self.buffer_particles.size(),
+        );
+        encoder.copy_buffer_to_buffer(
+            &reorder.buffer_motion_out,
+            0,
+            &self.buffer_motion,
+            0,
+            self.buffer_motion.size(),
+        );
+        queue.submit([encoder.finish()]);
+        self.expire_neighbor_list(queue);
+        reorder.steps.set(0);
+    }
+    // Rebuild the neighbor list now, with its own spatial lookup
+    pub fn compute_neighbor_list(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.neighbor_list.is_none() {
+            return;
+        }
+        self.expire_neighbor_list(queue);
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Hash Grid"),
+            label: Some("Command Encoder Neighbor List"),
         });
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Hash Grid"),
+            label: Some("Compute Pass Neighbor List"),
             timestamp_writes: None,
         });
-        // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_hash_grid);
-        compute_pass.set_bind_group(0, &self.bind_group_hash_grid, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
+        self.encode_neighbor_list(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
-        let command_buffer = encoder.finish();
-        queue.submit([command_buffer]);
+        queue.submit([encoder.finish()]);
     }
     pub fn compute_density_interpolant(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -687,9 +4546,7 @@ impl SphCompute {
             timestamp_writes: None,
         });
         // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_density_interpolant);
-        compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
+        self.encode_density_interpolant(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
@@ -705,9 +4562,7 @@ impl SphCompute {
             timestamp_writes: None,
         });
         // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_pressure_equation_of_state);
-        compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
+        self.encode_pressure_equation_of_state(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
@@ -723,31 +4578,1318 @@ impl SphCompute {
             timestamp_writes: None,
         });
         // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_equation_of_motion);
-        compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
+        self.encode_equation_of_motion(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
         let command_buffer = encoder.finish();
         queue.submit([command_buffer]);
     }
-    pub fn compute_leap_frog(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+    // Select the time integrator, best done before the first step as the integrators interpret
+    // velocity_p differently
+    pub fn set_integrator(&mut self, device: &wgpu::Device, integrator: Integrator) {
+        self.compute_pipelines_integrator = integrator_pipelines(
+            device,
+            &self.pipeline_layout_solver,
+            &self.module_solver,
+            integrator,
+        );
+        self.integrator = integrator;
+    }
+    // Advance stage `stage` of the selected integrator with the accelerations of the last
+    // equation of motion. Every stage needs its own force evaluation, and the boundaries are
+    // enforced once after the last stage with compute_boundaries.
+    pub fn compute_integration(&self, device: &wgpu::Device, queue: &wgpu::Queue, stage: u32) {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder Leap Frog"),
+            label: Some("Command Encoder Integration"),
         });
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Compute Pass Leap Frog"),
+            label: Some("Compute Pass Integration"),
             timestamp_writes: None,
         });
         // Setup compute pass commands
-        compute_pass.set_pipeline(&self.compute_pipeline_leap_frog);
-        compute_pass.set_bind_group(0, &self.bind_group_solver, &[]);
-        compute_pass.dispatch_workgroups((self.num_particles + 255) / 256, 1, 1);
+        self.encode_integration(&mut compute_pass, stage);
+        // Drop compute pass to gain access to encoder again
+        drop(compute_pass);
+        // Submit commands to queue
+        let command_buffer = encoder.finish();
+        queue.submit([command_buffer]);
+    }
+    pub fn compute_boundaries(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Boundaries"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Boundaries"),
+            timestamp_writes: None,
+        });
+        // Setup compute pass commands
+        self.encode_boundaries(&mut compute_pass);
         // Drop compute pass to gain access to encoder again
         drop(compute_pass);
         // Submit commands to queue
         let command_buffer = encoder.finish();
         queue.submit([command_buffer]);
     }
+
+    // Delete particles in outlets then append the particles emitted by inlets up to the time of
+    // the steps recorded so far, before `n_substeps` more are recorded
+    fn advance_flow_boundaries(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        let Some(flow_boundaries) = &self.flow_boundaries else {
+            return;
+        };
+        let time = flow_boundaries.time.get();
+        flow_boundaries
+            .time
+            .set(time + n_substeps as f32 * self.dt.get());
+        if let Some(outflow) = &flow_boundaries.outflow {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Outflow"),
+            });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass Outflow"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&outflow.compute_pipeline_mark_outflow);
+            compute_pass.set_bind_group(0, &outflow.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            outflow.prefix_sum.encode(&mut compute_pass);
+            compute_pass.set_pipeline(&outflow.compute_pipeline_compact_particles);
+            compute_pass.set_bind_group(0, &outflow.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            // Drop compute pass to gain access to encoder again
+            drop(compute_pass);
+            // Swap in the compacted particles and their count
+            encoder.copy_buffer_to_buffer(
+                &outflow.buffer_particles_out,
+                0,
+                &self.buffer_particles,
+                0,
+                self.buffer_particles.size(),
+            );
+            encoder.copy_buffer_to_buffer(
+                &outflow.buffer_motion_out,
+                0,
+                &self.buffer_motion,
+                0,
+                self.buffer_motion.size(),
+            );
+            encoder.copy_buffer_to_buffer(
+                outflow.prefix_sum.buffer_total(),
+                0,
+                &self.buffer_params,
+                std::mem::offset_of!(SimParams, num_particles) as u64,
+                std::mem::size_of::<u32>() as u64,
+            );
+            queue.submit([encoder.finish()]);
+        }
+        let emitted: Vec<EmittedParticle> = flow_boundaries
+            .inlets
+            .borrow_mut()
+            .iter_mut()
+            .flat_map(|inlet| inlet.emit(time))
+            .collect();
+        for chunk in emitted.chunks(flow_boundaries.chunk_len) {
+            let emission = Emission {
+                num_emitted: chunk.len() as u32,
+                first_id: self.next_id.get(),
+                _padding: [0; 2],
+            };
+            self.next_id.set(emission.num_emitted + emission.first_id);
+            queue.write_buffer(
+                &flow_boundaries.buffer_emitted,
+                0,
+                bytemuck::cast_slice(chunk),
+            );
+            queue.write_buffer(
+                &flow_boundaries.buffer_emission,
+                0,
+                bytemuck::bytes_of(&emission),
+            );
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Inflow"),
+            });
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass Inflow"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&flow_boundaries.compute_pipeline_emit_particles);
+            compute_pass.set_bind_group(0, &flow_boundaries.bind_group_inflow, &[]);
+            compute_pass.dispatch_workgroups(emission.num_emitted.div_ceil(256), 1, 1);
+            compute_pass.set_pipeline(&flow_boundaries.compute_pipeline_commit_emitted);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+            drop(compute_pass);
+            queue.submit([encoder.finish()]);
+        }
+        // Particle indices have changed
+        self.expire_neighbor_list(queue);
+    }
+}
+
+impl SphCompute {
+    // Settle the initial particles before t = 0: run `steps` time steps keeping `1 - damping` of
+    // the velocity after each, then start from rest
+    pub fn relax(&self, device: &wgpu::Device, queue: &wgpu::Queue, steps: u32, damping: f32) {
+        let module_relax = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./relax.wgsl"))
+            .build(device, Some("Shader Module Relaxation"));
+        let buffer_relaxation = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Relaxation"),
+            contents: bytemuck::cast_slice(&[1.0 - damping, 0.0, 0.0, 0.0]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Relaxation"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Relaxation"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_motion.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer_relaxation.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Relaxation"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Relaxation"),
+            layout: Some(&pipeline_layout),
+            module: &module_relax,
+            entry_point: Some("damp_velocities"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        let damp = |encoder: &mut wgpu::CommandEncoder| {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass Relaxation"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&compute_pipeline);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        };
+        for _ in 0..steps {
+            self.prepare_step(device, queue, 1);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Relaxation"),
+            });
+            self.encode_step(&mut encoder, 1);
+            damp(&mut encoder);
+            queue.submit([encoder.finish()]);
+        }
+        queue.write_buffer(&buffer_relaxation, 0, bytemuck::cast_slice(&[0.0f32; 4]));
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder Relaxation"),
+        });
+        damp(&mut encoder);
+        queue.submit([encoder.finish()]);
+    }
+}
+
+impl SphCompute {
+    // Advance `n_substeps` time steps in a single submission, see encode_step, or in submissions
+    // of at most SCHEDULE_STEPS steps with wavemakers
+    pub fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        let wavemakers = self
+            .rigid_body_coupling
+            .as_ref()
+            .is_some_and(|coupling| coupling.wavemakers.iter().any(Option::is_some));
+        let mut remaining = n_substeps;
+        while remaining > 0 {
+            // The paddle poses are scheduled for each submission, and a due reorder runs between
+            // two of them
+            let mut n_substeps = remaining;
+            if wavemakers {
+                n_substeps = n_substeps.min(SCHEDULE_STEPS);
+            }
+            if let Some(steps) = self.reorder.as_ref().and_then(Reorder::steps_to_next) {
+                n_substeps = n_substeps.min(steps);
+            }
+            remaining -= n_substeps;
+            self.prepare_step(device, queue, n_substeps);
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder Step"),
+            });
+            self.encode_step(&mut encoder, n_substeps);
+            queue.submit([encoder.finish()]);
+        }
+    }
+    // CPU side of the next `n_substeps` steps, to run before they are recorded with encode_step:
+    // reorder the particles when due, upload the rigid body schedule, then delete and emit the
+    // particles of the flow boundaries. These run in submissions of their own, the flow
+    // boundaries thus act once per submission.
+    pub fn prepare_step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        if let Some(reorder) = &self.reorder {
+            if reorder.interval > 0 && reorder.steps.get() >= reorder.interval {
+                self.compute_morton_reorder(device, queue);
+            }
+            reorder.steps.set(reorder.steps.get() + n_substeps);
+        }
+        self.schedule_rigid_bodies(queue, n_substeps);
+        self.advance_flow_boundaries(device, queue, n_substeps);
+    }
+    // Record `n_substeps` time steps into `encoder`: spatial lookup, density, equation of state,
+    // equation of motion and every integrator stage, then the boundaries. Callers can record
+    // their own passes after it, e.g. the particle instances of the renderer. A neighbor list is
+    // rebuilt on the GPU before any step that finds it expired, and rigid bodies are integrated
+    // after each one. Only records, see prepare_step for what the steps need from the CPU.
+    pub fn encode_step(&self, encoder: &mut wgpu::CommandEncoder, n_substeps: u32) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass Step"),
+            timestamp_writes: None,
+        });
+        for _ in 0..n_substeps {
+            for stage in 0..self.integrator.num_stages() {
+                if self.neighbor_list.is_none() {
+                    self.encode_spatial_lookup(&mut compute_pass);
+                } else if stage == 0 {
+                    self.encode_neighbor_list(&mut compute_pass);
+                }
+                let last_stage = stage + 1 == self.integrator.num_stages();
+                self.encode_density_interpolant(&mut compute_pass);
+                self.encode_rigid_body_images(&mut compute_pass, BodyPass::Density);
+                self.encode_pressure_equation_of_state(&mut compute_pass);
+                self.encode_equation_of_motion(&mut compute_pass);
+                self.encode_rigid_body_images(
+                    &mut compute_pass,
+                    match last_stage {
+                        true => BodyPass::ForcesReaction,
+                        false => BodyPass::Forces,
+                    },
+                );
+                // The last stage gives the acceleration of the step
+                if last_stage {
+                    self.encode_loads(&mut compute_pass, LoadPass::Forces);
+                }
+                self.encode_integration(&mut compute_pass, stage);
+            }
+            self.encode_boundaries(&mut compute_pass);
+            self.encode_probes(&mut compute_pass);
+        }
+    }
+
+    // Passes shared by the compute_* methods and step
+    fn encode_spatial_lookup(&self, compute_pass: &mut wgpu::ComputePass) {
+        self.encode_spatial_lookup_gated(compute_pass, None);
+    }
+    // Workgroups of the distinct dispatches of the spatial lookup, indexed by the slots of
+    // encode_spatial_lookup_gated
+    fn spatial_lookup_workgroups(&self) -> Vec<u32> {
+        let particles = self.capacity.div_ceil(256);
+        match (&self.cell_list, &self.spatial_sort) {
+            (Some(cell_list), _) => [
+                vec![(cell_list.num_cells + 1).div_ceil(256), particles],
+                cell_list.prefix_sum.num_workgroups(),
+            ]
+            .concat(),
+            (None, Some(spatial_sort)) => vec![particles, spatial_sort.num_workgroups()],
+            (None, None) => vec![particles],
+        }
+    }
+    // With `gate`, every dispatch takes its size from the indirect arguments of its slot, see
+    // gate_neighbor_list
+    fn encode_spatial_lookup_gated(
+        &self,
+        compute_pass: &mut wgpu::ComputePass,
+        gate: Option<&wgpu::Buffer>,
+    ) {
+        let workgroups = self.spatial_lookup_workgroups();
+        let args_size = std::mem::size_of::<wgpu::util::DispatchIndirectArgs>() as u64;
+        let dispatch = |compute_pass: &mut wgpu::ComputePass, slot: usize| match gate {
+            Some(buffer) => {
+                compute_pass.dispatch_workgroups_indirect(buffer, slot as u64 * args_size)
+            }
+            None => compute_pass.dispatch_workgroups(workgroups[slot], 1, 1),
+        };
+        if let Some(cell_list) = &self.cell_list {
+            compute_pass.set_bind_group(0, &cell_list.bind_group, &[]);
+            compute_pass.set_pipeline(&cell_list.compute_pipeline_clear_cells);
+            dispatch(compute_pass, 0);
+            compute_pass.set_pipeline(&cell_list.compute_pipeline_count_cells);
+            dispatch(compute_pass, 1);
+            match gate {
+                Some(buffer) => {
+                    cell_list
+                        .prefix_sum
+                        .encode_indirect(compute_pass, buffer, 2 * args_size)
+                }
+                None => cell_list.prefix_sum.encode(compute_pass),
+            }
+            // The prefix sum changes the bind group
+            compute_pass.set_pipeline(&cell_list.compute_pipeline_scatter_cells);
+            compute_pass.set_bind_group(0, &cell_list.bind_group, &[]);
+            dispatch(compute_pass, 1);
+            return;
+        }
+        compute_pass.set_pipeline(&self.compute_pipeline_hash_grid);
+        compute_pass.set_bind_group(0, &self.bind_group_hash_grid, &[]);
+        dispatch(compute_pass, 0);
+        match (&self.spatial_sort, gate) {
+            (Some(spatial_sort), Some(buffer)) => {
+                spatial_sort.encode_indirect(compute_pass, buffer, args_size)
+            }
+            (Some(spatial_sort), None) => spatial_sort.encode(compute_pass),
+            (None, _) => {}
+        }
+        compute_pass.set_pipeline(&self.compute_pipeline_start_indices);
+        compute_pass.set_bind_group(0, &self.bind_group_hash_grid, &[]);
+        dispatch(compute_pass, 0);
+    }
+    // Sample the probes at the end of a step, on a spatial lookup of the updated positions
+    fn encode_probes(&self, compute_pass: &mut wgpu::ComputePass) {
+        let Some(probe_sampling) = &self.probes else {
+            return;
+        };
+        // With a neighbor list the lookup of its last rebuild is reused: the points are fixed and
+        // the particles moved less than half the skin since, which the grid size covers
+        if self.neighbor_list.is_none() {
+            self.encode_spatial_lookup(compute_pass);
+        }
+        compute_pass.set_pipeline(&probe_sampling.compute_pipeline);
+        compute_pass.set_bind_group(0, &probe_sampling.bind_group, &[]);
+        let num_points = probe_sampling.probes.num_points();
+        compute_pass.dispatch_workgroups(num_points.div_ceil(64), 1, 1);
+        probe_sampling.probes.encode(compute_pass);
+    }
+    // Rebuild the list with its own spatial lookup if it has expired, decided on the GPU so that
+    // it can be recorded before every step of a submission
+    fn encode_neighbor_list(&self, compute_pass: &mut wgpu::ComputePass) {
+        let Some(neighbor_list) = &self.neighbor_list else {
+            return;
+        };
+        compute_pass.set_pipeline(&neighbor_list.compute_pipeline_gate);
+        compute_pass.set_bind_group(0, &neighbor_list.bind_group_build, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+        self.encode_spatial_lookup_gated(compute_pass, Some(&neighbor_list.buffer_dispatch));
+        compute_pass.set_pipeline(&neighbor_list.compute_pipeline_build);
+        compute_pass.set_bind_group(0, &neighbor_list.bind_group_build, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_density_interpolant(&self, compute_pass: &mut wgpu::ComputePass) {
+        match &self.neighbor_list {
+            Some(neighbor_list) => {
+                compute_pass.set_pipeline(&neighbor_list.compute_pipeline_density_interpolant);
+                compute_pass.set_bind_group(0, &neighbor_list.bind_group_hydrodynamics, &[]);
+            }
+            None => {
+                compute_pass.set_pipeline(&self.compute_pipeline_density_interpolant);
+                compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
+            }
+        }
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_pressure_equation_of_state(&self, compute_pass: &mut wgpu::ComputePass) {
+        compute_pass.set_pipeline(&self.compute_pipeline_pressure_equation_of_state);
+        compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_equation_of_motion(&self, compute_pass: &mut wgpu::ComputePass) {
+        match &self.neighbor_list {
+            Some(neighbor_list) => {
+                compute_pass.set_pipeline(&neighbor_list.compute_pipeline_equation_of_motion);
+                compute_pass.set_bind_group(0, &neighbor_list.bind_group_hydrodynamics, &[]);
+            }
+            None => {
+                compute_pass.set_pipeline(&self.compute_pipeline_equation_of_motion);
+                compute_pass.set_bind_group(0, &self.bind_group_hydrodynamics, &[]);
+            }
+        }
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_integration(&self, compute_pass: &mut wgpu::ComputePass, stage: u32) {
+        compute_pass.set_pipeline(&self.compute_pipelines_integrator[stage as usize]);
+        compute_pass.set_bind_group(0, &self.bind_group_solver, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    // Impulses of the particles on the load targets over a boundary pass or, for the pair and
+    // wall image forces, the time step
+    fn encode_loads(&self, compute_pass: &mut wgpu::ComputePass, load_pass: LoadPass) {
+        let Some(loads) = &self.loads else {
+            return;
+        };
+        let compute_pipeline = match load_pass {
+            LoadPass::Snapshot | LoadPass::Walls | LoadPass::Obstacles if !loads.boundaries => {
+                return;
+            }
+            LoadPass::Forces if !loads.forces => return,
+            LoadPass::Snapshot => &loads.compute_pipeline_snapshot,
+            LoadPass::Walls => &loads.compute_pipeline_walls,
+            LoadPass::Obstacles => &loads.compute_pipeline_obstacles,
+            LoadPass::Forces => &loads.compute_pipeline_forces,
+        };
+        compute_pass.set_bind_group(0, &loads.bind_group, &[]);
+        match &self.neighbor_list {
+            // The wall images walk the neighbor list instead of the spatial lookup
+            Some(neighbor_list) if load_pass == LoadPass::Forces => {
+                compute_pass.set_pipeline(&loads.compute_pipeline_forces_list);
+                compute_pass.set_bind_group(1, &neighbor_list.bind_group_walk, &[]);
+            }
+            _ => compute_pass.set_pipeline(compute_pipeline),
+        }
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(REDUCE_SIZE), 1, 1);
+        if load_pass != LoadPass::Snapshot {
+            loads.reduction.encode(compute_pass);
+        }
+    }
+    // Fluid mirrored across the rigid bodies, see attach_rigid_bodies
+    fn encode_rigid_body_images(&self, compute_pass: &mut wgpu::ComputePass, body_pass: BodyPass) {
+        let Some(rigid_body_coupling) = &self.rigid_body_coupling else {
+            return;
+        };
+        compute_pass.set_bind_group(0, &rigid_body_coupling.bind_group, &[]);
+        match &self.neighbor_list {
+            // The images walk the neighbor list instead of the spatial lookup
+            Some(neighbor_list) => {
+                compute_pass
+                    .set_pipeline(&rigid_body_coupling.compute_pipelines_list[body_pass as usize]);
+                compute_pass.set_bind_group(1, &neighbor_list.bind_group_walk, &[]);
+            }
+            None => compute_pass.set_pipeline(match body_pass {
+                BodyPass::Density => &rigid_body_coupling.compute_pipeline_density,
+                BodyPass::Forces => &rigid_body_coupling.compute_pipeline_forces,
+                BodyPass::ForcesReaction => &rigid_body_coupling.compute_pipeline_forces_reaction,
+            }),
+        }
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+    }
+    fn encode_boundaries(&self, compute_pass: &mut wgpu::ComputePass) {
+        self.encode_loads(compute_pass, LoadPass::Snapshot);
+        compute_pass.set_pipeline(&self.compute_pipeline_wall_boundaries);
+        compute_pass.set_bind_group(0, &self.bind_group_solver, &[]);
+        compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        self.encode_loads(compute_pass, LoadPass::Walls);
+        // Resolve collisions with obstacles after the update
+        if let Some(obstacle_collision) = &self.obstacle_collision {
+            compute_pass.set_pipeline(&obstacle_collision.compute_pipeline);
+            compute_pass.set_bind_group(0, &obstacle_collision.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            self.encode_loads(compute_pass, LoadPass::Obstacles);
+        }
+        // Project the particles out of rigid bodies, which take the reaction, and close the
+        // impulses of the step
+        if let Some(rigid_body_coupling) = &self.rigid_body_coupling {
+            compute_pass.set_pipeline(&rigid_body_coupling.compute_pipeline);
+            compute_pass.set_bind_group(0, &rigid_body_coupling.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+            rigid_body_coupling.buffers.encode_flush(compute_pass);
+        }
+        // Absorb outgoing waves
+        if let Some(damping) = &self.damping {
+            compute_pass.set_pipeline(&damping.compute_pipeline);
+            compute_pass.set_bind_group(0, &damping.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        }
+        // Flag the neighbor list for a rebuild once particles have moved too far
+        if let Some(neighbor_list) = &self.neighbor_list {
+            compute_pass.set_pipeline(&neighbor_list.compute_pipeline_check);
+            compute_pass.set_bind_group(0, &neighbor_list.bind_group_build, &[]);
+            compute_pass.dispatch_workgroups(self.capacity.div_ceil(256), 1, 1);
+        }
+    }
+}
+
+impl Scene for Sph {
+    fn init(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Box<dyn Simulation> {
+        let compute = pollster::block_on(SphCompute::new(device, &self.params));
+        compute.cpu2gpu_params(queue, &self.params);
+        compute.cpu2gpu_disturbance(queue, &self.disturbance);
+        compute.cpu2gpu_particles(queue, &self.particles, &self.motion);
+        compute.cpu2gpu_materials(queue, &self.materials);
+        Box::new(compute)
+    }
+    fn dt(&self) -> f32 {
+        self.params.dt
+    }
+    fn init_cpu(&self) -> anyhow::Result<Box<dyn CpuSimulation>> {
+        Ok(Box::new(SphCpu::new(
+            self.clone(),
+            Periodicity::default(),
+            Walls::default(),
+        )))
+    }
+}
+
+impl Simulation for SphCompute {
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    fn num_particles(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32 {
+        self.gpu2cpu_num_particles(device, queue)
+    }
+    fn prepare_step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        SphCompute::prepare_step(self, device, queue, n_substeps);
+    }
+    fn encode_step(&self, encoder: &mut wgpu::CommandEncoder, n_substeps: u32) {
+        SphCompute::encode_step(self, encoder, n_substeps);
+    }
+    fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, n_substeps: u32) {
+        SphCompute::step(self, device, queue, n_substeps);
+    }
+    fn gpu2cpu_particle_data(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> ParticleData {
+        let grid_size = self.gpu2cpu_params(device, queue).grid_size;
+        let particles = self.gpu2cpu_particles(device, queue);
+        let motion = self.gpu2cpu_motion(device, queue);
+        ParticleData {
+            ids: particles.iter().map(|particle| particle.id).collect(),
+            positions: particles
+                .iter()
+                .map(|particle| {
+                    [0, 1, 2].map(|a| (particle.coord[a] as f32 + particle.position[a]) * grid_size)
+                })
+                .collect(),
+            velocities: motion.iter().map(|motion| motion.velocity).collect(),
+            material_idx: particles
+                .iter()
+                .map(|particle| particle.material_idx)
+                .collect(),
+        }
+    }
+    fn instance_pass(
+        &self,
+        device: &wgpu::Device,
+        buffer_instances: &wgpu::Buffer,
+    ) -> InstancePass {
+        let module_instance = ShaderModuleBuilder::new()
+            .add_module(include_str!("./description.wgsl"))
+            .add_module(include_str!("./particle_to_instance.wgsl"))
+            .build(device, Some("Shader Module Particle Instance"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout Particle Instance"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Particle Instance"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.buffer_particles.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.buffer_materials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffer_instances.as_entire_binding(),
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pipeline Layout Particle Instance"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline Particle Instance"),
+            layout: Some(&pipeline_layout),
+            module: &module_instance,
+            entry_point: Some("particle_to_instance"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        InstancePass {
+            bind_group,
+            compute_pipeline,
+            num_workgroups: self.capacity.div_ceil(256),
+        }
+    }
+    fn checkpointing(&self) -> Option<&dyn Checkpointing> {
+        Some(self)
+    }
+    fn conservation(&self) -> Option<&dyn ConservationTotals> {
+        Some(self)
+    }
+    fn conservation_mut(&mut self) -> Option<&mut dyn ConservationTotals> {
+        Some(self)
+    }
+    fn probes(&self) -> Option<&dyn ProbeRecords> {
+        Some(self)
+    }
+    fn loads(&self) -> Option<&dyn BoundaryLoads> {
+        Some(self)
+    }
+    fn rigid_bodies(&self) -> Option<&dyn RigidBodyRecords> {
+        Some(self)
+    }
+}
+
+impl Checkpointing for SphCompute {
+    fn gpu2cpu_state(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SolverState {
+        SolverState::Sph(Sph {
+            params: self.gpu2cpu_params(device, queue),
+            disturbance: self.gpu2cpu_disturbance(device, queue),
+            particles: self.gpu2cpu_particles(device, queue),
+            motion: self.gpu2cpu_motion(device, queue),
+            materials: self.gpu2cpu_materials(device, queue),
+        })
+    }
+    fn cpu2gpu_state(&self, queue: &wgpu::Queue, state: &SolverState) {
+        let SolverState::Sph(sph) = state else {
+            panic!("SPH solver restarted from {} state", state.solver_name());
+        };
+        self.cpu2gpu_params(queue, &sph.params);
+        self.cpu2gpu_disturbance(queue, &sph.disturbance);
+        self.cpu2gpu_materials(queue, &sph.materials);
+        self.cpu2gpu_particles(queue, &sph.particles, &sph.motion);
+    }
+}
+
+impl ConservationTotals for SphCompute {
+    fn attach_conservation(&mut self, device: &wgpu::Device) {
+        SphCompute::attach_conservation(self, device);
+    }
+    fn gpu2cpu_totals(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Totals> {
+        SphCompute::gpu2cpu_totals(self, device, queue)
+    }
+}
+
+impl ProbeRecords for SphCompute {
+    fn probe_names(&self) -> Vec<String> {
+        match &self.probes {
+            Some(probe_sampling) => probe_sampling.probes.names().to_vec(),
+            None => vec![],
+        }
+    }
+    fn gpu2cpu_probes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Vec<f32>> {
+        SphCompute::gpu2cpu_probes(self, device, queue)
+    }
+}
+
+impl BoundaryLoads for SphCompute {
+    fn load_targets(&self) -> Vec<LoadTarget> {
+        SphCompute::load_targets(self)
+    }
+    fn gpu2cpu_loads(&self, device: &wgpu::Device, queue: &wgpu::Queue, elapsed: f32) -> Vec<Load> {
+        SphCompute::gpu2cpu_loads(self, device, queue, elapsed)
+    }
+}
+
+impl RigidBodyRecords for SphCompute {
+    fn gpu2cpu_rigid_body_motion(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<RigidBodyMotion> {
+        self.gpu2cpu_rigid_bodies(device, queue)
+            .iter()
+            .map(|body| body.motion(1.0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_grid() {
+        let cell_grid = CellGrid::new([-0.5, -0.5, 0.0], [0.5, 0.25, 0.05], 0.1);
+        assert_eq!(cell_grid.min, [-5, -5, 0]);
+        assert_eq!(cell_grid.dims, [10, 8, 1]);
+        assert_eq!(cell_grid.num_cells, 80);
+    }
+
+    #[test]
+    fn test_integrator_stages() {
+        assert_eq!(Integrator::default(), Integrator::LeapFrog);
+        assert_eq!(Integrator::VelocityVerlet.num_stages(), 2);
+        assert_eq!(Integrator::SymplecticEuler.num_stages(), 1);
+        assert_eq!(Integrator::PredictorCorrector.num_stages(), 2);
+        assert_eq!(Integrator::MidpointRk2.num_stages(), 2);
+        // Predictor-corrector and midpoint share the predictor
+        assert_eq!(
+            Integrator::PredictorCorrector.entry_points()[0],
+            Integrator::MidpointRk2.entry_points()[0]
+        );
+    }
+
+    // Velocity Verlet is exact under a constant acceleration
+    #[test]
+    fn test_velocity_verlet_free_fall() {
+        let water = Material {
+            density_reference: 1000.0,
+            density_ref_threshold: 0.7,
+            compressibility: 1000.0,
+            boundary_damping: 0.8,
+            cs: 20.0,
+            alpha: 1.0,
+            beta: 2.0,
+            eps: 0.01,
+            color: [0.0, 0.0, 1.0, 1.0],
+        };
+        let particle = Particle {
+            coord: [0; 3],
+            mass: 1.0,
+            position: [0.0; 3],
+            density: 1000.0,
+            pressure: 0.0,
+            smoothing_length: 0.1,
+            material_idx: 0,
+            id: 0,
+        };
+        let velocity = [1.0, 2.0, 0.0];
+        let motion = ParticleMotion {
+            velocity,
+            velocity_p: velocity,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.01,
+            grid_size: 0.1,
+            num_particles: 1,
+            _padding: [0.0; 2],
+        };
+        let disturbance = Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        };
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the test needs a wgpu adapter");
+        let mut compute = pollster::block_on(SphCompute::new(&device, &params));
+        compute.cpu2gpu_params(&queue, &params);
+        compute.cpu2gpu_disturbance(&queue, &disturbance);
+        compute.cpu2gpu_particles(&queue, &vec![particle], &vec![motion]);
+        compute.cpu2gpu_materials(&queue, &vec![water]);
+        compute.set_integrator(&device, Integrator::VelocityVerlet);
+        compute.step(&device, &queue, 10);
+        let t = 10.0 * params.dt;
+        let particle = compute.gpu2cpu_particles(&device, &queue)[0];
+        let motion = compute.gpu2cpu_motion(&device, &queue)[0];
+        for (a, (v0, g)) in velocity.into_iter().zip(disturbance.field).enumerate() {
+            let position = (particle.coord[a] as f32 + particle.position[a]) * params.grid_size;
+            let expected = v0 * t + 0.5 * g * t * t;
+            assert!(
+                (position - expected).abs() < 1e-5,
+                "{} {}",
+                position,
+                expected
+            );
+            assert!((motion.velocity[a] - (v0 + g * t)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_hash_collisions() {
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.001,
+            grid_size: 0.1,
+            num_particles: 4,
+            _padding: [0.0; 2],
+        };
+        let particle = |coord: [i32; 3]| Particle {
+            coord,
+            mass: 1.0,
+            position: [0.5; 3],
+            density: 0.0,
+            pressure: 0.0,
+            smoothing_length: 0.1,
+            material_idx: 0,
+            id: 0,
+        };
+        // Cells (0, 0, 0) and (4, 0, 0) share key 0, (1, 0, 0) has key 3
+        let particles = [[0, 0, 0], [0, 0, 0], [4, 0, 0], [1, 0, 0]].map(particle);
+        let collisions = HashCollisions::from_particles(&particles, &params);
+        assert_eq!(collisions.occupied_cells, 3);
+        assert_eq!(collisions.occupied_keys, 2);
+        assert_eq!(collisions.colliding_keys, 1);
+        assert!((collisions.foreign_fraction - 3.0 / 7.0).abs() < 1e-6);
+    }
+
+    // Still water 0.5 deep in the unit box with mirror walls, at hydrostatic pressure, without
+    // the particles inside `bodies`
+    fn hydrostatic_tank(bodies: &[RigidBody]) -> (Sph, wgpu::Device, wgpu::Queue, SphCompute) {
+        use crate::seeding::{Lattice, Seeder, Shape};
+        // Tension of the free surface layer is clipped
+        let water = Material {
+            density_reference: 1000.0,
+            density_ref_threshold: 1.0,
+            compressibility: 200.0,
+            boundary_damping: 0.8,
+            cs: 5.0,
+            alpha: 1.0,
+            beta: 2.0,
+            eps: 0.01,
+            color: [0.0, 0.0, 1.0, 1.0],
+        };
+        let tank = Shape::Box {
+            min: [-0.5; 3],
+            max: [0.5, 0.0, 0.5],
+        };
+        let (particles, motion): (Vec<_>, Vec<_>) = Seeder::new(Lattice::Cubic, 0.1)
+            .sph_particles(&tank, &water, 0, 0.2, 0.2, 0)
+            .0
+            .into_iter()
+            .zip(
+                Seeder::new(Lattice::Cubic, 0.1)
+                    .sph_particles(&tank, &water, 0, 0.2, 0.2, 0)
+                    .1,
+            )
+            .filter(|(particle, _)| {
+                let point =
+                    [0, 1, 2].map(|a| (particle.coord[a] as f32 + particle.position[a]) * 0.2);
+                bodies.iter().all(|body| body.distance(point) > 0.0)
+            })
+            .unzip();
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.001,
+            grid_size: 0.2,
+            num_particles: particles.len() as u32,
+            _padding: [0.0; 2],
+        };
+        let disturbance = Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        };
+        let mut sph = Sph::new(params, disturbance, particles, motion, vec![water]);
+        sph.hydrostatic_init(0.0, &Seeder::new(Lattice::Cubic, 0.1));
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the test needs a wgpu adapter");
+        let compute = pollster::block_on(SphCompute::new(&device, &sph.params));
+        compute.cpu2gpu_params(&queue, &sph.params);
+        compute.cpu2gpu_disturbance(&queue, &sph.disturbance);
+        compute.cpu2gpu_particles(&queue, &sph.particles, &sph.motion);
+        compute.cpu2gpu_materials(&queue, &sph.materials);
+        compute.cpu2gpu_walls(&queue, &Walls::mirrored());
+        (sph, device, queue, compute)
+    }
+
+    #[test]
+    fn test_hydrostatic_rest() {
+        let (sph, device, queue, mut compute) = hydrostatic_tank(&[]);
+        // The mirrored walls complete the neighbourhoods below the surface layer, whose summed
+        // densities are hydrostatic at the floor and in the corners too
+        compute.compute_spatial_lookup(&device, &queue);
+        compute.compute_density_interpolant(&device, &queue);
+        let summed = compute.gpu2cpu_particles(&device, &queue);
+        for (particle, initial) in summed.iter().zip(&sph.particles) {
+            let height = (particle.coord[1] as f32 + particle.position[1]) * 0.2;
+            if height < -0.1 {
+                let error = particle.density / initial.density - 1.0;
+                assert!(
+                    error.abs() < 1e-4,
+                    "density {} at {}",
+                    particle.density,
+                    height
+                );
+            }
+        }
+
+        compute.relax(&device, &queue, 100, 0.05);
+        compute.attach_conservation(&device);
+        for _ in 0..10 {
+            compute.step(&device, &queue, 10);
+            let totals = compute.gpu2cpu_totals(&device, &queue).unwrap();
+            assert!(totals.max_speed < 0.01, "max speed {}", totals.max_speed);
+        }
+        // Still at the initial heights after 0.1 s
+        let positions = |particles: &[Particle]| -> Vec<f32> {
+            particles
+                .iter()
+                .map(|p| (p.coord[1] as f32 + p.position[1]) * 0.2)
+                .collect()
+        };
+        let settled = positions(&compute.gpu2cpu_particles(&device, &queue));
+        for (y, y0) in settled.iter().zip(positions(&sph.particles)) {
+            assert!((y - y0).abs() < 0.005, "{} from {}", y, y0);
+        }
+    }
+
+    #[test]
+    fn test_morton_reorder_interval() {
+        let (sph, device, queue, compute) = hydrostatic_tank(&[]);
+        let (_, device_reordered, queue_reordered, mut reordered) = hydrostatic_tank(&[]);
+        reordered.attach_morton_reorder(&device_reordered, 7);
+        compute.step(&device, &queue, 30);
+        reordered.step(&device_reordered, &queue_reordered, 30);
+        let by_id = |mut particles: Vec<Particle>| {
+            particles.sort_by_key(|particle| particle.id);
+            particles
+        };
+        // step reorders the buffers, the particles are followed by their ids
+        let particles = reordered.gpu2cpu_particles(&device_reordered, &queue_reordered);
+        assert_eq!(particles.len(), sph.particles.len());
+        assert!(particles.windows(2).any(|pair| pair[0].id > pair[1].id));
+        let reference = by_id(compute.gpu2cpu_particles(&device, &queue));
+        for (particle, reference) in by_id(particles).iter().zip(&reference) {
+            for a in 0..3 {
+                let x = particle.coord[a] as f32 + particle.position[a];
+                let x_ref = reference.coord[a] as f32 + reference.position[a];
+                assert!((x - x_ref).abs() < 1e-4, "{} from {}", x, x_ref);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hydrostatic_loads() {
+        let (sph, device, queue, mut compute) = hydrostatic_tank(&[]);
+        let region = |name: &str, min: [f32; 3], max: [f32; 3]| LoadTarget::Region {
+            name: name.to_string(),
+            min,
+            max,
+            reference: [-0.5, -0.5, 0.0],
+            cutoff: None,
+        };
+        compute.attach_loads(
+            &device,
+            &[
+                region("floor", [-0.5, -0.5, -0.5], [0.5, -0.5, 0.5]),
+                region("wall", [0.5, -0.5, -0.5], [0.5, 0.5, 0.5]),
+            ],
+        );
+        compute.relax(&device, &queue, 100, 0.05);
+        compute.gpu2cpu_loads(&device, &queue, 1.0);
+        compute.step(&device, &queue, 100);
+        let loads = compute.gpu2cpu_loads(&device, &queue, 100.0 * sph.params.dt);
+        // The floor carries the weight of the water, centred half a tank from the reference
+        let weight = 9.81 * sph.particles.iter().map(|p| p.mass).sum::<f32>();
+        let [floor, wall] = loads[..] else { panic!() };
+        assert!((floor.force[1] / -weight - 1.0).abs() < 0.05, "{:?}", floor);
+        assert!(
+            (floor.moment[2] / (-0.5 * weight) - 1.0).abs() < 0.05,
+            "{:?}",
+            floor
+        );
+        assert!(floor.force[0].abs() < 0.01 * weight, "{:?}", floor);
+        // The side wall carries the hydrostatic pressure over its wetted height
+        let depth: f32 = 0.5;
+        let thrust = 0.5 * 1000.0 * 9.81 * depth * depth;
+        assert!((wall.force[0] / thrust - 1.0).abs() < 0.03, "{:?}", wall);
+    }
+
+    #[test]
+    fn test_piston_wavemaker() {
+        use crate::wavemaker::{MotionSignal, WavemakerMotion};
+        // Piston across the tank pushed 0.1 m into the water over 0.2 s
+        let paddle = RigidBody::cuboid([0.05, 0.5, 0.5], 1000.0, [-0.4, 0.0, 0.0]);
+        let (_, device, queue, mut compute) = hydrostatic_tank(std::slice::from_ref(&paddle));
+        compute.attach_rigid_bodies(&device, vec![paddle.clone()]);
+        compute.relax(&device, &queue, 100, 0.05);
+        let motion = WavemakerMotion::Piston {
+            direction: cgmath::Vector3::unit_x(),
+        };
+        let signal = MotionSignal::TimeSeries {
+            times: vec![0.0, 0.2],
+            values: vec![0.0, 0.1],
+        };
+        compute.attach_wavemakers(&queue, vec![Some(Wavemaker::new(motion, signal, &paddle))]);
+        let heights = |compute: &SphCompute, min_x: f32, max_x: f32| {
+            compute
+                .gpu2cpu_particles(&device, &queue)
+                .iter()
+                .map(|p| [0, 1].map(|a| (p.coord[a] as f32 + p.position[a]) * 0.2))
+                .filter(|[x, _]| min_x < *x && *x < max_x)
+                .map(|[_, y]| y)
+                .fold(f32::MIN, f32::max)
+        };
+        let still = heights(&compute, -0.35, 0.5);
+        let mut near = vec![];
+        for _ in 0..6 {
+            compute.step(&device, &queue, 50);
+            near.push(heights(&compute, -0.35, 0.0));
+        }
+        let far = heights(&compute, 0.2, 0.5);
+        // The paddle follows its signal rather than the fluid loads
+        let [driven] = &compute.gpu2cpu_rigid_bodies(&device, &queue)[..] else {
+            panic!()
+        };
+        assert!((driven.position.x + 0.3).abs() < 1e-4);
+        assert!(driven.linear_velocity.x.abs() < 1e-4);
+        // The displaced water piles up in front of the paddle and runs to the far end
+        let highest = near.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(highest - still > 0.03);
+        assert!(far - still > 0.03);
+    }
+
+    #[test]
+    fn test_floating_box() {
+        // Half as dense as the water, the box floats with half its height below the surface. It
+        // is released above its cavity and settles within a quarter of its draft.
+        let mut body = RigidBody::cuboid([0.2; 3], 500.0, [0.0; 3]);
+        let (_, device, queue, mut compute) = hydrostatic_tank(std::slice::from_ref(&body));
+        compute.attach_rigid_bodies(&device, vec![body.clone()]);
+        compute.relax(&device, &queue, 100, 0.05);
+        body.position.y = 0.1;
+        compute.cpu2gpu_rigid_bodies(&queue, std::slice::from_ref(&body));
+        let mut heights = vec![];
+        for _ in 0..100 {
+            compute.step(&device, &queue, 10);
+            let [floating] = &compute.gpu2cpu_rigid_bodies(&device, &queue)[..] else {
+                panic!()
+            };
+            heights.push(floating.position.y);
+        }
+        let lowest = heights.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(-0.1 < lowest && lowest < 0.05, "lowest {}", lowest);
+        let settled = heights[50..].iter().sum::<f32>() / 50.0;
+        assert!(settled.abs() < 0.05, "settled at {}", settled);
+    }
+
+    #[test]
+    fn test_neighbor_list_rebuilds() {
+        use crate::seeding::{Lattice, Seeder, Shape};
+        // Block of water collapsing in a corner of the unit box with mirror walls. The list
+        // reaches one grid size and leaves a skin of half a smoothing length.
+        let water = Material {
+            density_reference: 1000.0,
+            density_ref_threshold: 1.0,
+            compressibility: 200.0,
+            boundary_damping: 0.8,
+            cs: 5.0,
+            alpha: 1.0,
+            beta: 2.0,
+            eps: 0.01,
+            color: [0.0, 0.0, 1.0, 1.0],
+        };
+        let block = Shape::Box {
+            min: [-0.5; 3],
+            max: [-0.2, -0.2, -0.2],
+        };
+        let (particles, motion) =
+            Seeder::new(Lattice::Cubic, 0.05).sph_particles(&block, &water, 0, 0.1, 0.15, 0);
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.001,
+            grid_size: 0.15,
+            num_particles: particles.len() as u32,
+            _padding: [0.0; 2],
+        };
+        let disturbance = Disturbance {
+            field: [0.0, -9.81, 0.0],
+            _padding: 0.0,
+        };
+        let floor = LoadTarget::Region {
+            name: "floor".to_string(),
+            min: [-0.5; 3],
+            max: [0.5, -0.5, 0.5],
+            reference: [0.0; 3],
+            cutoff: None,
+        };
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the test needs a wgpu adapter");
+        let collapse = |skin: Option<f32>| {
+            let mut compute = pollster::block_on(SphCompute::new(&device, &params));
+            compute.cpu2gpu_params(&queue, &params);
+            compute.cpu2gpu_disturbance(&queue, &disturbance);
+            compute.cpu2gpu_particles(&queue, &particles, &motion);
+            compute.cpu2gpu_materials(&queue, &vec![water]);
+            compute.cpu2gpu_walls(&queue, &Walls::mirrored());
+            compute.attach_loads(&device, std::slice::from_ref(&floor));
+            if let Some(skin) = skin {
+                compute.attach_neighbor_list(&device, 192, skin);
+            }
+            // One submission over which the particles move further than the skin
+            compute.step(&device, &queue, 150);
+            let status = compute.gpu2cpu_neighbor_list_status(&device, &queue);
+            let loads = compute.gpu2cpu_loads(&device, &queue, 150.0 * params.dt);
+            (compute.gpu2cpu_particles(&device, &queue), loads, status)
+        };
+        let (searched, searched_loads, _) = collapse(None);
+        let (listed, listed_loads, status) = collapse(Some(0.05));
+        let status = status.unwrap();
+        assert!(status.builds > 1, "{:?}", status);
+        assert!(status.max_count < 192, "{:?}", status);
+        let position = |p: &Particle| [0, 1, 2].map(|a| (p.coord[a] as f32 + p.position[a]) * 0.15);
+        let mut moved = 0.0f32;
+        for ((a, b), initial) in searched.iter().zip(&listed).zip(&particles) {
+            let [a, b, initial] = [a, b, initial].map(position);
+            moved = moved.max(
+                (0..3)
+                    .map(|i| (a[i] - initial[i]).abs())
+                    .fold(0.0, f32::max),
+            );
+            for i in 0..3 {
+                assert!((a[i] - b[i]).abs() < 1e-3, "{:?} and {:?}", a, b);
+            }
+        }
+        assert!(moved > 0.05, "moved {}", moved);
+        // The wall images walk the list
+        let [searched_floor] = searched_loads[..] else {
+            panic!()
+        };
+        let [listed_floor] = listed_loads[..] else {
+            panic!()
+        };
+        assert!(searched_floor.force[1] < 0.0);
+        assert!(
+            (listed_floor.force[1] / searched_floor.force[1] - 1.0).abs() < 0.01,
+            "{:?} and {:?}",
+            listed_floor,
+            searched_floor
+        );
+    }
+
+    #[test]
+    fn test_flow_boundaries() {
+        use crate::seeding::{Lattice, Seeder, Shape};
+        use std::collections::HashSet;
+        // Without pressure and viscosity the particles coast and the counts are exact
+        let water = Material {
+            density_reference: 1000.0,
+            density_ref_threshold: 1.0,
+            compressibility: 0.0,
+            boundary_damping: 0.8,
+            cs: 5.0,
+            alpha: 0.0,
+            beta: 0.0,
+            eps: 0.01,
+            color: [0.0, 0.0, 1.0, 1.0],
+        };
+        // A block inside the outlet and a block away from the jet of the inlet
+        let seeder = Seeder::new(Lattice::Cubic, 0.1);
+        let block = |min: [f32; 3], max: [f32; 3], first_id: u32| {
+            seeder.sph_particles(&Shape::Box { min, max }, &water, 0, 0.2, 0.2, first_id)
+        };
+        let (mut particles, mut motion) = block([0.2, -0.2, -0.2], [0.4, 0.0, 0.0], 0);
+        let (kept, kept_motion) = block([-0.45, -0.45, 0.25], [-0.25, -0.25, 0.45], 8);
+        assert_eq!((particles.len(), kept.len()), (8, 8));
+        particles.extend(kept);
+        motion.extend(kept_motion);
+        let params = SimParams {
+            grid_prime: [59, 519, 1087],
+            dt: 0.001,
+            grid_size: 0.2,
+            num_particles: particles.len() as u32,
+            _padding: [0.0; 2],
+        };
+        let (_, device, queue) = pollster::block_on(crate::headless::request_device(true))
+            .expect("the test needs a wgpu adapter");
+        let mut compute = pollster::block_on(SphCompute::with_capacity(&device, &params, 64));
+        compute.cpu2gpu_params(&queue, &params);
+        compute.cpu2gpu_particles(&queue, &particles, &motion);
+        compute.cpu2gpu_materials(&queue, &vec![water]);
+        // Four points releasing a layer every 0.1 s along x, deleted again past x = 0.2
+        let inlet = Inlet::new(
+            [-0.1, -0.4, -0.1],
+            [0.0, 0.2, 0.0],
+            [0.0, 0.0, 0.2],
+            0.1,
+            1.0,
+            1000.0,
+        );
+        let outlet = Outlet::new([0.2, -0.5, -0.5], [0.5, 0.5, 0.5]);
+        compute.attach_flow_boundaries(&device, vec![inlet], &[outlet]);
+        let ids = |compute: &SphCompute| {
+            let particles = compute.gpu2cpu_particles(&device, &queue);
+            let ids: HashSet<u32> = particles.iter().map(|particle| particle.id).collect();
+            // The alive particles are read back, each id once
+            assert_eq!(ids.len(), particles.len(), "duplicate ids");
+            ids
+        };
+        // Boundaries act once at the start of a submission, up to its time. The block in the
+        // outlet is deleted before anything is emitted.
+        compute.step(&device, &queue, 2);
+        assert_eq!(ids(&compute), (8..16).collect());
+        compute.step(&device, &queue, 250);
+        assert_eq!(ids(&compute), (8..16).collect());
+        // Two layers emitted by 0.25 s with the ids after the seeded ones
+        compute.step(&device, &queue, 1);
+        assert_eq!(ids(&compute), (8..24).collect());
+        // Five layers by 0.55 s, the two leading ones have passed into the outlet and the third
+        // is emitted in it, after the deletion
+        compute.step(&device, &queue, 300);
+        compute.step(&device, &queue, 1);
+        assert_eq!(ids(&compute), (8..16).chain(24..36).collect());
+        // Each point emitted its three layers in a row, the leading one is deleted
+        compute.step(&device, &queue, 1);
+        let trailing = (24..36).filter(|id| (id - 24) % 3 != 0);
+        assert_eq!(ids(&compute), (8..16).chain(trailing).collect());
+    }
 }
@@ -0,0 +1,84 @@
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> material: array<Material>;
+
+@group(0) @binding(3)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(4)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(5)
+var<uniform> list_params: NeighborListParams;
+
+@group(0) @binding(6)
+var<storage, read> neighbor_counts: array<u32>;
+
+@group(0) @binding(7)
+var<storage, read> neighbor_indices: array<u32>;
+
+@group(0) @binding(8)
+var<uniform> disturbance: Disturbance;
+
+@group(0) @binding(9)
+var<uniform> walls: Walls;
+
+@compute @workgroup_size(256)
+fn density_interpolant_list(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    let h_a = particle.smoothing_length;
+    // Initialize Density
+    var density: f32 = particle.mass / (PI * h_a * h_a);
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let walls = near_walls(position, h_a);
+    // Loop through the cached neighbors
+    let offset = index * list_params.max_neighbors;
+    for (var n = 0u; n < neighbor_counts[index]; n++) {
+        let neighbor_idx = neighbor_indices[offset + n];
+        let neighbor = particles[neighbor_idx];
+        density += density_contribution(particle, neighbor);
+        if (walls != 0u) {
+            density += mirror_density_contribution(particle, position, walls, neighbor, particles_motion[neighbor_idx]);
+        }
+    }
+    // Update final density interpolant
+    particles[index].density = density;
+}
+
+@compute @workgroup_size(256)
+fn equation_of_motion_list(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    // Get particle motion
+    let motion = particles_motion[index];
+    var acceleration = vec3f(0.0,0.0,0.0);
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let walls = near_walls(position, particle.smoothing_length);
+    // Loop through the cached neighbors
+    let offset = index * list_params.max_neighbors;
+    for (var n = 0u; n < neighbor_counts[index]; n++) {
+        let neighbor_idx = neighbor_indices[offset + n];
+        let neighbor = particles[neighbor_idx];
+        let neighbor_motion = particles_motion[neighbor_idx];
+        acceleration += acceleration_contribution(particle, motion, neighbor, neighbor_motion);
+        if (walls != 0u) {
+            acceleration += mirror_acceleration_contribution(particle, motion, position, walls, neighbor, neighbor_motion);
+        }
+    }
+    // Set acceleration
+    particles_motion[index].acceleration += acceleration;
+}
@@ -0,0 +1,140 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> spatial: array<SpatialLookup>;
+
+@group(0) @binding(2)
+var<storage, read> start_indices: array<u32>;
+
+@group(0) @binding(3)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(4)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(5)
+var<uniform> list_params: NeighborListParams;
+
+@group(0) @binding(6)
+var<storage, read_write> neighbor_counts: array<u32>;
+
+@group(0) @binding(7)
+var<storage, read_write> neighbor_indices: array<u32>;
+
+@group(0) @binding(8)
+var<storage, read_write> references: array<ReferencePosition>;
+
+@group(0) @binding(9)
+var<storage, read_write> status: NeighborListStatus;
+
+@group(0) @binding(10)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(11)
+var<storage, read> dispatch_sizes: array<u32>;
+
+@group(0) @binding(12)
+var<storage, read_write> dispatch_args: array<u32>;
+
+struct NeighborListStatus {
+    expired: atomic<u32>,
+    max_count: atomic<u32>,
+    building: u32,
+    builds: u32,
+}
+
+// Latch the expiry of the list for the build of this step and size the indirect dispatches of
+// the spatial lookup before it, which are empty while the list is still valid
+@compute @workgroup_size(1)
+fn gate_neighbor_list() {
+    let building = atomicExchange(&status.expired, 0u);
+    status.building = building;
+    if (building != 0u) {
+        atomicStore(&status.max_count, 0u);
+        status.builds++;
+    }
+    for (var i = 0u; i < arrayLength(&dispatch_sizes); i++) {
+        dispatch_args[3u * i] = select(0u, dispatch_sizes[i], building != 0u);
+        dispatch_args[3u * i + 1u] = 1u;
+        dispatch_args[3u * i + 2u] = 1u;
+    }
+}
+
+@compute @workgroup_size(256)
+fn build_neighbor_list(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let num_particles = params.num_particles;
+    if (index >= num_particles || status.building == 0u) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    // The 27 cells cover every pair closer than one grid size
+    let radius2 = params.grid_size * params.grid_size;
+    let offset = index * list_params.max_neighbors;
+    var count = 0u;
+    var found = 0u;
+    // Keys already walked, colliding cells would otherwise list a neighbor twice
+    var keys: array<u32, 27>;
+    var num_keys = 0u;
+    for (var gx = -1i; gx < 2; gx++) {
+        for (var gy = -1i; gy < 2; gy++) {
+            for (var gz = -1i; gz < 2; gz++) {
+                // Calculate cell key
+                let grid_coord = wrap_coord(particle.coord + vec3i(gx, gy, gz));
+                let key = cell_key(grid_coord);
+                if (key == U32MAX) {
+                    continue;
+                }
+                var walked = false;
+                for (var k = 0u; k < num_keys; k++) {
+                    walked = walked || keys[k] == key;
+                }
+                if (walked) {
+                    continue;
+                }
+                keys[num_keys] = key;
+                num_keys++;
+                // Find start index in particle list and loop through neihbors
+                let idx0 = start_indices[key];
+                for (var spatial_idx = idx0; spatial_idx < num_particles; spatial_idx++) {
+                    if (spatial[spatial_idx].key != key) {
+                        break;
+                    }
+                    let neighbor_idx = spatial[spatial_idx].index;
+                    let rvec_ab = get_particle_distance(particle, particles[neighbor_idx], params.grid_size);
+                    if (dot(rvec_ab, rvec_ab) > radius2) {
+                        continue;
+                    }
+                    found++;
+                    if (count < list_params.max_neighbors) {
+                        neighbor_indices[offset + count] = neighbor_idx;
+                        count++;
+                    }
+                }
+            }
+        }
+    }
+    neighbor_counts[index] = count;
+    references[index] = ReferencePosition(particle.coord, 0u, particle.position, 0.0);
+    // Lists longer than the capacity are truncated, report the longest for diagnostics
+    atomicMax(&status.max_count, found);
+}
+
+@compute @workgroup_size(256)
+fn check_neighbor_list(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let particle = particles[index];
+    let reference = references[index];
+    let coord_dist = minimum_image(particle.coord - reference.coord);
+    let displacement = (vec3f(coord_dist) + particle.position - reference.position) * params.grid_size;
+    // Two particles approaching each other by half the skin each can enter the cutoff
+    let half_skin = 0.5 * list_params.skin;
+    if (dot(displacement, displacement) > half_skin * half_skin) {
+        atomicStore(&status.expired, 1u);
+    }
+}
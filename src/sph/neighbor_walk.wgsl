@@ -0,0 +1,34 @@
+// Candidate neighbors of a particle from the spatial lookup, in the 27 cells around it.
+// Requires the `particles`, `spatial`, `start_indices`, `params` and `periodicity` bindings and
+// a `cell_key` function. neighbor_walk_list.wgsl walks a cached neighbor list instead.
+struct NeighborWalk {
+    coord: vec3i,
+    cell: i32,
+    key: u32,
+    next: u32,
+    neighbor: u32,
+}
+
+fn neighbor_walk(index: u32) -> NeighborWalk {
+    return NeighborWalk(particles[index].coord, -1, U32MAX, 0u, 0u);
+}
+
+// Move to the next candidate and leave its index in `neighbor`, false once all were visited
+fn next_neighbor(walk: ptr<function, NeighborWalk>) -> bool {
+    while ((*walk).cell < 27) {
+        let next = (*walk).next;
+        if ((*walk).key != U32MAX && next < params.num_particles && spatial[next].key == (*walk).key) {
+            (*walk).neighbor = spatial[next].index;
+            (*walk).next = next + 1u;
+            return true;
+        }
+        (*walk).cell += 1;
+        let cell = (*walk).cell;
+        let offset = vec3i(cell % 3, (cell / 3) % 3, cell / 9) - vec3i(1);
+        (*walk).key = select(U32MAX, cell_key(wrap_coord((*walk).coord + offset)), cell < 27);
+        if ((*walk).key != U32MAX) {
+            (*walk).next = start_indices[(*walk).key];
+        }
+    }
+    return false;
+}
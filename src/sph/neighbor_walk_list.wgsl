@@ -0,0 +1,32 @@
+// Neighbors of a particle from the cached neighbor list, with the same interface as
+// neighbor_walk.wgsl. The list is bound as the second bind group.
+@group(1) @binding(0)
+var<uniform> walk_list_params: NeighborListParams;
+
+@group(1) @binding(1)
+var<storage, read> walk_counts: array<u32>;
+
+@group(1) @binding(2)
+var<storage, read> walk_indices: array<u32>;
+
+struct NeighborWalk {
+    offset: u32,
+    count: u32,
+    next: u32,
+    neighbor: u32,
+}
+
+fn neighbor_walk(index: u32) -> NeighborWalk {
+    return NeighborWalk(index * walk_list_params.max_neighbors, walk_counts[index], 0u, 0u);
+}
+
+// Move to the next neighbor and leave its index in `neighbor`, false once all were visited
+fn next_neighbor(walk: ptr<function, NeighborWalk>) -> bool {
+    let next = (*walk).next;
+    if (next >= (*walk).count) {
+        return false;
+    }
+    (*walk).neighbor = walk_indices[(*walk).offset + next];
+    (*walk).next = next + 1u;
+    return true;
+}
@@ -0,0 +1,52 @@
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> obstacles: array<SdfObstacle>;
+
+@group(0) @binding(4)
+var<storage, read> sdf: array<f32>;
+
+@compute @workgroup_size(256)
+fn obstacle_collision(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    // Get particle motion
+    let motion = particles_motion[index];
+    var position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    var velocity = motion.velocity;
+    var velocity_p = motion.velocity_p;
+    var collided = false;
+    for (var obstacle_idx = 0u; obstacle_idx < arrayLength(&obstacles); obstacle_idx++) {
+        let phi = sample_sdf(obstacle_idx, position);
+        if (phi >= 0.0) {
+            continue;
+        }
+        // Project particle back onto the surface and reflect its velocity
+        let obstacle = obstacles[obstacle_idx];
+        let normal = sdf_normal(obstacle_idx, position);
+        position -= phi * normal;
+        velocity = collision_response(velocity, normal, obstacle.friction, obstacle.restitution);
+        velocity_p = collision_response(velocity_p, normal, obstacle.friction, obstacle.restitution);
+        collided = true;
+    }
+    if (!collided) {
+        return;
+    }
+    // Set new states
+    let pos = position / params.grid_size;
+    particles[index].coord = vec3i(floor(pos));
+    particles[index].position = pos - floor(pos);
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity_p;
+}
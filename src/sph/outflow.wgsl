@@ -0,0 +1,51 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> outlets: array<Outlet>;
+
+@group(0) @binding(4)
+var<storage, read_write> keep: array<u32>;
+
+@group(0) @binding(5)
+var<storage, read_write> particles_out: array<Particle>;
+
+@group(0) @binding(6)
+var<storage, read_write> particles_motion_out: array<ParticleMotion>;
+
+fn is_kept(index: u32) -> bool {
+    if (index >= params.num_particles) {
+        return false;
+    }
+    let particle = particles[index];
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    return !in_outlet(position);
+}
+
+// Flag surviving particles over the whole capacity, followed by a prefix sum of the flags
+@compute @workgroup_size(256)
+fn mark_outflow(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&keep)) {
+        return;
+    }
+    keep[index] = select(0u, 1u, is_kept(index));
+}
+
+// Scatter surviving particles to their scanned slot, preserving order
+@compute @workgroup_size(256)
+fn compact_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (!is_kept(index)) {
+        return;
+    }
+    let slot = keep[index];
+    particles_out[slot] = particles[index];
+    particles_motion_out[slot] = particles_motion[index];
+}
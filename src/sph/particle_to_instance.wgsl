@@ -0,0 +1,27 @@
+struct Instance {
+    position: vec3f,
+    color: vec4f,
+}
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read> materials: array<Material>;
+@group(0) @binding(2) var<storage, read> params: SimParams;
+@group(0) @binding(3) var<storage, read_write> instance: array<Instance>;
+
+@compute @workgroup_size(256)
+fn particle_to_instance(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if (idx >= arrayLength(&instance)) {
+        return;
+    }
+    // Slots freed by outlets are moved beyond the far plane
+    if (idx >= params.num_particles) {
+        instance[idx].position = vec3f(1.0e30);
+        return;
+    }
+    let particle = particles[idx];
+    // The hash grid is centred on the origin, as is the camera target
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    
+    instance[idx].position = position;
+    instance[idx].color = materials[particle.material_idx].color;
+}
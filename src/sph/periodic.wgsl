@@ -0,0 +1,37 @@
+// WGSL file for periodic boundaries in hash grid coordinates
+// Requires a `periodicity: Periodicity` uniform binding
+
+// Remainder in [0, n), the GLSL backend leaves `%` undefined for negative operands
+fn modulo(a: i32, n: i32) -> i32 {
+    let r = abs(a) % n;
+    return select(r, (n - r) % n, a < 0);
+}
+
+// Wrap a grid coordinate into the periodic domain
+fn wrap_coord(coord: vec3i) -> vec3i {
+    var wrapped = coord;
+    for (var axis = 0u; axis < 3u; axis++) {
+        let cells = i32(periodicity.cells[axis]);
+        if (cells > 0) {
+            let offset = coord[axis] - periodicity.min[axis];
+            wrapped[axis] = periodicity.min[axis] + modulo(offset, cells);
+        }
+    }
+    return wrapped;
+}
+
+// Shortest periodic image of a grid coordinate difference
+fn minimum_image(coord_dist: vec3i) -> vec3i {
+    var image = coord_dist;
+    for (var axis = 0u; axis < 3u; axis++) {
+        let cells = i32(periodicity.cells[axis]);
+        if (cells > 0) {
+            var d = modulo(coord_dist[axis], cells);
+            if (2 * d > cells) {
+                d -= cells;
+            }
+            image[axis] = d;
+        }
+    }
+    return image;
+}
@@ -0,0 +1,36 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> spatial: array<SpatialLookup>;
+
+@group(0) @binding(3)
+var<storage, read> start_indices: array<u32>;
+
+@group(0) @binding(4)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(5)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(6)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(7)
+var<storage, read> probe_points: array<ProbePoint>;
+
+@group(0) @binding(8)
+var<storage, read_write> probe_samples: array<FieldSample>;
+
+// Kernel interpolation of the particles at each probe point, see interpolate_fields
+@compute @workgroup_size(64)
+fn sample_probe_points(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&probe_points)) {
+        return;
+    }
+    probe_samples[index] = interpolate_fields(probe_points[index].position);
+}
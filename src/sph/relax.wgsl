@@ -0,0 +1,27 @@
+@group(0) @binding(0)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(1)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(2)
+var<uniform> relaxation: Relaxation;
+
+struct Relaxation {
+    // Fraction of the velocity kept after each step
+    factor: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+
+// Bleed off kinetic energy while the particles settle, before the run starts
+@compute @workgroup_size(256)
+fn damp_velocities(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    particles_motion[index].velocity *= relaxation.factor;
+    particles_motion[index].velocity_p *= relaxation.factor;
+}
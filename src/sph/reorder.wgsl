@@ -0,0 +1,43 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read_write> pairs: array<vec2u>;
+
+@group(0) @binding(4)
+var<storage, read_write> particles_out: array<Particle>;
+
+@group(0) @binding(5)
+var<storage, read_write> particles_motion_out: array<ParticleMotion>;
+
+// Z-order key of the hash grid cell, slots past the alive particles sort last
+@compute @workgroup_size(256)
+fn morton_keys(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&pairs)) {
+        return;
+    }
+    var key = U32MAX;
+    if (index < params.num_particles) {
+        // Centre the 1024 cell key range on the origin
+        key = morton_key(vec3u(particles[index].coord + vec3i(512)));
+    }
+    pairs[index] = vec2u(index, key);
+}
+
+@compute @workgroup_size(256)
+fn gather_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let source = pairs[index].x;
+    particles_out[index] = particles[source];
+    particles_motion_out[index] = particles_motion[source];
+}
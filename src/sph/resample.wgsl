@@ -0,0 +1,39 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> spatial: array<SpatialLookup>;
+
+@group(0) @binding(3)
+var<storage, read> start_indices: array<u32>;
+
+@group(0) @binding(4)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(5)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(6)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(7)
+var<uniform> field_grid: FieldGrid;
+
+@group(0) @binding(8)
+var<storage, read_write> samples: array<FieldSample>;
+
+// Kernel interpolation of the particles at each grid node, see interpolate_fields
+@compute @workgroup_size(256)
+fn resample_fields(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = grid_node_index(global_id, num_workgroups);
+    if (index >= field_grid.num_nodes) {
+        return;
+    }
+    samples[index] = interpolate_fields(grid_node(index));
+}
@@ -0,0 +1,187 @@
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read_write> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> bodies: array<RigidBodyState>;
+
+@group(0) @binding(4)
+var<storage, read_write> impulses: array<atomic<i32>>;
+
+@group(0) @binding(5)
+var<storage, read> obstacles: array<SdfObstacle>;
+
+@group(0) @binding(6)
+var<storage, read> sdf: array<f32>;
+
+@group(0) @binding(7)
+var<storage, read> material: array<Material>;
+
+@group(0) @binding(8)
+var<storage, read> spatial: array<SpatialLookup>;
+
+@group(0) @binding(9)
+var<storage, read> start_indices: array<u32>;
+
+@group(0) @binding(10)
+var<uniform> periodicity: Periodicity;
+
+@group(0) @binding(11)
+var<uniform> cell_grid: CellGrid;
+
+@group(0) @binding(12)
+var<uniform> disturbance: Disturbance;
+
+@group(0) @binding(13)
+var<uniform> walls: Walls;
+
+// Tangent plane of a body surface within reach of a particle
+struct BodySurface {
+    // Outward normal in world frame
+    normal: vec3f,
+    // Distance of the particle from the surface
+    distance: f32,
+    velocity: vec3f,
+}
+
+fn body_surface(body: RigidBodyState, position: vec3f) -> BodySurface {
+    let local = quat_rotate_inverse(body.orientation, position - body.position);
+    let normal = quat_rotate(body.orientation, body_normal(body, local));
+    let distance = body_sdf(body, local);
+    return BodySurface(normal, distance, body_point_velocity(body, position - distance * normal));
+}
+
+// Neighbor reflected across the tangent plane of a body surface, the normal velocity relative to
+// the surface is reversed. Neighbors behind the plane, see in_front, have no image.
+fn body_image(surface: BodySurface, neighbor: Particle, neighbor_motion: ParticleMotion, rvec_ab: vec3f) -> MirrorImage {
+    var image = MirrorImage(neighbor, neighbor_motion, rvec_ab);
+    let distance = surface.distance - dot(surface.normal, rvec_ab);
+    image.rvec_ab = rvec_ab + 2.0 * distance * surface.normal;
+    let relative = neighbor_motion.velocity - surface.velocity;
+    image.motion.velocity -= 2.0 * dot(relative, surface.normal) * surface.normal;
+    return hydrostatic_image(image, rvec_ab);
+}
+
+fn in_front(surface: BodySurface, rvec_ab: vec3f) -> bool {
+    return surface.distance - dot(surface.normal, rvec_ab) > 0.0;
+}
+
+// Density or, with `forces`, acceleration due to the fluid mirrored across the bodies within
+// reach of a particle. The body takes the reaction of the acceleration over the step with
+// `react`, once per step.
+fn body_images(index: u32, forces: bool, react: bool) {
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let h = particle.smoothing_length;
+    var density = 0.0;
+    var acceleration = vec3f(0.0);
+    for (var body_idx = 0u; body_idx < arrayLength(&bodies); body_idx++) {
+        let body = bodies[body_idx];
+        let surface = body_surface(body, position);
+        if (surface.distance < 0.0 || surface.distance >= h) {
+            continue;
+        }
+        var body_acceleration = vec3f(0.0);
+        var walk = neighbor_walk(index);
+        while (next_neighbor(&walk)) {
+            let neighbor_idx = walk.neighbor;
+            let neighbor = particles[neighbor_idx];
+            let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+            if (!in_front(surface, rvec_ab)) {
+                continue;
+            }
+            let image = body_image(surface, neighbor, particles_motion[neighbor_idx], rvec_ab);
+            if (forces) {
+                body_acceleration += pair_acceleration(particle, motion, image.neighbor, image.motion, image.rvec_ab);
+            } else {
+                density += pair_density(particle, image.neighbor, image.rvec_ab);
+            }
+        }
+        if (react) {
+            let contact = position - surface.distance * surface.normal;
+            accumulate_body_impulse(body_idx, body, contact, -particle.mass * body_acceleration * params.dt);
+        }
+        acceleration += body_acceleration;
+    }
+    if (forces) {
+        particles_motion[index].acceleration += acceleration;
+    } else {
+        particles[index].density += density;
+    }
+}
+
+// After the density interpolant
+@compute @workgroup_size(256)
+fn body_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x < params.num_particles) {
+        body_images(global_id.x, false, false);
+    }
+}
+
+// After the equation of motion of an intermediate integrator stage
+@compute @workgroup_size(256)
+fn body_forces(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x < params.num_particles) {
+        body_images(global_id.x, true, false);
+    }
+}
+
+// After the equation of motion of the last integrator stage, which gives the acceleration of the
+// step
+@compute @workgroup_size(256)
+fn body_forces_reaction(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x < params.num_particles) {
+        body_images(global_id.x, true, true);
+    }
+}
+
+// Particles that entered a body are projected onto its surface after the boundaries
+@compute @workgroup_size(256)
+fn rigid_body_coupling(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    // Get particle
+    let particle = particles[index];
+    // Get particle motion
+    let motion = particles_motion[index];
+    var position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    var velocity = motion.velocity;
+    var velocity_p = motion.velocity_p;
+    var collided = false;
+    for (var body_idx = 0u; body_idx < arrayLength(&bodies); body_idx++) {
+        let body = bodies[body_idx];
+        let local = quat_rotate_inverse(body.orientation, position - body.position);
+        let phi = body_sdf(body, local);
+        if (phi >= 0.0) {
+            continue;
+        }
+        // Project particle onto the moving surface
+        let normal = quat_rotate(body.orientation, body_normal(body, local));
+        position -= phi * normal;
+        // Collide in the frame of the body surface
+        let surface_velocity = body_point_velocity(body, position);
+        velocity = surface_velocity + collision_response(velocity - surface_velocity, normal, body.friction, body.restitution);
+        let velocity_p_new = surface_velocity + collision_response(velocity_p - surface_velocity, normal, body.friction, body.restitution);
+        // Equal and opposite impulse on the body
+        accumulate_body_impulse(body_idx, body, position, -particle.mass * (velocity_p_new - velocity_p));
+        velocity_p = velocity_p_new;
+        collided = true;
+    }
+    if (!collided) {
+        return;
+    }
+    // Set new states
+    let pos = position / params.grid_size;
+    particles[index].coord = vec3i(floor(pos));
+    particles[index].position = pos - floor(pos);
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity_p;
+}
@@ -1,4 +1,3 @@
-
 @group(0) @binding(0)
 var<storage, read_write> particles: array<Particle>;
 
@@ -11,60 +10,161 @@ var<storage, read> params: SimParams;
 @group(0) @binding(3)
 var<uniform> disturbance: Disturbance;
 
+@group(0) @binding(4)
+var<uniform> periodicity: Periodicity;
+
+// Positions at the start of the step for the predictor-corrector and midpoint integrators
+@group(0) @binding(5)
+var<storage, read_write> start_positions: array<ReferencePosition>;
+
+// Move a particle from a reference position, keeping the coordinate frame of the hash grid
+fn drift(index: u32, coord: vec3i, position: vec3f, displacement: vec3f) {
+    let pos = vec3f(coord) + position + displacement / params.grid_size;
+    particles[index].coord = wrap_coord(vec3i(floor(pos)));
+    particles[index].position = pos - floor(pos);
+}
+
+// Total acceleration of a particle, the accumulator is cleared for the next force evaluation
+fn take_acceleration(index: u32) -> vec3f {
+    let acceleration = particles_motion[index].acceleration + disturbance.field;
+    particles_motion[index].acceleration = vec3f(0.0);
+    return acceleration;
+}
+
+// velocity_p holds the velocity half a step behind, velocity is the mean of the two half steps
 @compute @workgroup_size(256)
 fn leap_frog(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
-    let num_particles = params.num_particles;
     if (index >= params.num_particles) {
         return;
     }
-    // Get particle
     let particle = particles[index];
-    // Get particle motion
     let motion = particles_motion[index];
-    // Get timestep
     let dt = params.dt;
-    // Get new coord position & velocity
-    let velocity_ph = motion.velocity_p + motion.acceleration * dt + disturbance.field * dt;
-    let pos = vec3f(particle.coord) + particle.position + velocity_ph * dt / params.grid_size;
-    let coord = vec3i(floor(pos));
-    let pos_coord_frame = pos - floor(pos); 
-    let velocity = 0.5 * (motion.velocity_p + velocity_ph);
-    // Set new states
-    particles[index].coord = coord;
-    particles[index].position = pos_coord_frame;
-    particles_motion[index].velocity = velocity;
+    let velocity_ph = motion.velocity_p + take_acceleration(index) * dt;
+    drift(index, particle.coord, particle.position, velocity_ph * dt);
+    particles_motion[index].velocity = 0.5 * (motion.velocity_p + velocity_ph);
     particles_motion[index].velocity_p = velocity_ph;
-    particles_motion[index].acceleration = vec3f(0.0,0.0,0.0);
+}
 
-    // Boundary check here as cube, change to separate dispacth call
-    var position = pos * params.grid_size;
-    let boundary_damping = 0.7;
-    let bounds: f32 = 0.5;
-    if (abs(position.x) > bounds && sign(position.x) == sign(motion.velocity_p.x)) {
-        let velocity = -1.0 * motion.velocity_p.x * boundary_damping;
-        position.x += sign(position.x) * (abs(position.x) - bounds) * (1.0 + boundary_damping);
-        particles_motion[index].velocity.x = velocity;
-        particles_motion[index].velocity_p.x = velocity;
-        particles[index].coord.x = i32(floor(sign(position.x) * bounds / params.grid_size));
-        particles[index].position.x = (sign(position.x) * bounds / params.grid_size - floor(sign(position.x) * bounds / params.grid_size));
+// First stage of velocity Verlet, the opening kick with the acceleration at the start of the
+// step and the drift. velocity and velocity_p hold the half step velocity until the closing kick.
+@compute @workgroup_size(256)
+fn kick_drift(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
     }
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let dt = params.dt;
+    let velocity_ph = motion.velocity + 0.5 * take_acceleration(index) * dt;
+    drift(index, particle.coord, particle.position, velocity_ph * dt);
+    particles_motion[index].velocity = velocity_ph;
+    particles_motion[index].velocity_p = velocity_ph;
+}
+
+// Closing kick of velocity Verlet with the acceleration at the new positions
+@compute @workgroup_size(256)
+fn close_kick(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let velocity = particles_motion[index].velocity_p + 0.5 * take_acceleration(index) * params.dt;
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity;
+}
+
+// Velocity first, then position with the new velocity
+@compute @workgroup_size(256)
+fn symplectic_euler(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let dt = params.dt;
+    let velocity = motion.velocity + take_acceleration(index) * dt;
+    drift(index, particle.coord, particle.position, velocity * dt);
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity;
+}
 
-    if (abs(position.y) > bounds && sign(position.y) == sign(motion.velocity_p.y)) {
-        let velocity = -1.0 * motion.velocity_p.y * boundary_damping;
-        position.y += sign(position.y) * (abs(position.y) - bounds) * (1.0 + boundary_damping);
-        particles_motion[index].velocity.y = velocity;
-        particles_motion[index].velocity_p.y = velocity;
-        particles[index].coord.y = i32(floor(sign(position.y) * bounds / params.grid_size));
-        particles[index].position.y = (sign(position.x) * bounds / params.grid_size - floor(sign(position.x) * bounds / params.grid_size));
+// First stage of the predictor-corrector and midpoint schemes, advances to the half step and
+// keeps the start of the step in start_positions and velocity_p
+@compute @workgroup_size(256)
+fn predict_half_step(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let dt = params.dt;
+    start_positions[index].coord = particle.coord;
+    start_positions[index].position = particle.position;
+    drift(index, particle.coord, particle.position, 0.5 * motion.velocity * dt);
+    particles_motion[index].velocity = motion.velocity + 0.5 * take_acceleration(index) * dt;
+    particles_motion[index].velocity_p = motion.velocity;
+}
+
+// Monaghan's corrector, the half step is recomputed with the half step acceleration and then
+// extrapolated to the end of the step
+@compute @workgroup_size(256)
+fn correct_predictor_corrector(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let start = start_positions[index];
+    let velocity_n = particles_motion[index].velocity_p;
+    let dt = params.dt;
+    let velocity_half = velocity_n + 0.5 * take_acceleration(index) * dt;
+    drift(index, start.coord, start.position, velocity_half * dt);
+    let velocity = 2.0 * velocity_half - velocity_n;
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity;
+}
+
+// Midpoint Runge-Kutta, the full step uses the half step velocity and acceleration
+@compute @workgroup_size(256)
+fn correct_midpoint(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let start = start_positions[index];
+    let motion = particles_motion[index];
+    let dt = params.dt;
+    drift(index, start.coord, start.position, motion.velocity * dt);
+    let velocity = motion.velocity_p + take_acceleration(index) * dt;
+    particles_motion[index].velocity = velocity;
+    particles_motion[index].velocity_p = velocity;
+}
+
+// Reflect particles leaving the unit box, after the integrator. Periodic axes have no walls.
+@compute @workgroup_size(256)
+fn wall_boundaries(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let particle = particles[index];
+    let motion = particles_motion[index];
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    let boundary_damping = 0.7;
+    let bounds: f32 = 0.5;
+    for (var axis = 0u; axis < 3u; axis++) {
+        if (periodicity.cells[axis] == 0u && abs(position[axis]) > bounds && sign(position[axis]) == sign(motion.velocity_p[axis])) {
+            let velocity = -1.0 * motion.velocity_p[axis] * boundary_damping;
+            particles_motion[index].velocity[axis] = velocity;
+            particles_motion[index].velocity_p[axis] = velocity;
+            // Place the particle on the wall
+            let wall = sign(position[axis]) * bounds / params.grid_size;
+            particles[index].coord[axis] = i32(floor(wall));
+            particles[index].position[axis] = wall - floor(wall);
+        }
     }
-    
-    if (abs(position.z) > bounds && sign(position.z) == sign(motion.velocity_p.z)) {
-        let velocity = -1.0 * motion.velocity_p.z * boundary_damping;
-        position.z += sign(position.z) * (abs(position.z) - bounds) * (1.0 + boundary_damping);
-        particles_motion[index].velocity.z = velocity;
-        particles_motion[index].velocity_p.z = velocity;
-        particles[index].coord.z = i32(floor(sign(position.z) * bounds / params.grid_size));
-        particles[index].position.z = (sign(position.x) * bounds / params.grid_size - floor(sign(position.x) * bounds / params.grid_size));
-    }    
 }
@@ -0,0 +1,30 @@
+@group(0) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<storage, read> particles_motion: array<ParticleMotion>;
+
+@group(0) @binding(2)
+var<storage, read> params: SimParams;
+
+@group(0) @binding(3)
+var<storage, read> tracer_ids: array<u32>;
+
+@group(0) @binding(4)
+var<storage, read_write> samples: array<TracerSample>;
+
+// Scatter the alive tracers into their slots, in world coordinates
+@compute @workgroup_size(256)
+fn gather_tracers(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+    let particle = particles[index];
+    let slot = tracer_slot(particle.id);
+    if (slot == TRACER_NONE) {
+        return;
+    }
+    let position = (vec3f(particle.coord) + particle.position) * params.grid_size;
+    samples[slot] = TracerSample(position, particle.id, particles_motion[index].velocity, 1u);
+}
@@ -1,11 +1,9 @@
 // WGSL file for common SPH supporting functions
 
-// get distance from particle to neighbor
+// get distance from particle to neighbor, using the minimum image across periodic axes
 fn get_particle_distance(particle: Particle, neighbor: Particle, scale: f32) -> vec3f {
-    let coord_dist_x = f32(particle.coord.x - neighbor.coord.x);
-    let coord_dist_y = f32(particle.coord.y - neighbor.coord.y);
-    let coord_dist_z = f32(particle.coord.z - neighbor.coord.z);
-    let rvec_ab = (vec3f(coord_dist_x, coord_dist_y, coord_dist_z) + particle.position - neighbor.position) * scale;
+    let coord_dist = minimum_image(particle.coord - neighbor.coord);
+    let rvec_ab = (vec3f(coord_dist) + particle.position - neighbor.position) * scale;
     return rvec_ab;
 }
 
@@ -0,0 +1,80 @@
+// WGSL file for the mirror images of the fluid across the walls of the unit box
+// Requires `material`, `params`, `periodicity`, `walls` and `disturbance` bindings
+
+const WALL_BOUNDS: f32 = 0.5;
+
+// Walls within reach of a particle at `position`, one bit per axis
+fn near_walls(position: vec3f, h: f32) -> u32 {
+    var near = 0u;
+    if (walls.mirror == 0u) {
+        return near;
+    }
+    for (var axis = 0u; axis < 3u; axis++) {
+        if (periodicity.cells[axis] == 0u && WALL_BOUNDS - abs(position[axis]) < h) {
+            near |= 1u << axis;
+        }
+    }
+    return near;
+}
+
+// Neighbor reflected across the walls of `axes` next to the particle at `position`. The normal
+// velocity is reversed and the pressure is continued hydrostatically from the neighbor to its
+// image, with the density and mass of the equation of state. `rvec_ab` is updated to the image.
+struct MirrorImage {
+    neighbor: Particle,
+    motion: ParticleMotion,
+    rvec_ab: vec3f,
+}
+
+fn mirror_image(position: vec3f, axes: u32, neighbor: Particle, neighbor_motion: ParticleMotion, rvec_ab: vec3f) -> MirrorImage {
+    var image = MirrorImage(neighbor, neighbor_motion, rvec_ab);
+    for (var axis = 0u; axis < 3u; axis++) {
+        if ((axes & (1u << axis)) != 0u) {
+            let wall = sign(position[axis]) * WALL_BOUNDS;
+            image.rvec_ab[axis] = 2.0 * (position[axis] - wall) - rvec_ab[axis];
+            image.motion.velocity[axis] = -neighbor_motion.velocity[axis];
+        }
+    }
+    return hydrostatic_image(image, rvec_ab);
+}
+
+// Continue the pressure of the neighbor hydrostatically to an image moved from `rvec_ab` to
+// `image.rvec_ab`, with the density and mass of the equation of state
+fn hydrostatic_image(image: MirrorImage, rvec_ab: vec3f) -> MirrorImage {
+    var continued = image;
+    let neighbor_material = material[image.neighbor.material_idx];
+    let rho0 = neighbor_material.density_reference;
+    // The image sits at rvec_ab - image.rvec_ab from the neighbor
+    let pressure_delta = rho0 * dot(disturbance.field, rvec_ab - image.rvec_ab);
+    let density_delta = pressure_delta / neighbor_material.compressibility;
+    continued.neighbor.pressure += pressure_delta;
+    continued.neighbor.density += density_delta;
+    continued.neighbor.mass *= 1.0 + density_delta / rho0;
+    return continued;
+}
+
+// Density of the images of a neighbor across the near walls, each combination of them once
+fn mirror_density_contribution(particle: Particle, position: vec3f, walls: u32, neighbor: Particle, neighbor_motion: ParticleMotion) -> f32 {
+    let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+    var density = 0.0;
+    for (var axes = 1u; axes < 8u; axes++) {
+        if ((axes & walls) == axes) {
+            let image = mirror_image(position, axes, neighbor, neighbor_motion, rvec_ab);
+            density += pair_density(particle, image.neighbor, image.rvec_ab);
+        }
+    }
+    return density;
+}
+
+// Acceleration due to the images of a neighbor across the near walls
+fn mirror_acceleration_contribution(particle: Particle, motion: ParticleMotion, position: vec3f, walls: u32, neighbor: Particle, neighbor_motion: ParticleMotion) -> vec3f {
+    let rvec_ab = get_particle_distance(particle, neighbor, params.grid_size);
+    var acceleration = vec3f(0.0);
+    for (var axes = 1u; axes < 8u; axes++) {
+        if ((axes & walls) == axes) {
+            let image = mirror_image(position, axes, neighbor, neighbor_motion, rvec_ab);
+            acceleration += pair_acceleration(particle, motion, image.neighbor, image.motion, image.rvec_ab);
+        }
+    }
+    return acceleration;
+}
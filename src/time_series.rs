@@ -0,0 +1,116 @@
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// CSV time series of `time` followed by one column per name, e.g. probe values or
+// conservation totals
+pub struct TimeSeriesWriter<W: Write> {
+    writer: W,
+}
+
+impl TimeSeriesWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, names: &[String]) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        Self::new(BufWriter::new(file), names)
+    }
+
+    // Continue the series of a restarted run, dropping the rows written after `time`
+    pub fn resume<P: AsRef<Path>>(path: P, names: &[String], time: f64) -> Result<Self> {
+        let path = path.as_ref();
+        let Result::Ok(file) = File::open(path) else {
+            return Self::create(path, names);
+        };
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        if header != header_line(names) {
+            bail!(
+                "{} has the columns {:?}, expected {:?}",
+                path.display(),
+                header,
+                header_line(names)
+            );
+        }
+        let mut rows = vec![];
+        for line in lines {
+            let line = line?;
+            let row_time: f64 = line
+                .split(',')
+                .next()
+                .and_then(|field| field.parse().ok())
+                .with_context(|| format!("invalid row {:?} in {}", line, path.display()))?;
+            // Times are printed in full, rows of the restart step compare equal
+            if row_time > time {
+                break;
+            }
+            rows.push(line);
+        }
+        let mut writer = Self::create(path, names)?;
+        for row in rows {
+            writeln!(writer.writer, "{}", row)?;
+        }
+        Ok(writer)
+    }
+}
+
+impl<W: Write> TimeSeriesWriter<W> {
+    pub fn new(mut writer: W, names: &[String]) -> Result<Self> {
+        writeln!(writer, "{}", header_line(names))?;
+        Ok(TimeSeriesWriter { writer })
+    }
+
+    pub fn write_row(&mut self, time: f64, values: &[f32]) -> Result<()> {
+        write!(self.writer, "{}", time)?;
+        for value in values {
+            write!(self.writer, ",{}", value)?;
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn header_line(names: &[String]) -> String {
+    std::iter::once("time")
+        .chain(names.iter().map(|name| name.as_str()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_series_resume() {
+        let names = ["p".to_string(), "q".to_string()];
+        let path = std::env::temp_dir().join(format!("time_series_{}.csv", std::process::id()));
+        let mut writer = TimeSeriesWriter::create(&path, &names).unwrap();
+        for step in 1..=4 {
+            writer.write_row(step as f64 * 0.25, &[1.0, -0.5]).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+        let mut writer = TimeSeriesWriter::resume(&path, &names, 0.5).unwrap();
+        writer.write_row(0.625, &[2.0, 0.0]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text, "time,p,q\n0.25,1,-0.5\n0.5,1,-0.5\n0.625,2,0\n");
+        let error = TimeSeriesWriter::resume(&path, &names[..1], 0.5)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("columns"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct TracerSample {
+    pub position: [f32; 3],
+    pub id: u32,
+    pub velocity: [f32; 3],
+    // Zero once the tracer has left through an outlet
+    pub alive: u32,
+    // 32 bytes
+}
+
+pub struct TracerBuffers {
+    // Followed particle ids in ascending order, samples are in the same order
+    pub ids: Vec<u32>,
+    pub buffer_ids: wgpu::Buffer,
+    pub buffer_samples: wgpu::Buffer,
+    pub staging_buffer_samples: wgpu::Buffer,
+}
+
+// Records tracer trajectories as CSV rows of `time,id,x,y,z,vx,vy,vz`
+pub struct TrajectoryWriter<W: Write> {
+    writer: W,
+}
+
+impl TracerBuffers {
+    pub fn new(device: &wgpu::Device, ids: &[u32]) -> Self {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        // Bindings can't be empty, u32::MAX matches no particle
+        let gpu_ids = match ids.is_empty() {
+            true => vec![u32::MAX],
+            false => ids.clone(),
+        };
+        let buffer_ids = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Buffer Tracer Ids"),
+            contents: bytemuck::cast_slice(&gpu_ids),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let samples_size = (gpu_ids.len() * std::mem::size_of::<TracerSample>()) as u64;
+        let buffer_samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Tracer Samples"),
+            size: samples_size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging_buffer_samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer Tracer Samples"),
+            size: samples_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        TracerBuffers {
+            ids,
+            buffer_ids,
+            buffer_samples,
+            staging_buffer_samples,
+        }
+    }
+
+    // Read back the samples written by the gather pass, one per followed id
+    pub fn gpu2cpu_samples(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<TracerSample> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder GPU to CPU Tracer Samples"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer_samples,
+            0,
+            &self.staging_buffer_samples,
+            0,
+            self.buffer_samples.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+        // Read back buffer
+        let buffer_slice = self.staging_buffer_samples.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        // Wait for GPU to finish operation
+        _ = device.poll(wgpu::PollType::Wait);
+        let output_data = buffer_slice.get_mapped_range();
+        let mut samples: Vec<TracerSample> = bytemuck::cast_slice(&output_data).to_vec();
+        drop(output_data);
+        self.staging_buffer_samples.unmap();
+        samples.truncate(self.ids.len());
+        // Slots of tracers that were not found keep the id for the caller
+        for (sample, &id) in samples.iter_mut().zip(&self.ids) {
+            sample.id = id;
+        }
+        samples
+    }
+}
+
+impl TrajectoryWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writeln!(writer, "time,id,x,y,z,vx,vy,vz")?;
+        Ok(TrajectoryWriter { writer })
+    }
+
+    // One row per alive tracer, removed tracers simply stop appearing
+    pub fn write_samples(&mut self, time: f32, samples: &[TracerSample]) -> Result<()> {
+        for sample in samples.iter().filter(|sample| sample.alive != 0) {
+            let [x, y, z] = sample.position;
+            let [vx, vy, vz] = sample.velocity;
+            writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{},{}",
+                time, sample.id, x, y, z, vx, vy, vz
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trajectory_writer() {
+        let sample = |id: u32, alive: u32| TracerSample {
+            position: [1.0, 2.0, 0.5],
+            id,
+            velocity: [0.0, -1.0, 0.0],
+            alive,
+        };
+        let mut writer = TrajectoryWriter::new(vec![]).unwrap();
+        writer
+            .write_samples(0.25, &[sample(3, 1), sample(7, 0)])
+            .unwrap();
+        let text = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(text, "time,id,x,y,z,vx,vy,vz\n0.25,3,1,2,0.5,0,-1,0\n");
+    }
+}
@@ -0,0 +1,30 @@
+// WGSL file for tracer particles followed over time
+// Requires the sorted `tracer_ids` and the `samples` bindings
+
+struct TracerSample {
+    position: vec3f,
+    id: u32,
+    velocity: vec3f,
+    alive: u32,
+    // 32 bytes
+}
+
+const TRACER_NONE: u32 = 4294967295u;
+
+// Slot of a particle id in the sorted tracer ids, TRACER_NONE when the particle is not a tracer
+fn tracer_slot(id: u32) -> u32 {
+    var low = 0u;
+    var high = arrayLength(&tracer_ids);
+    while (low < high) {
+        let mid = (low + high) / 2u;
+        if (tracer_ids[mid] < id) {
+            low = mid + 1u;
+        } else {
+            high = mid;
+        }
+    }
+    if (low < arrayLength(&tracer_ids) && tracer_ids[low] == id) {
+        return low;
+    }
+    return TRACER_NONE;
+}
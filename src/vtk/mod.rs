@@ -0,0 +1,503 @@
+use crate::checkpoint::SolverState;
+use crate::mls_mpm;
+use crate::sph;
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// VTK cell type of a single point
+const VTK_VERTEX: u8 = 1;
+
+// Particle fields written for ParaView, positions, velocities and masses in world units. MLS-MPM
+// particles carry no density or pressure, they are recomputed with the grid density of the
+// constitutive model.
+#[derive(Clone, Debug, Default)]
+pub struct PointData {
+    pub ids: Vec<u32>,
+    pub positions: Vec<[f32; 3]>,
+    pub velocities: Vec<[f32; 3]>,
+    pub densities: Vec<f32>,
+    pub pressures: Vec<f32>,
+    pub masses: Vec<f32>,
+    pub material_idx: Vec<u32>,
+}
+
+// `.pvd` collection of the files of a time series, rewritten on every entry so that it stays
+// valid when a run stops early
+pub struct PvdWriter {
+    path: PathBuf,
+    entries: Vec<(f64, PathBuf)>,
+}
+
+impl PointData {
+    pub fn from_sph(
+        particles: &[sph::Particle],
+        motion: &[sph::ParticleMotion],
+        grid_size: f32,
+    ) -> Self {
+        PointData {
+            ids: particles.iter().map(|particle| particle.id).collect(),
+            positions: particles
+                .iter()
+                .map(|particle| {
+                    std::array::from_fn(|a| {
+                        (particle.coord[a] as f32 + particle.position[a]) * grid_size
+                    })
+                })
+                .collect(),
+            velocities: motion.iter().map(|motion| motion.velocity).collect(),
+            densities: particles.iter().map(|particle| particle.density).collect(),
+            pressures: particles.iter().map(|particle| particle.pressure).collect(),
+            masses: particles.iter().map(|particle| particle.mass).collect(),
+            material_idx: particles
+                .iter()
+                .map(|particle| particle.material_idx)
+                .collect(),
+        }
+    }
+
+    // Rigid particles have no pressure
+    pub fn from_mls_mpm(
+        particles: &[mls_mpm::Particle],
+        params: &mls_mpm::SimParams,
+        materials: &[mls_mpm::Material],
+    ) -> Self {
+        let scale = params.scale_distance;
+        // Particle masses are in grid cells
+        let cell_volume = (scale / params.grid_resolution as f32).powi(3);
+        let densities = mls_mpm::particle_densities(particles, params);
+        let pressures = particles
+            .iter()
+            .zip(&densities)
+            .map(|(particle, &density)| {
+                let material = &materials[particle.material_idx as usize];
+                if material.rigid_flag == 1 {
+                    0.0
+                } else {
+                    material.pressure(density)
+                }
+            })
+            .collect();
+        PointData {
+            ids: particles.iter().map(|particle| particle.id).collect(),
+            positions: particles
+                .iter()
+                .map(|particle| particle.position.map(|x| x * scale))
+                .collect(),
+            velocities: particles
+                .iter()
+                .map(|particle| particle.velocity.map(|v| v * scale))
+                .collect(),
+            densities,
+            pressures,
+            masses: particles
+                .iter()
+                .map(|particle| particle.mass * cell_volume)
+                .collect(),
+            material_idx: particles
+                .iter()
+                .map(|particle| particle.material_idx)
+                .collect(),
+        }
+    }
+
+    pub fn from_state(state: &SolverState) -> Self {
+        match state {
+            SolverState::Sph(sph) => {
+                Self::from_sph(&sph.particles, &sph.motion, sph.params.grid_size)
+            }
+            SolverState::MlsMpm(mls_mpm) => {
+                Self::from_mls_mpm(&mls_mpm.particles, &mls_mpm.params, &mls_mpm.materials)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    // Legacy `.vtk` or XML `.vtu` by extension
+    pub fn save<P: AsRef<Path>>(&self, path: P, time: f64) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("vtk") => self.write_legacy(&mut writer, time)?,
+            Some("vtu") => self.write_vtu(&mut writer, time)?,
+            _ => bail!("{} is not a .vtk or .vtu file", path.display()),
+        }
+        writer
+            .flush()
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    // Legacy binary format, big-endian as the format requires
+    pub fn write_legacy<W: Write>(&self, writer: &mut W, time: f64) -> Result<()> {
+        let n = self.len();
+        writeln!(writer, "# vtk DataFile Version 3.0")?;
+        writeln!(writer, "hydrocode particles t = {}", time)?;
+        writeln!(writer, "BINARY")?;
+        writeln!(writer, "DATASET UNSTRUCTURED_GRID")?;
+        writeln!(writer, "POINTS {} float", n)?;
+        for x in self.positions.iter().flatten() {
+            writer.write_all(&x.to_be_bytes())?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "CELLS {} {}", n, 2 * n)?;
+        for i in 0..n as i32 {
+            writer.write_all(&1i32.to_be_bytes())?;
+            writer.write_all(&i.to_be_bytes())?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "CELL_TYPES {}", n)?;
+        for _ in 0..n {
+            writer.write_all(&(VTK_VERTEX as i32).to_be_bytes())?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "POINT_DATA {}", n)?;
+        writeln!(writer, "VECTORS velocity float")?;
+        for v in self.velocities.iter().flatten() {
+            writer.write_all(&v.to_be_bytes())?;
+        }
+        writeln!(writer)?;
+        for (name, values) in [
+            ("density", &self.densities),
+            ("pressure", &self.pressures),
+            ("mass", &self.masses),
+        ] {
+            writeln!(writer, "SCALARS {} float 1", name)?;
+            writeln!(writer, "LOOKUP_TABLE default")?;
+            for x in values {
+                writer.write_all(&x.to_be_bytes())?;
+            }
+            writeln!(writer)?;
+        }
+        for (name, values) in [("material", &self.material_idx), ("id", &self.ids)] {
+            writeln!(writer, "SCALARS {} unsigned_int 1", name)?;
+            writeln!(writer, "LOOKUP_TABLE default")?;
+            for x in values {
+                writer.write_all(&x.to_be_bytes())?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    // XML unstructured grid with the arrays appended as raw little-endian binary, each block
+    // preceded by its UInt64 byte count
+    pub fn write_vtu<W: Write>(&self, writer: &mut W, time: f64) -> Result<()> {
+        let n = self.len();
+        let time_value = [time];
+        let connectivity: Vec<i64> = (0..n as i64).collect();
+        let offsets: Vec<i64> = (1..=n as i64).collect();
+        let types = vec![VTK_VERTEX; n];
+        // Name, type, components and data in the order of the appended blocks
+        let arrays: [AppendedArray; 11] = [
+            ("TimeValue", "Float64", 1, bytemuck::cast_slice(&time_value)),
+            (
+                "Points",
+                "Float32",
+                3,
+                bytemuck::cast_slice(&self.positions),
+            ),
+            (
+                "velocity",
+                "Float32",
+                3,
+                bytemuck::cast_slice(&self.velocities),
+            ),
+            (
+                "density",
+                "Float32",
+                1,
+                bytemuck::cast_slice(&self.densities),
+            ),
+            (
+                "pressure",
+                "Float32",
+                1,
+                bytemuck::cast_slice(&self.pressures),
+            ),
+            ("mass", "Float32", 1, bytemuck::cast_slice(&self.masses)),
+            (
+                "material",
+                "UInt32",
+                1,
+                bytemuck::cast_slice(&self.material_idx),
+            ),
+            ("id", "UInt32", 1, bytemuck::cast_slice(&self.ids)),
+            (
+                "connectivity",
+                "Int64",
+                1,
+                bytemuck::cast_slice(&connectivity),
+            ),
+            ("offsets", "Int64", 1, bytemuck::cast_slice(&offsets)),
+            ("types", "UInt8", 1, &types),
+        ];
+        let elements = appended_elements(&arrays);
+        writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            writer,
+            r#"<VTKFile type="UnstructuredGrid" version="1.0" byte_order="LittleEndian" header_type="UInt64">"#
+        )?;
+        writeln!(writer, "  <UnstructuredGrid>")?;
+        // ParaView reads the time of a single file from the TimeValue field
+        writeln!(writer, "    <FieldData>")?;
+        writeln!(
+            writer,
+            "      {}",
+            elements[0].replace("/>", r#" NumberOfTuples="1"/>"#)
+        )?;
+        writeln!(writer, "    </FieldData>")?;
+        writeln!(
+            writer,
+            r#"    <Piece NumberOfPoints="{}" NumberOfCells="{}">"#,
+            n, n
+        )?;
+        writeln!(writer, "      <Points>")?;
+        writeln!(writer, "        {}", elements[1])?;
+        writeln!(writer, "      </Points>")?;
+        writeln!(
+            writer,
+            r#"      <PointData Scalars="density" Vectors="velocity">"#
+        )?;
+        for element in &elements[2..8] {
+            writeln!(writer, "        {}", element)?;
+        }
+        writeln!(writer, "      </PointData>")?;
+        writeln!(writer, "      <Cells>")?;
+        for element in &elements[8..] {
+            writeln!(writer, "        {}", element)?;
+        }
+        writeln!(writer, "      </Cells>")?;
+        writeln!(writer, "    </Piece>")?;
+        writeln!(writer, "  </UnstructuredGrid>")?;
+        write_appended_data(writer, &arrays)?;
+        writeln!(writer, "</VTKFile>")?;
+        Ok(())
+    }
+}
+
+// Name, type, components and data of an array in the appended data section of an XML file
+pub type AppendedArray<'a> = (&'a str, &'a str, usize, &'a [u8]);
+
+// DataArray elements of the arrays, with the offsets of their blocks in write_appended_data
+pub fn appended_elements(arrays: &[AppendedArray]) -> Vec<String> {
+    let mut elements = vec![];
+    let mut offset = 0;
+    for (name, ty, components, data) in arrays {
+        elements.push(format!(
+            r#"<DataArray type="{}" Name="{}" NumberOfComponents="{}" format="appended" offset="{}"/>"#,
+            ty, name, components, offset
+        ));
+        offset += std::mem::size_of::<u64>() + data.len();
+    }
+    elements
+}
+
+// Raw little-endian blocks, each preceded by its UInt64 byte count
+pub fn write_appended_data<W: Write>(writer: &mut W, arrays: &[AppendedArray]) -> Result<()> {
+    write!(writer, r#"  <AppendedData encoding="raw">"#)?;
+    write!(writer, "\n   _")?;
+    for (_, _, _, data) in arrays {
+        writer.write_all(&(data.len() as u64).to_le_bytes())?;
+        writer.write_all(data)?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "  </AppendedData>")?;
+    Ok(())
+}
+
+impl PvdWriter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        PvdWriter {
+            path: path.as_ref().to_path_buf(),
+            entries: vec![],
+        }
+    }
+
+    // Continue the collection of a restarted run, keeping the entries up to `time`. A missing
+    // collection starts empty.
+    pub fn resume<P: AsRef<Path>>(path: P, time: f64) -> Result<Self> {
+        let mut pvd = Self::new(path);
+        let Result::Ok(content) = std::fs::read_to_string(&pvd.path) else {
+            return Ok(pvd);
+        };
+        let attribute = |line: &str, name: &str| -> Option<String> {
+            let value = line.split(&format!(r#"{}=""#, name)).nth(1)?;
+            Some(value.split('"').next()?.to_string())
+        };
+        for line in content.lines().filter(|line| line.contains("<DataSet")) {
+            let entry = attribute(line, "timestep")
+                .and_then(|timestep| timestep.parse::<f64>().ok())
+                .zip(attribute(line, "file"))
+                .with_context(|| format!("invalid entry {:?} in {}", line, pvd.path.display()))?;
+            if entry.0 <= time {
+                pvd.entries.push((entry.0, PathBuf::from(entry.1)));
+            }
+        }
+        Ok(pvd)
+    }
+
+    // `file` is stored relative to the collection when it lies in the same directory tree
+    pub fn add<P: AsRef<Path>>(&mut self, time: f64, file: P) -> Result<()> {
+        let file = file.as_ref();
+        let dir = self.path.parent().unwrap_or(Path::new(""));
+        let relative = file.strip_prefix(dir).unwrap_or(file);
+        self.entries.push((time, relative.to_path_buf()));
+        let output = File::create(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        let mut writer = BufWriter::new(output);
+        self.write(&mut writer)
+            .and_then(|_| Ok(writer.flush()?))
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            writer,
+            r#"<VTKFile type="Collection" version="1.0" byte_order="LittleEndian">"#
+        )?;
+        writeln!(writer, "  <Collection>")?;
+        for (time, file) in &self.entries {
+            writeln!(
+                writer,
+                r#"    <DataSet timestep="{}" part="0" file="{}"/>"#,
+                time,
+                file.display()
+            )?;
+        }
+        writeln!(writer, "  </Collection>")?;
+        writeln!(writer, "</VTKFile>")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vtu_appended_offsets() {
+        let data = PointData {
+            ids: vec![7, 8],
+            positions: vec![[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]],
+            velocities: vec![[1.0; 3], [-1.0; 3]],
+            densities: vec![1000.0, 1001.0],
+            pressures: vec![0.0, 9.81],
+            masses: vec![0.1; 2],
+            material_idx: vec![0, 1],
+        };
+        let mut bytes = vec![];
+        data.write_vtu(&mut bytes, 0.5).unwrap();
+        let marker = b"<AppendedData encoding=\"raw\">\n   _";
+        let start = bytes
+            .windows(marker.len())
+            .position(|w| w == marker)
+            .unwrap()
+            + marker.len();
+        let header = String::from_utf8_lossy(&bytes[..start]);
+        // Each array is found at its declared offset with the declared length
+        let appended = &bytes[start..];
+        let read_block = |name: &str| -> &[u8] {
+            let element = header
+                .lines()
+                .find(|line| line.contains(&format!(r#"Name="{}""#, name)))
+                .unwrap();
+            let offset: usize = element
+                .split(r#"offset=""#)
+                .nth(1)
+                .unwrap()
+                .split('"')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let len = u64::from_le_bytes(appended[offset..offset + 8].try_into().unwrap());
+            &appended[offset + 8..offset + 8 + len as usize]
+        };
+        assert_eq!(
+            bytemuck::pod_collect_to_vec::<u8, f32>(read_block("Points")),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 5.0]
+        );
+        assert_eq!(
+            bytemuck::pod_collect_to_vec::<u8, u32>(read_block("id")),
+            [7, 8]
+        );
+        assert_eq!(
+            bytemuck::pod_collect_to_vec::<u8, f64>(read_block("TimeValue")),
+            [0.5]
+        );
+        assert_eq!(read_block("types"), [VTK_VERTEX; 2]);
+        assert!(bytes.ends_with(b"</AppendedData>\n</VTKFile>\n"));
+
+        // Legacy files hold four bytes per value after the headers
+        let mut legacy = vec![];
+        data.write_legacy(&mut legacy, 0.5).unwrap();
+        assert!(legacy.starts_with(b"# vtk DataFile Version 3.0\n"));
+        let values = 2 * (3 + 2 + 1 + 3 + 3 + 1 + 1 + 1 + 1);
+        assert!(legacy.len() > 4 * values);
+    }
+
+    #[test]
+    fn test_mls_mpm_density_at_rest() {
+        // Two particles per cell and axis with the reference density of one
+        let grid_resolution = 16;
+        let spacing = 0.5 / grid_resolution as f32;
+        let mut particles = vec![];
+        for k in 0..16 {
+            for j in 0..16 {
+                for i in 0..16 {
+                    particles.push(mls_mpm::Particle {
+                        position: [i, j, k].map(|n| 0.25 + (n as f32 + 0.5) * spacing),
+                        mass: 0.125,
+                        velocity: [0.0; 3],
+                        material_idx: 0,
+                        C: [0.0; 12],
+                        id: particles.len() as u32,
+                        tag: 0,
+                        _padding: [0; 2],
+                    });
+                }
+            }
+        }
+        let params = mls_mpm::SimParams {
+            grid_resolution,
+            dt: 0.1,
+            scale_distance: 2.0,
+            num_particles: particles.len() as u32,
+            num_nodes: grid_resolution.pow(3),
+            periodic_axes: 0,
+        };
+        let material = mls_mpm::Material {
+            color: [0.0; 4],
+            eos_density: 1.0,
+            eos_threshold: 0.7,
+            eos_stiffness: 10.0,
+            eos_n: 4.0,
+            dynamic_viscosity: 0.1,
+            rigid_flag: 0,
+            _padding: [0; 2],
+        };
+        let data = PointData::from_mls_mpm(&particles, &params, &[material]);
+        // Interior particles see the reference density and no pressure
+        let center = particles.len() / 2 + 8 * 16 + 8;
+        assert!(
+            (data.densities[center] - 1.0).abs() < 1e-4,
+            "{}",
+            data.densities[center]
+        );
+        assert!(data.pressures[center].abs() < 1e-2);
+        // Masses in world units add up to the unit volume of the block
+        let total: f32 = data.masses.iter().sum();
+        assert!((total - 1.0).abs() < 1e-3, "{}", total);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::rigid_body::RigidBody;
+use anyhow::*;
+use cgmath::*;
+use serde::Deserialize;
+use std::path::Path;
+
+// Kinematics of a prescribed-motion boundary relative to its rest pose
+#[derive(Clone, Debug)]
+pub enum WavemakerMotion {
+    // Translation along a direction, the signal is the displacement
+    Piston {
+        direction: Vector3<f32>,
+    },
+    // Rotation about an axis through a hinge, the signal is the angle in radians.
+    // Covers bottom-hinged flaps and rotating paddles.
+    Flap {
+        hinge: Vector3<f32>,
+        axis: Vector3<f32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StokesOrder {
+    #[default]
+    First,
+    Second,
+}
+
+// Displacement or angle of the boundary as a function of time
+#[derive(Clone, Debug)]
+pub enum MotionSignal {
+    // Linear interpolation of samples, held constant outside the time range
+    TimeSeries {
+        times: Vec<f32>,
+        values: Vec<f32>,
+    },
+    // Regular wave generation, `second` is the amplitude of the double frequency component
+    Harmonic {
+        first: f32,
+        second: f32,
+        omega: f32,
+        phase: f32,
+        ramp_time: f32,
+    },
+    // Constant rate, e.g. a continuously rotating paddle
+    Rate {
+        rate: f32,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Wavemaker {
+    pub motion: WavemakerMotion,
+    pub signal: MotionSignal,
+    rest_position: Vector3<f32>,
+    rest_orientation: Quaternion<f32>,
+}
+
+impl MotionSignal {
+    // Two columns of time and value, separated by commas or whitespace. Lines starting with `#`
+    // and a non-numeric header are skipped.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse_csv(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn parse_csv(text: &str) -> Result<Self> {
+        let mut times = vec![];
+        let mut values = vec![];
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            let parsed: Vec<f32> = fields.iter().filter_map(|f| f.parse().ok()).collect();
+            if parsed.len() != fields.len() && times.is_empty() && values.is_empty() {
+                // Header
+                continue;
+            }
+            if fields.len() < 2 || parsed.len() != fields.len() {
+                bail!("line {}: expected two numeric columns", line_idx + 1);
+            }
+            if times.last().is_some_and(|&last| parsed[0] <= last) {
+                bail!("line {}: times must be strictly increasing", line_idx + 1);
+            }
+            times.push(parsed[0]);
+            values.push(parsed[1]);
+        }
+        if times.is_empty() {
+            bail!("no samples");
+        }
+        Ok(MotionSignal::TimeSeries { times, values })
+    }
+
+    // Times are in f64 so that the phase of long runs keeps its resolution
+    pub fn value(&self, time: f64) -> f32 {
+        match self {
+            MotionSignal::TimeSeries { times, values } => {
+                let upper = times.partition_point(|&t| t as f64 <= time);
+                if upper == 0 {
+                    values[0]
+                } else if upper == times.len() {
+                    values[upper - 1]
+                } else {
+                    let (t0, t1) = (times[upper - 1] as f64, times[upper] as f64);
+                    let f = ((time - t0) / (t1 - t0)) as f32;
+                    values[upper - 1] + f * (values[upper] - values[upper - 1])
+                }
+            }
+            MotionSignal::Harmonic {
+                first,
+                second,
+                omega,
+                phase,
+                ramp_time,
+            } => {
+                let (ramp, _) = harmonic_ramp(time, *ramp_time);
+                let angle = *omega as f64 * time + *phase as f64;
+                (ramp * (*first as f64 * angle.sin() + *second as f64 * (2.0 * angle).sin())) as f32
+            }
+            MotionSignal::Rate { rate } => (*rate as f64 * time) as f32,
+        }
+    }
+
+    // Time derivative of the value, zero outside the samples of a time series and one-sided at
+    // its knots
+    pub fn rate(&self, time: f64) -> f32 {
+        match self {
+            MotionSignal::TimeSeries { times, values } => {
+                let upper = times.partition_point(|&t| t as f64 <= time);
+                if upper == 0 || upper == times.len() {
+                    0.0
+                } else {
+                    (values[upper] - values[upper - 1]) / (times[upper] - times[upper - 1])
+                }
+            }
+            MotionSignal::Harmonic {
+                first,
+                second,
+                omega,
+                phase,
+                ramp_time,
+            } => {
+                let (ramp, ramp_rate) = harmonic_ramp(time, *ramp_time);
+                let (first, second, omega) = (*first as f64, *second as f64, *omega as f64);
+                let angle = omega * time + *phase as f64;
+                let wave = first * angle.sin() + second * (2.0 * angle).sin();
+                let wave_rate = omega * (first * angle.cos() + 2.0 * second * (2.0 * angle).cos());
+                (ramp_rate * wave + ramp * wave_rate) as f32
+            }
+            MotionSignal::Rate { rate } => *rate,
+        }
+    }
+}
+
+// Smooth start of a harmonic signal to avoid an impulsive transient, with its time derivative
+fn harmonic_ramp(time: f64, ramp_time: f32) -> (f64, f64) {
+    let ramp_time = ramp_time as f64;
+    if ramp_time <= 0.0 || time >= ramp_time {
+        return (1.0, 0.0);
+    }
+    if time <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let x = std::f64::consts::PI * time / ramp_time;
+    (
+        0.5 * (1.0 - x.cos()),
+        0.5 * std::f64::consts::PI / ramp_time * x.sin(),
+    )
+}
+
+impl Wavemaker {
+    // The current pose of the paddle body is taken as the rest pose
+    pub fn new(motion: WavemakerMotion, signal: MotionSignal, paddle: &RigidBody) -> Self {
+        Wavemaker {
+            motion,
+            signal,
+            rest_position: paddle.position,
+            rest_orientation: paddle.orientation,
+        }
+    }
+
+    // Piston generating regular waves of `height` and `period` in water of `depth`
+    pub fn piston_stokes(
+        paddle: &RigidBody,
+        direction: [f32; 3],
+        height: f32,
+        period: f32,
+        depth: f32,
+        gravity: f32,
+        order: StokesOrder,
+    ) -> Self {
+        let omega = 2.0 * std::f32::consts::PI / period;
+        let kh = wave_number(omega, depth, gravity) * depth;
+        let transfer = piston_transfer(kh);
+        let stroke = height / transfer;
+        // Madsen (1971) correction suppressing the spurious free second harmonic
+        let second = match order {
+            StokesOrder::First => 0.0,
+            StokesOrder::Second => {
+                height * height / (32.0 * depth)
+                    * (3.0 * kh.cosh() / kh.sinh().powi(3) - 2.0 / transfer)
+            }
+        };
+        let signal = MotionSignal::Harmonic {
+            first: 0.5 * stroke,
+            second,
+            omega,
+            phase: 0.0,
+            ramp_time: 2.0 * period,
+        };
+        let motion = WavemakerMotion::Piston {
+            direction: Vector3::from(direction).normalize(),
+        };
+        Self::new(motion, signal, paddle)
+    }
+
+    // Flap hinged on the bed at `hinge` generating regular first order waves. The paddle rotates
+    // about `axis`, with positive angles moving the free surface end towards the tank.
+    pub fn flap_stokes(
+        paddle: &RigidBody,
+        hinge: [f32; 3],
+        axis: [f32; 3],
+        height: f32,
+        period: f32,
+        depth: f32,
+        gravity: f32,
+    ) -> Self {
+        let omega = 2.0 * std::f32::consts::PI / period;
+        let kh = wave_number(omega, depth, gravity) * depth;
+        // Stroke at the still water level
+        let stroke = height / flap_transfer(kh);
+        let signal = MotionSignal::Harmonic {
+            first: (0.5 * stroke / depth).atan(),
+            second: 0.0,
+            omega,
+            phase: 0.0,
+            ramp_time: 2.0 * period,
+        };
+        let motion = WavemakerMotion::Flap {
+            hinge: hinge.into(),
+            axis: Vector3::from(axis).normalize(),
+        };
+        Self::new(motion, signal, paddle)
+    }
+
+    // Drive the paddle body to its pose and velocity at `time`. The body should not be integrated.
+    pub fn update(&self, paddle: &mut RigidBody, time: f64) {
+        let value = self.signal.value(time);
+        let rate = self.signal.rate(time);
+        match &self.motion {
+            WavemakerMotion::Piston { direction } => {
+                paddle.position = self.rest_position + direction * value;
+                paddle.orientation = self.rest_orientation;
+                paddle.linear_velocity = direction * rate;
+                paddle.angular_velocity = Vector3::zero();
+            }
+            WavemakerMotion::Flap { hinge, axis } => {
+                let rotation = Quaternion::from_axis_angle(*axis, Rad(value));
+                let arm = rotation.rotate_vector(self.rest_position - hinge);
+                paddle.position = hinge + arm;
+                paddle.orientation = rotation * self.rest_orientation;
+                paddle.angular_velocity = axis * rate;
+                paddle.linear_velocity = paddle.angular_velocity.cross(arm);
+            }
+        }
+    }
+}
+
+// Solve the linear dispersion relation omega^2 = g k tanh(k h) with Newton iterations
+pub fn wave_number(omega: f32, depth: f32, gravity: f32) -> f32 {
+    let (omega, depth, gravity) = (omega as f64, depth as f64, gravity as f64);
+    // Deep water guess
+    let mut k = omega * omega / gravity;
+    for _ in 0..50 {
+        let t = (k * depth).tanh();
+        let f = gravity * k * t - omega * omega;
+        let df = gravity * t + gravity * k * depth * (1.0 - t * t);
+        let dk = f / df;
+        k -= dk;
+        if dk.abs() < 1e-12 * k {
+            break;
+        }
+    }
+    k as f32
+}
+
+// Biesel transfer function, wave height over piston stroke
+pub fn piston_transfer(kh: f32) -> f32 {
+    2.0 * ((2.0 * kh).cosh() - 1.0) / ((2.0 * kh).sinh() + 2.0 * kh)
+}
+
+// Biesel transfer function, wave height over flap stroke at the still water level
+pub fn flap_transfer(kh: f32) -> f32 {
+    4.0 * kh.sinh() / kh * (kh * kh.sinh() - kh.cosh() + 1.0) / ((2.0 * kh).sinh() + 2.0 * kh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wavemaker_theory() {
+        // Dispersion relation
+        let (omega, depth, gravity) = (2.0, 0.5, 9.81);
+        let k = wave_number(omega, depth, gravity);
+        assert!((gravity * k * (k * depth).tanh() - omega * omega).abs() < 1e-4);
+        // Shallow water limit of the piston transfer function is kh
+        assert!((piston_transfer(1e-2) - 1e-2).abs() < 1e-4);
+        // Deep water limits, 2 for a piston and 2 (1 - 1 / kh) for a flap
+        assert!((piston_transfer(8.0) - 2.0).abs() < 1e-3);
+        assert!((flap_transfer(8.0) - 1.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_time_series_csv() {
+        let signal =
+            MotionSignal::parse_csv("time,x\n# comment\n0.0,0.0\n1.0,2.0\n2.0 1.0\n").unwrap();
+        assert_eq!(signal.value(-1.0), 0.0);
+        assert!((signal.value(0.5) - 1.0).abs() < 1e-6);
+        assert!((signal.value(1.5) - 1.5).abs() < 1e-6);
+        assert_eq!(signal.value(3.0), 1.0);
+        assert_eq!(signal.rate(0.5), 2.0);
+        assert_eq!(signal.rate(1.0), -1.0);
+        assert_eq!(signal.rate(3.0), 0.0);
+        let error = MotionSignal::parse_csv("0.0,0.0\n1.0,oops\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_harmonic_rate() {
+        let signal = MotionSignal::Harmonic {
+            first: 0.05,
+            second: 0.01,
+            omega: 4.0,
+            phase: 0.3,
+            ramp_time: 2.0,
+        };
+        // During the ramp and after a long run the rate matches the difference quotient
+        for time in [0.7, 600.3] {
+            let step = 1e-4;
+            let quotient = (signal.value(time + step) as f64 - signal.value(time - step) as f64)
+                / (2.0 * step);
+            assert!((signal.rate(time) as f64 - quotient).abs() < 1e-3);
+        }
+        assert_eq!(signal.rate(-1.0), 0.0);
+    }
+}